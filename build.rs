@@ -0,0 +1,19 @@
+use std::process::Command;
+
+// Recompila el binario cuando cambian las migraciones, ya que van incluidas en el
+// por medio de `sqlx::migrate!`, y guarda el commit de git para el servicio `/version`
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|salida| salida.status.success())
+        .and_then(|salida| String::from_utf8(salida.stdout).ok())
+        .map(|commit| commit.trim().to_owned())
+        .unwrap_or_else(|| "unknown".to_owned());
+    println!("cargo:rustc-env=GIT_HASH={commit}");
+}
@@ -0,0 +1,12 @@
+-- Add migration script here
+ALTER table clientes drop column created_at, drop column updated_at;
+
+ALTER table usuarios drop column created_at, drop column updated_at;
+
+ALTER table proveedores drop column created_at, drop column updated_at;
+
+ALTER table productos drop column created_at, drop column updated_at;
+
+ALTER table ventas drop column fecha, drop column created_at, drop column updated_at;
+
+ALTER table detalle_ventas drop column created_at, drop column updated_at;
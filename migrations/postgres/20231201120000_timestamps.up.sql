@@ -0,0 +1,25 @@
+-- Add migration script here
+ALTER table clientes
+	add column created_at timestamptz not null default now(),
+	add column updated_at timestamptz not null default now();
+
+ALTER table usuarios
+	add column created_at timestamptz not null default now(),
+	add column updated_at timestamptz not null default now();
+
+ALTER table proveedores
+	add column created_at timestamptz not null default now(),
+	add column updated_at timestamptz not null default now();
+
+ALTER table productos
+	add column created_at timestamptz not null default now(),
+	add column updated_at timestamptz not null default now();
+
+ALTER table ventas
+	add column fecha timestamptz not null default now(),
+	add column created_at timestamptz not null default now(),
+	add column updated_at timestamptz not null default now();
+
+ALTER table detalle_ventas
+	add column created_at timestamptz not null default now(),
+	add column updated_at timestamptz not null default now();
@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER table clientes drop column deleted_at;
+
+ALTER table usuarios drop column deleted_at;
+
+ALTER table proveedores drop column deleted_at;
+
+ALTER table productos drop column deleted_at;
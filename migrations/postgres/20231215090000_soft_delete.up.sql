@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER table clientes add column deleted_at timestamptz;
+
+ALTER table usuarios add column deleted_at timestamptz;
+
+ALTER table proveedores add column deleted_at timestamptz;
+
+ALTER table productos add column deleted_at timestamptz;
@@ -0,0 +1,14 @@
+-- Add migration script here
+CREATE table auditoria (
+	codigo bigserial primary key,
+	cedula_usuario bigint not null,
+	entidad varchar(40) not null,
+	llave bigint,
+	accion varchar(10) not null,
+	antes jsonb,
+	despues jsonb,
+	fecha timestamptz not null default now(),
+	foreign key(cedula_usuario) references usuarios(cedula)
+);
+
+CREATE index auditoria_entidad_llave on auditoria (entidad, llave);
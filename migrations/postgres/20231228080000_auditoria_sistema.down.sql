@@ -0,0 +1,4 @@
+-- Add migration script here
+DELETE from auditoria where cedula_usuario is null;
+
+ALTER table auditoria alter column cedula_usuario set not null;
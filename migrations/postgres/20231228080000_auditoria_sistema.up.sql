@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Las operaciones de la linea de comandos (importaciones, datos de demostracion, etc.)
+-- no tienen un usuario autenticado
+ALTER table auditoria alter column cedula_usuario drop not null;
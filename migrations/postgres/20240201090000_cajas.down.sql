@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER table ventas drop column codigo_turno;
+
+DROP table movimientos_caja;
+
+DROP table turnos;
+
+DROP table cajas;
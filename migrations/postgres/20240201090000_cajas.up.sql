@@ -0,0 +1,37 @@
+-- Add migration script here
+-- Las cajas registradoras y los turnos de los cajeros: cada turno empieza con un efectivo
+-- inicial, acumula las ventas del cajero y los movimientos de efectivo, y se cierra con el
+-- efectivo contado. Un usuario y una caja solo pueden tener un turno abierto a la vez
+CREATE table cajas (
+	codigo bigint primary key,
+	nombre varchar(40) not null,
+	created_at timestamptz not null,
+	updated_at timestamptz not null
+);
+
+CREATE table turnos (
+	codigo bigserial primary key,
+	codigo_caja bigint not null,
+	cedula_usuario bigint not null,
+	efectivo_inicial double precision not null,
+	efectivo_contado double precision,
+	abierto_at timestamptz not null,
+	cerrado_at timestamptz,
+	foreign key(codigo_caja) references cajas(codigo),
+	foreign key(cedula_usuario) references usuarios(cedula)
+);
+
+CREATE unique index turnos_abierto_usuario on turnos (cedula_usuario) where cerrado_at is null;
+CREATE unique index turnos_abierto_caja on turnos (codigo_caja) where cerrado_at is null;
+
+CREATE table movimientos_caja (
+	codigo bigserial primary key,
+	codigo_turno bigint not null,
+	tipo varchar(10) not null,
+	valor double precision not null,
+	motivo varchar(40) not null,
+	fecha timestamptz not null,
+	foreign key(codigo_turno) references turnos(codigo)
+);
+
+ALTER table ventas add column codigo_turno bigint references turnos(codigo);
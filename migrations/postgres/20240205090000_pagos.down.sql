@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER table ventas drop column finalizada_at;
+
+DROP table pagos;
@@ -0,0 +1,18 @@
+-- Add migration script here
+-- Los pagos de cada venta: una venta puede pagarse con varios medios (efectivo, tarjeta,
+-- transferencia o credito de la tienda) y solo se finaliza cuando los pagos suman el total
+CREATE table pagos (
+	codigo bigserial primary key,
+	codigo_venta bigint not null,
+	metodo varchar(20) not null,
+	valor double precision not null,
+	recibido double precision,
+	cambio double precision,
+	referencia varchar(40),
+	fecha timestamptz not null,
+	foreign key(codigo_venta) references ventas(codigo)
+);
+
+CREATE index pagos_venta on pagos (codigo_venta);
+
+ALTER table ventas add column finalizada_at timestamptz;
@@ -0,0 +1,4 @@
+-- Add migration script here
+DROP table precios_programados;
+
+DROP table precios_historial;
@@ -0,0 +1,10 @@
+-- Add migration script here
+ALTER table detalle_ventas alter column cantidad_producto type int using round(cantidad_producto);
+
+DROP index productos_categoria;
+
+ALTER table productos drop column unidad_medida;
+
+ALTER table productos drop column codigo_categoria;
+
+DROP table categorias;
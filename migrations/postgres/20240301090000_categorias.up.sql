@@ -0,0 +1,23 @@
+-- Add migration script here
+-- Las categorias de los productos, en un arbol: cada categoria puede tener una categoria padre
+CREATE table categorias (
+	codigo bigint primary key,
+	nombre varchar(40) not null,
+	codigo_padre bigint,
+	created_at timestamptz not null,
+	updated_at timestamptz not null,
+	deleted_at timestamptz,
+	foreign key(codigo_padre) references categorias(codigo)
+);
+
+CREATE index categorias_padre on categorias (codigo_padre);
+
+-- cada producto tiene una categoria (opcional) y una unidad de medida: 'unidad', 'kg' o 'litro'
+ALTER table productos add column codigo_categoria bigint references categorias(codigo);
+
+ALTER table productos add column unidad_medida varchar(10) not null default 'unidad';
+
+CREATE index productos_categoria on productos (codigo_categoria);
+
+-- los productos que se venden por peso o volumen se venden en cantidades fraccionarias
+ALTER table detalle_ventas alter column cantidad_producto type double precision;
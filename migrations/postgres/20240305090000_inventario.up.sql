@@ -0,0 +1,48 @@
+-- Add migration script here
+-- los umbrales de existencias de cada producto: por debajo del minimo hay que comprar,
+-- y el maximo es hasta donde se compra
+ALTER table productos add column stock_minimo double precision;
+
+ALTER table productos add column stock_maximo double precision;
+
+-- Las ordenes de compra a los proveedores: 'pendiente', 'recibida' o 'cancelada'
+CREATE table ordenes_compra (
+	codigo bigserial primary key,
+	nit_proveedor bigint not null,
+	estado varchar(20) not null,
+	cedula_usuario bigint,
+	created_at timestamptz not null,
+	recibida_at timestamptz,
+	foreign key(nit_proveedor) references proveedores(nit)
+);
+
+CREATE table detalle_ordenes_compra (
+	codigo bigserial primary key,
+	codigo_orden bigint not null,
+	codigo_producto bigint not null,
+	cantidad double precision not null,
+	precio_compra double precision not null,
+	foreign key(codigo_orden) references ordenes_compra(codigo) on delete cascade,
+	foreign key(codigo_producto) references productos(codigo)
+);
+
+CREATE index detalle_ordenes_compra_orden on detalle_ordenes_compra (codigo_orden);
+
+-- El historial de existencias de los productos: las existencias de un producto son la suma de sus
+-- movimientos. Las ventas finalizadas descuentan (negativos) y las ordenes de compra recibidas suman
+CREATE table movimientos_inventario (
+	codigo bigserial primary key,
+	codigo_producto bigint not null,
+	tipo varchar(20) not null,
+	cantidad double precision not null,
+	codigo_venta bigint,
+	codigo_orden bigint,
+	motivo varchar(100),
+	cedula_usuario bigint,
+	fecha timestamptz not null,
+	foreign key(codigo_producto) references productos(codigo),
+	foreign key(codigo_venta) references ventas(codigo),
+	foreign key(codigo_orden) references ordenes_compra(codigo)
+);
+
+CREATE index movimientos_inventario_producto on movimientos_inventario (codigo_producto);
@@ -0,0 +1,64 @@
+-- Add migration script here
+-- Las sucursales de la cadena. Los productos, los proveedores y los clientes son de toda la cadena;
+-- las ventas, las cajas y las existencias son de cada sucursal. Los datos anteriores quedan en la
+-- sucursal principal
+CREATE table sucursales (
+	codigo bigint primary key,
+	nombre varchar(40) not null,
+	direccion varchar(100),
+	created_at timestamptz not null,
+	updated_at timestamptz not null
+);
+
+INSERT into sucursales (codigo, nombre, created_at, updated_at)
+values (1, 'Principal', now(), now());
+
+-- las sucursales en las que trabaja cada usuario; un usuario sin sucursales trabaja en todas
+CREATE table usuarios_sucursales (
+	codigo bigserial primary key,
+	cedula_usuario bigint not null,
+	codigo_sucursal bigint not null,
+	created_at timestamptz not null,
+	foreign key(cedula_usuario) references usuarios(cedula),
+	foreign key(codigo_sucursal) references sucursales(codigo),
+	unique(cedula_usuario, codigo_sucursal)
+);
+
+-- los traslados de mercancia entre sucursales: cada producto trasladado sale de una y entra
+-- a la otra como dos movimientos de inventario
+CREATE table traslados (
+	codigo bigserial primary key,
+	codigo_sucursal_origen bigint not null,
+	codigo_sucursal_destino bigint not null,
+	motivo varchar(100),
+	cedula_usuario bigint,
+	fecha timestamptz not null,
+	foreign key(codigo_sucursal_origen) references sucursales(codigo),
+	foreign key(codigo_sucursal_destino) references sucursales(codigo)
+);
+
+ALTER table cajas add column codigo_sucursal bigint references sucursales(codigo);
+
+ALTER table ventas add column codigo_sucursal bigint references sucursales(codigo);
+
+ALTER table ordenes_compra add column codigo_sucursal bigint references sucursales(codigo);
+
+ALTER table conteos add column codigo_sucursal bigint references sucursales(codigo);
+
+ALTER table movimientos_inventario add column codigo_sucursal bigint references sucursales(codigo);
+
+ALTER table movimientos_inventario add column codigo_traslado bigint references traslados(codigo);
+
+UPDATE cajas set codigo_sucursal = 1;
+
+UPDATE ventas set codigo_sucursal = 1;
+
+UPDATE ordenes_compra set codigo_sucursal = 1;
+
+UPDATE conteos set codigo_sucursal = 1;
+
+UPDATE movimientos_inventario set codigo_sucursal = 1;
+
+CREATE index ventas_sucursal on ventas (codigo_sucursal);
+
+CREATE index movimientos_inventario_sucursal on movimientos_inventario (codigo_sucursal, codigo_producto);
@@ -0,0 +1,8 @@
+-- Add migration script here
+DROP table auditoria;
+DROP table detalle_ventas;
+DROP table ventas;
+DROP table productos;
+DROP table proveedores;
+DROP table usuarios;
+DROP table clientes;
@@ -0,0 +1,6 @@
+-- Add migration script here
+ALTER table detalle_ventas drop column descuento;
+
+ALTER table detalle_ventas drop column codigo_promocion;
+
+DROP table promociones;
@@ -0,0 +1,27 @@
+-- Add migration script here
+-- Las promociones: un descuento porcentual, un valor fijo por unidad o "lleve X pague Y",
+-- opcionalmente limitado a un producto, a los productos de un proveedor, a un cliente
+-- y a un rango de fechas. Cada detalle de venta guarda la promocion aplicada y el descuento
+CREATE table promociones (
+	codigo bigint primary key,
+	nombre varchar(40) not null,
+	tipo varchar(20) not null,
+	valor double precision not null,
+	lleve integer,
+	pague integer,
+	codigo_producto bigint,
+	nit_proveedor bigint,
+	cedula_cliente bigint,
+	desde text,
+	hasta text,
+	created_at text not null,
+	updated_at text not null,
+	deleted_at text,
+	foreign key(codigo_producto) references productos(codigo),
+	foreign key(nit_proveedor) references proveedores(nit),
+	foreign key(cedula_cliente) references clientes(cedula)
+);
+
+ALTER table detalle_ventas add column codigo_promocion bigint references promociones(codigo);
+
+ALTER table detalle_ventas add column descuento double precision not null default 0;
@@ -0,0 +1,17 @@
+-- Add migration script here
+-- El historial de puntos de fidelidad de los clientes: los puntos que se acumulan al finalizar
+-- una venta (positivos, con su fecha de vencimiento) y los que se redimen como medio de pago (negativos)
+CREATE table movimientos_puntos (
+	codigo integer primary key autoincrement,
+	cedula_cliente bigint not null,
+	codigo_venta bigint,
+	codigo_pago bigint,
+	puntos bigint not null,
+	vence text,
+	fecha text not null,
+	foreign key(cedula_cliente) references clientes(cedula),
+	foreign key(codigo_venta) references ventas(codigo),
+	foreign key(codigo_pago) references pagos(codigo) on delete cascade
+);
+
+CREATE index movimientos_puntos_cliente on movimientos_puntos (cedula_cliente);
@@ -0,0 +1,34 @@
+-- Add migration script here
+-- El historial de precios de los productos: una fila por cada cambio de precio_compra o precio_venta
+-- (al crear, importar o actualizar el producto, o al aplicar un cambio programado)
+CREATE table precios_historial (
+	codigo integer primary key autoincrement,
+	codigo_producto bigint not null,
+	precio_compra double precision not null,
+	precio_venta double precision not null,
+	origen varchar(20) not null,
+	cedula_usuario bigint,
+	fecha text not null,
+	foreign key(codigo_producto) references productos(codigo)
+);
+
+CREATE index precios_historial_producto on precios_historial (codigo_producto);
+
+-- Los cambios de precio programados, que el servidor aplica cuando llega su fecha
+CREATE table precios_programados (
+	codigo integer primary key autoincrement,
+	codigo_producto bigint not null,
+	precio_compra double precision,
+	precio_venta double precision,
+	fecha text not null,
+	cedula_usuario bigint,
+	aplicado_at text,
+	created_at text not null,
+	foreign key(codigo_producto) references productos(codigo)
+);
+
+CREATE index precios_programados_pendientes on precios_programados (fecha) where aplicado_at is null;
+
+-- los precios actuales son el primer registro del historial
+INSERT into precios_historial (codigo_producto, precio_compra, precio_venta, origen, fecha)
+select codigo, precio_compra, precio_venta, 'creacion', created_at from productos;
@@ -0,0 +1,12 @@
+-- Add migration script here
+-- Los codigos de barras de los productos (EAN-13, y UPC-A con un 0 adelante): un producto puede
+-- tener varios, pero cada codigo identifica a un solo producto
+CREATE table codigos_barras (
+	codigo integer primary key autoincrement,
+	codigo_barras varchar(13) not null unique,
+	codigo_producto bigint not null,
+	created_at text not null,
+	foreign key(codigo_producto) references productos(codigo)
+);
+
+CREATE index codigos_barras_producto on codigos_barras (codigo_producto);
@@ -0,0 +1,32 @@
+-- Add migration script here
+CREATE table detalle_ventas_anterior (
+	codigo bigint primary key,
+	codigo_producto bigint not null,
+	codigo_venta bigint not null,
+	cantidad_producto int not null,
+	valor_total double precision not null,
+	valor_venta double precision not null,
+	valor_iva double precision not null,
+	created_at text not null,
+	updated_at text not null,
+	codigo_promocion bigint,
+	descuento double precision not null default 0,
+	foreign key(codigo_producto) references productos(codigo),
+	foreign key(codigo_venta) references ventas(codigo),
+	foreign key(codigo_promocion) references promociones(codigo)
+);
+
+INSERT into detalle_ventas_anterior select codigo, codigo_producto, codigo_venta, cast(round(cantidad_producto) as integer), valor_total,
+	valor_venta, valor_iva, created_at, updated_at, codigo_promocion, descuento from detalle_ventas;
+
+DROP table detalle_ventas;
+
+ALTER table detalle_ventas_anterior rename to detalle_ventas;
+
+DROP index productos_categoria;
+
+ALTER table productos drop column unidad_medida;
+
+ALTER table productos drop column codigo_categoria;
+
+DROP table categorias;
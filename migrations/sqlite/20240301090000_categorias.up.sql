@@ -0,0 +1,45 @@
+-- Add migration script here
+-- Las categorias de los productos, en un arbol: cada categoria puede tener una categoria padre
+CREATE table categorias (
+	codigo bigint primary key,
+	nombre varchar(40) not null,
+	codigo_padre bigint,
+	created_at text not null,
+	updated_at text not null,
+	deleted_at text,
+	foreign key(codigo_padre) references categorias(codigo)
+);
+
+CREATE index categorias_padre on categorias (codigo_padre);
+
+-- cada producto tiene una categoria (opcional) y una unidad de medida: 'unidad', 'kg' o 'litro'
+ALTER table productos add column codigo_categoria bigint references categorias(codigo);
+
+ALTER table productos add column unidad_medida varchar(10) not null default 'unidad';
+
+CREATE index productos_categoria on productos (codigo_categoria);
+
+-- los productos que se venden por peso o volumen se venden en cantidades fraccionarias.
+-- SQLite no cambia el tipo de una columna, por lo que la tabla se vuelve a crear
+CREATE table detalle_ventas_nueva (
+	codigo bigint primary key,
+	codigo_producto bigint not null,
+	codigo_venta bigint not null,
+	cantidad_producto double precision not null,
+	valor_total double precision not null,
+	valor_venta double precision not null,
+	valor_iva double precision not null,
+	created_at text not null,
+	updated_at text not null,
+	codigo_promocion bigint,
+	descuento double precision not null default 0,
+	foreign key(codigo_producto) references productos(codigo),
+	foreign key(codigo_venta) references ventas(codigo),
+	foreign key(codigo_promocion) references promociones(codigo)
+);
+
+INSERT into detalle_ventas_nueva select * from detalle_ventas;
+
+DROP table detalle_ventas;
+
+ALTER table detalle_ventas_nueva rename to detalle_ventas;
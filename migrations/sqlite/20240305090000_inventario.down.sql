@@ -0,0 +1,10 @@
+-- Add migration script here
+DROP table movimientos_inventario;
+
+DROP table detalle_ordenes_compra;
+
+DROP table ordenes_compra;
+
+ALTER table productos drop column stock_maximo;
+
+ALTER table productos drop column stock_minimo;
@@ -0,0 +1,6 @@
+-- Add migration script here
+ALTER table movimientos_inventario drop column codigo_conteo;
+
+DROP table detalle_conteos;
+
+DROP table conteos;
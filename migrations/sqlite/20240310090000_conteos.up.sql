@@ -0,0 +1,27 @@
+-- Add migration script here
+-- Los conteos fisicos del inventario: 'abierto' mientras se registran las cantidades contadas,
+-- y 'aplicado' (o 'cancelado') cuando sus diferencias se ajustan en el inventario
+CREATE table conteos (
+	codigo integer primary key autoincrement,
+	descripcion varchar(100),
+	estado varchar(20) not null,
+	cedula_usuario bigint,
+	created_at text not null,
+	aplicado_at text
+);
+
+-- la cantidad contada de cada producto en un conteo (contarlo de nuevo la reemplaza)
+CREATE table detalle_conteos (
+	codigo integer primary key autoincrement,
+	codigo_conteo bigint not null,
+	codigo_producto bigint not null,
+	cantidad double precision not null,
+	motivo varchar(100),
+	updated_at text not null,
+	foreign key(codigo_conteo) references conteos(codigo) on delete cascade,
+	foreign key(codigo_producto) references productos(codigo),
+	unique(codigo_conteo, codigo_producto)
+);
+
+-- los ajustes de un conteo quedan en el historial de existencias con su motivo
+ALTER table movimientos_inventario add column codigo_conteo bigint references conteos(codigo);
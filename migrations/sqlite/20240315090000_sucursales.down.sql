@@ -0,0 +1,22 @@
+-- Add migration script here
+DROP index movimientos_inventario_sucursal;
+
+DROP index ventas_sucursal;
+
+ALTER table movimientos_inventario drop column codigo_traslado;
+
+ALTER table movimientos_inventario drop column codigo_sucursal;
+
+ALTER table conteos drop column codigo_sucursal;
+
+ALTER table ordenes_compra drop column codigo_sucursal;
+
+ALTER table ventas drop column codigo_sucursal;
+
+ALTER table cajas drop column codigo_sucursal;
+
+DROP table traslados;
+
+DROP table usuarios_sucursales;
+
+DROP table sucursales;
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER table usuarios drop column admin;
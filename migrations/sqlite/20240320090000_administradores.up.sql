@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Los administradores pueden cambiar la configuracion del servidor (por ejemplo el nivel de los logs).
+-- Los usuarios existentes no son administradores; el comando `create-admin` crea uno nuevo
+ALTER table usuarios add column admin boolean not null default false;
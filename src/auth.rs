@@ -0,0 +1,83 @@
+//! Este archivo contiene la autenticacion de los servicios REST que modifican datos.
+//! El frontend envia las credenciales del usuario que ingreso en el login en cada request,
+//! usando el header `Authorization: Basic base64(usuario:password)`.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use actix_web::{
+    error::{ErrorInternalServerError, ErrorUnauthorized},
+    http::header,
+    web::Data,
+    Error, FromRequest, HttpRequest, HttpResponse,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::{registro, AppState};
+
+/// El usuario autenticado que realiza la operacion.
+/// Las operaciones de la linea de comandos no tienen usuario (`cedula` es `None`)
+#[derive(Debug, Clone)]
+pub struct Actor {
+    pub cedula: Option<i64>,
+}
+
+impl Actor {
+    /// El actor de las operaciones realizadas desde la linea de comandos
+    pub fn sistema() -> Self {
+        Actor { cedula: None }
+    }
+}
+
+/// Verifica que el actor sea un administrador (ver el comando `create-admin`).
+/// Las operaciones de la linea de comandos no tienen restricciones
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+pub(crate) async fn administrador(state: &AppState, actor: &Actor) -> Result<(), HttpResponse> {
+    let Some(cedula) = actor.cedula else {
+        return Ok(());
+    };
+    match state.repos.usuarios.obtener(cedula).await {
+        Ok(Some(usuario)) if usuario.admin => Ok(()),
+        Ok(_) => Err(HttpResponse::Forbidden().json("only an admin can do this")),
+        Err(_) => Err(HttpResponse::InternalServerError().json("could not read usuarios")),
+    }
+}
+
+/// Obtiene el usuario y la contraseña del header `Authorization`
+fn credenciales(req: &HttpRequest) -> Option<(String, String)> {
+    let valor = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let codificado = valor.strip_prefix("Basic ")?;
+    let decodificado = String::from_utf8(STANDARD.decode(codificado.trim()).ok()?).ok()?;
+    let (usuario, password) = decodificado.split_once(':')?;
+    Some((usuario.to_owned(), password.to_owned()))
+}
+
+impl FromRequest for Actor {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut actix_web::dev::Payload) -> Self::Future {
+        let credenciales = credenciales(req);
+        let state = req.app_data::<Data<AppState>>().cloned();
+        Box::pin(async move {
+            let (usuario, password) = credenciales.ok_or_else(|| ErrorUnauthorized("missing credentials"))?;
+            let state = state.ok_or_else(|| ErrorInternalServerError("missing state"))?;
+            let fila = state
+                .repos
+                .usuarios
+                .por_usuario(&usuario)
+                .await
+                .map_err(|_| ErrorInternalServerError("Internal Server Error"))?;
+            match fila {
+                Some(guardado) if guardado.password == password => {
+                    let actor = Actor { cedula: guardado.cedula };
+                    registro::registrar_actor(&actor);
+                    Ok(actor)
+                }
+                _ => Err(ErrorUnauthorized("bad credentials")),
+            }
+        })
+    }
+}
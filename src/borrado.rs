@@ -0,0 +1,62 @@
+//! Este archivo contiene la politica de borrado compartida por los servicios REST.
+//! Clientes, proveedores, productos y usuarios se borran de forma logica (`deleted_at`)
+//! para que el historial de ventas siga intacto; con `?dry_run` el servicio de borrado
+//! solo informa que filas dependientes bloquean o se ven afectadas por el borrado.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Los parametros de consulta del servicio de borrado.
+/// `?dry_run` (o `?dry_run=true`) simula el borrado sin modificar la base de datos
+#[derive(Debug, Deserialize)]
+pub struct Borrado {
+    dry_run: Option<String>,
+}
+
+impl Borrado {
+    pub fn es_simulacro(&self) -> bool {
+        self.dry_run.as_deref().is_some_and(|v| v != "false")
+    }
+}
+
+/// El informe de un borrado simulado.
+/// `bloqueantes` son las filas que impiden el borrado y `afectados` las que
+/// seguiran apuntando a la fila borrada
+#[derive(Debug, Serialize)]
+pub struct InformeBorrado {
+    entidad: &'static str,
+    id: i64,
+    bloqueado: bool,
+    bloqueantes: BTreeMap<&'static str, i64>,
+    afectados: BTreeMap<&'static str, i64>,
+}
+
+impl InformeBorrado {
+    pub fn new(entidad: &'static str, id: i64) -> Self {
+        Self {
+            entidad,
+            id,
+            bloqueado: false,
+            bloqueantes: BTreeMap::new(),
+            afectados: BTreeMap::new(),
+        }
+    }
+
+    /// Registra filas dependientes que impiden el borrado
+    pub fn bloquea(mut self, tabla: &'static str, filas: i64) -> Self {
+        if filas > 0 {
+            self.bloqueado = true;
+            self.bloqueantes.insert(tabla, filas);
+        }
+        self
+    }
+
+    /// Registra filas dependientes que quedaran apuntando a la fila borrada
+    pub fn afecta(mut self, tabla: &'static str, filas: i64) -> Self {
+        if filas > 0 {
+            self.afectados.insert(tabla, filas);
+        }
+        self
+    }
+}
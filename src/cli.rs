@@ -0,0 +1,253 @@
+//! Este archivo contiene la linea de comandos del backend. Ademas de iniciar el servidor,
+//! permite al personal de la tienda cargar datos y preparar la base de datos sin pasar
+//! por la API web, reutilizando las mismas operaciones de las entidades que usan los servicios REST.
+
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use validator::Validate;
+
+use crate::{
+    auth::Actor,
+    entities::{clientes, detalle_ventas, productos, proveedores, usuarios, ventas},
+    filtros::RangoFechas,
+    migraciones,
+    repos::{Db, Repos},
+    validacion,
+};
+
+/// Backend de la tienda generica
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub comando: Option<Comando>,
+}
+
+/// Los subcomandos disponibles. Sin subcomando se inicia el servidor
+#[derive(Debug, Subcommand)]
+pub enum Comando {
+    /// Inicia el servidor http
+    Serve {
+        /// Aplica las migraciones pendientes antes de iniciar el servidor
+        #[arg(long)]
+        migrate: bool,
+    },
+    /// Administra las migraciones de la base de datos
+    Migrate {
+        #[command(subcommand)]
+        accion: AccionMigracion,
+    },
+    /// Importa un archivo CSV (con encabezados) a una tabla
+    Import {
+        entidad: Importable,
+        archivo: PathBuf,
+    },
+    /// Exporta una tabla a CSV
+    Export {
+        entidad: Exportable,
+        /// Primer dia incluido (AAAA-MM-DD)
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// Ultimo dia incluido (AAAA-MM-DD)
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        /// El archivo de salida; por defecto la salida estandar
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Crea un usuario administrador, para poder ingresar por primera vez
+    CreateAdmin {
+        #[arg(long)]
+        cedula: i64,
+        #[arg(long, default_value = "admin")]
+        usuario: String,
+        #[arg(long)]
+        password: String,
+        #[arg(long)]
+        email: String,
+        #[arg(long, default_value = "Administrador")]
+        nombre: String,
+    },
+    /// Carga datos de ejemplo en la base de datos
+    Seed {
+        /// Datos de demostracion: un proveedor, clientes, un cajero, los productos de
+        /// `prod.csv` y algunas ventas (por ahora son los unicos datos disponibles)
+        #[arg(long, required = true)]
+        demo: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AccionMigracion {
+    /// Aplica las migraciones pendientes
+    Up,
+    /// Revierte la ultima migracion aplicada
+    Down,
+    /// Lista las migraciones y si estan aplicadas
+    Status,
+}
+
+/// Las tablas que se pueden importar desde CSV
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Importable {
+    Productos,
+}
+
+/// Las tablas que se pueden exportar a CSV
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Exportable {
+    Ventas,
+}
+
+/// Ejecuta un subcomando que no es `serve`
+pub async fn ejecutar(db: &Db, comando: Comando) -> Result<(), Box<dyn Error>> {
+    let repos = db.repos();
+    match comando {
+        Comando::Serve { .. } => unreachable!("el servidor se inicia desde main"),
+        Comando::Migrate { accion } => migrar(db, accion).await,
+        Comando::Import { entidad: Importable::Productos, archivo } => {
+            let importados = productos::importar_csv(repos.productos.as_ref(), &Actor::sistema(), File::open(archivo)?).await?;
+            println!("{importados} productos importados");
+            Ok(())
+        }
+        Comando::Export { entidad: Exportable::Ventas, from, to, output } => {
+            let ventas = repos.ventas.listar(&RangoFechas { desde: from, hasta: to }, None).await?;
+            let salida: Box<dyn Write> = match output {
+                Some(archivo) => Box::new(File::create(archivo)?),
+                None => Box::new(io::stdout()),
+            };
+            let mut csv_writer = csv::Writer::from_writer(salida);
+            for venta in &ventas {
+                csv_writer.serialize(venta)?;
+            }
+            csv_writer.flush()?;
+            Ok(())
+        }
+        Comando::CreateAdmin { cedula, usuario, password, email, nombre } => {
+            let admin = usuarios::Usuarios {
+                admin: true,
+                ..desde_json(json!({
+                    "cedula": cedula,
+                    "email": email,
+                    "nombre": nombre,
+                    "password": password,
+                    "usuario": usuario,
+                }))?
+            };
+            repos.usuarios.insertar(&Actor::sistema(), &admin).await?;
+            println!("usuario '{usuario}' creado");
+            Ok(())
+        }
+        Comando::Seed { .. } => sembrar_demo(&repos).await,
+    }
+}
+
+async fn migrar(db: &Db, accion: AccionMigracion) -> Result<(), Box<dyn Error>> {
+    match accion {
+        AccionMigracion::Up => {
+            migraciones::subir(db).await?;
+            println!("migraciones aplicadas");
+        }
+        AccionMigracion::Down => match migraciones::bajar(db).await? {
+            Some(version) => println!("migracion {version} revertida"),
+            None => println!("no hay migraciones aplicadas"),
+        },
+        AccionMigracion::Status => {
+            for m in migraciones::estado(db).await? {
+                let estado = if m.aplicada { "aplicada" } else { "pendiente" };
+                println!("{} {:<12} {}", m.version, estado, m.descripcion);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Construye y valida una entidad a partir del mismo json que recibiria su servicio REST
+fn desde_json<T: DeserializeOwned + Validate>(valor: Value) -> Result<T, Box<dyn Error>> {
+    let entidad: T = serde_json::from_value(valor)?;
+    entidad
+        .validate()
+        .map_err(|e| serde_json::to_string(&validacion::por_campo(&e)).unwrap_or_default())?;
+    Ok(entidad)
+}
+
+/// Los productos de demostracion
+const PRODUCTOS_DEMO: &str = include_str!("../prod.csv");
+
+/// Carga los datos de demostracion en una base de datos vacia
+async fn sembrar_demo(repos: &Repos) -> Result<(), Box<dyn Error>> {
+    let actor = Actor::sistema();
+
+    let proveedor: proveedores::Proveedores = desde_json(json!({
+        "nit": 1234, "ciudad": "Bogota", "direccion": "Calle 1 # 2-3",
+        "nombre": "Distribuidora Demo", "telefono": "601 555 1234",
+    }))?;
+    repos.proveedores.insertar(&actor, &proveedor).await?;
+
+    let cajero: usuarios::Usuarios = desde_json(json!({
+        "cedula": 1000, "email": "cajero@tienda.demo", "nombre": "Cajero Demo",
+        "password": "cajero", "usuario": "cajero",
+    }))?;
+    repos.usuarios.insertar(&actor, &cajero).await?;
+
+    let clientes_demo = [
+        (2001, "Ana Perez", "ana@correo.demo", "300 111 2233"),
+        (2002, "Luis Gomez", "luis@correo.demo", "300 444 5566"),
+        (2003, "Maria Diaz", "maria@correo.demo", "300 777 8899"),
+    ];
+    for (cedula, nombre, email, telefono) in clientes_demo {
+        let cliente: clientes::Clientes = desde_json(json!({
+            "cedula": cedula, "direccion": "Carrera 10 # 20-30", "email": email,
+            "nombre": nombre, "telefono": telefono,
+        }))?;
+        repos.clientes.insertar(&actor, &cliente).await?;
+    }
+
+    productos::importar_csv(repos.productos.as_ref(), &actor, PRODUCTOS_DEMO.as_bytes()).await?;
+
+    let mut lineas = csv::Reader::from_reader(PRODUCTOS_DEMO.as_bytes());
+    let precios: Vec<(i64, f64, f64)> = lineas
+        .deserialize::<(i64, i64, f64, String, f64, f64)>()
+        .filter_map(Result::ok)
+        .take(6)
+        .map(|(codigo, _, iva, _, _, precio)| (codigo, iva, precio))
+        .collect();
+    for (i, (codigo_venta, cedula_cliente)) in [(1, 2001), (2, 2002), (3, 2001)].into_iter().enumerate() {
+        let detalles = &precios[i * 2..i * 2 + 2];
+        let linea = |cantidad: i32, (_, iva, precio): (i64, f64, f64)| {
+            let valor_venta = precio * f64::from(cantidad);
+            let valor_iva = valor_venta * iva / 100.0;
+            (valor_venta, valor_iva, valor_venta + valor_iva)
+        };
+        let totales = detalles
+            .iter()
+            .map(|p| linea(2, *p))
+            .fold((0.0, 0.0, 0.0), |a, l| (a.0 + l.0, a.1 + l.1, a.2 + l.2));
+        let venta: ventas::Ventas = desde_json(json!({
+            "codigo": codigo_venta, "cedula_cliente": cedula_cliente, "cedula_usuario": 1000,
+            "iva_venta": totales.1, "total_venta": totales.2, "valor_venta": totales.0,
+        }))?;
+        repos.ventas.insertar(&actor, &venta).await?;
+        for (j, producto) in detalles.iter().enumerate() {
+            let (valor_venta, valor_iva, valor_total) = linea(2, *producto);
+            let detalle: detalle_ventas::DetalleVentas = desde_json(json!({
+                "codigo": codigo_venta * 10 + j as i64, "codigo_producto": producto.0,
+                "codigo_venta": codigo_venta, "cantidad_producto": 2,
+                "valor_total": valor_total, "valor_venta": valor_venta, "valor_iva": valor_iva,
+            }))?;
+            repos.detalle_ventas.insertar(&actor, &detalle).await?;
+        }
+    }
+
+    println!("datos de demostracion cargados (usuario 'cajero', contraseña 'cajero')");
+    Ok(())
+}
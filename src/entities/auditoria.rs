@@ -0,0 +1,123 @@
+//!Este archivo representa el servicio REST de la tabla 'auditoria'.
+//!Contiene el registro de cada operacion que modifica datos (quien, que, cuando,
+//!y la fila antes y despues del cambio) y el servicio para consultarlo.
+//!Los repositorios (ver `repos`) registran cada operacion en la misma transaccion.
+
+use crate::{
+    auth::Actor,
+    filtros::{Paginacion, RangoFechas},
+    AppState,
+};
+use actix_web::{
+    get,
+    web::{Data, Query},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{types::Json, FromRow};
+
+/// Las acciones que quedan registradas en la auditoria
+#[derive(Debug, Clone, Copy)]
+pub enum Accion {
+    Create,
+    Update,
+    Delete,
+    Restore,
+    Import,
+}
+
+impl Accion {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Accion::Create => "create",
+            Accion::Update => "update",
+            Accion::Delete => "delete",
+            Accion::Restore => "restore",
+            Accion::Import => "import",
+        }
+    }
+}
+
+/// Una tabla auditada: su nombre y el nombre de su llave primaria
+#[derive(Debug, Clone, Copy)]
+pub struct Tabla {
+    pub nombre: &'static str,
+    pub llave: &'static str,
+}
+
+pub const CLIENTES: Tabla = Tabla { nombre: "clientes", llave: "cedula" };
+pub const USUARIOS: Tabla = Tabla { nombre: "usuarios", llave: "cedula" };
+pub const PROVEEDORES: Tabla = Tabla { nombre: "proveedores", llave: "nit" };
+pub const CATEGORIAS: Tabla = Tabla { nombre: "categorias", llave: "codigo" };
+pub const PRODUCTOS: Tabla = Tabla { nombre: "productos", llave: "codigo" };
+pub const CODIGOS_BARRAS: Tabla = Tabla { nombre: "codigos_barras", llave: "codigo" };
+pub const VENTAS: Tabla = Tabla { nombre: "ventas", llave: "codigo" };
+pub const DETALLE_VENTAS: Tabla = Tabla { nombre: "detalle_ventas", llave: "codigo" };
+pub const PRECIOS_PROGRAMADOS: Tabla = Tabla { nombre: "precios_programados", llave: "codigo" };
+pub const PROMOCIONES: Tabla = Tabla { nombre: "promociones", llave: "codigo" };
+pub const MOVIMIENTOS_PUNTOS: Tabla = Tabla { nombre: "movimientos_puntos", llave: "codigo" };
+pub const PAGOS: Tabla = Tabla { nombre: "pagos", llave: "codigo" };
+pub const CAJAS: Tabla = Tabla { nombre: "cajas", llave: "codigo" };
+pub const TURNOS: Tabla = Tabla { nombre: "turnos", llave: "codigo" };
+pub const MOVIMIENTOS_CAJA: Tabla = Tabla { nombre: "movimientos_caja", llave: "codigo" };
+pub const MOVIMIENTOS_INVENTARIO: Tabla = Tabla { nombre: "movimientos_inventario", llave: "codigo" };
+pub const ORDENES_COMPRA: Tabla = Tabla { nombre: "ordenes_compra", llave: "codigo" };
+pub const CONTEOS: Tabla = Tabla { nombre: "conteos", llave: "codigo" };
+pub const DETALLE_CONTEOS: Tabla = Tabla { nombre: "detalle_conteos", llave: "codigo" };
+pub const SUCURSALES: Tabla = Tabla { nombre: "sucursales", llave: "codigo" };
+pub const USUARIOS_SUCURSALES: Tabla = Tabla { nombre: "usuarios_sucursales", llave: "codigo" };
+pub const TRASLADOS: Tabla = Tabla { nombre: "traslados", llave: "codigo" };
+
+/// La tabla 'auditoria' representada como un struct.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct Auditoria {
+    pub(crate) codigo: i64,
+    pub(crate) cedula_usuario: Option<i64>,
+    pub(crate) entidad: String,
+    pub(crate) llave: Option<i64>,
+    pub(crate) accion: String,
+    pub(crate) antes: Option<Json<Value>>,
+    pub(crate) despues: Option<Json<Value>>,
+    pub(crate) fecha: DateTime<Utc>,
+}
+
+/// Convierte una fila en json (sin contraseñas), para guardarla en la auditoria
+pub fn instantanea<T: Serialize>(fila: &T) -> Value {
+    let mut valor = serde_json::to_value(fila).unwrap_or_default();
+    if let Some(campos) = valor.as_object_mut() {
+        campos.remove("password");
+    }
+    valor
+}
+
+/// Los filtros del servicio de consulta de la auditoria, todos opcionales
+#[derive(Debug, Deserialize)]
+pub struct FiltroAuditoria {
+    pub entidad: Option<String>,
+    pub llave: Option<i64>,
+    pub accion: Option<String>,
+    pub cedula_usuario: Option<i64>,
+}
+
+/// Obtiene los registros de la auditoria, del mas reciente al mas antiguo
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `_actor` - El usuario autenticado que consulta la auditoria
+/// * `filtro` - `?entidad=productos&llave=10&accion=update&cedula_usuario=1`
+/// * `rango` - `?desde=AAAA-MM-DD&hasta=AAAA-MM-DD`
+/// * `paginacion` - `?pagina=N&por_pagina=M`
+#[get("/")]
+pub async fn read_all(
+    state: Data<AppState>,
+    _actor: Actor,
+    filtro: Query<FiltroAuditoria>,
+    rango: Query<RangoFechas>,
+    paginacion: Query<Paginacion>,
+) -> impl Responder {
+    match state.repos.auditoria.listar(&filtro, &rango, &paginacion).await {
+        Ok(registros) => HttpResponse::Ok().json(registros),
+        Err(_) => HttpResponse::NotFound().json("auditoria not found"),
+    }
+}
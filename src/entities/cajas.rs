@@ -0,0 +1,105 @@
+//!Este archivo representa el servicio REST de la tabla 'cajas'.
+//!Contiene las cajas registradoras de cada sucursal, en las que los cajeros abren sus turnos
+//!(ver `turnos`).
+
+use crate::{
+    auth::Actor,
+    entities::sucursales,
+    filtros::FiltroSucursal,
+    repos::ErrorRepo,
+    validacion,
+    AppState,
+};
+use actix_web::{
+    get, patch, post,
+    web::{Data, Json, Path, Query},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use validator::Validate;
+
+/// La tabla 'cajas' representada como un struct.
+/// El codigo es opcional debido a la operacion de `update`
+/// Sin `codigo_sucursal` una caja nueva queda en la sucursal principal, y una caja actualizada
+/// conserva la suya.
+/// Las fechas son asignadas por el servidor y se ignoran en el json del request
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, Validate)]
+pub(crate) struct Cajas {
+    #[validate(range(min = 1))]
+    pub(crate) codigo: Option<i64>,
+    #[validate(length(min = 1, max = 40))]
+    pub(crate) nombre: String,
+    #[serde(default)]
+    #[validate(range(min = 1))]
+    pub(crate) codigo_sucursal: Option<i64>,
+    #[serde(skip_deserializing)]
+    pub(crate) created_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) updated_at: Option<DateTime<Utc>>,
+}
+
+/// Crea una nueva caja y la envia a la base de datos
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `caja` - Un json en el body del request representando la caja
+#[post("/")]
+pub async fn create(state: Data<AppState>, actor: Actor, caja: Json<Cajas>) -> impl Responder {
+    if let Err(errores) = caja.validate() {
+        return validacion::rechazar(errores);
+    }
+    let caja = Cajas { codigo_sucursal: caja.codigo_sucursal.or(sucursales::principal()), ..caja.into_inner() };
+    match state.repos.cajas.insertar(&actor, &caja).await {
+        Ok(_) => HttpResponse::Created().json("Caja creada"),
+        Err(ErrorRepo::LlaveForanea) => HttpResponse::BadRequest().json("the sucursal of the caja does not exist"),
+        Err(_) => HttpResponse::InternalServerError().json("could not create caja"),
+    }
+}
+
+/// Obtiene todas las cajas de la base de datos, opcionalmente solo las de una sucursal
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `filtro` - `?sucursal=2`, opcional
+#[get("/")]
+pub async fn read_all(state: Data<AppState>, filtro: Query<FiltroSucursal>) -> impl Responder {
+    match state.repos.cajas.listar(filtro.sucursal).await {
+        Ok(cajas) => HttpResponse::Ok().json(cajas),
+        Err(_) => HttpResponse::NotFound().json("cajas not found"),
+    }
+}
+
+/// Obtiene una caja de la base de datos, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id
+#[get("/{id}")]
+pub async fn read_by_id(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.cajas.obtener(id).await {
+        Ok(Some(caja)) => HttpResponse::Ok().json(caja),
+        Ok(None) => HttpResponse::NotFound().json("caja not found"),
+        Err(_) => HttpResponse::InternalServerError().json("could not read cajas"),
+    }
+}
+
+/// Actualiza una caja de la base de datos, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+/// * `caja` - Un json en el body del request representando la caja a actualizar
+#[patch("/{id}")]
+pub async fn update(state: Data<AppState>, actor: Actor, path: Path<i64>, caja: Json<Cajas>) -> impl Responder {
+    if let Err(errores) = caja.validate() {
+        return validacion::rechazar(errores);
+    }
+    let id = path.into_inner();
+    match state.repos.cajas.actualizar(&actor, id, &caja).await {
+        Ok(0) => HttpResponse::NotFound().json("caja not found"),
+        Ok(_) => HttpResponse::Ok().json("Caja updated"),
+        Err(ErrorRepo::LlaveForanea) => HttpResponse::BadRequest().json("the sucursal of the caja does not exist"),
+        Err(_) => HttpResponse::InternalServerError().json("could not update caja"),
+    }
+}
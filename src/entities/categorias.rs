@@ -0,0 +1,252 @@
+//!Este archivo representa el servicio REST de la tabla 'categorias'.
+//!Contiene las operaciones CRUD relacionadas y el informe de ventas por categoria.
+//!Las categorias forman un arbol (`codigo_padre`): filtrar por una categoria incluye
+//!todas sus subcategorias.
+
+use std::collections::BTreeSet;
+
+use crate::{
+    auth::Actor,
+    borrado::Borrado,
+    filtros::{Borrados, FiltroCategoria, FiltroSucursal, RangoFechas},
+    repos::ErrorRepo,
+    validacion,
+    AppState,
+};
+use actix_web::{
+    delete, get, patch, post,
+    web::{Data, Json, Path, Query},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use validator::Validate;
+
+/// La tabla 'categorias' representada como un struct.
+/// Sin `codigo_padre` la categoria es una raiz del arbol.
+/// El codigo es opcional debido a la operacion de `update`
+/// Las fechas son asignadas por el servidor y se ignoran en el json del request
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, Validate)]
+pub(crate) struct Categorias {
+    #[validate(range(min = 1))]
+    pub(crate) codigo: Option<i64>,
+    #[validate(length(min = 1, max = 40))]
+    pub(crate) nombre: String,
+    #[validate(range(min = 1))]
+    pub(crate) codigo_padre: Option<i64>,
+    #[serde(skip_deserializing)]
+    pub(crate) created_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) updated_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) deleted_at: Option<DateTime<Utc>>,
+}
+
+/// Las ventas de los productos de una categoria (sin contar sus subcategorias) en un rango de fechas.
+/// La fila sin `codigo_categoria` son las ventas de los productos sin categoria
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct VentasCategoria {
+    pub(crate) codigo_categoria: Option<i64>,
+    pub(crate) nombre: Option<String>,
+    pub(crate) cantidad: f64,
+    pub(crate) ventas: i64,
+    pub(crate) descuento: f64,
+    pub(crate) valor_venta: f64,
+    pub(crate) valor_iva: f64,
+    pub(crate) valor_total: f64,
+}
+
+/// Los codigos de una categoria y de todas sus subcategorias
+/// ### Parametros
+/// * `categorias` - Todas las categorias
+/// * `codigo` - El codigo de la categoria
+pub(crate) fn descendientes(categorias: &[Categorias], codigo: i64) -> BTreeSet<i64> {
+    let mut arbol = BTreeSet::from([codigo]);
+    let mut pendientes = vec![codigo];
+    while let Some(padre) = pendientes.pop() {
+        for hija in categorias.iter().filter(|c| c.codigo_padre == Some(padre)).filter_map(|c| c.codigo) {
+            if arbol.insert(hija) {
+                pendientes.push(hija);
+            }
+        }
+    }
+    arbol
+}
+
+/// Verifica que el padre de una categoria no sea ella misma ni una de sus subcategorias
+async fn sin_ciclos(state: &AppState, codigo: i64, codigo_padre: Option<i64>) -> Result<(), HttpResponse> {
+    let Some(codigo_padre) = codigo_padre else {
+        return Ok(());
+    };
+    match state.repos.categorias.listar(true).await {
+        Ok(categorias) if descendientes(&categorias, codigo).contains(&codigo_padre) => {
+            Err(HttpResponse::BadRequest().json("a categoria cannot be inside itself or one of its subcategorias"))
+        }
+        Ok(_) => Ok(()),
+        Err(_) => Err(HttpResponse::InternalServerError().json("could not read categorias")),
+    }
+}
+
+/// Crea una nueva categoria y la envia a la base de datos
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `categoria` - Un json en el body del request representando la categoria
+#[post("/")]
+pub async fn create(state: Data<AppState>, actor: Actor, categoria: Json<Categorias>) -> impl Responder {
+    if let Err(errores) = categoria.validate() {
+        return validacion::rechazar(errores);
+    }
+    if categoria.codigo.is_some() && categoria.codigo == categoria.codigo_padre {
+        return HttpResponse::BadRequest().json("a categoria cannot be inside itself or one of its subcategorias");
+    }
+    match state.repos.categorias.insertar(&actor, &categoria).await {
+        Ok(_) => HttpResponse::Created().json("Categoria creada"),
+        Err(ErrorRepo::LlaveForanea) => HttpResponse::BadRequest().json("the codigo_padre of the categoria does not exist"),
+        Err(ErrorRepo::Duplicado) => HttpResponse::Conflict().json("categoria already exists"),
+        Err(_) => HttpResponse::InternalServerError().json("could not create categoria"),
+    }
+}
+
+/// Obtiene todas las categorias de la base de datos
+/// (sin las categorias borradas, a menos que se pida `?incluir_borrados=true`)
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `borrados` - si se incluyen las categorias borradas
+#[get("/")]
+pub async fn read_all(state: Data<AppState>, borrados: Query<Borrados>) -> impl Responder {
+    match state.repos.categorias.listar(borrados.incluir()).await {
+        Ok(categorias) => HttpResponse::Ok().json(categorias),
+        Err(_) => HttpResponse::NotFound().json("categorias not found"),
+    }
+}
+
+/// Obtiene una categoria de la base de datos, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id
+#[get("/{id}")]
+pub async fn read_by_id(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.categorias.obtener(id).await {
+        Ok(Some(categoria)) => HttpResponse::Ok().json(categoria),
+        Ok(None) => HttpResponse::NotFound().json("categoria not found"),
+        Err(_) => HttpResponse::InternalServerError().json("could not read categorias"),
+    }
+}
+
+/// Actualiza una categoria de la base de datos, por medio de la id en la uri.
+/// El nuevo padre no puede ser la misma categoria ni una de sus subcategorias
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+/// * `categoria` - Un json en el body del request representando la categoria a actualizar
+#[patch("/{id}")]
+pub async fn update(
+    state: Data<AppState>,
+    actor: Actor,
+    path: Path<i64>,
+    categoria: Json<Categorias>,
+) -> impl Responder {
+    if let Err(errores) = categoria.validate() {
+        return validacion::rechazar(errores);
+    }
+    let id = path.into_inner();
+    if let Err(respuesta) = sin_ciclos(&state, id, categoria.codigo_padre).await {
+        return respuesta;
+    }
+    match state.repos.categorias.actualizar(&actor, id, &categoria).await {
+        Ok(0) => HttpResponse::NotFound().json("categoria not found"),
+        Ok(_) => HttpResponse::Ok().json("Categoria updated"),
+        Err(ErrorRepo::LlaveForanea) => HttpResponse::BadRequest().json("the codigo_padre of the categoria does not exist"),
+        Err(_) => HttpResponse::InternalServerError().json("could not update categoria"),
+    }
+}
+
+/// Borra una categoria de la base de datos de forma logica (`deleted_at`), por medio de la id en la uri.
+/// Sus subcategorias y productos siguen apuntando a ella.
+/// Con `?dry_run` no borra nada y responde con las filas dependientes que se verian afectadas
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+/// * `borrado` - si el borrado es simulado
+#[delete("/{id}")]
+pub async fn delete(state: Data<AppState>, actor: Actor, path: Path<i64>, borrado: Query<Borrado>) -> impl Responder {
+    let id = path.into_inner();
+    if borrado.es_simulacro() {
+        return match state.repos.categorias.informe_borrado(id).await {
+            Ok(Some(informe)) => HttpResponse::Ok().json(informe),
+            Ok(None) => HttpResponse::NotFound().json("categoria not found"),
+            Err(_) => HttpResponse::InternalServerError().json("could not delete categoria"),
+        };
+    }
+    match state.repos.categorias.borrar(&actor, id).await {
+        Ok(0) => HttpResponse::NotFound().json("categoria not found"),
+        Ok(_) => HttpResponse::Ok().json("Categoria deleted"),
+        Err(_) => HttpResponse::InternalServerError().json("could not delete categoria"),
+    }
+}
+
+/// Restaura una categoria borrada de forma logica, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+#[post("/{id}/restaurar")]
+pub async fn restore(state: Data<AppState>, actor: Actor, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.categorias.restaurar(&actor, id).await {
+        Ok(0) => HttpResponse::NotFound().json("deleted categoria not found"),
+        Ok(_) => HttpResponse::Ok().json("Categoria restored"),
+        Err(_) => HttpResponse::InternalServerError().json("could not restore categoria"),
+    }
+}
+
+/// Obtiene las ventas de un rango de fechas agrupadas por la categoria de los productos vendidos.
+/// Con `?categoria=` solo incluye esa categoria y sus subcategorias, y con `?sucursal=` solo las ventas
+/// de esa sucursal
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `rango` - `?desde=AAAA-MM-DD&hasta=AAAA-MM-DD`, ambos opcionales e inclusivos
+/// * `filtro` - la categoria de los productos
+/// * `sucursal` - la sucursal de las ventas
+#[get("/por_categoria")]
+pub async fn read_ventas(
+    state: Data<AppState>,
+    rango: Query<RangoFechas>,
+    filtro: Query<FiltroCategoria>,
+    sucursal: Query<FiltroSucursal>,
+) -> impl Responder {
+    match state.repos.categorias.ventas(&rango, filtro.categoria, sucursal.sucursal).await {
+        Ok(ventas) => HttpResponse::Ok().json(ventas),
+        Err(_) => HttpResponse::InternalServerError().json("could not read ventas"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn categoria(codigo: i64, codigo_padre: Option<i64>) -> Categorias {
+        Categorias {
+            codigo: Some(codigo),
+            nombre: format!("categoria {codigo}"),
+            codigo_padre,
+            created_at: None,
+            updated_at: None,
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn los_descendientes_incluyen_todo_el_subarbol() {
+        // 1 > (2 > 4, 3), 5
+        let categorias = [categoria(1, None), categoria(2, Some(1)), categoria(3, Some(1)), categoria(4, Some(2)), categoria(5, None)];
+        assert_eq!(descendientes(&categorias, 1), BTreeSet::from([1, 2, 3, 4]));
+        assert_eq!(descendientes(&categorias, 2), BTreeSet::from([2, 4]));
+        assert_eq!(descendientes(&categorias, 5), BTreeSet::from([5]));
+    }
+}
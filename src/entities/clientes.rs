@@ -1,58 +1,71 @@
 //!Este archivo representa el servicio REST de la tabla 'clientes'.
 //!Contiene todas las operaciones CRUD relacionadas.
 
-use crate::AppState;
+use crate::{
+    auth::Actor,
+    borrado::Borrado,
+    filtros::{Borrados, FiltroSucursal, Paginacion},
+    validacion,
+    AppState,
+};
 use actix_web::{
     delete, get, patch, post,
-    web::{Data, Json, Path},
+    web::{Data, Json, Path, Query},
     HttpResponse, Responder,
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use validator::Validate;
 
 /// La tabla 'cliente' representada como un struct.
 /// Cedula es opcional debido a la operacion de `update`
-#[derive(Debug, FromRow, Serialize, Deserialize)]
-struct Clientes {
-    cedula: Option<i64>,
-    direccion: String,
-    email: String,
-    nombre: String,
-    telefono: String,
+/// Los campos se validan antes de ejecutar cualquier consulta.
+/// Las fechas son asignadas por el servidor y se ignoran en el json del request
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, Validate)]
+pub(crate) struct Clientes {
+    #[validate(range(min = 1))]
+    pub(crate) cedula: Option<i64>,
+    #[validate(length(min = 1, max = 40))]
+    pub(crate) direccion: String,
+    #[validate(email, length(max = 40))]
+    pub(crate) email: String,
+    #[validate(length(min = 1, max = 40))]
+    pub(crate) nombre: String,
+    #[validate(length(max = 40), custom = "validacion::telefono")]
+    pub(crate) telefono: String,
+    #[serde(skip_deserializing)]
+    pub(crate) created_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) updated_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) deleted_at: Option<DateTime<Utc>>,
 }
 
 /// Crea un nuevo cliente y lo envia a la base de datos
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `cliente` - Un json en el body del request representando el cliente
 #[post("/")]
-pub async fn create(state: Data<AppState>, cliente: Json<Clientes>) -> impl Responder {
-    match sqlx::query_as!(
-        Clientes,
-        "insert into clientes values ($1,$2,$3,$4,$5);",
-        cliente.cedula,
-        cliente.direccion.as_str(),
-        cliente.email.as_str(),
-        cliente.nombre.as_str(),
-        cliente.telefono.as_str()
-    )
-        .execute(&state.db)
-        .await
-    {
+pub async fn create(state: Data<AppState>, actor: Actor, cliente: Json<Clientes>) -> impl Responder {
+    if let Err(errores) = cliente.validate() {
+        return validacion::rechazar(errores);
+    }
+    match state.repos.clientes.insertar(&actor, &cliente).await {
         Ok(_) => HttpResponse::Created().json("Cliente creado"),
         Err(_) => HttpResponse::InternalServerError().json("could not create client"),
     }
 }
 
 /// Obtiene todos los clientes de la base de datos
+/// (sin los clientes borrados, a menos que se pida `?incluir_borrados=true`)
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `borrados` - si se incluyen los clientes borrados
 #[get("/")]
-pub async fn read_all(state: Data<AppState>) -> impl Responder {
-    match sqlx::query_as!(Clientes,"select * from clientes;")
-        .fetch_all(&state.db)
-        .await
-    {
+pub async fn read_all(state: Data<AppState>, borrados: Query<Borrados>) -> impl Responder {
+    match state.repos.clientes.listar(borrados.incluir()).await {
         Ok(clientes) => HttpResponse::Ok().json(clientes),
         Err(_) => HttpResponse::NotFound().json("not clientes found"),
     }
@@ -65,52 +78,91 @@ pub async fn read_all(state: Data<AppState>) -> impl Responder {
 #[get("/{id}")]
 pub async fn read_by_id(state: Data<AppState>, path: Path<i64>) -> impl Responder {
     let id = path.into_inner();
-    match sqlx::query_as!(Clientes, "select * from clientes where cedula = $1;", id)
-        .fetch_one(&state.db)
-        .await
-    {
-        Ok(cliente) => HttpResponse::Ok().json(cliente),
-        Err(_) => HttpResponse::NotFound().json("cliente not found"),
+    match state.repos.clientes.obtener(id).await {
+        Ok(Some(cliente)) => HttpResponse::Ok().json(cliente),
+        Ok(None) => HttpResponse::NotFound().json("cliente not found"),
+        Err(_) => HttpResponse::InternalServerError().json("could not read clientes"),
+    }
+}
+
+/// Obtiene las compras (ventas) de un cliente, por medio de la id en la uri, de forma paginada
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id
+/// * `paginacion` - `?pagina=N&por_pagina=M`
+/// * `filtro` - `?sucursal=2`, opcional
+#[get("/{id}/ventas")]
+pub async fn read_ventas(
+    state: Data<AppState>,
+    path: Path<i64>,
+    paginacion: Query<Paginacion>,
+    filtro: Query<FiltroSucursal>,
+) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.clientes.obtener(id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json("cliente not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read clientes"),
+    }
+    match state.repos.ventas.por_cliente(id, &paginacion, filtro.sucursal).await {
+        Ok(ventas) => HttpResponse::Ok().json(ventas),
+        Err(_) => HttpResponse::NotFound().json("ventas not found"),
     }
 }
 
 /// Actualiza un cliente de la base de datos, por medio de la id en la uri
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `path` - la uri relativa a la api, esto es la id
 /// * `cliente` - Un json en el body del request representando el cliente a actualizar
 #[patch("/{id}")]
-pub async fn update(state: Data<AppState>, path: Path<i64>, cliente: Json<Clientes>) -> impl Responder {
+pub async fn update(state: Data<AppState>, actor: Actor, path: Path<i64>, cliente: Json<Clientes>) -> impl Responder {
+    if let Err(errores) = cliente.validate() {
+        return validacion::rechazar(errores);
+    }
     let id = path.into_inner();
-    match sqlx::query_as!(
-        Clientes,
-        "update clientes set direccion = $1, email = $2, nombre = $3, telefono = $4 where cedula = $5;",
-        cliente.direccion.as_str(),
-        cliente.email.as_str(),
-        cliente.nombre.as_str(),
-        cliente.telefono.as_str(),
-        id
-    )
-        .execute(&state.db)
-        .await
-    {
+    match state.repos.clientes.actualizar(&actor, id, &cliente).await {
         Ok(_) => HttpResponse::Ok().json("Client updated"),
         Err(_) => HttpResponse::InternalServerError().json("could not update client")
     }
 }
 
-/// Borra un cliente de la base de datos, por medio de la id en la uri
+/// Borra un cliente de la base de datos de forma logica (`deleted_at`), por medio de la id en la uri.
+/// Con `?dry_run` no borra nada y responde con las filas dependientes que se verian afectadas
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `path` - la uri relativa a la api, esto es la id
+/// * `borrado` - si el borrado es simulado
 #[delete("/{id}")]
-pub async fn delete(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+pub async fn delete(state: Data<AppState>, actor: Actor, path: Path<i64>, borrado: Query<Borrado>) -> impl Responder {
     let id = path.into_inner();
-    match sqlx::query_as!(Clientes, "delete from clientes where cedula = $1;", id)
-        .execute(&state.db)
-        .await
-    {
+    if borrado.es_simulacro() {
+        return match state.repos.clientes.informe_borrado(id).await {
+            Ok(Some(informe)) => HttpResponse::Ok().json(informe),
+            Ok(None) => HttpResponse::NotFound().json("cliente not found"),
+            Err(_) => HttpResponse::InternalServerError().json("could not delete client"),
+        };
+    }
+    match state.repos.clientes.borrar(&actor, id).await {
+        Ok(0) => HttpResponse::NotFound().json("cliente not found"),
         Ok(_) => HttpResponse::Ok().json("Client deleted"),
-        Err(e) => HttpResponse::InternalServerError().body(format!("Could not delete, reason: {e}"))
+        Err(_) => HttpResponse::InternalServerError().json("could not delete client"),
+    }
+}
+
+/// Restaura un cliente borrado de forma logica, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+#[post("/{id}/restaurar")]
+pub async fn restore(state: Data<AppState>, actor: Actor, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.clientes.restaurar(&actor, id).await {
+        Ok(0) => HttpResponse::NotFound().json("deleted cliente not found"),
+        Ok(_) => HttpResponse::Ok().json("Client restored"),
+        Err(_) => HttpResponse::InternalServerError().json("could not restore cliente"),
     }
 }
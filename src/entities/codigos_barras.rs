@@ -0,0 +1,249 @@
+//!Este archivo representa el servicio REST de los codigos de barras de los productos
+//!(tabla 'codigos_barras'). Un producto puede tener varios codigos EAN-13 o UPC-A; los UPC-A se
+//!guardan como EAN-13 con un 0 adelante, que es como los leen la mayoria de las cajas registradoras.
+//!Los productos sin codigo de barras tienen uno interno (prefijo 2, de uso en la tienda) que se
+//!deriva de su `codigo`, de modo que se pueden imprimir sus etiquetas y buscarlos igual que los demas.
+
+use crate::{auth::Actor, etiquetas, repos::ErrorRepo, validacion, AppState};
+use actix_web::{
+    delete, get, post,
+    web::{Data, Json, Path, Query},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use validator::{Validate, ValidationError};
+
+/// La tabla 'codigos_barras' representada como un struct.
+/// El `codigo_barras` siempre tiene 13 digitos
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct CodigosBarras {
+    pub(crate) codigo: Option<i64>,
+    pub(crate) codigo_barras: String,
+    pub(crate) codigo_producto: Option<i64>,
+    pub(crate) created_at: Option<DateTime<Utc>>,
+}
+
+/// El json del request para asignar un codigo de barras a un producto
+#[derive(Debug, Deserialize, Validate)]
+pub(crate) struct NuevoCodigoBarras {
+    #[validate(custom = "validar")]
+    pub(crate) codigo_barras: String,
+}
+
+/// El digito de control de los primeros 12 digitos de un EAN-13
+fn digito_control(digitos: &[u8]) -> u8 {
+    let suma: u32 = digitos.iter().enumerate().map(|(i, d)| u32::from(d - b'0') * if i % 2 == 0 { 1 } else { 3 }).sum();
+    b'0' + ((10 - suma % 10) % 10) as u8
+}
+
+/// Normaliza un codigo de barras a 13 digitos: un EAN-13 queda igual y un UPC-A (12 digitos)
+/// queda con un 0 adelante. Falla si no tiene 12 o 13 digitos o si su digito de control no coincide
+/// ### Parametros
+/// * `codigo` - El codigo de barras como lo lee el escaner
+pub(crate) fn normalizar(codigo: &str) -> Result<String, ValidationError> {
+    let codigo = codigo.trim();
+    let normalizado = match codigo.len() {
+        12 => format!("0{codigo}"),
+        13 => codigo.to_owned(),
+        _ => "".to_owned(),
+    };
+    let error = |mensaje: &'static str| {
+        let mut error = ValidationError::new("codigo_barras");
+        error.message = Some(mensaje.into());
+        error
+    };
+    if normalizado.is_empty() || !normalizado.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(error("must have 12 (UPC-A) or 13 (EAN-13) digits"));
+    }
+    let (cuerpo, control) = normalizado.as_bytes().split_at(12);
+    if digito_control(cuerpo) != control[0] {
+        return Err(error("invalid check digit"));
+    }
+    Ok(normalizado)
+}
+
+/// Valida un codigo de barras EAN-13 o UPC-A
+fn validar(codigo: &str) -> Result<(), ValidationError> {
+    normalizar(codigo).map(|_| ())
+}
+
+/// El codigo de barras interno de un producto: 2, su `codigo` con 11 digitos y el digito de control.
+/// Los productos cuyo codigo tiene mas de 11 digitos no tienen codigo interno
+/// ### Parametros
+/// * `codigo_producto` - El codigo del producto
+pub(crate) fn interno(codigo_producto: i64) -> Option<String> {
+    if !(1..=99_999_999_999).contains(&codigo_producto) {
+        return None;
+    }
+    let cuerpo = format!("2{codigo_producto:011}");
+    let control = digito_control(cuerpo.as_bytes()) as char;
+    Some(format!("{cuerpo}{control}"))
+}
+
+/// El codigo del producto de un codigo de barras interno (ya normalizado)
+fn producto_interno(codigo_barras: &str) -> Option<i64> {
+    let codigo = codigo_barras.strip_prefix('2')?.get(..11)?.parse().ok()?;
+    (interno(codigo).as_deref() == Some(codigo_barras)).then_some(codigo)
+}
+
+/// Busca un producto por su codigo de barras, como lo lee el escaner (EAN-13 o UPC-A).
+/// Los codigos asignados tienen prioridad sobre los internos. Los productos borrados no se encuentran
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es el codigo de barras
+#[get("/barcode/{code}")]
+pub async fn read_by_barcode(state: Data<AppState>, path: Path<String>) -> impl Responder {
+    let codigo_barras = match normalizar(&path.into_inner()) {
+        Ok(codigo_barras) => codigo_barras,
+        Err(error) => {
+            return HttpResponse::BadRequest().json(format!("invalid barcode: {}", error.message.unwrap_or_default()))
+        }
+    };
+    let codigo_producto = match state.repos.codigos_barras.buscar(&codigo_barras).await {
+        Ok(Some(asignado)) => asignado.codigo_producto,
+        Ok(None) => producto_interno(&codigo_barras),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read codigos_barras"),
+    };
+    let Some(codigo_producto) = codigo_producto else {
+        return HttpResponse::NotFound().json("producto not found");
+    };
+    match state.repos.productos.obtener(codigo_producto).await {
+        Ok(Some(producto)) if producto.deleted_at.is_none() => HttpResponse::Ok().json(producto),
+        Ok(_) => HttpResponse::NotFound().json("producto not found"),
+        Err(_) => HttpResponse::InternalServerError().json("could not read productos"),
+    }
+}
+
+/// Obtiene los codigos de barras de un producto, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id del producto
+#[get("/{id}/codigos_barras")]
+pub async fn read_by_producto(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.productos.obtener(id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json("producto not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read productos"),
+    }
+    match state.repos.codigos_barras.por_producto(id).await {
+        Ok(codigos) => HttpResponse::Ok().json(codigos),
+        Err(_) => HttpResponse::InternalServerError().json("could not read codigos_barras"),
+    }
+}
+
+/// Asigna un codigo de barras a un producto. El codigo se valida y se guarda normalizado
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id del producto
+/// * `nuevo` - Un json en el body del request con el codigo de barras
+#[post("/{id}/codigos_barras")]
+pub async fn create(
+    state: Data<AppState>,
+    actor: Actor,
+    path: Path<i64>,
+    nuevo: Json<NuevoCodigoBarras>,
+) -> impl Responder {
+    if let Err(errores) = nuevo.validate() {
+        return validacion::rechazar(errores);
+    }
+    let codigo_barras = normalizar(&nuevo.codigo_barras).unwrap_or_default();
+    match state.repos.codigos_barras.insertar(&actor, path.into_inner(), &codigo_barras).await {
+        Ok(Some(codigo)) => match state.repos.codigos_barras.buscar(&codigo_barras).await {
+            Ok(Some(creado)) if creado.codigo == Some(codigo) => HttpResponse::Created().json(creado),
+            _ => HttpResponse::InternalServerError().json("could not read codigos_barras"),
+        },
+        Ok(None) | Err(ErrorRepo::LlaveForanea) => HttpResponse::NotFound().json("producto not found"),
+        Err(ErrorRepo::Duplicado) => HttpResponse::Conflict().json("codigo_barras already assigned"),
+        Err(_) => HttpResponse::InternalServerError().json("could not create codigo_barras"),
+    }
+}
+
+/// Quita un codigo de barras de un producto
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id del producto y el codigo de barras
+#[delete("/{id}/codigos_barras/{code}")]
+pub async fn delete(state: Data<AppState>, actor: Actor, path: Path<(i64, String)>) -> impl Responder {
+    let (id, codigo_barras) = path.into_inner();
+    let Ok(codigo_barras) = normalizar(&codigo_barras) else {
+        return HttpResponse::NotFound().json("codigo_barras not found");
+    };
+    match state.repos.codigos_barras.borrar(&actor, id, &codigo_barras).await {
+        Ok(0) => HttpResponse::NotFound().json("codigo_barras not found"),
+        Ok(_) => HttpResponse::Ok().json("Codigo de barras deleted"),
+        Err(_) => HttpResponse::InternalServerError().json("could not delete codigo_barras"),
+    }
+}
+
+/// El formato de una etiqueta
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Formato {
+    #[default]
+    Svg,
+    Png,
+}
+
+/// El query de una etiqueta: `?formato=svg` (predeterminado) o `?formato=png`
+#[derive(Debug, Deserialize)]
+pub(crate) struct Etiqueta {
+    #[serde(default)]
+    formato: Formato,
+}
+
+/// Genera la etiqueta de un producto con su primer codigo de barras, o con su codigo interno
+/// si no tiene ninguno, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id del producto
+/// * `etiqueta` - el formato de la etiqueta
+#[get("/{id}/etiqueta")]
+pub async fn read_etiqueta(state: Data<AppState>, path: Path<i64>, etiqueta: Query<Etiqueta>) -> impl Responder {
+    let id = path.into_inner();
+    let producto = match state.repos.productos.obtener(id).await {
+        Ok(Some(producto)) => producto,
+        Ok(None) => return HttpResponse::NotFound().json("producto not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read productos"),
+    };
+    let codigo_barras = match state.repos.codigos_barras.por_producto(id).await {
+        Ok(codigos) => codigos.into_iter().next().map(|c| c.codigo_barras).or_else(|| interno(id)),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read codigos_barras"),
+    };
+    let Some(codigo_barras) = codigo_barras else {
+        return HttpResponse::Conflict().json("producto has no codigo_barras and its codigo is too long for an internal one");
+    };
+    match etiqueta.formato {
+        Formato::Svg => HttpResponse::Ok()
+            .content_type("image/svg+xml")
+            .body(etiquetas::svg(&codigo_barras, &producto.nombre_producto)),
+        Formato::Png => HttpResponse::Ok().content_type("image/png").body(etiquetas::png(&codigo_barras)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn los_codigos_se_validan_y_normalizan() {
+        assert_eq!(normalizar("4006381333931").unwrap(), "4006381333931");
+        assert_eq!(normalizar("036000291452").unwrap(), "0036000291452");
+        assert!(normalizar("4006381333932").is_err());
+        assert!(normalizar("40063813339").is_err());
+        assert!(normalizar("40063813339a1").is_err());
+    }
+
+    #[test]
+    fn los_codigos_internos_se_derivan_del_codigo_del_producto() {
+        assert_eq!(interno(77).as_deref(), Some("2000000000770"));
+        assert_eq!(producto_interno("2000000000770"), Some(77));
+        assert_eq!(producto_interno("4006381333931"), None);
+        assert_eq!(interno(100_000_000_000), None);
+        assert!(interno(25_075_864).is_some_and(|codigo| normalizar(&codigo).is_ok()));
+    }
+}
@@ -0,0 +1,381 @@
+//!Este archivo representa el servicio REST de los conteos fisicos del inventario
+//!(tablas 'conteos' y 'detalle_conteos'). Mientras un conteo esta abierto se registran las cantidades
+//!contadas de cada producto (en json o en un CSV), se revisan sus diferencias con las existencias
+//!del sistema y al aplicarlo cada diferencia queda como un movimiento de ajuste en el inventario.
+//!Cada conteo es de una sucursal y se compara con las existencias de esa sucursal.
+
+use std::collections::HashMap;
+
+use crate::{
+    auth::Actor,
+    entities::{inventario::Existencias, productos, sucursales},
+    filtros::{FiltroEstado, FiltroSucursal},
+    repos::ErrorRepo,
+    validacion,
+    AppState,
+};
+use actix_multipart::Multipart;
+use actix_web::{
+    get, post,
+    web::{Data, Json, Path, Query},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, Utc};
+use csv::ReaderBuilder;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::io::Read;
+use validator::Validate;
+
+/// Los estados de un conteo
+pub(crate) const ABIERTO: &str = "abierto";
+pub(crate) const APLICADO: &str = "aplicado";
+pub(crate) const CANCELADO: &str = "cancelado";
+
+/// La tabla 'conteos' representada como un struct.
+/// El estado, el usuario y las fechas los asigna el servidor
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct Conteos {
+    pub(crate) codigo: Option<i64>,
+    pub(crate) descripcion: Option<String>,
+    pub(crate) codigo_sucursal: Option<i64>,
+    pub(crate) estado: String,
+    pub(crate) cedula_usuario: Option<i64>,
+    pub(crate) created_at: Option<DateTime<Utc>>,
+    pub(crate) aplicado_at: Option<DateTime<Utc>>,
+}
+
+/// La tabla 'detalle_conteos' representada como un struct: la cantidad contada de un producto.
+/// Tambien es cada fila del CSV de cantidades (`codigo_producto,cantidad,motivo`)
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, Validate)]
+pub(crate) struct DetalleConteos {
+    #[serde(skip_deserializing)]
+    pub(crate) codigo: Option<i64>,
+    #[serde(skip_deserializing)]
+    pub(crate) codigo_conteo: Option<i64>,
+    #[validate(range(min = 1))]
+    pub(crate) codigo_producto: Option<i64>,
+    #[validate(range(min = 0.0))]
+    pub(crate) cantidad: f64,
+    /// El motivo del ajuste de este producto; si no tiene se usa el del conteo
+    #[serde(default)]
+    #[validate(length(min = 1, max = 100))]
+    pub(crate) motivo: Option<String>,
+    #[serde(skip_deserializing)]
+    pub(crate) updated_at: Option<DateTime<Utc>>,
+}
+
+/// El json del request para abrir un conteo; sin `codigo_sucursal` el conteo es de la sucursal principal
+#[derive(Debug, Deserialize, Validate)]
+pub(crate) struct NuevoConteo {
+    #[serde(default)]
+    #[validate(length(min = 1, max = 100))]
+    pub(crate) descripcion: Option<String>,
+    #[serde(default = "sucursales::principal")]
+    #[validate(range(min = 1))]
+    pub(crate) codigo_sucursal: Option<i64>,
+}
+
+/// El json del request para aplicar un conteo: el motivo general de sus ajustes
+#[derive(Debug, Deserialize, Validate)]
+pub(crate) struct Aplicacion {
+    #[validate(length(min = 1, max = 100))]
+    pub(crate) motivo: String,
+}
+
+/// Un conteo con sus cantidades contadas
+#[derive(Debug, Serialize)]
+struct ConteoCompleto {
+    #[serde(flatten)]
+    conteo: Conteos,
+    detalles: Vec<DetalleConteos>,
+}
+
+/// La diferencia entre lo contado de un producto y sus existencias en el sistema
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Diferencia {
+    pub(crate) codigo_producto: i64,
+    pub(crate) nombre_producto: String,
+    pub(crate) sistema: f64,
+    pub(crate) contado: f64,
+    pub(crate) diferencia: f64,
+    pub(crate) valor: f64,
+    pub(crate) motivo: Option<String>,
+}
+
+/// El ajuste que lleva las existencias del sistema a lo contado, redondeado a milesimas
+/// para no registrar los residuos de sumar cantidades decimales
+pub(crate) fn ajuste(sistema: f64, contado: f64) -> f64 {
+    let ajuste = ((contado - sistema) * 1000.0).round() / 1000.0;
+    if ajuste == 0.0 {
+        0.0
+    } else {
+        ajuste
+    }
+}
+
+/// Compara las cantidades contadas con las existencias del sistema, valorizando cada diferencia
+/// a precio de compra. Los productos borrados (sin existencias) se omiten
+/// ### Parametros
+/// * `detalles` - Las cantidades contadas
+/// * `existencias` - Las existencias actuales de los productos
+pub(crate) fn diferencias(detalles: &[DetalleConteos], existencias: &[Existencias]) -> Vec<Diferencia> {
+    let existencias: HashMap<i64, &Existencias> = existencias.iter().map(|e| (e.codigo_producto, e)).collect();
+    detalles
+        .iter()
+        .filter_map(|detalle| {
+            let producto = existencias.get(&detalle.codigo_producto?)?;
+            let diferencia = ajuste(producto.cantidad, detalle.cantidad);
+            Some(Diferencia {
+                codigo_producto: producto.codigo_producto,
+                nombre_producto: producto.nombre_producto.clone(),
+                sistema: producto.cantidad,
+                contado: detalle.cantidad,
+                diferencia,
+                valor: diferencia * producto.precio_compra,
+                motivo: detalle.motivo.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Un conteo con sus detalles
+async fn completo(state: &AppState, codigo: i64) -> Result<ConteoCompleto, HttpResponse> {
+    let conteo = match state.repos.conteos.obtener(codigo).await {
+        Ok(Some(conteo)) => conteo,
+        Ok(None) => return Err(HttpResponse::NotFound().json("conteo not found")),
+        Err(_) => return Err(HttpResponse::InternalServerError().json("could not read conteos")),
+    };
+    match state.repos.conteos.detalles(codigo).await {
+        Ok(detalles) => Ok(ConteoCompleto { conteo, detalles }),
+        Err(_) => Err(HttpResponse::InternalServerError().json("could not read conteos")),
+    }
+}
+
+/// Valida las cantidades contadas y las registra en un conteo abierto
+async fn registrar(state: &AppState, actor: &Actor, codigo: i64, contados: &[DetalleConteos]) -> HttpResponse {
+    for contado in contados {
+        if let Err(errores) = contado.validate() {
+            return validacion::rechazar(errores);
+        }
+        let Some(codigo_producto) = contado.codigo_producto else { continue };
+        match state.repos.productos.obtener(codigo_producto).await {
+            Ok(Some(producto)) => {
+                if let Err(error) = producto.cantidad_valida(contado.cantidad) {
+                    return HttpResponse::BadRequest().json(error);
+                }
+            }
+            Ok(None) => return HttpResponse::BadRequest().json(format!("producto {codigo_producto} does not exist")),
+            Err(_) => return HttpResponse::InternalServerError().json("could not read productos"),
+        }
+    }
+    match state.repos.conteos.registrar(actor, codigo, contados).await {
+        Ok(None) => HttpResponse::Conflict().json("conteo not found or not abierto"),
+        Ok(Some(_)) => match completo(state, codigo).await {
+            Ok(conteo) => HttpResponse::Ok().json(conteo),
+            Err(respuesta) => respuesta,
+        },
+        Err(ErrorRepo::LlaveForanea) => HttpResponse::BadRequest().json("a producto of the conteo does not exist"),
+        Err(_) => HttpResponse::InternalServerError().json("could not register cantidades"),
+    }
+}
+
+/// Lee las cantidades contadas de un CSV con encabezados (`codigo_producto,cantidad` y
+/// opcionalmente `motivo`); una fila que no se puede leer rechaza todo el archivo
+fn leer_csv(archivo: impl Read) -> Result<Vec<DetalleConteos>, HttpResponse> {
+    let mut csv_reader = ReaderBuilder::new().has_headers(true).from_reader(archivo);
+    let headers = match csv_reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => return Err(HttpResponse::BadRequest().json(e.to_string())),
+    };
+    let mut contados = Vec::new();
+    for result in csv_reader.records() {
+        let contado = result.and_then(|record| record.deserialize::<DetalleConteos>(Some(&headers)));
+        match contado {
+            Ok(contado) => contados.push(contado),
+            Err(e) => return Err(HttpResponse::BadRequest().json(e.to_string())),
+        }
+    }
+    Ok(contados)
+}
+
+/// Abre un conteo fisico del inventario de una de las sucursales del usuario
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `nuevo` - Un json en el body del request con la descripcion (opcional) y la sucursal del conteo
+#[post("/")]
+pub async fn create(state: Data<AppState>, actor: Actor, nuevo: Json<NuevoConteo>) -> impl Responder {
+    if let Err(errores) = nuevo.validate() {
+        return validacion::rechazar(errores);
+    }
+    let NuevoConteo { descripcion, codigo_sucursal } = nuevo.into_inner();
+    let codigo_sucursal = codigo_sucursal.unwrap_or(sucursales::PRINCIPAL);
+    if let Err(respuesta) = sucursales::autorizar(&state, actor.cedula, codigo_sucursal).await {
+        return respuesta;
+    }
+    let conteo = Conteos {
+        codigo: None,
+        descripcion,
+        codigo_sucursal: Some(codigo_sucursal),
+        estado: ABIERTO.to_owned(),
+        cedula_usuario: actor.cedula,
+        created_at: None,
+        aplicado_at: None,
+    };
+    match state.repos.conteos.insertar(&actor, &conteo).await {
+        Ok(codigo) => match completo(&state, codigo).await {
+            Ok(conteo) => HttpResponse::Created().json(conteo),
+            Err(respuesta) => respuesta,
+        },
+        Err(ErrorRepo::LlaveForanea) => HttpResponse::BadRequest().json("the sucursal of the conteo does not exist"),
+        Err(_) => HttpResponse::InternalServerError().json("could not create conteo"),
+    }
+}
+
+/// Obtiene todos los conteos, del mas reciente al mas antiguo
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `filtro` - `?estado=abierto`, opcional
+/// * `sucursal` - `?sucursal=2`, opcional
+#[get("/")]
+pub async fn read_all(state: Data<AppState>, filtro: Query<FiltroEstado>, sucursal: Query<FiltroSucursal>) -> impl Responder {
+    match state.repos.conteos.listar(filtro.estado.as_deref(), sucursal.sucursal).await {
+        Ok(conteos) => HttpResponse::Ok().json(conteos),
+        Err(_) => HttpResponse::InternalServerError().json("could not read conteos"),
+    }
+}
+
+/// Obtiene un conteo con sus cantidades contadas, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id
+#[get("/{id}")]
+pub async fn read_by_id(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+    match completo(&state, path.into_inner()).await {
+        Ok(conteo) => HttpResponse::Ok().json(conteo),
+        Err(respuesta) => respuesta,
+    }
+}
+
+/// Registra las cantidades contadas de uno o varios productos en un conteo abierto;
+/// contar de nuevo un producto reemplaza su cantidad anterior
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+/// * `contados` - Un json en el body del request con la lista de productos y sus cantidades
+#[post("/{id}/cantidades")]
+pub async fn create_cantidades(
+    state: Data<AppState>,
+    actor: Actor,
+    path: Path<i64>,
+    contados: Json<Vec<DetalleConteos>>,
+) -> impl Responder {
+    registrar(&state, &actor, path.into_inner(), &contados).await
+}
+
+/// Como `create_cantidades`, pero recibe las cantidades en un archivo CSV en binario
+/// (igual que `productos::create`)
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+/// * `payload` - El formulario con el CSV de las cantidades
+#[post("/{id}/csv")]
+pub async fn create_csv(state: Data<AppState>, actor: Actor, path: Path<i64>, payload: Multipart) -> impl Responder {
+    let contados = match productos::archivo_temporal(payload).await.and_then(leer_csv) {
+        Ok(contados) => contados,
+        Err(respuesta) => return respuesta,
+    };
+    registrar(&state, &actor, path.into_inner(), &contados).await
+}
+
+/// Obtiene las diferencias entre lo contado y las existencias del sistema en la sucursal de un conteo
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id
+#[get("/{id}/diferencias")]
+pub async fn read_diferencias(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+    let conteo = match completo(&state, path.into_inner()).await {
+        Ok(conteo) => conteo,
+        Err(respuesta) => return respuesta,
+    };
+    match state.repos.inventario.existencias(conteo.conteo.codigo_sucursal).await {
+        Ok(existencias) => HttpResponse::Ok().json(diferencias(&conteo.detalles, &existencias)),
+        Err(_) => HttpResponse::InternalServerError().json("could not read inventario"),
+    }
+}
+
+/// Aplica un conteo abierto: cada diferencia con las existencias del sistema se registra como un
+/// movimiento de ajuste con el motivo del producto o, si no tiene, el del request
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+/// * `aplicacion` - Un json en el body del request con el motivo de los ajustes
+#[post("/{id}/aplicar")]
+pub async fn apply(state: Data<AppState>, actor: Actor, path: Path<i64>, aplicacion: Json<Aplicacion>) -> impl Responder {
+    if let Err(errores) = aplicacion.validate() {
+        return validacion::rechazar(errores);
+    }
+    let id = path.into_inner();
+    match state.repos.conteos.aplicar(&actor, id, &aplicacion.motivo).await {
+        Ok(None) => HttpResponse::Conflict().json("conteo not found or not abierto"),
+        Ok(Some(ajustes)) => HttpResponse::Ok().json(serde_json::json!({ "codigo": id, "ajustes": ajustes })),
+        Err(_) => HttpResponse::InternalServerError().json("could not apply conteo"),
+    }
+}
+
+/// Cancela un conteo abierto sin ajustar el inventario
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+#[post("/{id}/cancelar")]
+pub async fn cancel(state: Data<AppState>, actor: Actor, path: Path<i64>) -> impl Responder {
+    match state.repos.conteos.cancelar(&actor, path.into_inner()).await {
+        Ok(0) => HttpResponse::Conflict().json("conteo not found or not abierto"),
+        Ok(_) => HttpResponse::Ok().json("Conteo cancelado"),
+        Err(_) => HttpResponse::InternalServerError().json("could not cancel conteo"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contado(codigo_producto: i64, cantidad: f64) -> DetalleConteos {
+        DetalleConteos {
+            codigo: None,
+            codigo_conteo: Some(1),
+            codigo_producto: Some(codigo_producto),
+            cantidad,
+            motivo: None,
+            updated_at: None,
+        }
+    }
+
+    fn existencia(codigo: i64, cantidad: f64) -> Existencias {
+        Existencias {
+            codigo_producto: codigo,
+            nombre_producto: format!("producto {codigo}"),
+            nit_proveedor: Some(1),
+            unidad_medida: productos::KG.to_owned(),
+            precio_compra: 10.0,
+            stock_minimo: None,
+            stock_maximo: None,
+            cantidad,
+        }
+    }
+
+    #[test]
+    fn las_diferencias_se_redondean_y_omiten_los_productos_borrados() {
+        // 0.1 + 0.2 en el sistema no es exactamente 0.3
+        let existencias = [existencia(1, 0.1 + 0.2), existencia(2, 5.0)];
+        let detalles = [contado(1, 0.3), contado(2, 3.5), contado(3, 1.0)];
+        let diferencias = diferencias(&detalles, &existencias);
+        let resumen: Vec<_> = diferencias.iter().map(|d| (d.codigo_producto, d.diferencia, d.valor)).collect();
+        assert_eq!(resumen, [(1, 0.0, 0.0), (2, -1.5, -15.0)]);
+    }
+}
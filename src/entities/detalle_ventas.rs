@@ -1,61 +1,151 @@
 //!Este archivo representa el servicio REST de la tabla 'detalle_ventas'.
-//!Contiene todas las operaciones CRUD relacionadas.
+//!Contiene todas las operaciones CRUD relacionadas. Los detalles de una venta finalizada
+//!ya no se pueden crear, modificar ni borrar.
 
-use crate::AppState;
+use crate::{
+    auth::Actor,
+    filtros::{FiltroCategoria, FiltroSucursal, RangoFechas},
+    validacion,
+    AppState,
+};
 use actix_web::{
     delete, get, patch, post,
-    web::{Data, Json, Path},
+    web::{Data, Json, Path, Query},
     HttpResponse, Responder,
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use validator::Validate;
 
 /// La tabla 'detalle_ventas' representada como un struct.
 /// La llave primaria y las foraneas son opcionales debido a la operacion de `update`
-#[derive(Debug, FromRow, Serialize, Deserialize)]
-struct DetalleVentas {
-    codigo: Option<i64>,
+/// `codigo_promocion` y `descuento` son la promocion aplicada a la linea y su descuento (antes de IVA),
+/// como los calcula `/api/ventas/calcular`; el servidor verifica que el descuento sea el de la promocion
+/// La cantidad es fraccionaria para los productos que se venden por peso o volumen
+/// Los campos se validan antes de ejecutar cualquier consulta.
+/// Las fechas son asignadas por el servidor y se ignoran en el json del request
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, Validate)]
+pub(crate) struct DetalleVentas {
+    #[validate(range(min = 1))]
+    pub(crate) codigo: Option<i64>,
+    #[validate(range(min = 1))]
+    pub(crate) codigo_producto: Option<i64>,
+    #[validate(range(min = 1))]
+    pub(crate) codigo_venta: Option<i64>,
+    #[validate(range(min = 0.001))]
+    pub(crate) cantidad_producto: f64,
+    #[validate(range(min = 0.0))]
+    pub(crate) valor_total: f64,
+    #[validate(range(min = 0.0))]
+    pub(crate) valor_venta: f64,
+    #[validate(range(min = 0.0))]
+    pub(crate) valor_iva: f64,
+    #[validate(range(min = 1))]
+    pub(crate) codigo_promocion: Option<i64>,
+    #[serde(default)]
+    #[validate(range(min = 0.0))]
+    pub(crate) descuento: f64,
+    #[serde(skip_deserializing)]
+    pub(crate) created_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) updated_at: Option<DateTime<Utc>>,
+}
+
+/// Verifica que la cantidad de un detalle se pueda vender segun la unidad de medida de su producto
+/// y que su descuento sea el que da su promocion, calculado en el servidor con el cliente de la venta y la
+/// fecha de hoy: un descuento sin promocion o distinto al de `/api/ventas/calcular` se rechaza.
+/// Si el producto o la venta no existen no hay nada que verificar: la base de datos rechaza el detalle
+async fn linea_valida(
+    state: &AppState,
+    detalle_venta: &DetalleVentas,
     codigo_producto: Option<i64>,
     codigo_venta: Option<i64>,
-    cantidad_producto: i32,
-    valor_total: f64,
-    valor_venta: f64,
-    valor_iva: f64,
+) -> Result<(), HttpResponse> {
+    let Some(codigo_producto) = codigo_producto else {
+        return Ok(());
+    };
+    let producto = match state.repos.productos.obtener(codigo_producto).await {
+        Ok(Some(producto)) => producto,
+        Ok(None) => return Ok(()),
+        Err(_) => return Err(HttpResponse::InternalServerError().json("could not read productos")),
+    };
+    let cantidad = detalle_venta.cantidad_producto;
+    producto.cantidad_valida(cantidad).map_err(|mensaje| HttpResponse::BadRequest().json(mensaje))?;
+    let Some(codigo_promocion) = detalle_venta.codigo_promocion else {
+        if detalle_venta.descuento > 0.0 {
+            return Err(HttpResponse::BadRequest().json("a descuento needs a codigo_promocion"));
+        }
+        return Ok(());
+    };
+    let promocion = match state.repos.promociones.obtener(codigo_promocion).await {
+        Ok(Some(promocion)) => promocion,
+        Ok(None) => return Err(HttpResponse::BadRequest().json(format!("promocion {codigo_promocion} not found"))),
+        Err(_) => return Err(HttpResponse::InternalServerError().json("could not read promociones")),
+    };
+    let cedula_cliente = match codigo_venta {
+        Some(codigo_venta) => match state.repos.ventas.obtener(codigo_venta).await {
+            Ok(Some(venta)) => venta.cedula_cliente,
+            Ok(None) => return Ok(()),
+            Err(_) => return Err(HttpResponse::InternalServerError().json("could not read ventas")),
+        },
+        None => None,
+    };
+    promocion
+        .descuento_valido(&producto, cantidad, cedula_cliente, detalle_venta.descuento, Utc::now().date_naive())
+        .map_err(|mensaje| HttpResponse::BadRequest().json(mensaje))
+}
+
+/// La respuesta de una operacion que no afecto ningun detalle: la venta no existe
+/// o ya esta finalizada
+async fn venta_cerrada(state: &AppState, codigo_venta: Option<i64>) -> HttpResponse {
+    let Some(codigo_venta) = codigo_venta else {
+        return HttpResponse::NotFound().json("venta not found");
+    };
+    match state.repos.ventas.obtener(codigo_venta).await {
+        Ok(Some(_)) => HttpResponse::Conflict().json("venta is already finalized"),
+        Ok(None) => HttpResponse::NotFound().json("venta not found"),
+        Err(_) => HttpResponse::InternalServerError().json("could not read ventas"),
+    }
 }
 
-/// Crea un nuevo detalle de la venta y lo envia a la base de datos
+/// Crea un nuevo detalle de una venta abierta y lo envia a la base de datos
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `venta` - Un json en el body del request representando el detalle de la venta
 #[post("/")]
-pub async fn create(state: Data<AppState>, detalle_venta: Json<DetalleVentas>) -> impl Responder {
-    match sqlx::query_as::<_, DetalleVentas>(
-        "insert into detalle_ventas values ($1,$2,$3,$4,$5,$6,$7);",
-    )
-    .bind(detalle_venta.codigo)
-    .bind(detalle_venta.codigo_producto)
-    .bind(detalle_venta.codigo_venta)
-    .bind(detalle_venta.cantidad_producto)
-    .bind(detalle_venta.valor_total)
-    .bind(detalle_venta.valor_venta)
-    .bind(detalle_venta.valor_iva)
-    .fetch_optional(&state.db)
-    .await
-    {
+pub async fn create(state: Data<AppState>, actor: Actor, detalle_venta: Json<DetalleVentas>) -> impl Responder {
+    if let Err(errores) = detalle_venta.validate() {
+        return validacion::rechazar(errores);
+    }
+    if let Err(respuesta) = linea_valida(&state, &detalle_venta, detalle_venta.codigo_producto, detalle_venta.codigo_venta).await {
+        return respuesta;
+    }
+    match state.repos.detalle_ventas.insertar(&actor, &detalle_venta).await {
+        Ok(0) => venta_cerrada(&state, detalle_venta.codigo_venta).await,
         Ok(_) => HttpResponse::Created().json("detalle venta creada"),
         Err(_) => HttpResponse::InternalServerError().json("could not create detalle venta")
     }
 }
 
-/// Obtiene todos los detalles de la venta de la base de datos
+/// Obtiene todos los detalles de la venta de la base de datos.
+/// El filtro de fechas se aplica sobre la `fecha` de la venta a la que pertenece cada detalle,
+/// con `?categoria=` solo se incluyen los productos de esa categoria y sus subcategorias
+/// y con `?sucursal=` solo los de las ventas de esa sucursal
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `rango` - `?desde=AAAA-MM-DD&hasta=AAAA-MM-DD`, ambos opcionales e inclusivos
+/// * `filtro` - la categoria de los productos
+/// * `sucursal` - la sucursal de las ventas
 #[get("/")]
-pub async fn read_all(state: Data<AppState>) -> impl Responder {
-    match sqlx::query_as::<_, DetalleVentas>("select * from detalle_ventas;")
-        .fetch_all(&state.db)
-        .await
-    {
+pub async fn read_all(
+    state: Data<AppState>,
+    rango: Query<RangoFechas>,
+    filtro: Query<FiltroCategoria>,
+    sucursal: Query<FiltroSucursal>,
+) -> impl Responder {
+    match state.repos.detalle_ventas.listar(&rango, filtro.categoria, sucursal.sucursal).await {
         Ok(ventas) => HttpResponse::Ok().json(ventas),
         Err(_) => HttpResponse::NotFound().json("detalle ventas not found"),
     }
@@ -68,50 +158,55 @@ pub async fn read_all(state: Data<AppState>) -> impl Responder {
 #[get("/{id}")]
 pub async fn read_by_id(state: Data<AppState>, path: Path<i64>) -> impl Responder {
     let id = path.into_inner();
-    match sqlx::query_as::<_, DetalleVentas>("select * from detalle_ventas where codigo = $1;")
-        .bind(id)
-        .fetch_one(&state.db)
-        .await
-    {
-        Ok(venta) => HttpResponse::Ok().json(venta),
-        Err(_) => HttpResponse::NotFound().json("detalle venta not found"),
+    match state.repos.detalle_ventas.obtener(id).await {
+        Ok(Some(venta)) => HttpResponse::Ok().json(venta),
+        Ok(None) => HttpResponse::NotFound().json("detalle venta not found"),
+        Err(_) => HttpResponse::InternalServerError().json("could not read detalle ventas"),
     }
 }
 
-/// Actualiza un detalle de la venta de la base de datos, por medio de la id en la uri
+/// Actualiza un detalle de una venta abierta, por medio de la id en la uri
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `path` - la uri relativa a la api, esto es la id
 /// * `detalle_venta` - Un json en el body del request representando el detalle de la venta a actualizar
 #[patch("/{id}")]
-pub async fn update(state: Data<AppState>, path: Path<i64>, detalle_venta: Json<DetalleVentas>) -> impl Responder {
+pub async fn update(state: Data<AppState>, actor: Actor, path: Path<i64>, detalle_venta: Json<DetalleVentas>) -> impl Responder {
+    if let Err(errores) = detalle_venta.validate() {
+        return validacion::rechazar(errores);
+    }
     let id = path.into_inner();
-    match sqlx::query_as::<_, DetalleVentas>("update detalle_ventas set cantidad_producto = $1, valor_total = $2, valor_venta = $3, valor_iva = $4 where codigo = $5;")
-        .bind(detalle_venta.cantidad_producto)
-        .bind(detalle_venta.valor_total)
-        .bind(detalle_venta.valor_venta)
-        .bind(detalle_venta.valor_iva)
-        .bind(id)
-        .fetch_optional(&state.db)
-        .await
-    {
+    let actual = match state.repos.detalle_ventas.obtener(id).await {
+        Ok(Some(actual)) => actual,
+        Ok(None) => return HttpResponse::NotFound().json("detalle venta not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read detalle ventas"),
+    };
+    if let Err(respuesta) = linea_valida(&state, &detalle_venta, actual.codigo_producto, actual.codigo_venta).await {
+        return respuesta;
+    }
+    match state.repos.detalle_ventas.actualizar(&actor, id, &detalle_venta).await {
+        Ok(0) => venta_cerrada(&state, actual.codigo_venta).await,
         Ok(_) => HttpResponse::Ok().json("detalle venta updated"),
         Err(_) => HttpResponse::InternalServerError().json("could not update detalle venta")
     }
 }
 
-/// Borra un detalle de la venta de la base de datos, por medio de la id en la uri
+/// Borra un detalle de una venta abierta, por medio de la id en la uri
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `path` - la uri relativa a la api, esto es la id
 #[delete("/{id}")]
-pub async fn delete(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+pub async fn delete(state: Data<AppState>, actor: Actor, path: Path<i64>) -> impl Responder {
     let id = path.into_inner();
-    match sqlx::query_as::<_, DetalleVentas>("delete from detalle_ventas where codigo = $1;")
-        .bind(id)
-        .fetch_optional(&state.db)
-        .await
-    {
+    let actual = match state.repos.detalle_ventas.obtener(id).await {
+        Ok(Some(actual)) => actual,
+        Ok(None) => return HttpResponse::NotFound().json("detalle venta not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read detalle ventas"),
+    };
+    match state.repos.detalle_ventas.borrar(&actor, id).await {
+        Ok(0) => venta_cerrada(&state, actual.codigo_venta).await,
         Ok(_) => HttpResponse::Ok().json("detalle venta deleted"),
         Err(_) => HttpResponse::InternalServerError().json("could not delete detalle venta"),
     }
@@ -0,0 +1,306 @@
+//!Este archivo representa el servicio REST de las existencias de los productos
+//!(tabla 'movimientos_inventario'). Las existencias de un producto son la suma de sus movimientos:
+//!las ventas finalizadas las descuentan (ver `ventas::finalize`) y las ordenes de compra recibidas
+//!las suman (ver `ordenes_compra`) y los conteos fisicos las ajustan (ver `conteos`). Con los umbrales
+//!de cada producto (`stock_minimo` y `stock_maximo`) y la velocidad de sus ventas recientes se generan
+//!las alertas y las sugerencias de compra. Cada movimiento es de una sucursal (ver `sucursales`) y los
+//!traslados mueven existencias entre ellas (ver `traslados`).
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{filtros::FiltroSucursal, AppState};
+use actix_web::{
+    get,
+    web::{Data, Path, Query},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, Days, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use super::productos::UNIDAD;
+
+/// Los tipos de movimiento de inventario
+pub(crate) const VENTA: &str = "venta";
+pub(crate) const COMPRA: &str = "compra";
+pub(crate) const AJUSTE: &str = "ajuste";
+pub(crate) const TRASLADO: &str = "traslado";
+
+/// La tabla 'movimientos_inventario' representada como un struct.
+/// Cada movimiento es del inventario de una sucursal. Las entradas son positivas y las salidas negativas;
+/// `codigo_venta`, `codigo_orden`, `codigo_conteo` o `codigo_traslado` indican la venta, la orden de compra,
+/// el conteo o el traslado que origino el movimiento
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct MovimientosInventario {
+    pub(crate) codigo: Option<i64>,
+    pub(crate) codigo_producto: Option<i64>,
+    pub(crate) codigo_sucursal: Option<i64>,
+    pub(crate) tipo: String,
+    pub(crate) cantidad: f64,
+    pub(crate) codigo_venta: Option<i64>,
+    pub(crate) codigo_orden: Option<i64>,
+    pub(crate) codigo_conteo: Option<i64>,
+    pub(crate) codigo_traslado: Option<i64>,
+    pub(crate) motivo: Option<String>,
+    pub(crate) cedula_usuario: Option<i64>,
+    pub(crate) fecha: Option<DateTime<Utc>>,
+}
+
+impl MovimientosInventario {
+    /// Un movimiento sin origen en una sucursal; el usuario y la fecha los asigna el repositorio
+    pub(crate) fn new(codigo_sucursal: Option<i64>, codigo_producto: Option<i64>, tipo: &str, cantidad: f64) -> Self {
+        MovimientosInventario {
+            codigo: None,
+            codigo_producto,
+            codigo_sucursal,
+            tipo: tipo.to_owned(),
+            cantidad,
+            codigo_venta: None,
+            codigo_orden: None,
+            codigo_conteo: None,
+            codigo_traslado: None,
+            motivo: None,
+            cedula_usuario: None,
+            fecha: None,
+        }
+    }
+}
+
+/// Las existencias de un producto (sin borrar) con sus umbrales
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct Existencias {
+    pub(crate) codigo_producto: i64,
+    pub(crate) nombre_producto: String,
+    pub(crate) nit_proveedor: Option<i64>,
+    pub(crate) unidad_medida: String,
+    pub(crate) precio_compra: f64,
+    pub(crate) stock_minimo: Option<f64>,
+    pub(crate) stock_maximo: Option<f64>,
+    pub(crate) cantidad: f64,
+}
+
+/// La cantidad vendida de un producto desde una fecha
+#[derive(Debug, Clone, FromRow)]
+pub(crate) struct Vendido {
+    pub(crate) codigo_producto: Option<i64>,
+    pub(crate) cantidad: f64,
+}
+
+fn dias_ventas() -> u32 {
+    30
+}
+
+fn dias_cobertura() -> u32 {
+    15
+}
+
+/// Los parametros de las sugerencias de compra: `?dias=30&cobertura=15&sucursal=2`
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct ParametrosSugerencia {
+    /// Los dias de ventas con los que se calcula la venta diaria de cada producto
+    #[serde(default = "dias_ventas")]
+    pub(crate) dias: u32,
+    /// Los dias de venta que deben cubrir las existencias
+    #[serde(default = "dias_cobertura")]
+    pub(crate) cobertura: u32,
+    /// La sucursal de las existencias y las ventas; sin sucursal, toda la cadena
+    #[serde(default)]
+    pub(crate) sucursal: Option<i64>,
+}
+
+impl Default for ParametrosSugerencia {
+    fn default() -> Self {
+        ParametrosSugerencia { dias: dias_ventas(), cobertura: dias_cobertura(), sucursal: None }
+    }
+}
+
+impl ParametrosSugerencia {
+    /// Desde cuando se cuentan las ventas
+    pub(crate) fn desde(&self, ahora: DateTime<Utc>) -> DateTime<Utc> {
+        ahora.checked_sub_days(Days::new(u64::from(self.dias))).unwrap_or(ahora)
+    }
+}
+
+/// Un producto que se sugiere comprar
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct LineaSugerida {
+    pub(crate) codigo_producto: i64,
+    pub(crate) nombre_producto: String,
+    pub(crate) existencias: f64,
+    pub(crate) stock_minimo: Option<f64>,
+    pub(crate) stock_maximo: Option<f64>,
+    pub(crate) vendido: f64,
+    pub(crate) venta_diaria: f64,
+    pub(crate) cantidad: f64,
+    pub(crate) precio_compra: f64,
+    pub(crate) valor: f64,
+}
+
+/// Los productos que se sugiere comprar a un proveedor
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Sugerencia {
+    pub(crate) nit_proveedor: i64,
+    pub(crate) productos: Vec<LineaSugerida>,
+    pub(crate) valor_total: f64,
+}
+
+/// Calcula las compras sugeridas, agrupadas por proveedor. Un producto se sugiere si sus existencias
+/// estan por debajo de su minimo o no alcanzan para los dias de cobertura a su venta diaria; se compra
+/// hasta su maximo o, si no tiene, hasta cubrir su minimo y los dias de cobertura.
+/// Los productos por unidad se compran en cantidades enteras
+/// ### Parametros
+/// * `existencias` - Las existencias de los productos
+/// * `vendidos` - Lo vendido de cada producto en los ultimos `parametros.dias`
+/// * `parametros` - Los dias de ventas y de cobertura
+pub(crate) fn sugerir(existencias: &[Existencias], vendidos: &[Vendido], parametros: &ParametrosSugerencia) -> Vec<Sugerencia> {
+    let vendidos: HashMap<i64, f64> = vendidos.iter().filter_map(|v| Some((v.codigo_producto?, v.cantidad))).collect();
+    let mut proveedores: BTreeMap<i64, Vec<LineaSugerida>> = BTreeMap::new();
+    for producto in existencias {
+        let Some(nit_proveedor) = producto.nit_proveedor else { continue };
+        let vendido = vendidos.get(&producto.codigo_producto).copied().unwrap_or_default();
+        let venta_diaria = if parametros.dias == 0 { 0.0 } else { vendido / f64::from(parametros.dias) };
+        let demanda = venta_diaria * f64::from(parametros.cobertura);
+        let minimo = producto.stock_minimo.unwrap_or_default();
+        if producto.cantidad >= minimo && producto.cantidad >= demanda {
+            continue;
+        }
+        let objetivo = producto.stock_maximo.unwrap_or(minimo.max(demanda));
+        let mut cantidad = objetivo - producto.cantidad;
+        if producto.unidad_medida == UNIDAD {
+            cantidad = cantidad.ceil();
+        }
+        if cantidad <= 0.0 {
+            continue;
+        }
+        proveedores.entry(nit_proveedor).or_default().push(LineaSugerida {
+            codigo_producto: producto.codigo_producto,
+            nombre_producto: producto.nombre_producto.clone(),
+            existencias: producto.cantidad,
+            stock_minimo: producto.stock_minimo,
+            stock_maximo: producto.stock_maximo,
+            vendido,
+            venta_diaria,
+            cantidad,
+            precio_compra: producto.precio_compra,
+            valor: cantidad * producto.precio_compra,
+        });
+    }
+    proveedores
+        .into_iter()
+        .map(|(nit_proveedor, productos)| {
+            let valor_total = productos.iter().map(|p| p.valor).sum();
+            Sugerencia { nit_proveedor, productos, valor_total }
+        })
+        .collect()
+}
+
+/// Las sugerencias de compra actuales
+pub(crate) async fn sugerencias(state: &AppState, parametros: &ParametrosSugerencia) -> Result<Vec<Sugerencia>, HttpResponse> {
+    let existencias = state.repos.inventario.existencias(parametros.sucursal).await;
+    let vendidos = state.repos.inventario.vendido(parametros.desde(Utc::now()), parametros.sucursal).await;
+    match (existencias, vendidos) {
+        (Ok(existencias), Ok(vendidos)) => Ok(sugerir(&existencias, &vendidos, parametros)),
+        _ => Err(HttpResponse::InternalServerError().json("could not read inventario")),
+    }
+}
+
+/// Obtiene las existencias de todos los productos (sin los borrados), en toda la cadena
+/// o en una sucursal
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `filtro` - `?sucursal=2`, opcional
+#[get("/")]
+pub async fn read_all(state: Data<AppState>, filtro: Query<FiltroSucursal>) -> impl Responder {
+    match state.repos.inventario.existencias(filtro.sucursal).await {
+        Ok(existencias) => HttpResponse::Ok().json(existencias),
+        Err(_) => HttpResponse::InternalServerError().json("could not read inventario"),
+    }
+}
+
+/// Obtiene los productos cuyas existencias (en toda la cadena o en una sucursal) estan por debajo
+/// de su `stock_minimo`
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `filtro` - `?sucursal=2`, opcional
+#[get("/alertas")]
+pub async fn read_alertas(state: Data<AppState>, filtro: Query<FiltroSucursal>) -> impl Responder {
+    match state.repos.inventario.existencias(filtro.sucursal).await {
+        Ok(existencias) => {
+            let alertas: Vec<_> =
+                existencias.into_iter().filter(|e| e.stock_minimo.is_some_and(|minimo| e.cantidad < minimo)).collect();
+            HttpResponse::Ok().json(alertas)
+        }
+        Err(_) => HttpResponse::InternalServerError().json("could not read inventario"),
+    }
+}
+
+/// Obtiene las compras sugeridas por proveedor (ver `sugerir`).
+/// Cada sugerencia se puede convertir en una orden de compra con `POST /api/ordenes_compra/sugerida`
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `parametros` - `?dias=30&cobertura=15&sucursal=2`, todos opcionales
+#[get("/sugerencias")]
+pub async fn read_sugerencias(state: Data<AppState>, parametros: Query<ParametrosSugerencia>) -> impl Responder {
+    match sugerencias(&state, &parametros).await {
+        Ok(sugerencias) => HttpResponse::Ok().json(sugerencias),
+        Err(respuesta) => respuesta,
+    }
+}
+
+/// Obtiene los movimientos de inventario de un producto, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id del producto
+/// * `filtro` - `?sucursal=2`, opcional
+#[get("/{id}/movimientos")]
+pub async fn read_movimientos(state: Data<AppState>, path: Path<i64>, filtro: Query<FiltroSucursal>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.productos.obtener(id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json("producto not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read productos"),
+    }
+    match state.repos.inventario.movimientos(id, filtro.sucursal).await {
+        Ok(movimientos) => HttpResponse::Ok().json(movimientos),
+        Err(_) => HttpResponse::InternalServerError().json("could not read inventario"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn existencia(codigo: i64, cantidad: f64, minimo: Option<f64>, maximo: Option<f64>) -> Existencias {
+        Existencias {
+            codigo_producto: codigo,
+            nombre_producto: format!("producto {codigo}"),
+            nit_proveedor: Some(if codigo < 10 { 1 } else { 2 }),
+            unidad_medida: UNIDAD.to_owned(),
+            precio_compra: 10.0,
+            stock_minimo: minimo,
+            stock_maximo: maximo,
+            cantidad,
+        }
+    }
+
+    #[test]
+    fn se_sugiere_comprar_hasta_el_maximo_o_la_cobertura() {
+        let existencias = [
+            // bajo el minimo: hasta el maximo
+            existencia(1, 2.0, Some(5.0), Some(20.0)),
+            // sobre el minimo pero se vende rapido: 3 diarios por 15 dias
+            existencia(2, 6.0, Some(5.0), None),
+            // sin movimiento y sobre el minimo
+            existencia(3, 8.0, Some(5.0), Some(20.0)),
+            // bajo el minimo, sin maximo ni ventas: hasta el minimo
+            existencia(11, 0.5, Some(4.0), None),
+        ];
+        let vendidos = [Vendido { codigo_producto: Some(2), cantidad: 90.0 }];
+        let sugerencias = sugerir(&existencias, &vendidos, &ParametrosSugerencia::default());
+        assert_eq!(sugerencias.len(), 2);
+        let cantidades: Vec<_> = sugerencias[0].productos.iter().map(|p| (p.codigo_producto, p.cantidad)).collect();
+        assert_eq!(cantidades, [(1, 18.0), (2, 39.0)]);
+        assert_eq!(sugerencias[0].valor_total, 570.0);
+        assert_eq!((sugerencias[1].nit_proveedor, sugerencias[1].productos[0].cantidad), (2, 4.0));
+    }
+}
@@ -1,9 +1,23 @@
 //! Este paquete (o mejor dicho crate) contiene todas las entidades que 
 //! se conectan a la base de datos de Postgres.
 
+pub mod auditoria;
+pub mod cajas;
+pub mod categorias;
 pub mod clientes;
+pub mod codigos_barras;
+pub mod conteos;
 pub mod detalle_ventas;
+pub mod inventario;
+pub mod ordenes_compra;
+pub mod pagos;
+pub mod precios;
 pub mod productos;
+pub mod promociones;
+pub mod puntos;
 pub mod proveedores;
+pub mod sucursales;
+pub mod traslados;
+pub mod turnos;
 pub mod usuarios;
 pub mod ventas;
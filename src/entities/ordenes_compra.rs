@@ -0,0 +1,244 @@
+//!Este archivo representa el servicio REST de las ordenes de compra a los proveedores
+//!(tablas 'ordenes_compra' y 'detalle_ordenes_compra'). Una orden se crea a mano o a partir de
+//!la sugerencia de compra de su proveedor (ver `inventario::sugerir`), y al recibirla sus productos
+//!entran al inventario de la sucursal de la orden.
+
+use crate::{
+    auth::Actor,
+    entities::{
+        inventario::{self, ParametrosSugerencia},
+        sucursales,
+    },
+    filtros::{FiltroEstado, FiltroSucursal},
+    repos::ErrorRepo,
+    validacion,
+    AppState,
+};
+use actix_web::{
+    get, post,
+    web::{Data, Json, Path, Query},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use validator::Validate;
+
+/// Los estados de una orden de compra
+pub(crate) const PENDIENTE: &str = "pendiente";
+pub(crate) const RECIBIDA: &str = "recibida";
+pub(crate) const CANCELADA: &str = "cancelada";
+
+/// La tabla 'ordenes_compra' representada como un struct.
+/// El estado, el usuario y las fechas los asigna el servidor
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct OrdenesCompra {
+    pub(crate) codigo: Option<i64>,
+    pub(crate) nit_proveedor: Option<i64>,
+    pub(crate) codigo_sucursal: Option<i64>,
+    pub(crate) estado: String,
+    pub(crate) cedula_usuario: Option<i64>,
+    pub(crate) created_at: Option<DateTime<Utc>>,
+    pub(crate) recibida_at: Option<DateTime<Utc>>,
+}
+
+/// La tabla 'detalle_ordenes_compra' representada como un struct
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, Validate)]
+pub(crate) struct DetalleOrdenesCompra {
+    #[serde(skip_deserializing)]
+    pub(crate) codigo: Option<i64>,
+    #[serde(skip_deserializing)]
+    pub(crate) codigo_orden: Option<i64>,
+    #[validate(range(min = 1))]
+    pub(crate) codigo_producto: Option<i64>,
+    #[validate(range(min = 0.001))]
+    pub(crate) cantidad: f64,
+    #[validate(range(min = 0.0))]
+    pub(crate) precio_compra: f64,
+}
+
+/// El json del request para crear una orden de compra; sin `codigo_sucursal` la orden es
+/// de la sucursal principal
+#[derive(Debug, Deserialize, Validate)]
+pub(crate) struct NuevaOrden {
+    #[validate(range(min = 1))]
+    pub(crate) nit_proveedor: i64,
+    #[serde(default = "sucursales::principal")]
+    #[validate(range(min = 1))]
+    pub(crate) codigo_sucursal: Option<i64>,
+    #[validate(length(min = 1))]
+    #[validate]
+    pub(crate) detalles: Vec<DetalleOrdenesCompra>,
+}
+
+/// El json del request para crear una orden de compra a partir de una sugerencia; la sugerencia
+/// se calcula con las existencias y las ventas de la sucursal de la orden
+#[derive(Debug, Deserialize)]
+pub(crate) struct OrdenSugerida {
+    pub(crate) nit_proveedor: i64,
+    #[serde(default = "sucursales::principal")]
+    pub(crate) codigo_sucursal: Option<i64>,
+}
+
+/// Una orden de compra con sus detalles
+#[derive(Debug, Serialize)]
+struct OrdenCompleta {
+    #[serde(flatten)]
+    orden: OrdenesCompra,
+    detalles: Vec<DetalleOrdenesCompra>,
+    valor_total: f64,
+}
+
+/// Crea una orden de compra en una de las sucursales del usuario y responde con ella
+async fn crear(
+    state: &AppState,
+    actor: &Actor,
+    nit_proveedor: i64,
+    codigo_sucursal: i64,
+    detalles: &[DetalleOrdenesCompra],
+) -> HttpResponse {
+    if let Err(respuesta) = sucursales::autorizar(state, actor.cedula, codigo_sucursal).await {
+        return respuesta;
+    }
+    let orden = OrdenesCompra {
+        codigo: None,
+        nit_proveedor: Some(nit_proveedor),
+        codigo_sucursal: Some(codigo_sucursal),
+        estado: PENDIENTE.to_owned(),
+        cedula_usuario: actor.cedula,
+        created_at: None,
+        recibida_at: None,
+    };
+    match state.repos.ordenes_compra.insertar(actor, &orden, detalles).await {
+        Ok(codigo) => match completa(state, codigo).await {
+            Ok(orden) => HttpResponse::Created().json(orden),
+            Err(respuesta) => respuesta,
+        },
+        Err(ErrorRepo::LlaveForanea) => HttpResponse::BadRequest().json("the proveedor, the sucursal or a producto of the orden does not exist"),
+        Err(_) => HttpResponse::InternalServerError().json("could not create orden_compra"),
+    }
+}
+
+/// Una orden de compra con sus detalles y su valor total
+async fn completa(state: &AppState, codigo: i64) -> Result<OrdenCompleta, HttpResponse> {
+    let orden = match state.repos.ordenes_compra.obtener(codigo).await {
+        Ok(Some(orden)) => orden,
+        Ok(None) => return Err(HttpResponse::NotFound().json("orden_compra not found")),
+        Err(_) => return Err(HttpResponse::InternalServerError().json("could not read ordenes_compra")),
+    };
+    match state.repos.ordenes_compra.detalles(codigo).await {
+        Ok(detalles) => {
+            let valor_total = detalles.iter().map(|d| d.cantidad * d.precio_compra).sum();
+            Ok(OrdenCompleta { orden, detalles, valor_total })
+        }
+        Err(_) => Err(HttpResponse::InternalServerError().json("could not read ordenes_compra")),
+    }
+}
+
+/// Crea una orden de compra a un proveedor con sus detalles
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `nueva` - Un json en el body del request con el proveedor y los productos de la orden
+#[post("/")]
+pub async fn create(state: Data<AppState>, actor: Actor, nueva: Json<NuevaOrden>) -> impl Responder {
+    if let Err(errores) = nueva.validate() {
+        return validacion::rechazar(errores);
+    }
+    let codigo_sucursal = nueva.codigo_sucursal.unwrap_or(sucursales::PRINCIPAL);
+    crear(&state, &actor, nueva.nit_proveedor, codigo_sucursal, &nueva.detalles).await
+}
+
+/// Crea una orden de compra con los productos que se sugiere comprar a un proveedor
+/// (ver `inventario::read_sugerencias`). Si no hay nada que comprarle responde `409 Conflict`
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `parametros` - `?dias=30&cobertura=15`, ambos opcionales
+/// * `sugerida` - Un json en el body del request con el proveedor y la sucursal
+#[post("/sugerida")]
+pub async fn create_sugerida(
+    state: Data<AppState>,
+    actor: Actor,
+    parametros: Query<ParametrosSugerencia>,
+    sugerida: Json<OrdenSugerida>,
+) -> impl Responder {
+    let codigo_sucursal = sugerida.codigo_sucursal.unwrap_or(sucursales::PRINCIPAL);
+    let parametros = ParametrosSugerencia { sucursal: Some(codigo_sucursal), ..parametros.into_inner() };
+    let sugerencias = match inventario::sugerencias(&state, &parametros).await {
+        Ok(sugerencias) => sugerencias,
+        Err(respuesta) => return respuesta,
+    };
+    let Some(sugerencia) = sugerencias.into_iter().find(|s| s.nit_proveedor == sugerida.nit_proveedor) else {
+        return HttpResponse::Conflict().json("there is nothing to order from this proveedor");
+    };
+    let detalles: Vec<_> = sugerencia
+        .productos
+        .iter()
+        .map(|linea| DetalleOrdenesCompra {
+            codigo: None,
+            codigo_orden: None,
+            codigo_producto: Some(linea.codigo_producto),
+            cantidad: linea.cantidad,
+            precio_compra: linea.precio_compra,
+        })
+        .collect();
+    crear(&state, &actor, sugerida.nit_proveedor, codigo_sucursal, &detalles).await
+}
+
+/// Obtiene todas las ordenes de compra, de la mas reciente a la mas antigua
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `filtro` - `?estado=pendiente`, opcional
+/// * `sucursal` - `?sucursal=2`, opcional
+#[get("/")]
+pub async fn read_all(state: Data<AppState>, filtro: Query<FiltroEstado>, sucursal: Query<FiltroSucursal>) -> impl Responder {
+    match state.repos.ordenes_compra.listar(filtro.estado.as_deref(), sucursal.sucursal).await {
+        Ok(ordenes) => HttpResponse::Ok().json(ordenes),
+        Err(_) => HttpResponse::InternalServerError().json("could not read ordenes_compra"),
+    }
+}
+
+/// Obtiene una orden de compra con sus detalles, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id
+#[get("/{id}")]
+pub async fn read_by_id(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+    match completa(&state, path.into_inner()).await {
+        Ok(orden) => HttpResponse::Ok().json(orden),
+        Err(respuesta) => respuesta,
+    }
+}
+
+/// Recibe una orden de compra pendiente: sus productos entran al inventario de su sucursal
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+#[post("/{id}/recibir")]
+pub async fn receive(state: Data<AppState>, actor: Actor, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.ordenes_compra.recibir(&actor, id).await {
+        Ok(0) => HttpResponse::Conflict().json("orden_compra not found or not pendiente"),
+        Ok(_) => match completa(&state, id).await {
+            Ok(orden) => HttpResponse::Ok().json(orden),
+            Err(respuesta) => respuesta,
+        },
+        Err(_) => HttpResponse::InternalServerError().json("could not receive orden_compra"),
+    }
+}
+
+/// Cancela una orden de compra pendiente
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+#[post("/{id}/cancelar")]
+pub async fn cancel(state: Data<AppState>, actor: Actor, path: Path<i64>) -> impl Responder {
+    match state.repos.ordenes_compra.cancelar(&actor, path.into_inner()).await {
+        Ok(0) => HttpResponse::Conflict().json("orden_compra not found or not pendiente"),
+        Ok(_) => HttpResponse::Ok().json("Orden de compra cancelada"),
+        Err(_) => HttpResponse::InternalServerError().json("could not cancel orden_compra"),
+    }
+}
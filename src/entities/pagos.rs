@@ -0,0 +1,253 @@
+//!Este archivo representa el servicio REST de la tabla 'pagos'.
+//!Una venta se puede pagar con varios medios (pagos divididos): efectivo, tarjeta,
+//!transferencia o credito de la tienda (fiado). Los pagos se registran mientras la venta
+//!esta abierta y la venta solo se finaliza cuando suman su `total_venta` (ver `ventas::finalize`).
+//!Con el medio `puntos` el cliente de la venta paga con sus puntos de fidelidad (ver `puntos`).
+
+use crate::{auth::Actor, entities::puntos, validacion, AppState};
+use actix_web::{
+    delete, get, post,
+    web::{Data, Json, Path},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use validator::{Validate, ValidationError};
+
+/// Los medios de pago
+pub(crate) const EFECTIVO: &str = "efectivo";
+pub(crate) const TARJETA: &str = "tarjeta";
+pub(crate) const TRANSFERENCIA: &str = "transferencia";
+pub(crate) const CREDITO: &str = "credito";
+pub(crate) const PUNTOS: &str = "puntos";
+
+/// Valida el medio de un pago
+fn metodo_pago(metodo: &str) -> Result<(), ValidationError> {
+    if [EFECTIVO, TARJETA, TRANSFERENCIA, CREDITO, PUNTOS].contains(&metodo) {
+        Ok(())
+    } else {
+        let mut error = ValidationError::new("metodo");
+        error.message = Some("must be 'efectivo', 'tarjeta', 'transferencia', 'credito' or 'puntos'".into());
+        Err(error)
+    }
+}
+
+/// Redondea un valor de dinero a centavos
+pub(crate) fn centavos(valor: f64) -> f64 {
+    (valor * 100.0).round() / 100.0
+}
+
+/// La tabla 'pagos' representada como un struct.
+/// `valor` es la parte del total de la venta que cubre el pago. En efectivo, `recibido` es lo que
+/// entrega el cliente (por defecto el valor exacto) y el servidor calcula el `cambio`;
+/// con tarjeta o transferencia, `referencia` es el numero de aprobacion.
+/// El codigo, la venta y la fecha los asigna el servidor
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, Validate)]
+pub(crate) struct Pagos {
+    #[serde(skip_deserializing)]
+    pub(crate) codigo: Option<i64>,
+    #[serde(skip_deserializing)]
+    pub(crate) codigo_venta: Option<i64>,
+    #[validate(custom = "metodo_pago")]
+    pub(crate) metodo: String,
+    #[validate(range(min = 0.01))]
+    pub(crate) valor: f64,
+    #[validate(range(min = 0.0))]
+    pub(crate) recibido: Option<f64>,
+    #[serde(skip_deserializing)]
+    pub(crate) cambio: Option<f64>,
+    #[validate(length(min = 1, max = 40))]
+    pub(crate) referencia: Option<String>,
+    #[serde(skip_deserializing)]
+    pub(crate) fecha: Option<DateTime<Utc>>,
+}
+
+impl Pagos {
+    /// Calcula el cambio de un pago en efectivo. Falla si el efectivo recibido no alcanza
+    /// o si se envia `recibido` con otro medio de pago
+    fn con_cambio(self) -> Result<Pagos, &'static str> {
+        if self.metodo != EFECTIVO {
+            return match self.recibido {
+                Some(_) => Err("recibido only applies to efectivo payments"),
+                None => Ok(self),
+            };
+        }
+        let recibido = self.recibido.unwrap_or(self.valor);
+        if centavos(recibido) < centavos(self.valor) {
+            return Err("recibido must cover the valor of the payment");
+        }
+        Ok(Pagos { recibido: Some(recibido), cambio: Some(centavos(recibido - self.valor)), ..self })
+    }
+}
+
+/// El total de una venta frente a lo pagado
+#[derive(Debug, Serialize)]
+pub(crate) struct Saldo {
+    pub(crate) total_venta: f64,
+    pub(crate) pagado: f64,
+    pub(crate) pendiente: f64,
+}
+
+impl Saldo {
+    pub(crate) fn new(total_venta: f64, pagos: &[Pagos]) -> Self {
+        let pagado = centavos(pagos.iter().map(|p| p.valor).sum());
+        Saldo { total_venta, pagado, pendiente: centavos(total_venta - pagado) }
+    }
+}
+
+/// Los pagos de una venta junto con su saldo
+#[derive(Debug, Serialize)]
+struct PagosVenta {
+    #[serde(flatten)]
+    saldo: Saldo,
+    finalizada: bool,
+    pagos: Vec<Pagos>,
+}
+
+/// Obtiene la venta y sus pagos, o una respuesta de error si la venta no existe
+async fn pagos_venta(state: &AppState, id: i64) -> Result<PagosVenta, HttpResponse> {
+    let venta = match state.repos.ventas.obtener(id).await {
+        Ok(Some(venta)) => venta,
+        Ok(None) => return Err(HttpResponse::NotFound().json("venta not found")),
+        Err(_) => return Err(HttpResponse::InternalServerError().json("could not read ventas")),
+    };
+    match state.repos.pagos.por_venta(id).await {
+        Ok(pagos) => Ok(PagosVenta {
+            saldo: Saldo::new(venta.total_venta, &pagos),
+            finalizada: venta.finalizada_at.is_some(),
+            pagos,
+        }),
+        Err(_) => Err(HttpResponse::InternalServerError().json("could not read pagos")),
+    }
+}
+
+/// Verifica que el cliente de una venta tenga los puntos para un pago con puntos,
+/// o retorna una respuesta de error
+async fn puntos_pago(state: &AppState, id: i64, pago: &Pagos) -> Result<(), HttpResponse> {
+    let cedula = match state.repos.ventas.obtener(id).await {
+        Ok(Some(venta)) => venta.cedula_cliente,
+        Ok(None) => return Err(HttpResponse::NotFound().json("venta not found")),
+        Err(_) => return Err(HttpResponse::InternalServerError().json("could not read ventas")),
+    };
+    let Some(cedula) = cedula else {
+        return Err(HttpResponse::BadRequest().json("the venta has no cliente to redeem puntos from"));
+    };
+    let saldo = puntos::estado(state, cedula).await?.saldo;
+    let necesarios = state.puntos.para_pagar(pago.valor);
+    if necesarios > saldo {
+        return Err(HttpResponse::Conflict().json(format!("not enough puntos: {necesarios} needed, {saldo} available")));
+    }
+    Ok(())
+}
+
+/// Registra un pago de una venta abierta, sin exceder lo que falta por pagar.
+/// Responde con el pago, que incluye el cambio si es en efectivo.
+/// Un pago con puntos redime los puntos del cliente de la venta, si le alcanzan, en la misma transaccion
+/// que registra el pago
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id de la venta
+/// * `pago` - Un json en el body del request representando el pago
+#[post("/{id}/pagos")]
+pub async fn create(state: Data<AppState>, actor: Actor, path: Path<i64>, pago: Json<Pagos>) -> impl Responder {
+    if let Err(errores) = pago.validate() {
+        return validacion::rechazar(errores);
+    }
+    let pago = match pago.into_inner().con_cambio() {
+        Ok(pago) => pago,
+        Err(mensaje) => return HttpResponse::BadRequest().json(mensaje),
+    };
+    let id = path.into_inner();
+    match state.repos.pagos.insertar(&actor, id, &pago, &state.puntos).await {
+        Ok(Some(codigo)) => {
+            let creado = state.repos.pagos.por_venta(id).await.ok().and_then(|pagos| {
+                pagos.into_iter().find(|p| p.codigo == Some(codigo))
+            });
+            match creado {
+                Some(pago) => HttpResponse::Created().json(pago),
+                None => HttpResponse::InternalServerError().json("could not read pagos"),
+            }
+        }
+        Ok(None) => {
+            let venta = match pagos_venta(&state, id).await {
+                Ok(venta) => venta,
+                Err(respuesta) => return respuesta,
+            };
+            if venta.finalizada {
+                return HttpResponse::Conflict().json("venta is already finalized");
+            }
+            if pago.metodo == PUNTOS {
+                if let Err(respuesta) = puntos_pago(&state, id, &pago).await {
+                    return respuesta;
+                }
+            }
+            HttpResponse::Conflict().json(format!("payment exceeds the pending balance of {}", venta.saldo.pendiente))
+        }
+        Err(_) => HttpResponse::InternalServerError().json("could not create pago"),
+    }
+}
+
+/// Obtiene los pagos de una venta, con el total pagado y lo que falta por pagar
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id de la venta
+#[get("/{id}/pagos")]
+pub async fn read_all(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+    match pagos_venta(&state, path.into_inner()).await {
+        Ok(venta) => HttpResponse::Ok().json(venta),
+        Err(respuesta) => respuesta,
+    }
+}
+
+/// Borra un pago de una venta que aun no se ha finalizado
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id de la venta y la del pago
+#[delete("/{id}/pagos/{pago}")]
+pub async fn delete(state: Data<AppState>, actor: Actor, path: Path<(i64, i64)>) -> impl Responder {
+    let (id, pago) = path.into_inner();
+    match state.repos.pagos.borrar(&actor, id, pago).await {
+        Ok(0) => match pagos_venta(&state, id).await {
+            Ok(venta) if venta.finalizada && venta.pagos.iter().any(|p| p.codigo == Some(pago)) => {
+                HttpResponse::Conflict().json("venta is already finalized")
+            }
+            Ok(_) => HttpResponse::NotFound().json("pago not found"),
+            Err(respuesta) => respuesta,
+        },
+        Ok(_) => HttpResponse::Ok().json("Pago deleted"),
+        Err(_) => HttpResponse::InternalServerError().json("could not delete pago"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pago(metodo: &str, valor: f64, recibido: Option<f64>) -> Pagos {
+        Pagos {
+            codigo: None,
+            codigo_venta: None,
+            metodo: metodo.to_owned(),
+            valor,
+            recibido,
+            cambio: None,
+            referencia: None,
+            fecha: None,
+        }
+    }
+
+    #[test]
+    fn el_cambio_se_calcula_solo_en_efectivo() {
+        let efectivo = pago(EFECTIVO, 17_850.5, Some(20_000.0)).con_cambio().unwrap();
+        assert_eq!(efectivo.cambio, Some(2_149.5));
+        let exacto = pago(EFECTIVO, 100.0, None).con_cambio().unwrap();
+        assert_eq!((exacto.recibido, exacto.cambio), (Some(100.0), Some(0.0)));
+        assert!(pago(EFECTIVO, 100.0, Some(99.99)).con_cambio().is_err());
+
+        assert_eq!(pago(TARJETA, 100.0, None).con_cambio().unwrap().cambio, None);
+        assert!(pago(TRANSFERENCIA, 100.0, Some(100.0)).con_cambio().is_err());
+    }
+}
@@ -0,0 +1,167 @@
+//!Este archivo representa el servicio REST del historial de precios de los productos
+//!(tablas 'precios_historial' y 'precios_programados'). Cada cambio de `precio_compra` o
+//!`precio_venta` queda en el historial: al crear, importar o actualizar el producto y al aplicar
+//!un cambio programado. Los cambios programados los aplica el servidor cuando llega su fecha
+//!(ver `tareas`).
+
+use crate::{auth::Actor, repos::ErrorRepo, validacion, AppState, Repos};
+use actix_web::{
+    delete, get, post,
+    web::{Data, Json, Path},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use validator::{Validate, ValidationError};
+
+/// El origen de un cambio de precio en el historial
+pub(crate) const CREACION: &str = "creacion";
+pub(crate) const IMPORTACION: &str = "importacion";
+pub(crate) const ACTUALIZACION: &str = "actualizacion";
+pub(crate) const PROGRAMADO: &str = "programado";
+
+/// La tabla 'precios_historial' representada como un struct.
+/// Cada fila son los precios de un producto desde su `fecha` y el usuario que los cambio
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct PreciosHistorial {
+    pub(crate) codigo: Option<i64>,
+    pub(crate) codigo_producto: i64,
+    pub(crate) precio_compra: f64,
+    pub(crate) precio_venta: f64,
+    pub(crate) origen: String,
+    pub(crate) cedula_usuario: Option<i64>,
+    pub(crate) fecha: Option<DateTime<Utc>>,
+}
+
+/// Valida que la fecha de un cambio programado sea futura
+fn fecha_futura(fecha: &DateTime<Utc>) -> Result<(), ValidationError> {
+    if *fecha > Utc::now() {
+        Ok(())
+    } else {
+        let mut error = ValidationError::new("fecha");
+        error.message = Some("must be in the future".into());
+        Err(error)
+    }
+}
+
+/// La tabla 'precios_programados' representada como un struct.
+/// Los precios que no se envian no cambian. El codigo, el producto, el usuario
+/// y las fechas de creacion y aplicacion los asigna el servidor
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, Validate)]
+pub(crate) struct PreciosProgramados {
+    #[serde(skip_deserializing)]
+    pub(crate) codigo: Option<i64>,
+    #[serde(skip_deserializing)]
+    pub(crate) codigo_producto: Option<i64>,
+    #[validate(range(min = 0.0))]
+    pub(crate) precio_compra: Option<f64>,
+    #[validate(range(min = 0.0))]
+    pub(crate) precio_venta: Option<f64>,
+    #[validate(custom = "fecha_futura")]
+    pub(crate) fecha: DateTime<Utc>,
+    #[serde(skip_deserializing)]
+    pub(crate) cedula_usuario: Option<i64>,
+    #[serde(skip_deserializing)]
+    pub(crate) aplicado_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) created_at: Option<DateTime<Utc>>,
+}
+
+/// Los precios de un producto a lo largo del tiempo: los actuales, el historial
+/// y los cambios programados que aun no se aplican
+#[derive(Debug, Serialize)]
+struct LineaDeTiempo {
+    codigo_producto: i64,
+    precio_compra: f64,
+    precio_venta: f64,
+    historial: Vec<PreciosHistorial>,
+    programados: Vec<PreciosProgramados>,
+}
+
+/// Aplica los cambios de precio programados cuya fecha ya paso y retorna cuantos aplico
+/// ### Parametros
+/// * `repos` - Los repositorios de la base de datos
+pub(crate) async fn aplicar_programados(repos: &Repos) -> Result<u64, ErrorRepo> {
+    let aplicados = repos.precios.aplicar(Utc::now()).await?;
+    if aplicados > 0 {
+        tracing::info!(aplicados, "scheduled price changes applied");
+    }
+    Ok(aplicados)
+}
+
+/// Obtiene la linea de tiempo de los precios de un producto, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id del producto
+#[get("/{id}/precios")]
+pub async fn read_historial(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    let producto = match state.repos.productos.obtener(id).await {
+        Ok(Some(producto)) => producto,
+        Ok(None) => return HttpResponse::NotFound().json("producto not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read productos"),
+    };
+    let historial = state.repos.precios.historial(id).await;
+    let programados = state.repos.precios.programados(id).await;
+    match (historial, programados) {
+        (Ok(historial), Ok(programados)) => HttpResponse::Ok().json(LineaDeTiempo {
+            codigo_producto: id,
+            precio_compra: producto.precio_compra,
+            precio_venta: producto.precio_venta,
+            historial,
+            programados,
+        }),
+        _ => HttpResponse::InternalServerError().json("could not read precios"),
+    }
+}
+
+/// Programa un cambio de precio de un producto, que se aplica automaticamente en la fecha dada
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id del producto
+/// * `programado` - Un json en el body del request con los nuevos precios y la fecha
+#[post("/{id}/precios/programados")]
+pub async fn create_programado(
+    state: Data<AppState>,
+    actor: Actor,
+    path: Path<i64>,
+    programado: Json<PreciosProgramados>,
+) -> impl Responder {
+    if let Err(errores) = programado.validate() {
+        return validacion::rechazar(errores);
+    }
+    if programado.precio_compra.is_none() && programado.precio_venta.is_none() {
+        return HttpResponse::BadRequest().json("precio_compra or precio_venta is required");
+    }
+    let id = path.into_inner();
+    match state.repos.precios.programar(&actor, id, &programado).await {
+        Ok(Some(codigo)) => {
+            let creado = state.repos.precios.programados(id).await.ok().and_then(|programados| {
+                programados.into_iter().find(|p| p.codigo == Some(codigo))
+            });
+            match creado {
+                Some(programado) => HttpResponse::Created().json(programado),
+                None => HttpResponse::InternalServerError().json("could not read precios"),
+            }
+        }
+        Ok(None) | Err(ErrorRepo::LlaveForanea) => HttpResponse::NotFound().json("producto not found"),
+        Err(_) => HttpResponse::InternalServerError().json("could not schedule precio"),
+    }
+}
+
+/// Cancela un cambio de precio programado que aun no se ha aplicado
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id del producto y la del cambio programado
+#[delete("/{id}/precios/programados/{programado}")]
+pub async fn delete_programado(state: Data<AppState>, actor: Actor, path: Path<(i64, i64)>) -> impl Responder {
+    let (id, programado) = path.into_inner();
+    match state.repos.precios.cancelar(&actor, id, programado).await {
+        Ok(0) => HttpResponse::NotFound().json("pending scheduled precio not found"),
+        Ok(_) => HttpResponse::Ok().json("Scheduled precio deleted"),
+        Err(_) => HttpResponse::InternalServerError().json("could not delete scheduled precio"),
+    }
+}
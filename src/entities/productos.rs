@@ -1,42 +1,220 @@
 //!Este archivo representa el servicio REST de la tabla 'productos'.
 //!Contiene todas las operaciones CRUD relacionadas.
 
-use crate::AppState;
+use crate::{
+    auth::Actor,
+    borrado::Borrado,
+    filtros::{Borrados, FiltroCategoria},
+    entities::codigos_barras::{self, CodigosBarras},
+    metricas,
+    repos::{ErrorRepo, ProductosRepo},
+    validacion,
+    AppState,
+};
 use actix_multipart::Multipart;
 use actix_web::{
     delete, get, patch, post,
-    web::{Data, Json, Path},
+    web::{Data, Json, Path, Query},
     HttpResponse, Responder,
 };
 use csv::ReaderBuilder;
 use futures_util::stream::StreamExt;
-use std::io::{Seek, Write};
+use std::io::{Read, Seek, Write};
 use tempfile::NamedTempFile;
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+/// Las unidades de medida de los productos
+pub(crate) const UNIDAD: &str = "unidad";
+pub(crate) const KG: &str = "kg";
+pub(crate) const LITRO: &str = "litro";
+
+fn unidad() -> String {
+    UNIDAD.to_owned()
+}
+
+/// Valida la unidad de medida de un producto
+fn unidad_medida(unidad: &str) -> Result<(), ValidationError> {
+    if [UNIDAD, KG, LITRO].contains(&unidad) {
+        Ok(())
+    } else {
+        let mut error = ValidationError::new("unidad_medida");
+        error.message = Some("must be 'unidad', 'kg' or 'litro'".into());
+        Err(error)
+    }
+}
+
+/// Valida que el stock maximo de un producto no sea menor que su stock minimo
+fn umbrales(producto: &Productos) -> Result<(), ValidationError> {
+    match (producto.stock_minimo, producto.stock_maximo) {
+        (Some(minimo), Some(maximo)) if maximo < minimo => {
+            let mut error = ValidationError::new("stock_maximo");
+            error.message = Some("stock_maximo must not be lower than stock_minimo".into());
+            Err(error)
+        }
+        _ => Ok(()),
+    }
+}
 
 /// La tabla 'productos' representada como un struct.
 /// El codigo y el nit son opcionales debido a la operacion de `update`
-#[derive(Debug, FromRow, Serialize, Deserialize)]
-struct Productos {
-    codigo: Option<i64>,
-    nit_proveedor: Option<i64>,
-    iva_compra: f64,
-    nombre_producto: String,
-    precio_compra: f64,
-    precio_venta: f64,
+/// La categoria es opcional y la unidad de medida es `unidad` si no se envia;
+/// los productos por unidad solo se venden en cantidades enteras
+/// Los umbrales de existencias (`stock_minimo` y `stock_maximo`) son opcionales (ver `inventario`)
+/// Los campos se validan antes de ejecutar cualquier consulta.
+/// Las fechas son asignadas por el servidor y se ignoran en el json del request
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "umbrales"))]
+pub(crate) struct Productos {
+    #[validate(range(min = 1))]
+    pub(crate) codigo: Option<i64>,
+    #[validate(range(min = 1))]
+    pub(crate) nit_proveedor: Option<i64>,
+    #[validate(range(min = 0.0))]
+    pub(crate) iva_compra: f64,
+    #[validate(length(min = 1, max = 40))]
+    pub(crate) nombre_producto: String,
+    #[validate(range(min = 0.0))]
+    pub(crate) precio_compra: f64,
+    #[validate(range(min = 0.0))]
+    pub(crate) precio_venta: f64,
+    #[serde(default)]
+    #[validate(range(min = 1))]
+    pub(crate) codigo_categoria: Option<i64>,
+    #[serde(default = "unidad")]
+    #[validate(custom = "unidad_medida")]
+    pub(crate) unidad_medida: String,
+    #[serde(default)]
+    #[validate(range(min = 0.0))]
+    pub(crate) stock_minimo: Option<f64>,
+    #[serde(default)]
+    #[validate(range(min = 0.0))]
+    pub(crate) stock_maximo: Option<f64>,
+    #[serde(skip_deserializing)]
+    pub(crate) created_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) updated_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) deleted_at: Option<DateTime<Utc>>,
 }
 
-/// Crea un nuevo producto o nuevos productos y lo envia a la base de datos.
-/// A diferencia de los otros servicios REST, este recibe un archivo CSV en binario,
-/// lee su contenido y genera una nueva transaccion hacia la tabla 'productos'
-/// (El servicio falla si el proveedor de tal producto no existe en la base de datos)
+impl Productos {
+    /// Verifica que el producto se pueda vender en una cantidad
+    pub(crate) fn cantidad_valida(&self, cantidad: f64) -> Result<(), String> {
+        if self.unidad_medida == UNIDAD && cantidad.fract() != 0.0 {
+            return Err(format!("producto {} is sold by unidad: cantidad_producto must be a whole number", self.codigo.unwrap_or_default()));
+        }
+        Ok(())
+    }
+}
+
+/// Los motivos por los que puede fallar una importacion de productos
+#[derive(Debug)]
+pub(crate) enum ErrorImportacion {
+    /// El archivo no es un CSV valido
+    Csv(csv::Error),
+    /// Una fila no cumple las validaciones de `Productos`
+    Invalida { linea: u64, errores: ValidationErrors },
+    /// La base de datos rechazo una fila (por ejemplo, su proveedor no existe)
+    Db(ErrorRepo),
+}
+
+impl std::fmt::Display for ErrorImportacion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorImportacion::Csv(e) => write!(f, "{e}"),
+            ErrorImportacion::Invalida { linea, errores } => {
+                write!(f, "linea {linea}: {:?}", validacion::por_campo(errores))
+            }
+            ErrorImportacion::Db(e) => write!(f, "Este csv contiene proveedores que no existen, o datos incorrectos. Reason: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ErrorImportacion {}
+
+impl From<ErrorRepo> for ErrorImportacion {
+    fn from(e: ErrorRepo) -> Self {
+        ErrorImportacion::Db(e)
+    }
+}
+
+/// Importa los productos de un CSV (con encabezados) en una sola transaccion:
+/// si alguna fila falla no se inserta ninguna. Las columnas se leen por el nombre de su encabezado
+/// (los de los campos de `Productos`) y las filas que no se pueden leer como producto se ignoran.
+/// La columna opcional `codigo_barras` tiene los codigos de barras del producto, separados por `|`.
+/// Todas las filas se validan antes de tocar la base de datos y
+/// cada producto importado queda registrado en la auditoria.
+/// Retorna la cantidad de productos importados
 /// ### Parametros
-/// * `state` - La coneccion a la base de datos
-/// * `producto` - Un json en el body del request representando el producto
-#[post("/")]
-pub async fn create(state: Data<AppState>, mut payload: Multipart) -> impl Responder {
+/// * `repo` - El repositorio de productos
+/// * `actor` - El usuario que realiza la importacion
+/// * `archivo` - El contenido del CSV
+#[tracing::instrument(skip_all, fields(filas, ignoradas), err(Display))]
+pub(crate) async fn importar_csv(
+    repo: &dyn ProductosRepo,
+    actor: &Actor,
+    archivo: impl Read,
+) -> Result<u64, ErrorImportacion> {
+    let resultado = match leer_csv(archivo) {
+        Ok((productos, codigos)) => repo.importar(actor, &productos, &codigos).await.map_err(ErrorImportacion::from),
+        Err(e) => Err(e),
+    };
+    match resultado {
+        Ok(importados) => {
+            metricas::registrar_importacion(importados);
+            tracing::info!(importados, "csv imported");
+        }
+        Err(_) => metricas::registrar_importacion_fallida(),
+    }
+    resultado
+}
+
+/// Lee y valida los productos de un CSV y sus codigos de barras (ya normalizados), sin tocar la base de datos
+fn leer_csv(archivo: impl Read) -> Result<(Vec<Productos>, Vec<CodigosBarras>), ErrorImportacion> {
+    let mut csv_reader = ReaderBuilder::new()
+        .has_headers(true)
+        .from_reader(archivo);
+    let headers = csv_reader.headers().map_err(ErrorImportacion::Csv)?.clone();
+    let columna_codigos = headers.iter().position(|header| header == "codigo_barras");
+
+    let mut productos = Vec::new();
+    let mut codigos = Vec::new();
+    let mut ignoradas = 0;
+    for result in csv_reader.records() {
+        let record = result.map_err(ErrorImportacion::Csv)?;
+        let Ok(producto) = record.deserialize::<Productos>(Some(&headers)) else {
+            ignoradas += 1;
+            continue;
+        };
+        let linea = record.position().map_or(0, |p| p.line());
+        if let Err(errores) = producto.validate() {
+            return Err(ErrorImportacion::Invalida { linea, errores });
+        }
+        let leidos = columna_codigos.and_then(|columna| record.get(columna)).unwrap_or_default();
+        for codigo_barras in leidos.split('|').map(str::trim).filter(|codigo| !codigo.is_empty()) {
+            let codigo_barras = codigos_barras::normalizar(codigo_barras).map_err(|error| {
+                let mut errores = ValidationErrors::new();
+                errores.add("codigo_barras", error);
+                ErrorImportacion::Invalida { linea, errores }
+            })?;
+            codigos.push(CodigosBarras { codigo: None, codigo_barras, codigo_producto: producto.codigo, created_at: None });
+        }
+        productos.push(producto);
+    }
+    let span = tracing::Span::current();
+    span.record("filas", productos.len());
+    span.record("ignoradas", ignoradas);
+    Ok((productos, codigos))
+}
+
+/// Copia los archivos de un formulario multipart a un archivo temporal, listo para leerse desde el inicio
+/// ### Parametros
+/// * `payload` - El formulario del request
+pub(crate) async fn archivo_temporal(mut payload: Multipart) -> Result<NamedTempFile, HttpResponse> {
     let mut temp_file = NamedTempFile::new().expect("some temp file");
     while let Some(item) = payload.next().await {
         if let Ok(mut field) = item {
@@ -45,74 +223,69 @@ pub async fn create(state: Data<AppState>, mut payload: Multipart) -> impl Respo
                 temp_file.write_all(&data).expect("could not write to temp file");
             }
         } else {
-            return HttpResponse::InternalServerError().body("incomplete form");
-        }  
+            return Err(HttpResponse::InternalServerError().body("incomplete form"));
+        }
     }
-   
     temp_file.as_file_mut().seek(std::io::SeekFrom::Start(0)).unwrap();
-    let mut csv_reader = ReaderBuilder::new()
-        .has_headers(true) 
-        .from_reader(temp_file);
+    Ok(temp_file)
+}
 
-    let transaction = state.db.begin().await.expect("failed connection");
-    for result in csv_reader.records() {
-        match result {
-            Ok(record) => {
-                if let Ok(producto) = record.deserialize::<Productos>(None) {
-                    if let Err(e) = sqlx::query_as::<_, Productos>(
-                        "INSERT INTO productos VALUES ($1, $2, $3, $4, $5, $6);",
-                    )
-                    .bind(producto.codigo)
-                    .bind(producto.nit_proveedor)
-                    .bind(producto.iva_compra)
-                    .bind(producto.nombre_producto.as_str())
-                    .bind(producto.precio_compra)
-                    .bind(producto.precio_venta)
-                    .fetch_optional(&state.db)
-                    .await
-                    {
-                        transaction.rollback().await.expect("failed connection");
-                        return HttpResponse::InternalServerError().body(format!("Este csv contiene proveedores que no existen, o datos incorrectos. Reason: {e}"));
-                    }
-                }
-            }
-            Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
-        }
+/// Crea un nuevo producto o nuevos productos y lo envia a la base de datos.
+/// A diferencia de los otros servicios REST, este recibe un archivo CSV en binario,
+/// lee su contenido y genera una nueva transaccion hacia la tabla 'productos'
+/// (El servicio falla si el proveedor de tal producto no existe en la base de datos).
+/// Cada fila se valida antes de insertarla (una fila invalida cancela toda la importacion)
+/// y cada producto importado queda registrado en la auditoria
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `producto` - Un json en el body del request representando el producto
+#[post("/")]
+pub async fn create(state: Data<AppState>, actor: Actor, payload: Multipart) -> impl Responder {
+    let temp_file = match archivo_temporal(payload).await {
+        Ok(temp_file) => temp_file,
+        Err(respuesta) => return respuesta,
+    };
+    match importar_csv(state.repos.productos.as_ref(), &actor, temp_file).await {
+        Ok(_) => HttpResponse::Ok().body("CSV file processed and data inserted into the table"),
+        Err(ErrorImportacion::Csv(e)) => HttpResponse::InternalServerError().body(e.to_string()),
+        Err(ErrorImportacion::Invalida { linea, errores }) => HttpResponse::BadRequest().json(serde_json::json!({
+            "linea": linea,
+            "errores": validacion::por_campo(&errores),
+        })),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
-    transaction.commit().await.expect("failed connection");
-    HttpResponse::Ok().body("CSV file processed and data inserted into the table")
 }
 
 /// Crea un nuevo producto y lo envia a la base de datos.
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `producto` - Un json en el body del request representando el producto
 #[post("/json")]
-pub async fn create_json(state: Data<AppState>, producto: Json<Productos>) -> impl Responder {
-    match sqlx::query_as::<_, Productos>("insert into productos values ($1,$2,$3,$4,$5,$6);")
-        .bind(producto.codigo)
-        .bind(producto.nit_proveedor)
-        .bind(producto.iva_compra)
-        .bind(producto.nombre_producto.as_str())
-        .bind(producto.precio_compra)
-        .bind(producto.precio_venta)
-        .fetch_optional(&state.db)
-        .await
-    {
+pub async fn create_json(state: Data<AppState>, actor: Actor, producto: Json<Productos>) -> impl Responder {
+    if let Err(errores) = producto.validate() {
+        return validacion::rechazar(errores);
+    }
+    match state.repos.productos.insertar(&actor, &producto).await {
         Ok(_) => HttpResponse::Created().json("Producto creado"),
+        Err(ErrorRepo::LlaveForanea) => {
+            HttpResponse::BadRequest().json("the proveedor or categoria of the producto does not exist")
+        }
         Err(_) => HttpResponse::InternalServerError().json("could not create producto"),
     }
 }
 
 /// Obtiene todos los productos de la base de datos
+/// (sin los productos borrados, a menos que se pida `?incluir_borrados=true`).
+/// Con `?categoria=` solo los de esa categoria y sus subcategorias
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `borrados` - si se incluyen los productos borrados
+/// * `filtro` - la categoria de los productos
 #[get("/")]
-pub async fn read_all(state: Data<AppState>) -> impl Responder {
-    match sqlx::query_as::<_, Productos>("select * from productos;")
-        .fetch_all(&state.db)
-        .await
-    {
+pub async fn read_all(state: Data<AppState>, borrados: Query<Borrados>, filtro: Query<FiltroCategoria>) -> impl Responder {
+    match state.repos.productos.listar(borrados.incluir(), filtro.categoria).await {
         Ok(productos) => HttpResponse::Ok().json(productos),
         Err(_) => HttpResponse::NotFound().json("productos not found"),
     }
@@ -125,55 +298,72 @@ pub async fn read_all(state: Data<AppState>) -> impl Responder {
 #[get("/{id}")]
 pub async fn read_by_id(state: Data<AppState>, path: Path<i64>) -> impl Responder {
     let id = path.into_inner();
-    match sqlx::query_as::<_, Productos>("select * from productos where codigo = $1;")
-        .bind(id)
-        .fetch_one(&state.db)
-        .await
-    {
-        Ok(producto) => HttpResponse::Ok().json(producto),
-        Err(_) => HttpResponse::NotFound().json("producto not found"),
+    match state.repos.productos.obtener(id).await {
+        Ok(Some(producto)) => HttpResponse::Ok().json(producto),
+        Ok(None) => HttpResponse::NotFound().json("producto not found"),
+        Err(_) => HttpResponse::InternalServerError().json("could not read productos"),
     }
 }
 
 /// Actualiza un producto de la base de datos, por medio de la id en la uri
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `path` - la uri relativa a la api, esto es la id
 /// * `producto` - Un json en el body del request representando el producto a actualizar
 #[patch("/{id}")]
 pub async fn update(
     state: Data<AppState>,
+    actor: Actor,
     path: Path<i64>,
     producto: Json<Productos>,
 ) -> impl Responder {
+    if let Err(errores) = producto.validate() {
+        return validacion::rechazar(errores);
+    }
     let id = path.into_inner();
-    match sqlx::query_as::<_, Productos>("update productos set iva_compra = $1, nombre_producto = $2, precio_compra = $3, precio_venta = $4 where codigo = $5;")
-        .bind(producto.iva_compra)
-        .bind(producto.nombre_producto.as_str())
-        .bind(producto.precio_compra)
-        .bind(producto.precio_venta)
-        .bind(id)
-        .fetch_optional(&state.db)
-        .await
-    {
+    match state.repos.productos.actualizar(&actor, id, &producto).await {
         Ok(_) => HttpResponse::Ok().json("Producto updated"),
+        Err(ErrorRepo::LlaveForanea) => HttpResponse::BadRequest().json("the categoria of the producto does not exist"),
         Err(_) => HttpResponse::InternalServerError().json("could not update producto")
     }
 }
 
-/// Borra un producto de la base de datos, por medio de la id en la uri
+/// Borra un producto de la base de datos de forma logica (`deleted_at`), por medio de la id en la uri.
+/// Con `?dry_run` no borra nada y responde con las filas dependientes que se verian afectadas
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `path` - la uri relativa a la api, esto es la id
+/// * `borrado` - si el borrado es simulado
 #[delete("/{id}")]
-pub async fn delete(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+pub async fn delete(state: Data<AppState>, actor: Actor, path: Path<i64>, borrado: Query<Borrado>) -> impl Responder {
     let id = path.into_inner();
-    match sqlx::query_as::<_, Productos>("delete from productos where codigo = $1;")
-        .bind(id)
-        .fetch_optional(&state.db)
-        .await
-    {
+    if borrado.es_simulacro() {
+        return match state.repos.productos.informe_borrado(id).await {
+            Ok(Some(informe)) => HttpResponse::Ok().json(informe),
+            Ok(None) => HttpResponse::NotFound().json("producto not found"),
+            Err(_) => HttpResponse::InternalServerError().json("could not delete producto"),
+        };
+    }
+    match state.repos.productos.borrar(&actor, id).await {
+        Ok(0) => HttpResponse::NotFound().json("producto not found"),
         Ok(_) => HttpResponse::Ok().json("Producto deleted"),
         Err(_) => HttpResponse::InternalServerError().json("could not delete producto"),
     }
 }
+
+/// Restaura un producto borrado de forma logica, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+#[post("/{id}/restaurar")]
+pub async fn restore(state: Data<AppState>, actor: Actor, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.productos.restaurar(&actor, id).await {
+        Ok(0) => HttpResponse::NotFound().json("deleted producto not found"),
+        Ok(_) => HttpResponse::Ok().json("Producto restored"),
+        Err(_) => HttpResponse::InternalServerError().json("could not restore producto"),
+    }
+}
@@ -0,0 +1,451 @@
+//!Este archivo representa el servicio REST de la tabla 'promociones'.
+//!Contiene las operaciones CRUD relacionadas y el calculo de los detalles de una venta
+//!con las promociones vigentes (ver `ventas::calculate`): a cada linea se le aplica
+//!la promocion que le da el mayor descuento, y el detalle guarda cual fue.
+
+use std::collections::HashMap;
+
+use crate::{
+    auth::Actor,
+    borrado::Borrado,
+    entities::{pagos::centavos, productos::Productos},
+    filtros::Borrados,
+    repos::ErrorRepo,
+    validacion,
+    AppState,
+};
+use actix_web::{
+    delete, get, patch, post,
+    web::{Data, Json, Path, Query},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use validator::{Validate, ValidationError};
+
+/// Los tipos de promocion
+pub(crate) const PORCENTAJE: &str = "porcentaje";
+pub(crate) const VALOR: &str = "valor";
+pub(crate) const LLEVE_PAGUE: &str = "lleve_pague";
+
+/// Valida el tipo de una promocion
+fn tipo_promocion(tipo: &str) -> Result<(), ValidationError> {
+    if [PORCENTAJE, VALOR, LLEVE_PAGUE].contains(&tipo) {
+        Ok(())
+    } else {
+        let mut error = ValidationError::new("tipo");
+        error.message = Some("must be 'porcentaje', 'valor' or 'lleve_pague'".into());
+        Err(error)
+    }
+}
+
+/// La tabla 'promociones' representada como un struct.
+/// Segun el `tipo`, `valor` es el porcentaje de descuento (`porcentaje`) o el descuento por unidad
+/// (`valor`); con `lleve_pague` el cliente lleva `lleve` unidades y paga solo `pague`.
+/// El producto, el proveedor, el cliente y las fechas (`desde` y `hasta`, inclusivas) son opcionales
+/// y limitan a que lineas se aplica la promocion; sin ninguno se aplica a todas.
+/// El codigo es opcional debido a la operacion de `update`
+/// Las fechas de creacion son asignadas por el servidor y se ignoran en el json del request
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, Validate)]
+pub(crate) struct Promociones {
+    #[validate(range(min = 1))]
+    pub(crate) codigo: Option<i64>,
+    #[validate(length(min = 1, max = 40))]
+    pub(crate) nombre: String,
+    #[validate(custom = "tipo_promocion")]
+    pub(crate) tipo: String,
+    #[serde(default)]
+    #[validate(range(min = 0.0))]
+    pub(crate) valor: f64,
+    #[validate(range(min = 2))]
+    pub(crate) lleve: Option<i32>,
+    #[validate(range(min = 1))]
+    pub(crate) pague: Option<i32>,
+    #[validate(range(min = 1))]
+    pub(crate) codigo_producto: Option<i64>,
+    #[validate(range(min = 1))]
+    pub(crate) nit_proveedor: Option<i64>,
+    #[validate(range(min = 1))]
+    pub(crate) cedula_cliente: Option<i64>,
+    pub(crate) desde: Option<NaiveDate>,
+    pub(crate) hasta: Option<NaiveDate>,
+    #[serde(skip_deserializing)]
+    pub(crate) created_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) updated_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) deleted_at: Option<DateTime<Utc>>,
+}
+
+impl Promociones {
+    /// Las reglas que dependen de varios campos
+    fn consistente(&self) -> Result<(), &'static str> {
+        match self.tipo.as_str() {
+            PORCENTAJE if self.valor > 100.0 => return Err("a porcentaje promocion must be at most 100"),
+            PORCENTAJE | VALOR if self.valor <= 0.0 => return Err("valor must be greater than 0"),
+            LLEVE_PAGUE => match (self.lleve, self.pague) {
+                (Some(lleve), Some(pague)) if pague < lleve => {}
+                _ => return Err("a lleve_pague promocion needs pague lower than lleve"),
+            },
+            _ => {}
+        }
+        match (self.desde, self.hasta) {
+            (Some(desde), Some(hasta)) if hasta < desde => Err("hasta must not be before desde"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Si la promocion se aplica a un producto vendido a un cliente en una fecha
+    fn aplica(&self, producto: &Productos, cedula_cliente: Option<i64>, fecha: NaiveDate) -> bool {
+        self.deleted_at.is_none()
+            && self.desde.is_none_or(|desde| desde <= fecha)
+            && self.hasta.is_none_or(|hasta| fecha <= hasta)
+            && self.codigo_producto.is_none_or(|codigo| producto.codigo == Some(codigo))
+            && self.nit_proveedor.is_none_or(|nit| producto.nit_proveedor == Some(nit))
+            && self.cedula_cliente.is_none_or(|cedula| cedula_cliente == Some(cedula))
+    }
+
+    /// El descuento (antes de IVA) de una linea con la cantidad y el precio unitario dados
+    fn descuento(&self, precio: f64, cantidad: f64) -> f64 {
+        let descuento = match self.tipo.as_str() {
+            PORCENTAJE => precio * cantidad * self.valor / 100.0,
+            VALOR => self.valor.min(precio) * cantidad,
+            LLEVE_PAGUE => match (self.lleve, self.pague) {
+                (Some(lleve), Some(pague)) if lleve > 0 => {
+                    (cantidad / f64::from(lleve)).floor() * f64::from(lleve - pague) * precio
+                }
+                _ => 0.0,
+            },
+            _ => 0.0,
+        };
+        centavos(descuento)
+    }
+
+    /// Verifica que el descuento de una linea sea el que da la promocion al producto, la cantidad y el cliente
+    /// en la fecha, como lo calcula `/api/ventas/calcular`
+    pub(crate) fn descuento_valido(
+        &self,
+        producto: &Productos,
+        cantidad: f64,
+        cedula_cliente: Option<i64>,
+        descuento: f64,
+        fecha: NaiveDate,
+    ) -> Result<(), String> {
+        let codigo = self.codigo.unwrap_or_default();
+        let esperado = self.descuento(producto.precio_venta, cantidad);
+        if !self.aplica(producto, cedula_cliente, fecha) || esperado <= 0.0 {
+            return Err(format!("promocion {codigo} does not apply to this line"));
+        }
+        if (centavos(descuento) - esperado).abs() >= 0.01 {
+            return Err(format!("descuento must be {esperado} for promocion {codigo}"));
+        }
+        Ok(())
+    }
+}
+
+/// Un producto y la cantidad que se quiere vender
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct Item {
+    #[validate(range(min = 1))]
+    codigo_producto: i64,
+    #[validate(range(min = 0.001))]
+    cantidad_producto: f64,
+}
+
+/// El json del request para calcular una venta
+#[derive(Debug, Deserialize, Validate)]
+pub struct Calculo {
+    #[validate(range(min = 1))]
+    pub(crate) cedula_cliente: Option<i64>,
+    #[validate(length(min = 1))]
+    #[validate]
+    pub(crate) detalles: Vec<Item>,
+}
+
+/// Una linea calculada, con los mismos campos de `detalle_ventas`
+#[derive(Debug, Serialize)]
+pub(crate) struct Linea {
+    codigo_producto: i64,
+    cantidad_producto: f64,
+    precio_unitario: f64,
+    descuento: f64,
+    codigo_promocion: Option<i64>,
+    valor_venta: f64,
+    valor_iva: f64,
+    valor_total: f64,
+}
+
+/// Los detalles calculados de una venta y sus totales, con los mismos campos de `ventas`
+#[derive(Debug, Serialize)]
+pub(crate) struct VentaCalculada {
+    detalles: Vec<Linea>,
+    descuento: f64,
+    valor_venta: f64,
+    iva_venta: f64,
+    total_venta: f64,
+}
+
+/// Calcula una linea: el valor de venta es el precio por la cantidad menos el descuento
+/// de la mejor promocion que aplique, y el IVA se calcula sobre ese valor
+fn linea(producto: &Productos, cantidad: f64, promociones: &[Promociones], calculo: &Calculo, fecha: NaiveDate) -> Linea {
+    let precio = producto.precio_venta;
+    let mejor = promociones
+        .iter()
+        .filter(|p| p.aplica(producto, calculo.cedula_cliente, fecha))
+        .map(|p| (p.descuento(precio, cantidad), p.codigo))
+        .filter(|(descuento, _)| *descuento > 0.0)
+        .max_by(|a, b| a.0.total_cmp(&b.0));
+    let (descuento, codigo_promocion) = mejor.unwrap_or((0.0, None));
+    let valor_venta = centavos(precio * cantidad - descuento);
+    let valor_iva = centavos(valor_venta * producto.iva_compra / 100.0);
+    Linea {
+        codigo_producto: producto.codigo.unwrap_or_default(),
+        cantidad_producto: cantidad,
+        precio_unitario: precio,
+        descuento,
+        codigo_promocion,
+        valor_venta,
+        valor_iva,
+        valor_total: centavos(valor_venta + valor_iva),
+    }
+}
+
+/// Calcula los detalles y los totales de una venta con las promociones vigentes en la fecha.
+/// `productos` debe incluir todos los productos del calculo
+pub(crate) fn calcular(
+    calculo: &Calculo,
+    productos: &HashMap<i64, Productos>,
+    promociones: &[Promociones],
+    fecha: NaiveDate,
+) -> VentaCalculada {
+    let detalles: Vec<Linea> = calculo
+        .detalles
+        .iter()
+        .filter_map(|item| {
+            let producto = productos.get(&item.codigo_producto)?;
+            Some(linea(producto, item.cantidad_producto, promociones, calculo, fecha))
+        })
+        .collect();
+    let suma = |campo: fn(&Linea) -> f64| centavos(detalles.iter().map(campo).sum());
+    VentaCalculada {
+        descuento: suma(|l| l.descuento),
+        valor_venta: suma(|l| l.valor_venta),
+        iva_venta: suma(|l| l.valor_iva),
+        total_venta: suma(|l| l.valor_total),
+        detalles,
+    }
+}
+
+impl Calculo {
+    /// Los codigos de los productos del calculo
+    pub(crate) fn productos(&self) -> impl Iterator<Item = i64> + '_ {
+        self.detalles.iter().map(|item| item.codigo_producto)
+    }
+
+    /// Verifica que cada producto se pueda vender en la cantidad pedida.
+    /// `productos` debe incluir todos los productos del calculo
+    pub(crate) fn cantidades_validas(&self, productos: &HashMap<i64, Productos>) -> Result<(), String> {
+        self.detalles.iter().try_for_each(|item| match productos.get(&item.codigo_producto) {
+            Some(producto) => producto.cantidad_valida(item.cantidad_producto),
+            None => Ok(()),
+        })
+    }
+}
+
+/// Crea una nueva promocion y la envia a la base de datos
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `promocion` - Un json en el body del request representando la promocion
+#[post("/")]
+pub async fn create(state: Data<AppState>, actor: Actor, promocion: Json<Promociones>) -> impl Responder {
+    if let Err(errores) = promocion.validate() {
+        return validacion::rechazar(errores);
+    }
+    if let Err(mensaje) = promocion.consistente() {
+        return HttpResponse::BadRequest().json(mensaje);
+    }
+    match state.repos.promociones.insertar(&actor, &promocion).await {
+        Ok(_) => HttpResponse::Created().json("Promocion creada"),
+        Err(ErrorRepo::LlaveForanea) => {
+            HttpResponse::BadRequest().json("the producto, proveedor or cliente of the promocion does not exist")
+        }
+        Err(_) => HttpResponse::InternalServerError().json("could not create promocion"),
+    }
+}
+
+/// Obtiene todas las promociones de la base de datos
+/// (sin las promociones borradas, a menos que se pida `?incluir_borrados=true`)
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `borrados` - si se incluyen las promociones borradas
+#[get("/")]
+pub async fn read_all(state: Data<AppState>, borrados: Query<Borrados>) -> impl Responder {
+    match state.repos.promociones.listar(borrados.incluir()).await {
+        Ok(promociones) => HttpResponse::Ok().json(promociones),
+        Err(_) => HttpResponse::NotFound().json("promociones not found"),
+    }
+}
+
+/// Obtiene una promocion de la base de datos, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id
+#[get("/{id}")]
+pub async fn read_by_id(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.promociones.obtener(id).await {
+        Ok(Some(promocion)) => HttpResponse::Ok().json(promocion),
+        Ok(None) => HttpResponse::NotFound().json("promocion not found"),
+        Err(_) => HttpResponse::InternalServerError().json("could not read promociones"),
+    }
+}
+
+/// Actualiza una promocion de la base de datos, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+/// * `promocion` - Un json en el body del request representando la promocion a actualizar
+#[patch("/{id}")]
+pub async fn update(
+    state: Data<AppState>,
+    actor: Actor,
+    path: Path<i64>,
+    promocion: Json<Promociones>,
+) -> impl Responder {
+    if let Err(errores) = promocion.validate() {
+        return validacion::rechazar(errores);
+    }
+    if let Err(mensaje) = promocion.consistente() {
+        return HttpResponse::BadRequest().json(mensaje);
+    }
+    let id = path.into_inner();
+    match state.repos.promociones.actualizar(&actor, id, &promocion).await {
+        Ok(0) => HttpResponse::NotFound().json("promocion not found"),
+        Ok(_) => HttpResponse::Ok().json("Promocion updated"),
+        Err(ErrorRepo::LlaveForanea) => {
+            HttpResponse::BadRequest().json("the producto, proveedor or cliente of the promocion does not exist")
+        }
+        Err(_) => HttpResponse::InternalServerError().json("could not update promocion"),
+    }
+}
+
+/// Borra una promocion de la base de datos de forma logica (`deleted_at`), por medio de la id en la uri.
+/// Los detalles de venta en los que se aplico siguen apuntando a ella.
+/// Con `?dry_run` no borra nada y responde con las filas dependientes que se verian afectadas
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+/// * `borrado` - si el borrado es simulado
+#[delete("/{id}")]
+pub async fn delete(state: Data<AppState>, actor: Actor, path: Path<i64>, borrado: Query<Borrado>) -> impl Responder {
+    let id = path.into_inner();
+    if borrado.es_simulacro() {
+        return match state.repos.promociones.informe_borrado(id).await {
+            Ok(Some(informe)) => HttpResponse::Ok().json(informe),
+            Ok(None) => HttpResponse::NotFound().json("promocion not found"),
+            Err(_) => HttpResponse::InternalServerError().json("could not delete promocion"),
+        };
+    }
+    match state.repos.promociones.borrar(&actor, id).await {
+        Ok(0) => HttpResponse::NotFound().json("promocion not found"),
+        Ok(_) => HttpResponse::Ok().json("Promocion deleted"),
+        Err(_) => HttpResponse::InternalServerError().json("could not delete promocion"),
+    }
+}
+
+/// Restaura una promocion borrada de forma logica, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+#[post("/{id}/restaurar")]
+pub async fn restore(state: Data<AppState>, actor: Actor, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.promociones.restaurar(&actor, id).await {
+        Ok(0) => HttpResponse::NotFound().json("deleted promocion not found"),
+        Ok(_) => HttpResponse::Ok().json("Promocion restored"),
+        Err(_) => HttpResponse::InternalServerError().json("could not restore promocion"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::DeserializeOwned;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn desde_json<T: DeserializeOwned>(valor: Value) -> T {
+        serde_json::from_value(valor).unwrap()
+    }
+
+    fn fecha(texto: &str) -> NaiveDate {
+        texto.parse().unwrap()
+    }
+
+    fn productos() -> HashMap<i64, Productos> {
+        [(1, 10, 1000.0), (2, 20, 500.0)]
+            .into_iter()
+            .map(|(codigo, nit, precio): (i64, i64, f64)| {
+                let producto = desde_json(json!({
+                    "codigo": codigo, "nit_proveedor": nit, "iva_compra": 19.0,
+                    "nombre_producto": "Producto", "precio_compra": 1.0, "precio_venta": precio,
+                }));
+                (codigo, producto)
+            })
+            .collect()
+    }
+
+    fn calculo(cedula_cliente: Option<i64>) -> Calculo {
+        desde_json(json!({
+            "cedula_cliente": cedula_cliente,
+            "detalles": [
+                { "codigo_producto": 1, "cantidad_producto": 3 },
+                { "codigo_producto": 2, "cantidad_producto": 2 },
+            ],
+        }))
+    }
+
+    #[test]
+    fn cada_linea_usa_la_mejor_promocion_que_aplica() {
+        let promociones: Vec<Promociones> = vec![
+            // 10% en los productos del proveedor 10, solo en enero
+            desde_json(json!({
+                "codigo": 1, "nombre": "Enero", "tipo": "porcentaje", "valor": 10.0,
+                "nit_proveedor": 10, "desde": "2024-01-01", "hasta": "2024-01-31",
+            })),
+            // lleve 3 pague 2 en el producto 1
+            desde_json(json!({ "codigo": 2, "nombre": "3x2", "tipo": "lleve_pague", "lleve": 3, "pague": 2, "codigo_producto": 1 })),
+            // 100 pesos menos por unidad para el cliente 7
+            desde_json(json!({ "codigo": 3, "nombre": "Cliente", "tipo": "valor", "valor": 100.0, "cedula_cliente": 7 })),
+        ];
+
+        let venta = calcular(&calculo(None), &productos(), &promociones, fecha("2024-01-15"));
+        assert_eq!(venta.detalles[0].codigo_promocion, Some(2));
+        assert_eq!(venta.detalles[0].descuento, 1000.0);
+        assert_eq!(venta.detalles[0].valor_venta, 2000.0);
+        assert_eq!(venta.detalles[0].valor_total, 2380.0);
+        assert_eq!(venta.detalles[1].codigo_promocion, None);
+        assert_eq!(venta.total_venta, 3570.0);
+
+        let venta = calcular(&calculo(Some(7)), &productos(), &promociones, fecha("2024-02-01"));
+        assert_eq!(venta.detalles[0].codigo_promocion, Some(2));
+        assert_eq!(venta.detalles[1].codigo_promocion, Some(3));
+        assert_eq!(venta.detalles[1].valor_venta, 800.0);
+        assert_eq!(venta.descuento, 1200.0);
+    }
+
+    #[test]
+    fn las_reglas_dependen_del_tipo() {
+        let promocion = |valor: Value| desde_json::<Promociones>(valor).consistente();
+        assert!(promocion(json!({ "nombre": "x", "tipo": "porcentaje", "valor": 150.0 })).is_err());
+        assert!(promocion(json!({ "nombre": "x", "tipo": "valor" })).is_err());
+        assert!(promocion(json!({ "nombre": "x", "tipo": "lleve_pague", "lleve": 2, "pague": 2 })).is_err());
+        assert!(promocion(json!({ "nombre": "x", "tipo": "lleve_pague", "lleve": 2, "pague": 1 })).is_ok());
+        assert!(promocion(json!({ "nombre": "x", "tipo": "valor", "valor": 5.0, "desde": "2024-02-01", "hasta": "2024-01-01" })).is_err());
+    }
+}
@@ -1,57 +1,71 @@
 //!Este archivo representa el servicio REST de la tabla 'proveedores'.
 //!Contiene todas las operaciones CRUD relacionadas.
 
-use crate::AppState;
+use crate::{
+    auth::Actor,
+    borrado::Borrado,
+    filtros::{Borrados, Paginacion},
+    validacion,
+    AppState,
+};
 use actix_web::{
     delete, get, patch, post,
-    web::{Data, Json, Path},
+    web::{Data, Json, Path, Query},
     HttpResponse, Responder,
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use validator::Validate;
 
 /// La tabla 'proveedores' representada como un struct.
 /// NIT es opcional debido a la operacion de `update`
-#[derive(Debug, FromRow, Serialize, Deserialize)]
-struct Proveedores {
-    nit: Option<i64>,
-    ciudad: String,
-    direccion: String,
-    nombre: String,
-    telefono: String,
+/// Los campos se validan antes de ejecutar cualquier consulta.
+/// Las fechas son asignadas por el servidor y se ignoran en el json del request
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, Validate)]
+pub(crate) struct Proveedores {
+    #[validate(range(min = 1))]
+    pub(crate) nit: Option<i64>,
+    #[validate(length(min = 1, max = 40))]
+    pub(crate) ciudad: String,
+    #[validate(length(min = 1, max = 40))]
+    pub(crate) direccion: String,
+    #[validate(length(min = 1, max = 40))]
+    pub(crate) nombre: String,
+    #[validate(length(max = 40), custom = "validacion::telefono")]
+    pub(crate) telefono: String,
+    #[serde(skip_deserializing)]
+    pub(crate) created_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) updated_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) deleted_at: Option<DateTime<Utc>>,
 }
 
 /// Crea un nuevo proveedor y lo envia a la base de datos
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `proveedor` - Un json en el body del request representando el proveedor
 #[post("/")]
-pub async fn create(state: Data<AppState>, proveedor: Json<Proveedores>) -> impl Responder {
-    match sqlx::query_as!(Proveedores, 
-        "insert into proveedores values ($1,$2,$3,$4,$5);",
-        proveedor.nit,
-        proveedor.ciudad.as_str(),
-        proveedor.direccion.as_str(),
-        proveedor.nombre.as_str(),
-        proveedor.telefono.as_str()
-    )
-        .execute(&state.db)
-        .await
-    {
+pub async fn create(state: Data<AppState>, actor: Actor, proveedor: Json<Proveedores>) -> impl Responder {
+    if let Err(errores) = proveedor.validate() {
+        return validacion::rechazar(errores);
+    }
+    match state.repos.proveedores.insertar(&actor, &proveedor).await {
         Ok(_) => HttpResponse::Created().json("Proveedor creado"),
         Err(_) => HttpResponse::InternalServerError().json("could not create proveedor"),
     }
 }
 
 /// Obtiene todos los proveedores de la base de datos
+/// (sin los proveedores borrados, a menos que se pida `?incluir_borrados=true`)
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `borrados` - si se incluyen los proveedores borrados
 #[get("/")]
-pub async fn read_all(state: Data<AppState>) -> impl Responder {
-    match sqlx::query_as!(Proveedores, "select * from proveedores;")
-        .fetch_all(&state.db)
-        .await
-    {
+pub async fn read_all(state: Data<AppState>, borrados: Query<Borrados>) -> impl Responder {
+    match state.repos.proveedores.listar(borrados.incluir()).await {
         Ok(proveedores) => HttpResponse::Ok().json(proveedores),
         Err(_) => HttpResponse::NotFound().json("proveedores not found"),
     }
@@ -64,51 +78,91 @@ pub async fn read_all(state: Data<AppState>) -> impl Responder {
 #[get("/{id}")]
 pub async fn read_by_id(state: Data<AppState>, path: Path<i64>) -> impl Responder {
     let id = path.into_inner();
-    match sqlx::query_as!(Proveedores, "select * from proveedores where nit = $1;", id)
-        .fetch_one(&state.db)
-        .await
-    {
-        Ok(proveedor) => HttpResponse::Ok().json(proveedor),
-        Err(_) => HttpResponse::NotFound().json("proveedor not found"),
+    match state.repos.proveedores.obtener(id).await {
+        Ok(Some(proveedor)) => HttpResponse::Ok().json(proveedor),
+        Ok(None) => HttpResponse::NotFound().json("proveedor not found"),
+        Err(_) => HttpResponse::InternalServerError().json("could not read proveedores"),
+    }
+}
+
+/// Obtiene los productos que suministra un proveedor, por medio de la id en la uri, de forma paginada
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id
+/// * `paginacion` - `?pagina=N&por_pagina=M`
+/// * `borrados` - si se incluyen los productos borrados
+#[get("/{id}/productos")]
+pub async fn read_productos(
+    state: Data<AppState>,
+    path: Path<i64>,
+    paginacion: Query<Paginacion>,
+    borrados: Query<Borrados>,
+) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.proveedores.obtener(id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json("proveedor not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read proveedores"),
+    }
+    match state.repos.productos.por_proveedor(id, &paginacion, borrados.incluir()).await {
+        Ok(productos) => HttpResponse::Ok().json(productos),
+        Err(_) => HttpResponse::NotFound().json("productos not found"),
     }
 }
 
 /// Actualiza un proveedor de la base de datos, por medio de la id en la uri
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `path` - la uri relativa a la api, esto es la id
 /// * `proveedor` - Un json en el body del request representando el proveedor a actualizar
 #[patch("/{id}")]
-pub async fn update(state: Data<AppState>, path: Path<i64>, proveedor: Json<Proveedores>) -> impl Responder {
+pub async fn update(state: Data<AppState>, actor: Actor, path: Path<i64>, proveedor: Json<Proveedores>) -> impl Responder {
+    if let Err(errores) = proveedor.validate() {
+        return validacion::rechazar(errores);
+    }
     let id = path.into_inner();
-    match sqlx::query_as!(Proveedores, 
-        "update proveedores set ciudad = $1, direccion = $2, nombre = $3, telefono = $4 where nit = $5;",
-        proveedor.ciudad.as_str(),
-        proveedor.direccion.as_str(),
-        proveedor.nombre.as_str(),
-        proveedor.telefono.as_str(),
-        id
-    )
-        .execute(&state.db)
-        .await
-    {
+    match state.repos.proveedores.actualizar(&actor, id, &proveedor).await {
         Ok(_) => HttpResponse::Ok().json("Proveedor updated"),
         Err(_) => HttpResponse::InternalServerError().json("could not update proveedor")
     }
 }
 
-/// Borra un proveedor de la base de datos, por medio de la id en la uri
+/// Borra un proveedor de la base de datos de forma logica (`deleted_at`), por medio de la id en la uri.
+/// Con `?dry_run` no borra nada y responde con las filas dependientes que se verian afectadas
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `path` - la uri relativa a la api, esto es la id
+/// * `borrado` - si el borrado es simulado
 #[delete("/{id}")]
-pub async fn delete(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+pub async fn delete(state: Data<AppState>, actor: Actor, path: Path<i64>, borrado: Query<Borrado>) -> impl Responder {
     let id = path.into_inner();
-    match sqlx::query_as!(Proveedores, "delete from proveedores where nit = $1;", id)
-        .execute(&state.db)
-        .await
-    {
+    if borrado.es_simulacro() {
+        return match state.repos.proveedores.informe_borrado(id).await {
+            Ok(Some(informe)) => HttpResponse::Ok().json(informe),
+            Ok(None) => HttpResponse::NotFound().json("proveedor not found"),
+            Err(_) => HttpResponse::InternalServerError().json("could not delete proveedor"),
+        };
+    }
+    match state.repos.proveedores.borrar(&actor, id).await {
+        Ok(0) => HttpResponse::NotFound().json("proveedor not found"),
         Ok(_) => HttpResponse::Ok().json("Proveedor deleted"),
         Err(_) => HttpResponse::InternalServerError().json("could not delete proveedor"),
     }
 }
+
+/// Restaura un proveedor borrado de forma logica, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+#[post("/{id}/restaurar")]
+pub async fn restore(state: Data<AppState>, actor: Actor, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.proveedores.restaurar(&actor, id).await {
+        Ok(0) => HttpResponse::NotFound().json("deleted proveedor not found"),
+        Ok(_) => HttpResponse::Ok().json("Proveedor restored"),
+        Err(_) => HttpResponse::InternalServerError().json("could not restore proveedor"),
+    }
+}
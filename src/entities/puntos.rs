@@ -0,0 +1,267 @@
+//!Este archivo representa el servicio REST de los puntos de fidelidad de los clientes
+//!(tabla 'movimientos_puntos'). Al finalizar una venta con cliente se le acumulan puntos segun el
+//!`total_venta` menos lo pagado con puntos (ver `ventas::finalize`), que vencen despues de un periodo configurable, y los puntos
+//!se redimen como medio de pago (`pagos::PUNTOS`). El saldo se calcula del historial: cada redencion
+//!consume primero los puntos que vencen antes, y los que no se redimen a tiempo se vencen.
+
+use crate::{entities::pagos::centavos, repos::variable, AppState};
+use actix_web::{
+    get,
+    web::{Data, Path},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+
+/// La configuracion de los puntos. Se toma de las variables de entorno `PUNTOS_PESOS_POR_PUNTO`,
+/// `PUNTOS_VALOR` y `PUNTOS_VIGENCIA_DIAS`; las que no esten definidas usan el valor predeterminado
+#[derive(Debug, Clone)]
+pub struct ConfigPuntos {
+    /// Cuantos pesos del `total_venta` (sin lo pagado con puntos) dan un punto
+    pub pesos_por_punto: f64,
+    /// Cuantos pesos vale un punto al redimirlo
+    pub valor_punto: f64,
+    /// Los dias que duran los puntos acumulados; con 0 no se vencen
+    pub vigencia_dias: u64,
+}
+
+impl Default for ConfigPuntos {
+    fn default() -> Self {
+        ConfigPuntos { pesos_por_punto: 1000.0, valor_punto: 10.0, vigencia_dias: 365 }
+    }
+}
+
+impl ConfigPuntos {
+    /// Lee la configuracion de las variables de entorno
+    pub fn desde_entorno() -> Result<Self, String> {
+        let predeterminada = ConfigPuntos::default();
+        let config = ConfigPuntos {
+            pesos_por_punto: variable("PUNTOS_PESOS_POR_PUNTO")?.unwrap_or(predeterminada.pesos_por_punto),
+            valor_punto: variable("PUNTOS_VALOR")?.unwrap_or(predeterminada.valor_punto),
+            vigencia_dias: variable("PUNTOS_VIGENCIA_DIAS")?.unwrap_or(predeterminada.vigencia_dias),
+        };
+        if config.pesos_por_punto <= 0.0 || config.valor_punto <= 0.0 {
+            return Err("PUNTOS_PESOS_POR_PUNTO and PUNTOS_VALOR must be greater than 0".to_owned());
+        }
+        Ok(config)
+    }
+
+    /// Los puntos que se acumulan por una venta
+    pub(crate) fn acumulados(&self, total_venta: f64) -> i64 {
+        (total_venta / self.pesos_por_punto).floor() as i64
+    }
+
+    /// Los puntos que se necesitan para pagar un valor (redondeados hacia arriba)
+    pub(crate) fn para_pagar(&self, valor: f64) -> i64 {
+        centavos(valor / self.valor_punto).ceil() as i64
+    }
+
+    /// La fecha en que vencen los puntos acumulados en una fecha
+    pub(crate) fn vencimiento(&self, fecha: NaiveDate) -> Option<NaiveDate> {
+        match self.vigencia_dias {
+            0 => None,
+            dias => fecha.checked_add_days(Days::new(dias)),
+        }
+    }
+}
+
+/// La tabla 'movimientos_puntos' representada como un struct.
+/// Los puntos acumulados por una venta son positivos y tienen su fecha de vencimiento (inclusiva);
+/// los redimidos con un pago son negativos
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct MovimientosPuntos {
+    pub(crate) codigo: Option<i64>,
+    pub(crate) cedula_cliente: Option<i64>,
+    pub(crate) codigo_venta: Option<i64>,
+    pub(crate) codigo_pago: Option<i64>,
+    pub(crate) puntos: i64,
+    pub(crate) vence: Option<NaiveDate>,
+    pub(crate) fecha: Option<DateTime<Utc>>,
+}
+
+/// Los tipos de entrada del historial de puntos
+const ACUMULACION: &str = "acumulacion";
+const REDENCION: &str = "redencion";
+const VENCIMIENTO: &str = "vencimiento";
+
+/// Una entrada del historial de puntos, con el saldo despues de ella
+#[derive(Debug, Serialize)]
+struct Entrada {
+    tipo: &'static str,
+    puntos: i64,
+    saldo: i64,
+    codigo_venta: Option<i64>,
+    codigo_pago: Option<i64>,
+    vence: Option<NaiveDate>,
+    fecha: Option<DateTime<Utc>>,
+}
+
+/// Los puntos de una venta que aun no se han redimido ni vencido
+#[derive(Debug, Serialize)]
+struct Lote {
+    puntos: i64,
+    vence: Option<NaiveDate>,
+}
+
+/// El saldo de puntos de un cliente y su historial, incluidos los vencimientos
+#[derive(Debug, Serialize)]
+pub(crate) struct EstadoPuntos {
+    cedula_cliente: i64,
+    pub(crate) saldo: i64,
+    valor_saldo: f64,
+    proximo_vencimiento: Option<Lote>,
+    historial: Vec<Entrada>,
+}
+
+/// El momento en que se vencen los puntos que vencen en una fecha (al terminar ese dia)
+fn vencidos_en(vence: NaiveDate) -> Option<DateTime<Utc>> {
+    vence.checked_add_days(Days::new(1))?.and_hms_opt(0, 0, 0).map(|fecha| fecha.and_utc())
+}
+
+/// Vence los lotes cuya fecha de vencimiento es anterior a la fecha dada
+fn vencer(lotes: &mut Vec<Lote>, historial: &mut Vec<Entrada>, saldo: &mut i64, fecha: NaiveDate) {
+    lotes.retain(|lote| match lote.vence {
+        Some(vence) if vence < fecha => {
+            *saldo -= lote.puntos;
+            historial.push(Entrada {
+                tipo: VENCIMIENTO,
+                puntos: -lote.puntos,
+                saldo: *saldo,
+                codigo_venta: None,
+                codigo_pago: None,
+                vence: Some(vence),
+                fecha: vencidos_en(vence),
+            });
+            false
+        }
+        _ => true,
+    });
+}
+
+impl EstadoPuntos {
+    /// Calcula el saldo de un cliente a una fecha a partir de sus movimientos, en orden cronologico
+    pub(crate) fn new(cedula_cliente: i64, movimientos: &[MovimientosPuntos], hoy: NaiveDate, config: &ConfigPuntos) -> Self {
+        let mut lotes: Vec<Lote> = Vec::new();
+        let mut historial = Vec::new();
+        let mut saldo = 0;
+        for movimiento in movimientos {
+            let fecha = movimiento.fecha.map_or(hoy, |fecha| fecha.date_naive());
+            vencer(&mut lotes, &mut historial, &mut saldo, fecha);
+            if movimiento.puntos >= 0 {
+                // los lotes quedan ordenados por vencimiento, los que no vencen al final
+                let posicion = lotes.partition_point(|lote| match (lote.vence, movimiento.vence) {
+                    (Some(vence), Some(nuevo)) => vence <= nuevo,
+                    (_, None) => true,
+                    (None, Some(_)) => false,
+                });
+                lotes.insert(posicion, Lote { puntos: movimiento.puntos, vence: movimiento.vence });
+            } else {
+                let mut pendientes = -movimiento.puntos;
+                for lote in lotes.iter_mut() {
+                    let consumidos = lote.puntos.min(pendientes);
+                    lote.puntos -= consumidos;
+                    pendientes -= consumidos;
+                }
+                lotes.retain(|lote| lote.puntos > 0);
+            }
+            saldo += movimiento.puntos;
+            historial.push(Entrada {
+                tipo: if movimiento.puntos >= 0 { ACUMULACION } else { REDENCION },
+                puntos: movimiento.puntos,
+                saldo,
+                codigo_venta: movimiento.codigo_venta,
+                codigo_pago: movimiento.codigo_pago,
+                vence: movimiento.vence,
+                fecha: movimiento.fecha,
+            });
+        }
+        vencer(&mut lotes, &mut historial, &mut saldo, hoy);
+        let proximo_vencimiento = lotes.iter().find(|lote| lote.vence.is_some()).map(|lote| Lote {
+            puntos: lotes.iter().filter(|otro| otro.vence == lote.vence).map(|otro| otro.puntos).sum(),
+            vence: lote.vence,
+        });
+        EstadoPuntos {
+            cedula_cliente,
+            saldo,
+            valor_saldo: saldo as f64 * config.valor_punto,
+            proximo_vencimiento,
+            historial,
+        }
+    }
+}
+
+/// Obtiene el estado de puntos de un cliente, o una respuesta de error si el cliente no existe
+pub(crate) async fn estado(state: &AppState, cedula: i64) -> Result<EstadoPuntos, HttpResponse> {
+    match state.repos.clientes.obtener(cedula).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(HttpResponse::NotFound().json("cliente not found")),
+        Err(_) => return Err(HttpResponse::InternalServerError().json("could not read clientes")),
+    }
+    match state.repos.puntos.movimientos(cedula).await {
+        Ok(movimientos) => Ok(EstadoPuntos::new(cedula, &movimientos, Utc::now().date_naive(), &state.puntos)),
+        Err(_) => Err(HttpResponse::InternalServerError().json("could not read puntos")),
+    }
+}
+
+/// Obtiene el saldo de puntos de un cliente, lo que vale en pesos, los proximos puntos por vencer
+/// y el historial de acumulaciones, redenciones y vencimientos, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id del cliente
+#[get("/{id}/puntos")]
+pub async fn read_by_cliente(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+    match estado(&state, path.into_inner()).await {
+        Ok(estado) => HttpResponse::Ok().json(estado),
+        Err(respuesta) => respuesta,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn movimiento(fecha: &str, puntos: i64, vence: Option<&str>) -> MovimientosPuntos {
+        MovimientosPuntos {
+            codigo: None,
+            cedula_cliente: Some(1),
+            codigo_venta: None,
+            codigo_pago: None,
+            puntos,
+            vence: vence.map(|vence| vence.parse().unwrap()),
+            fecha: Some(format!("{fecha}T12:00:00Z").parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn las_redenciones_consumen_primero_los_puntos_que_vencen_antes() {
+        let movimientos = [
+            movimiento("2024-01-10", 100, Some("2024-03-31")),
+            movimiento("2024-02-10", 50, Some("2024-04-30")),
+            movimiento("2024-03-01", -120, None),
+            movimiento("2024-04-01", 30, None),
+        ];
+        let config = ConfigPuntos::default();
+        let estado = EstadoPuntos::new(1, &movimientos, "2024-04-15".parse().unwrap(), &config);
+        assert_eq!(estado.saldo, 60);
+        assert_eq!(estado.proximo_vencimiento.as_ref().map(|lote| lote.puntos), Some(30));
+
+        // los 30 puntos que quedan del segundo lote se vencen el 30 de abril
+        let estado = EstadoPuntos::new(1, &movimientos, "2024-05-01".parse().unwrap(), &config);
+        assert_eq!(estado.saldo, 30);
+        assert_eq!(estado.historial.last().map(|e| (e.tipo, e.puntos)), Some((VENCIMIENTO, -30)));
+        assert!(estado.proximo_vencimiento.is_none());
+    }
+
+    #[test]
+    fn la_configuracion_convierte_pesos_y_puntos() {
+        let config = ConfigPuntos::default();
+        assert_eq!(config.acumulados(119_000.0), 119);
+        assert_eq!(config.acumulados(999.0), 0);
+        assert_eq!(config.para_pagar(1000.0), 100);
+        assert_eq!(config.para_pagar(1005.0), 101);
+        let fecha = "2024-01-31".parse().unwrap();
+        assert_eq!(config.vencimiento(fecha), "2025-01-30".parse().ok());
+        assert_eq!(ConfigPuntos { vigencia_dias: 0, ..config }.vencimiento(fecha), None);
+    }
+}
@@ -0,0 +1,187 @@
+//!Este archivo representa el servicio REST de las sucursales de la cadena
+//!(tablas 'sucursales' y 'usuarios_sucursales'). Los productos, los proveedores y los clientes son
+//!de toda la cadena; las ventas, las cajas y las existencias son de cada sucursal. Cada usuario
+//!trabaja en las sucursales que tiene asignadas, o en todas si no tiene ninguna.
+
+use crate::{auth::Actor, repos::ErrorRepo, validacion, AppState};
+use actix_web::{
+    delete, get, patch, post,
+    web::{Data, Json, Path},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use validator::Validate;
+
+/// La sucursal en la que quedan los datos anteriores a las sucursales, y la predeterminada
+/// de las cajas, las ordenes de compra y los conteos
+pub(crate) const PRINCIPAL: i64 = 1;
+
+/// La sucursal predeterminada en el json de los requests
+pub(crate) fn principal() -> Option<i64> {
+    Some(PRINCIPAL)
+}
+
+/// La tabla 'sucursales' representada como un struct.
+/// El codigo es opcional debido a la operacion de `update`
+/// Las fechas son asignadas por el servidor y se ignoran en el json del request
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, Validate)]
+pub(crate) struct Sucursales {
+    #[validate(range(min = 1))]
+    pub(crate) codigo: Option<i64>,
+    #[validate(length(min = 1, max = 40))]
+    pub(crate) nombre: String,
+    #[serde(default)]
+    #[validate(length(min = 1, max = 100))]
+    pub(crate) direccion: Option<String>,
+    #[serde(skip_deserializing)]
+    pub(crate) created_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) updated_at: Option<DateTime<Utc>>,
+}
+
+/// La tabla 'usuarios_sucursales' representada como un struct
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct UsuariosSucursales {
+    pub(crate) codigo: Option<i64>,
+    pub(crate) cedula_usuario: i64,
+    pub(crate) codigo_sucursal: i64,
+    pub(crate) created_at: Option<DateTime<Utc>>,
+}
+
+/// El json del request para asignar una sucursal a un usuario
+#[derive(Debug, Deserialize, Validate)]
+pub(crate) struct NuevaAsignacion {
+    #[validate(range(min = 1))]
+    pub(crate) codigo_sucursal: i64,
+}
+
+/// Verifica que un usuario pueda trabajar en una sucursal: que sea una de sus sucursales asignadas,
+/// o que no tenga ninguna
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `cedula_usuario` - El usuario (un actor sin cedula no tiene restricciones)
+/// * `codigo_sucursal` - La sucursal en la que se realiza la operacion
+pub(crate) async fn autorizar(state: &AppState, cedula_usuario: Option<i64>, codigo_sucursal: i64) -> Result<(), HttpResponse> {
+    let Some(cedula_usuario) = cedula_usuario else {
+        return Ok(());
+    };
+    match state.repos.sucursales.asignadas(cedula_usuario).await {
+        Ok(asignadas) if asignadas.is_empty() || asignadas.iter().any(|s| s.codigo == Some(codigo_sucursal)) => Ok(()),
+        Ok(_) => Err(HttpResponse::Forbidden()
+            .json(format!("usuario {cedula_usuario} is not assigned to sucursal {codigo_sucursal}"))),
+        Err(_) => Err(HttpResponse::InternalServerError().json("could not read sucursales")),
+    }
+}
+
+/// Crea una nueva sucursal y la envia a la base de datos
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `sucursal` - Un json en el body del request representando la sucursal
+#[post("/")]
+pub async fn create(state: Data<AppState>, actor: Actor, sucursal: Json<Sucursales>) -> impl Responder {
+    if let Err(errores) = sucursal.validate() {
+        return validacion::rechazar(errores);
+    }
+    match state.repos.sucursales.insertar(&actor, &sucursal).await {
+        Ok(_) => HttpResponse::Created().json("Sucursal creada"),
+        Err(ErrorRepo::Duplicado) => HttpResponse::Conflict().json("sucursal already exists"),
+        Err(_) => HttpResponse::InternalServerError().json("could not create sucursal"),
+    }
+}
+
+/// Obtiene todas las sucursales de la base de datos
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+#[get("/")]
+pub async fn read_all(state: Data<AppState>) -> impl Responder {
+    match state.repos.sucursales.listar().await {
+        Ok(sucursales) => HttpResponse::Ok().json(sucursales),
+        Err(_) => HttpResponse::NotFound().json("sucursales not found"),
+    }
+}
+
+/// Obtiene una sucursal de la base de datos, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id
+#[get("/{id}")]
+pub async fn read_by_id(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+    match state.repos.sucursales.obtener(path.into_inner()).await {
+        Ok(Some(sucursal)) => HttpResponse::Ok().json(sucursal),
+        Ok(None) => HttpResponse::NotFound().json("sucursal not found"),
+        Err(_) => HttpResponse::InternalServerError().json("could not read sucursales"),
+    }
+}
+
+/// Actualiza una sucursal de la base de datos, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+/// * `sucursal` - Un json en el body del request representando la sucursal a actualizar
+#[patch("/{id}")]
+pub async fn update(state: Data<AppState>, actor: Actor, path: Path<i64>, sucursal: Json<Sucursales>) -> impl Responder {
+    if let Err(errores) = sucursal.validate() {
+        return validacion::rechazar(errores);
+    }
+    match state.repos.sucursales.actualizar(&actor, path.into_inner(), &sucursal).await {
+        Ok(0) => HttpResponse::NotFound().json("sucursal not found"),
+        Ok(_) => HttpResponse::Ok().json("Sucursal updated"),
+        Err(_) => HttpResponse::InternalServerError().json("could not update sucursal"),
+    }
+}
+
+/// Obtiene las sucursales asignadas a un usuario, por medio de su cedula en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la cedula del usuario
+#[get("/{id}/sucursales")]
+pub async fn read_by_usuario(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.usuarios.obtener(id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json("usuario not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read usuarios"),
+    }
+    match state.repos.sucursales.asignadas(id).await {
+        Ok(sucursales) => HttpResponse::Ok().json(sucursales),
+        Err(_) => HttpResponse::InternalServerError().json("could not read sucursales"),
+    }
+}
+
+/// Asigna una sucursal a un usuario
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la cedula del usuario
+/// * `nueva` - Un json en el body del request con la sucursal
+#[post("/{id}/sucursales")]
+pub async fn assign(state: Data<AppState>, actor: Actor, path: Path<i64>, nueva: Json<NuevaAsignacion>) -> impl Responder {
+    if let Err(errores) = nueva.validate() {
+        return validacion::rechazar(errores);
+    }
+    match state.repos.sucursales.asignar(&actor, path.into_inner(), nueva.codigo_sucursal).await {
+        Ok(_) => HttpResponse::Created().json("Sucursal asignada"),
+        Err(ErrorRepo::LlaveForanea) => HttpResponse::NotFound().json("usuario or sucursal not found"),
+        Err(ErrorRepo::Duplicado) => HttpResponse::Conflict().json("the sucursal is already assigned to the usuario"),
+        Err(_) => HttpResponse::InternalServerError().json("could not assign sucursal"),
+    }
+}
+
+/// Quita una sucursal de las asignadas a un usuario
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la cedula del usuario y el codigo de la sucursal
+#[delete("/{id}/sucursales/{codigo}")]
+pub async fn unassign(state: Data<AppState>, actor: Actor, path: Path<(i64, i64)>) -> impl Responder {
+    let (id, codigo) = path.into_inner();
+    match state.repos.sucursales.desasignar(&actor, id, codigo).await {
+        Ok(0) => HttpResponse::NotFound().json("the sucursal is not assigned to the usuario"),
+        Ok(_) => HttpResponse::Ok().json("Sucursal unassigned"),
+        Err(_) => HttpResponse::InternalServerError().json("could not unassign sucursal"),
+    }
+}
@@ -0,0 +1,189 @@
+//!Este archivo representa el servicio REST de los traslados de mercancia entre sucursales
+//!(tabla 'traslados'). Cada producto trasladado sale del inventario de la sucursal de origen y
+//!entra al de la sucursal de destino como dos movimientos de inventario (ver `inventario`).
+
+use crate::{
+    auth::Actor,
+    entities::{conteos, inventario::MovimientosInventario, sucursales},
+    filtros::{FiltroSucursal, RangoFechas},
+    repos::ErrorRepo,
+    validacion,
+    AppState,
+};
+use actix_web::{
+    get, post,
+    web::{Data, Json, Path, Query},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use validator::{Validate, ValidationError};
+
+/// La tabla 'traslados' representada como un struct.
+/// El usuario y la fecha los asigna el servidor
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct Traslados {
+    pub(crate) codigo: Option<i64>,
+    pub(crate) codigo_sucursal_origen: i64,
+    pub(crate) codigo_sucursal_destino: i64,
+    pub(crate) motivo: Option<String>,
+    pub(crate) cedula_usuario: Option<i64>,
+    pub(crate) fecha: Option<DateTime<Utc>>,
+}
+
+/// Un producto trasladado
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub(crate) struct LineaTraslado {
+    #[validate(range(min = 1))]
+    pub(crate) codigo_producto: i64,
+    #[validate(range(min = 0.001))]
+    pub(crate) cantidad: f64,
+}
+
+fn sucursales_distintas(traslado: &NuevoTraslado) -> Result<(), ValidationError> {
+    if traslado.codigo_sucursal_origen == traslado.codigo_sucursal_destino {
+        let mut error = ValidationError::new("codigo_sucursal_destino");
+        error.message = Some("codigo_sucursal_destino must be different from codigo_sucursal_origen".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// El json del request para trasladar productos entre dos sucursales
+#[derive(Debug, Deserialize, Validate)]
+#[validate(schema(function = "sucursales_distintas"))]
+pub(crate) struct NuevoTraslado {
+    #[validate(range(min = 1))]
+    pub(crate) codigo_sucursal_origen: i64,
+    #[validate(range(min = 1))]
+    pub(crate) codigo_sucursal_destino: i64,
+    #[serde(default)]
+    #[validate(length(min = 1, max = 100))]
+    pub(crate) motivo: Option<String>,
+    #[validate(length(min = 1))]
+    #[validate]
+    pub(crate) productos: Vec<LineaTraslado>,
+}
+
+/// La cantidad total a trasladar de cada producto (un producto puede venir en varias lineas)
+pub(crate) fn totales(productos: &[LineaTraslado]) -> BTreeMap<i64, f64> {
+    let mut totales = BTreeMap::new();
+    for linea in productos {
+        *totales.entry(linea.codigo_producto).or_default() += linea.cantidad;
+    }
+    totales
+}
+
+/// Si las existencias alcanzan para trasladar una cantidad, comparando en milesimas
+/// (ver `conteos::ajuste`)
+pub(crate) fn alcanza(existencias: f64, cantidad: f64) -> bool {
+    conteos::ajuste(existencias, cantidad) <= 0.0
+}
+
+/// Un traslado con sus movimientos de inventario
+#[derive(Debug, Serialize)]
+struct TrasladoCompleto {
+    #[serde(flatten)]
+    traslado: Traslados,
+    movimientos: Vec<MovimientosInventario>,
+}
+
+/// Un traslado con sus movimientos
+async fn completo(state: &AppState, codigo: i64) -> Result<TrasladoCompleto, HttpResponse> {
+    let traslado = match state.repos.traslados.obtener(codigo).await {
+        Ok(Some(traslado)) => traslado,
+        Ok(None) => return Err(HttpResponse::NotFound().json("traslado not found")),
+        Err(_) => return Err(HttpResponse::InternalServerError().json("could not read traslados")),
+    };
+    match state.repos.traslados.movimientos(codigo).await {
+        Ok(movimientos) => Ok(TrasladoCompleto { traslado, movimientos }),
+        Err(_) => Err(HttpResponse::InternalServerError().json("could not read traslados")),
+    }
+}
+
+/// Traslada productos de una sucursal a otra. Si la sucursal de origen no tiene las existencias
+/// suficientes de alguno de los productos responde `409 Conflict` y no traslada ninguno
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `nuevo` - Un json en el body del request con las sucursales y los productos y cantidades
+#[post("/")]
+pub async fn create(state: Data<AppState>, actor: Actor, nuevo: Json<NuevoTraslado>) -> impl Responder {
+    if let Err(errores) = nuevo.validate() {
+        return validacion::rechazar(errores);
+    }
+    for codigo_sucursal in [nuevo.codigo_sucursal_origen, nuevo.codigo_sucursal_destino] {
+        if let Err(respuesta) = sucursales::autorizar(&state, actor.cedula, codigo_sucursal).await {
+            return respuesta;
+        }
+    }
+    for linea in &nuevo.productos {
+        match state.repos.productos.obtener(linea.codigo_producto).await {
+            Ok(Some(producto)) => {
+                if let Err(error) = producto.cantidad_valida(linea.cantidad) {
+                    return HttpResponse::BadRequest().json(error);
+                }
+            }
+            Ok(None) => return HttpResponse::BadRequest().json(format!("producto {} does not exist", linea.codigo_producto)),
+            Err(_) => return HttpResponse::InternalServerError().json("could not read productos"),
+        }
+    }
+    let traslado = Traslados {
+        codigo: None,
+        codigo_sucursal_origen: nuevo.codigo_sucursal_origen,
+        codigo_sucursal_destino: nuevo.codigo_sucursal_destino,
+        motivo: nuevo.motivo.clone(),
+        cedula_usuario: actor.cedula,
+        fecha: None,
+    };
+    match state.repos.traslados.insertar(&actor, &traslado, &nuevo.productos).await {
+        Ok(Some(codigo)) => match completo(&state, codigo).await {
+            Ok(traslado) => HttpResponse::Created().json(traslado),
+            Err(respuesta) => respuesta,
+        },
+        Ok(None) => HttpResponse::Conflict().json("the origin sucursal does not have enough stock"),
+        Err(ErrorRepo::LlaveForanea) => HttpResponse::BadRequest().json("a sucursal or producto of the traslado does not exist"),
+        Err(_) => HttpResponse::InternalServerError().json("could not create traslado"),
+    }
+}
+
+/// Obtiene los traslados, opcionalmente filtrados por fecha y por sucursal (de origen o de destino)
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `rango` - `?desde=2023-11-01&hasta=2023-11-30`, ambos opcionales
+/// * `filtro` - `?sucursal=2`, opcional
+#[get("/")]
+pub async fn read_all(state: Data<AppState>, rango: Query<RangoFechas>, filtro: Query<FiltroSucursal>) -> impl Responder {
+    match state.repos.traslados.listar(&rango, filtro.sucursal).await {
+        Ok(traslados) => HttpResponse::Ok().json(traslados),
+        Err(_) => HttpResponse::InternalServerError().json("could not read traslados"),
+    }
+}
+
+/// Obtiene un traslado con sus movimientos de inventario, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id
+#[get("/{id}")]
+pub async fn read_by_id(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+    match completo(&state, path.into_inner()).await {
+        Ok(traslado) => HttpResponse::Ok().json(traslado),
+        Err(respuesta) => respuesta,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn los_totales_suman_las_lineas_de_cada_producto() {
+        let productos = [(77, 1.5), (78, 2.0), (77, 0.25)].map(|(codigo_producto, cantidad)| LineaTraslado { codigo_producto, cantidad });
+        assert_eq!(totales(&productos).into_iter().collect::<Vec<_>>(), [(77, 1.75), (78, 2.0)]);
+        // 0.1 + 0.2 en el sistema no es exactamente 0.3
+        assert!(alcanza(0.1 + 0.2, 0.3));
+        assert!(!alcanza(0.3, 0.301));
+    }
+}
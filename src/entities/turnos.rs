@@ -0,0 +1,364 @@
+//!Este archivo representa el servicio REST de la tabla 'turnos'.
+//!Un cajero abre un turno en una caja con el efectivo inicial; mientras el turno esta abierto
+//!sus ventas quedan asociadas a el y se registran las entradas y salidas de efectivo
+//!(tabla 'movimientos_caja'). Al cerrar el turno con el efectivo contado se obtiene el arqueo:
+//!el efectivo esperado frente al contado.
+
+use crate::{
+    auth::Actor,
+    entities::{pagos::centavos, sucursales},
+    filtros::{FiltroSucursal, RangoFechas},
+    repos::ErrorRepo,
+    validacion,
+    AppState,
+};
+use actix_web::{
+    get, post,
+    web::{Data, Json, Path, Query},
+    HttpResponse, Responder,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use validator::{Validate, ValidationError};
+
+/// La tabla 'turnos' representada como un struct.
+/// El codigo lo asigna la base de datos, el usuario es el cajero autenticado que abre el turno
+/// y el efectivo contado y las fechas se asignan al abrir y cerrar el turno,
+/// por lo que el json del request solo incluye la caja y el efectivo inicial
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, Validate)]
+pub(crate) struct Turnos {
+    #[serde(skip_deserializing)]
+    pub(crate) codigo: Option<i64>,
+    #[validate(range(min = 1))]
+    pub(crate) codigo_caja: Option<i64>,
+    #[serde(skip_deserializing)]
+    pub(crate) cedula_usuario: Option<i64>,
+    #[validate(range(min = 0.0))]
+    pub(crate) efectivo_inicial: f64,
+    #[serde(skip_deserializing)]
+    pub(crate) efectivo_contado: Option<f64>,
+    #[serde(skip_deserializing)]
+    pub(crate) abierto_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) cerrado_at: Option<DateTime<Utc>>,
+}
+
+/// Los tipos de movimiento de efectivo
+pub(crate) const ENTRADA: &str = "entrada";
+pub(crate) const SALIDA: &str = "salida";
+
+/// Valida el tipo de un movimiento de efectivo
+fn tipo_movimiento(tipo: &str) -> Result<(), ValidationError> {
+    if tipo == ENTRADA || tipo == SALIDA {
+        Ok(())
+    } else {
+        let mut error = ValidationError::new("tipo");
+        error.message = Some("must be 'entrada' or 'salida'".into());
+        Err(error)
+    }
+}
+
+/// La tabla 'movimientos_caja' representada como un struct: una entrada o salida de efectivo
+/// durante un turno que no corresponde a una venta (como un retiro parcial o el pago a un proveedor).
+/// El codigo, el turno y la fecha los asigna el servidor
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, Validate)]
+pub(crate) struct MovimientosCaja {
+    #[serde(skip_deserializing)]
+    pub(crate) codigo: Option<i64>,
+    #[serde(skip_deserializing)]
+    pub(crate) codigo_turno: Option<i64>,
+    #[validate(custom = "tipo_movimiento")]
+    pub(crate) tipo: String,
+    #[validate(range(min = 0.01))]
+    pub(crate) valor: f64,
+    #[validate(length(min = 1, max = 40))]
+    pub(crate) motivo: String,
+    #[serde(skip_deserializing)]
+    pub(crate) fecha: Option<DateTime<Utc>>,
+}
+
+/// Las sumas de un turno que se usan en el arqueo
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct TotalesTurno {
+    pub(crate) ventas: i64,
+    pub(crate) total_ventas: f64,
+    /// Los pagos en efectivo de las ventas del turno (sin el cambio)
+    pub(crate) efectivo: f64,
+    pub(crate) entradas: f64,
+    pub(crate) salidas: f64,
+}
+
+/// El arqueo de un turno: el efectivo esperado en la caja (el inicial, mas los pagos en efectivo
+/// de las ventas finalizadas y las entradas, menos las salidas) frente al contado al cerrar el turno.
+/// Las ventas abiertas no cuentan; las pagadas con otros medios cuentan en `total_ventas` pero no en el efectivo.
+/// Mientras el turno esta abierto el contado y la diferencia son `null`
+#[derive(Debug, Serialize)]
+struct Arqueo {
+    turno: Turnos,
+    ventas: i64,
+    total_ventas: f64,
+    efectivo_ventas: f64,
+    entradas: f64,
+    salidas: f64,
+    esperado: f64,
+    contado: Option<f64>,
+    diferencia: Option<f64>,
+}
+
+impl Arqueo {
+    fn new(turno: Turnos, totales: TotalesTurno) -> Self {
+        let esperado =
+            centavos(turno.efectivo_inicial + totales.efectivo + totales.entradas - totales.salidas);
+        let contado = turno.efectivo_contado;
+        Arqueo {
+            turno,
+            ventas: totales.ventas,
+            total_ventas: centavos(totales.total_ventas),
+            efectivo_ventas: centavos(totales.efectivo),
+            entradas: centavos(totales.entradas),
+            salidas: centavos(totales.salidas),
+            esperado,
+            contado,
+            diferencia: contado.map(|contado| centavos(contado - esperado)),
+        }
+    }
+}
+
+/// El json del request para cerrar un turno
+#[derive(Debug, Deserialize, Validate)]
+pub struct Cierre {
+    #[validate(range(min = 0.0))]
+    efectivo_contado: f64,
+}
+
+/// Abre un turno para el usuario autenticado en una caja de una de sus sucursales
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que abre el turno
+/// * `turno` - Un json en el body del request con la caja y el efectivo inicial
+#[post("/")]
+pub async fn create(state: Data<AppState>, actor: Actor, turno: Json<Turnos>) -> impl Responder {
+    if let Err(errores) = turno.validate() {
+        return validacion::rechazar(errores);
+    }
+    if let Some(codigo_caja) = turno.codigo_caja {
+        let codigo_sucursal = match state.repos.cajas.obtener(codigo_caja).await {
+            Ok(Some(caja)) => caja.codigo_sucursal.unwrap_or(sucursales::PRINCIPAL),
+            Ok(None) => return HttpResponse::BadRequest().json("caja not found"),
+            Err(_) => return HttpResponse::InternalServerError().json("could not read cajas"),
+        };
+        if let Err(respuesta) = sucursales::autorizar(&state, actor.cedula, codigo_sucursal).await {
+            return respuesta;
+        }
+    }
+    let turno = Turnos { cedula_usuario: actor.cedula, ..turno.into_inner() };
+    let codigo = match state.repos.turnos.abrir(&actor, &turno).await {
+        Ok(codigo) => codigo,
+        Err(ErrorRepo::Duplicado) => {
+            return HttpResponse::Conflict().json("the usuario or the caja already has an open turno")
+        }
+        Err(ErrorRepo::LlaveForanea) => return HttpResponse::BadRequest().json("caja not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("could not open turno"),
+    };
+    match state.repos.turnos.obtener(codigo).await {
+        Ok(Some(turno)) => HttpResponse::Created().json(turno),
+        _ => HttpResponse::InternalServerError().json("could not read turnos"),
+    }
+}
+
+/// Obtiene todos los turnos de la base de datos, opcionalmente filtrados por la fecha de apertura
+/// y por la sucursal de su caja
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `rango` - `?desde=AAAA-MM-DD&hasta=AAAA-MM-DD`, ambos opcionales e inclusivos
+/// * `filtro` - `?sucursal=2`, opcional
+#[get("/")]
+pub async fn read_all(state: Data<AppState>, rango: Query<RangoFechas>, filtro: Query<FiltroSucursal>) -> impl Responder {
+    match state.repos.turnos.listar(&rango, filtro.sucursal).await {
+        Ok(turnos) => HttpResponse::Ok().json(turnos),
+        Err(_) => HttpResponse::NotFound().json("turnos not found"),
+    }
+}
+
+/// Obtiene el turno abierto del usuario autenticado
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado
+#[get("/actual")]
+pub async fn read_actual(state: Data<AppState>, actor: Actor) -> impl Responder {
+    let Some(cedula) = actor.cedula else {
+        return HttpResponse::NotFound().json("turno not found");
+    };
+    match state.repos.turnos.abierto(cedula).await {
+        Ok(Some(turno)) => HttpResponse::Ok().json(turno),
+        Ok(None) => HttpResponse::NotFound().json("turno not found"),
+        Err(_) => HttpResponse::InternalServerError().json("could not read turnos"),
+    }
+}
+
+/// Obtiene un turno de la base de datos, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id
+#[get("/{id}")]
+pub async fn read_by_id(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.turnos.obtener(id).await {
+        Ok(Some(turno)) => HttpResponse::Ok().json(turno),
+        Ok(None) => HttpResponse::NotFound().json("turno not found"),
+        Err(_) => HttpResponse::InternalServerError().json("could not read turnos"),
+    }
+}
+
+/// Registra una entrada o salida de efectivo en un turno abierto
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id del turno
+/// * `movimiento` - Un json en el body del request con el tipo, el valor y el motivo
+#[post("/{id}/movimientos")]
+pub async fn create_movimiento(
+    state: Data<AppState>,
+    actor: Actor,
+    path: Path<i64>,
+    movimiento: Json<MovimientosCaja>,
+) -> impl Responder {
+    if let Err(errores) = movimiento.validate() {
+        return validacion::rechazar(errores);
+    }
+    let id = path.into_inner();
+    match state.repos.turnos.insertar_movimiento(&actor, id, &movimiento).await {
+        Ok(Some(_)) => HttpResponse::Created().json("Movimiento creado"),
+        Ok(None) => match state.repos.turnos.obtener(id).await {
+            Ok(Some(_)) => HttpResponse::Conflict().json("turno is closed"),
+            Ok(None) => HttpResponse::NotFound().json("turno not found"),
+            Err(_) => HttpResponse::InternalServerError().json("could not read turnos"),
+        },
+        Err(_) => HttpResponse::InternalServerError().json("could not create movimiento"),
+    }
+}
+
+/// Obtiene los movimientos de efectivo de un turno, por medio de la id del turno en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id del turno
+#[get("/{id}/movimientos")]
+pub async fn read_movimientos(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.turnos.obtener(id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json("turno not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read turnos"),
+    }
+    match state.repos.turnos.movimientos(id).await {
+        Ok(movimientos) => HttpResponse::Ok().json(movimientos),
+        Err(_) => HttpResponse::NotFound().json("movimientos not found"),
+    }
+}
+
+/// Obtiene el arqueo de un turno
+async fn arqueo(state: &AppState, id: i64) -> Result<Option<Arqueo>, ErrorRepo> {
+    let Some(turno) = state.repos.turnos.obtener(id).await? else {
+        return Ok(None);
+    };
+    let totales = state.repos.turnos.totales(id).await?;
+    Ok(Some(Arqueo::new(turno, totales)))
+}
+
+/// Obtiene el arqueo de un turno, por medio de la id en la uri. Si el turno esta abierto
+/// muestra el efectivo esperado hasta el momento
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id del turno
+#[get("/{id}/arqueo")]
+pub async fn read_arqueo(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+    match arqueo(&state, path.into_inner()).await {
+        Ok(Some(arqueo)) => HttpResponse::Ok().json(arqueo),
+        Ok(None) => HttpResponse::NotFound().json("turno not found"),
+        Err(_) => HttpResponse::InternalServerError().json("could not read turnos"),
+    }
+}
+
+/// Verifica que el actor pueda cerrar un turno: debe ser el usuario del turno y estar asignado a la
+/// sucursal de su caja (un actor sin cedula no tiene restricciones)
+async fn autorizar_cierre(state: &AppState, actor: &Actor, turno: &Turnos) -> Result<(), HttpResponse> {
+    if actor.cedula.is_some() && actor.cedula != turno.cedula_usuario {
+        return Err(HttpResponse::Forbidden().json("only the usuario of the turno can close it"));
+    }
+    let Some(codigo_caja) = turno.codigo_caja else {
+        return Ok(());
+    };
+    let codigo_sucursal = match state.repos.cajas.obtener(codigo_caja).await {
+        Ok(Some(caja)) => caja.codigo_sucursal.unwrap_or(sucursales::PRINCIPAL),
+        Ok(None) => sucursales::PRINCIPAL,
+        Err(_) => return Err(HttpResponse::InternalServerError().json("could not read cajas")),
+    };
+    sucursales::autorizar(state, actor.cedula, codigo_sucursal).await
+}
+
+/// Cierra un turno abierto con el efectivo contado en la caja y responde con el arqueo.
+/// Solo lo puede cerrar su usuario, en una de sus sucursales
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id del turno
+/// * `cierre` - Un json en el body del request con el efectivo contado
+#[post("/{id}/cierre")]
+pub async fn close(state: Data<AppState>, actor: Actor, path: Path<i64>, cierre: Json<Cierre>) -> impl Responder {
+    if let Err(errores) = cierre.validate() {
+        return validacion::rechazar(errores);
+    }
+    let id = path.into_inner();
+    let turno = match state.repos.turnos.obtener(id).await {
+        Ok(Some(turno)) => turno,
+        Ok(None) => return HttpResponse::NotFound().json("turno not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read turnos"),
+    };
+    if let Err(respuesta) = autorizar_cierre(&state, &actor, &turno).await {
+        return respuesta;
+    }
+    match state.repos.turnos.cerrar(&actor, id, cierre.efectivo_contado).await {
+        Ok(0) => return HttpResponse::Conflict().json("turno is already closed"),
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().json("could not close turno"),
+    }
+    match arqueo(&state, id).await {
+        Ok(Some(arqueo)) => {
+            tracing::info!(turno = id, diferencia = arqueo.diferencia, "turno closed");
+            HttpResponse::Ok().json(arqueo)
+        }
+        _ => HttpResponse::InternalServerError().json("could not read turnos"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn el_arqueo_compara_el_efectivo_esperado_con_el_contado() {
+        let turno = Turnos {
+            codigo: Some(1),
+            codigo_caja: Some(1),
+            cedula_usuario: Some(1000),
+            efectivo_inicial: 100_000.0,
+            efectivo_contado: Some(245_000.0),
+            abierto_at: None,
+            cerrado_at: None,
+        };
+        let totales = TotalesTurno {
+            ventas: 3,
+            total_ventas: 200_000.0,
+            efectivo: 160_000.1,
+            entradas: 20_000.0,
+            salidas: 30_000.0,
+        };
+        let arqueo = Arqueo::new(turno.clone(), totales);
+        assert_eq!(arqueo.esperado, 250_000.1);
+        assert_eq!(arqueo.diferencia, Some(-5_000.1));
+
+        let arqueo = Arqueo::new(Turnos { efectivo_contado: None, ..turno }, totales);
+        assert_eq!(arqueo.diferencia, None);
+    }
+}
@@ -1,86 +1,131 @@
 //!Este archivo representa el servicio REST de la tabla 'usuarios'.
 //!Contiene todas las operaciones CRUD relacionadas.
 
-use crate::AppState;
+use crate::{
+    auth::Actor,
+    borrado::Borrado,
+    filtros::{Borrados, FiltroSucursal, Paginacion},
+    validacion,
+    AppState,
+};
 use actix_web::{
     delete, get, patch, post,
-    web::{Data, Json, Path},
+    web::{Data, Json, Path, Query},
     HttpResponse, Responder,
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use validator::Validate;
 
 /// La tabla 'usuarios' representada como un struct.
 /// Cedula es opcional debido a la operacion de `update`
-#[derive(Debug, FromRow, Serialize, Deserialize)]
-struct Usuarios {
-    cedula: Option<i64>,
-    email: Option<String>,
-    nombre: Option<String>,
-    password: String,
-    usuario: String,
+/// `admin` solo lo asigna el comando `create-admin` y se ignora en el json del request
+/// La contraseña se recibe en el json del request pero nunca se incluye en las respuestas
+/// Los campos se validan antes de ejecutar cualquier consulta.
+/// Las fechas son asignadas por el servidor y se ignoran en el json del request
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, Validate)]
+pub(crate) struct Usuarios {
+    #[validate(range(min = 1))]
+    pub(crate) cedula: Option<i64>,
+    #[validate(email, length(max = 40))]
+    pub(crate) email: Option<String>,
+    #[validate(length(min = 1, max = 40))]
+    pub(crate) nombre: Option<String>,
+    #[serde(skip_serializing)]
+    #[validate(length(min = 1, max = 40))]
+    pub(crate) password: String,
+    #[validate(length(min = 1, max = 40))]
+    pub(crate) usuario: String,
+    #[serde(skip_deserializing)]
+    pub(crate) admin: bool,
+    #[serde(skip_deserializing)]
+    pub(crate) created_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) updated_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) deleted_at: Option<DateTime<Utc>>,
 }
 
 /// Crea un nuevo usuario y lo envia a la base de datos
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `usuario` - Un json en el body del request representando el usuario
 #[post("/")]
-pub async fn create(state: Data<AppState>, usuario: Json<Usuarios>) -> impl Responder {
-    match sqlx::query_as!(
-        Usuarios,
-        "insert into usuarios values ($1,$2,$3,$4,$5);",
-        usuario.cedula,
-        usuario.email,
-        usuario.nombre,
-        usuario.password.as_str(),
-        usuario.usuario.as_str()
-    )
-        .execute(&state.db)
-        .await
-    {
+pub async fn create(state: Data<AppState>, actor: Actor, usuario: Json<Usuarios>) -> impl Responder {
+    if let Err(errores) = usuario.validate() {
+        return validacion::rechazar(errores);
+    }
+    match state.repos.usuarios.insertar(&actor, &usuario).await {
         Ok(_) => HttpResponse::Created().json("Usuario creado"),
         Err(_) => HttpResponse::InternalServerError().json("could not create user"),
     }
 }
 
 /// Obtiene todos los usuarios de la base de datos
+/// (sin los usuarios borrados, a menos que se pida `?incluir_borrados=true`),
+/// opcionalmente solo los asignados a una sucursal. Requiere un usuario autenticado
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `_actor` - El usuario autenticado
+/// * `borrados` - si se incluyen los usuarios borrados
+/// * `filtro` - `?sucursal=2`, opcional
 #[get("/")]
-pub async fn read_all(state: Data<AppState>) -> impl Responder {
-    match sqlx::query_as!(Usuarios, "select * from usuarios;")
-        .fetch_all(&state.db)
-        .await
-    {
+pub async fn read_all(state: Data<AppState>, _actor: Actor, borrados: Query<Borrados>, filtro: Query<FiltroSucursal>) -> impl Responder {
+    match state.repos.usuarios.listar(borrados.incluir(), filtro.sucursal).await {
         Ok(usuarios) => HttpResponse::Ok().json(usuarios),
         Err(_) => HttpResponse::NotFound().json("users not found"),
     }
 }
 
-/// Obtiene un usuario de la base de datos, por medio de la id en la uri
+/// Obtiene un usuario de la base de datos, por medio de la id en la uri. Requiere un usuario autenticado
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `_actor` - El usuario autenticado
 /// * `path` - la uri relativa a la api, esto es la id
 #[get("/{id}")]
-pub async fn read_by_id(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+pub async fn read_by_id(state: Data<AppState>, _actor: Actor, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.usuarios.obtener(id).await {
+        Ok(Some(usuario)) => HttpResponse::Ok().json(usuario),
+        Ok(None) => HttpResponse::NotFound().json("user not found"),
+        Err(_) => HttpResponse::InternalServerError().json("could not read usuarios"),
+    }
+}
+
+/// Obtiene las ventas registradas por un usuario, por medio de la id en la uri, de forma paginada
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id
+/// * `paginacion` - `?pagina=N&por_pagina=M`
+/// * `filtro` - `?sucursal=2`, opcional
+#[get("/{id}/ventas")]
+pub async fn read_ventas(
+    state: Data<AppState>,
+    path: Path<i64>,
+    paginacion: Query<Paginacion>,
+    filtro: Query<FiltroSucursal>,
+) -> impl Responder {
     let id = path.into_inner();
-    match sqlx::query_as!(Usuarios,"select * from usuarios where cedula = $1;", id)
-        .fetch_one(&state.db)
-        .await
-    {
-        Ok(usuario) => HttpResponse::Ok().json(usuario),
-        Err(_) => HttpResponse::NotFound().json("user not found"),
+    match state.repos.usuarios.obtener(id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json("user not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read usuarios"),
+    }
+    match state.repos.ventas.por_usuario(id, &paginacion, filtro.sucursal).await {
+        Ok(ventas) => HttpResponse::Ok().json(ventas),
+        Err(_) => HttpResponse::NotFound().json("ventas not found"),
     }
 }
 
 
-/// Autenticacion basica de un usuario
+/// Autenticacion basica de un usuario (los usuarios borrados no pueden ingresar)
 /// * `state` - La coneccion a la base de datos
 /// * `form` - Un json en el body del request representando el usuario que se ingreso en el formulario de login
 #[post("/login")]
 pub async fn auth(state: Data<AppState>, form: Json<Usuarios>) -> impl Responder {
-    match sqlx::query_as!(Usuarios, "select * from usuarios where usuario = $1;", form.usuario).fetch_optional(&state.db).await {
+    match state.repos.usuarios.por_usuario(&form.usuario).await {
         Ok(Some(user)) => {
             if user.password == form.password {
                 HttpResponse::Ok().body("success")
@@ -96,40 +141,56 @@ pub async fn auth(state: Data<AppState>, form: Json<Usuarios>) -> impl Responder
 /// Actualiza un usuario de la base de datos, por medio de la id en la uri
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `path` - la uri relativa a la api, esto es la id
 /// * `usuario` - Un json en el body del request representando el usuario a actualizar
 #[patch("/{id}")]
-pub async fn update(state: Data<AppState>, path: Path<i64>, usuario: Json<Usuarios>) -> impl Responder {
+pub async fn update(state: Data<AppState>, actor: Actor, path: Path<i64>, usuario: Json<Usuarios>) -> impl Responder {
+    if let Err(errores) = usuario.validate() {
+        return validacion::rechazar(errores);
+    }
     let id = path.into_inner();
-    match sqlx::query_as!(
-        Usuarios,
-        "update usuarios set email = $1, nombre = $2, password = $3, usuario = $4 where cedula = $5;",
-        usuario.email,
-        usuario.nombre,
-        usuario.password.as_str(),
-        usuario.usuario.as_str(),
-        id
-    )
-        .execute(&state.db)
-        .await
-    {
+    match state.repos.usuarios.actualizar(&actor, id, &usuario).await {
         Ok(_) => HttpResponse::Ok().json("Usuario updated"),
         Err(_) => HttpResponse::InternalServerError().json("could not update user")
     }
 }
 
-/// Borra un usuario de la base de datos, por medio de la id en la uri
+/// Borra un usuario de la base de datos de forma logica (`deleted_at`), por medio de la id en la uri.
+/// Con `?dry_run` no borra nada y responde con las filas dependientes que se verian afectadas
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `path` - la uri relativa a la api, esto es la id
+/// * `borrado` - si el borrado es simulado
 #[delete("/{id}")]
-pub async fn delete(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+pub async fn delete(state: Data<AppState>, actor: Actor, path: Path<i64>, borrado: Query<Borrado>) -> impl Responder {
     let id = path.into_inner();
-    match sqlx::query_as!(Usuarios, "delete from usuarios where cedula = $1;", id)
-        .execute(&state.db)
-        .await
-    {
+    if borrado.es_simulacro() {
+        return match state.repos.usuarios.informe_borrado(id).await {
+            Ok(Some(informe)) => HttpResponse::Ok().json(informe),
+            Ok(None) => HttpResponse::NotFound().json("user not found"),
+            Err(_) => HttpResponse::InternalServerError().json("could not delete user"),
+        };
+    }
+    match state.repos.usuarios.borrar(&actor, id).await {
+        Ok(0) => HttpResponse::NotFound().json("user not found"),
         Ok(_) => HttpResponse::Ok().json("Usuario deleted"),
         Err(_) => HttpResponse::InternalServerError().json("could not delete user"),
     }
 }
+
+/// Restaura un usuario borrado de forma logica, por medio de la id en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+#[post("/{id}/restaurar")]
+pub async fn restore(state: Data<AppState>, actor: Actor, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.usuarios.restaurar(&actor, id).await {
+        Ok(0) => HttpResponse::NotFound().json("deleted user not found"),
+        Ok(_) => HttpResponse::Ok().json("Usuario restored"),
+        Err(_) => HttpResponse::InternalServerError().json("could not restore usuario"),
+    }
+}
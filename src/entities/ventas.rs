@@ -1,117 +1,416 @@
 //!Este archivo representa el servicio REST de la tabla 'ventas'.
 //!Contiene todas las operaciones CRUD relacionadas.
 
-use crate::AppState;
+use crate::{
+    auth::Actor,
+    borrado::Borrado,
+    entities::{
+        auditoria,
+        clientes::Clientes,
+        detalle_ventas::DetalleVentas,
+        pagos::{centavos, Saldo},
+        promociones::{self, Calculo},
+        sucursales,
+    },
+    filtros::{FiltroSucursal, RangoFechas},
+    metricas,
+    repos::ErrorRepo,
+    validacion,
+    AppState,
+};
 use actix_web::{
     delete, get, patch, post,
-    web::{Data, Json, Path},
+    web::{Data, Json, Path, Query},
     HttpResponse, Responder,
 };
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use sqlx::FromRow;
+use validator::Validate;
 
 /// La tabla 'ventas' representada como un struct.
 /// La llave primaria y las foraneas son opcionales debido a la operacion de `update`
-#[derive(Debug, FromRow, Serialize, Deserialize)]
-struct Ventas {
-    codigo: Option<i64>,
-    cedula_cliente: Option<i64>,
-    cedula_usuario: Option<i64>,
-    iva_venta: f64,
-    total_venta: f64,
-    valor_venta: f64,
+/// Los campos se validan antes de ejecutar cualquier consulta.
+/// La `fecha` de la venta y las marcas de tiempo las asigna el servidor al momento del cobro,
+/// al igual que el turno (el turno abierto del usuario, si tiene uno),
+/// por lo que se ignoran en el json del request.
+/// Sin `codigo_sucursal` la venta queda en la sucursal de la caja del turno abierto del usuario,
+/// o en la sucursal principal si no tiene turno abierto.
+/// Una venta finalizada (`finalizada_at`, ver `finalize`) ya no se puede modificar
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, Validate)]
+pub(crate) struct Ventas {
+    #[validate(range(min = 1))]
+    pub(crate) codigo: Option<i64>,
+    #[validate(range(min = 1))]
+    pub(crate) cedula_cliente: Option<i64>,
+    #[validate(range(min = 1))]
+    pub(crate) cedula_usuario: Option<i64>,
+    #[validate(range(min = 0.0))]
+    pub(crate) iva_venta: f64,
+    #[validate(range(min = 0.0))]
+    pub(crate) total_venta: f64,
+    #[validate(range(min = 0.0))]
+    pub(crate) valor_venta: f64,
+    #[serde(default)]
+    #[validate(range(min = 1))]
+    pub(crate) codigo_sucursal: Option<i64>,
+    #[serde(skip_deserializing)]
+    pub(crate) codigo_turno: Option<i64>,
+    #[serde(skip_deserializing)]
+    pub(crate) fecha: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) created_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) updated_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    pub(crate) finalizada_at: Option<DateTime<Utc>>,
+}
+
+/// La sucursal de una venta nueva: la del request, la de la caja del turno abierto del usuario
+/// o la principal
+async fn sucursal(state: &AppState, venta: &Ventas) -> Result<i64, ErrorRepo> {
+    if let Some(codigo_sucursal) = venta.codigo_sucursal {
+        return Ok(codigo_sucursal);
+    }
+    let Some(cedula_usuario) = venta.cedula_usuario else {
+        return Ok(sucursales::PRINCIPAL);
+    };
+    let caja = match state.repos.turnos.abierto(cedula_usuario).await? {
+        Some(turno) => match turno.codigo_caja {
+            Some(codigo_caja) => state.repos.cajas.obtener(codigo_caja).await?,
+            None => None,
+        },
+        None => None,
+    };
+    Ok(caja.and_then(|caja| caja.codigo_sucursal).unwrap_or(sucursales::PRINCIPAL))
 }
 
-/// Crea una nueva venta y la envia a la base de datos
+/// Crea una nueva venta y la envia a la base de datos.
+/// El usuario de la venta debe estar asignado a su sucursal (ver `sucursales::autorizar`)
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `venta` - Un json en el body del request representando la venta
 #[post("/")]
-pub async fn create(state: Data<AppState>, venta: Json<Ventas>) -> impl Responder {
-    match sqlx::query_as::<_, Ventas>("insert into ventas values ($1,$2,$3,$4,$5,$6);")
-        .bind(venta.codigo)
-        .bind(venta.cedula_cliente)
-        .bind(venta.cedula_usuario)
-        .bind(venta.iva_venta)
-        .bind(venta.total_venta)
-        .bind(venta.valor_venta)
-        .fetch_optional(&state.db)
-        .await
-    {
+pub async fn create(state: Data<AppState>, actor: Actor, venta: Json<Ventas>) -> impl Responder {
+    if let Err(errores) = venta.validate() {
+        return validacion::rechazar(errores);
+    }
+    let codigo_sucursal = match sucursal(&state, &venta).await {
+        Ok(codigo_sucursal) => codigo_sucursal,
+        Err(_) => return HttpResponse::InternalServerError().json("could not create venta"),
+    };
+    if let Err(respuesta) = sucursales::autorizar(&state, venta.cedula_usuario, codigo_sucursal).await {
+        return respuesta;
+    }
+    let venta = Ventas { codigo_sucursal: Some(codigo_sucursal), ..venta.into_inner() };
+    match state.repos.ventas.insertar(&actor, &venta).await {
         Ok(_) => HttpResponse::Created().json("venta creada"),
+        Err(ErrorRepo::LlaveForanea) => HttpResponse::BadRequest().json("the cliente, usuario or sucursal of the venta does not exist"),
         Err(e) => {
-            eprintln!("{}",e);
-            HttpResponse::InternalServerError().json("could not create venta")},
+            tracing::error!(error = %e, "could not create venta");
+            HttpResponse::InternalServerError().json("could not create venta")
+        }
+    }
+}
+
+/// Calcula los detalles y los totales de una venta con los precios de los productos y las
+/// promociones vigentes hoy (incluidas las del cliente), sin registrar nada. La respuesta tiene los
+/// valores con los que el frontend crea la venta y sus detalles, con la promocion aplicada a cada linea
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `calculo` - Un json en el body del request con el cliente y los productos y cantidades
+#[post("/calcular")]
+pub async fn calculate(state: Data<AppState>, calculo: Json<Calculo>) -> impl Responder {
+    if let Err(errores) = calculo.validate() {
+        return validacion::rechazar(errores);
+    }
+    let mut productos = HashMap::new();
+    for codigo in calculo.productos() {
+        match state.repos.productos.obtener(codigo).await {
+            Ok(Some(producto)) if producto.deleted_at.is_none() => {
+                productos.insert(codigo, producto);
+            }
+            Ok(_) => return HttpResponse::BadRequest().json(format!("producto {codigo} not found")),
+            Err(_) => return HttpResponse::InternalServerError().json("could not read productos"),
+        }
+    }
+    if let Err(mensaje) = calculo.cantidades_validas(&productos) {
+        return HttpResponse::BadRequest().json(mensaje);
+    }
+    let hoy = Utc::now().date_naive();
+    match state.repos.promociones.vigentes(hoy).await {
+        Ok(vigentes) => HttpResponse::Ok().json(promociones::calcular(&calculo, &productos, &vigentes, hoy)),
+        Err(_) => HttpResponse::InternalServerError().json("could not read promociones"),
     }
 }
 
-/// Obtiene todos las ventas de la base de datos
+/// Obtiene todos las ventas de la base de datos, opcionalmente filtradas por fecha y por sucursal
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `rango` - `?desde=AAAA-MM-DD&hasta=AAAA-MM-DD`, ambos opcionales e inclusivos
+/// * `filtro` - `?sucursal=2`, opcional
 #[get("/")]
-pub async fn read_all(state: Data<AppState>) -> impl Responder {
-    match sqlx::query_as::<_, Ventas>("select * from ventas;")
-        .fetch_all(&state.db)
-        .await
-    {
+pub async fn read_all(state: Data<AppState>, rango: Query<RangoFechas>, filtro: Query<FiltroSucursal>) -> impl Responder {
+    match state.repos.ventas.listar(&rango, filtro.sucursal).await {
         Ok(ventas) => HttpResponse::Ok().json(ventas),
         Err(_) => HttpResponse::NotFound().json("ventas not found"),
     }
 }
 
-/// Obtiene una venta de la base de datos, por medio de la id en la uri
+/// Los parametros de consulta de `read_by_id`.
+/// `expand` es una lista separada por comas con `detalles`, `cliente` y/o `usuario`
+#[derive(Debug, Deserialize)]
+pub struct Expandir {
+    expand: Option<String>,
+}
+
+/// El documento compuesto que se envia al frontend: una venta junto con sus detalles,
+/// su cliente y el usuario (cajero) que la registro.
+/// Solo se incluyen las relaciones pedidas en `expand`
+#[derive(Debug, Serialize)]
+struct VentaExpandida {
+    #[serde(flatten)]
+    venta: Ventas,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detalles: Option<Vec<DetalleVentas>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cliente: Option<Clientes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usuario: Option<Value>,
+}
+
+/// Obtiene una venta de la base de datos, por medio de la id en la uri.
+/// Con `?expand=detalles,cliente,usuario` incluye los datos relacionados
+/// (la contraseña del usuario nunca se incluye)
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
 /// * `path` - la uri relativa a la api, esto es la id
+/// * `expandir` - las relaciones a incluir en la respuesta
 #[get("/{id}")]
-pub async fn read_by_id(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+pub async fn read_by_id(state: Data<AppState>, path: Path<i64>, expandir: Query<Expandir>) -> impl Responder {
+    let id = path.into_inner();
+    let (mut detalles, mut cliente, mut usuario) = (false, false, false);
+    for relacion in expandir.expand.iter().flat_map(|e| e.split(',')) {
+        match relacion.trim() {
+            "detalles" => detalles = true,
+            "cliente" => cliente = true,
+            "usuario" => usuario = true,
+            "" => {}
+            otra => return HttpResponse::BadRequest().json(format!("cannot expand '{otra}'")),
+        }
+    }
+
+    let venta = match state.repos.ventas.obtener(id).await {
+        Ok(Some(venta)) => venta,
+        Ok(None) => return HttpResponse::NotFound().json("venta not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read ventas"),
+    };
+    if !(detalles || cliente || usuario) {
+        return HttpResponse::Ok().json(venta);
+    }
+
+    let relaciones = async {
+        let detalles = if detalles {
+            Some(state.repos.detalle_ventas.por_venta(id).await?)
+        } else {
+            None
+        };
+        let cliente = match (cliente, venta.cedula_cliente) {
+            (true, Some(cedula)) => state.repos.clientes.obtener(cedula).await?,
+            _ => None,
+        };
+        let usuario = match (usuario, venta.cedula_usuario) {
+            (true, Some(cedula)) => state.repos.usuarios.obtener(cedula).await?,
+            _ => None,
+        };
+        Ok::<_, ErrorRepo>((detalles, cliente, usuario))
+    };
+    match relaciones.await {
+        Ok((detalles, cliente, usuario)) => HttpResponse::Ok().json(VentaExpandida {
+            venta,
+            detalles,
+            cliente,
+            usuario: usuario.as_ref().map(auditoria::instantanea),
+        }),
+        Err(_) => HttpResponse::InternalServerError().json("could not read ventas"),
+    }
+}
+
+/// Obtiene los detalles de una venta, por medio de la id de la venta en la uri
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `path` - la uri relativa a la api, esto es la id de la venta
+#[get("/{id}/detalles")]
+pub async fn read_detalles(state: Data<AppState>, path: Path<i64>) -> impl Responder {
     let id = path.into_inner();
-    match sqlx::query_as::<_, Ventas>("select * from ventas where codigo = $1;")
-        .bind(id)
-        .fetch_one(&state.db)
-        .await
-    {
-        Ok(venta) => HttpResponse::Ok().json(venta),
-        Err(_) => HttpResponse::NotFound().json("venta not found"),
+    match state.repos.ventas.obtener(id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json("venta not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read ventas"),
+    }
+    match state.repos.detalle_ventas.por_venta(id).await {
+        Ok(detalles) => HttpResponse::Ok().json(detalles),
+        Err(_) => HttpResponse::NotFound().json("detalle ventas not found"),
     }
 }
 
-/// Actualiza una venta de la base de datos, por medio de la id en la uri
+/// Actualiza una venta de la base de datos, por medio de la id en la uri,
+/// siempre que la venta no se haya finalizado
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `path` - la uri relativa a la api, esto es la id
 /// * `venta` - Un json en el body del request representando la venta a actualizar
 #[patch("/{id}")]
-pub async fn update(state: Data<AppState>, path: Path<i64>, venta: Json<Ventas>) -> impl Responder {
+pub async fn update(state: Data<AppState>, actor: Actor, path: Path<i64>, venta: Json<Ventas>) -> impl Responder {
+    if let Err(errores) = venta.validate() {
+        return validacion::rechazar(errores);
+    }
     let id = path.into_inner();
-    match sqlx::query_as::<_, Ventas>(
-        "update ventas set iva_venta = $1, total_venta = $2, valor_venta = $3 where codigo = $4;",
-    )
-    .bind(venta.iva_venta)
-    .bind(venta.total_venta)
-    .bind(venta.valor_venta)
-    .bind(id)
-    .fetch_optional(&state.db)
-    .await
-    {
+    match state.repos.ventas.actualizar(&actor, id, &venta).await {
+        Ok(0) => match state.repos.ventas.obtener(id).await {
+            Ok(Some(_)) => HttpResponse::Conflict().json("venta is already finalized"),
+            Ok(None) => HttpResponse::NotFound().json("venta not found"),
+            Err(_) => HttpResponse::InternalServerError().json("could not read ventas"),
+        },
         Ok(_) => HttpResponse::Ok().json("venta updated"),
         Err(_) => HttpResponse::InternalServerError().json("could not update venta"),
     }
 }
 
-/// Borra una venta de la base de datos, por medio de la id en la uri
+/// Finaliza (cobra) una venta: sus detalles y los pagos registrados deben sumar exactamente su `total_venta`.
+/// Si no, responde `409 Conflict` con la diferencia de los detalles, o con lo que falta o sobra por pagar.
+/// Al finalizarla se le acumulan al cliente los puntos de fidelidad de la venta (ver `puntos`)
+/// y sus productos se descuentan del inventario (ver `inventario`), en la misma transaccion:
+/// si algo falla la venta no se finaliza
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `path` - la uri relativa a la api, esto es la id
+#[post("/{id}/finalizar")]
+pub async fn finalize(state: Data<AppState>, actor: Actor, path: Path<i64>) -> impl Responder {
+    let id = path.into_inner();
+    match state.repos.ventas.finalizar(&actor, id, &state.puntos).await {
+        Ok(0) => {}
+        Ok(_) => {
+            return match state.repos.ventas.obtener(id).await {
+                Ok(Some(venta)) => {
+                    metricas::registrar_venta(venta.total_venta);
+                    HttpResponse::Ok().json(venta)
+                }
+                _ => HttpResponse::InternalServerError().json("could not read ventas"),
+            };
+        }
+        Err(_) => return HttpResponse::InternalServerError().json("could not finalize venta"),
+    }
+    let venta = match state.repos.ventas.obtener(id).await {
+        Ok(Some(venta)) => venta,
+        Ok(None) => return HttpResponse::NotFound().json("venta not found"),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read ventas"),
+    };
+    if venta.finalizada_at.is_some() {
+        return HttpResponse::Conflict().json("venta is already finalized");
+    }
+    let detallado = match state.repos.detalle_ventas.por_venta(id).await {
+        Ok(detalles) => centavos(detalles.iter().fold(0.0, |suma, d| suma + d.valor_total)),
+        Err(_) => return HttpResponse::InternalServerError().json("could not read detalle_ventas"),
+    };
+    if detallado != centavos(venta.total_venta) {
+        return HttpResponse::Conflict()
+            .json(format!("detalle_ventas add up to {detallado} but total_venta is {}", venta.total_venta));
+    }
+    match state.repos.pagos.por_venta(id).await {
+        Ok(pagos) => {
+            let saldo = Saldo::new(venta.total_venta, &pagos);
+            HttpResponse::Conflict().json(format!(
+                "pagos add up to {} but total_venta is {} (pending {})",
+                saldo.pagado, saldo.total_venta, saldo.pendiente
+            ))
+        }
+        Err(_) => HttpResponse::InternalServerError().json("could not read pagos"),
+    }
+}
+
+/// Borra una venta de la base de datos, por medio de la id en la uri.
+/// Las ventas se borran de forma fisica, por lo que sus detalles, pagos y movimientos de puntos o de
+/// inventario impiden el borrado; una venta finalizada no se puede borrar.
+/// Con `?dry_run` no borra nada y responde con las filas que lo bloquean
 /// ### Parametros
 /// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
 /// * `path` - la uri relativa a la api, esto es la id
+/// * `borrado` - si el borrado es simulado
 #[delete("/{id}")]
-pub async fn delete(state: Data<AppState>, path: Path<i64>) -> impl Responder {
+pub async fn delete(state: Data<AppState>, actor: Actor, path: Path<i64>, borrado: Query<Borrado>) -> impl Responder {
     let id = path.into_inner();
-    match sqlx::query_as::<_, Ventas>("delete from ventas where codigo = $1;")
-        .bind(id)
-        .fetch_optional(&state.db)
-        .await
-    {
+    if borrado.es_simulacro() {
+        return match state.repos.ventas.informe_borrado(id).await {
+            Ok(Some(informe)) => HttpResponse::Ok().json(informe),
+            Ok(None) => HttpResponse::NotFound().json("venta not found"),
+            Err(_) => HttpResponse::InternalServerError().json("could not delete venta"),
+        };
+    }
+    match state.repos.ventas.borrar(&actor, id).await {
+        Ok(0) => match state.repos.ventas.obtener(id).await {
+            Ok(Some(_)) => HttpResponse::Conflict().json("venta is already finalized"),
+            Ok(None) => HttpResponse::NotFound().json("venta not found"),
+            Err(_) => HttpResponse::InternalServerError().json("could not delete venta"),
+        },
         Ok(_) => HttpResponse::Ok().json("Venta deleted"),
+        Err(ErrorRepo::LlaveForanea) => {
+            HttpResponse::Conflict().json("venta has detalle ventas, pagos or movimientos, delete them first")
+        }
         Err(_) => HttpResponse::InternalServerError().json("could not delete venta"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web, App};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{entities::usuarios::Usuarios, repos::Repos};
+
+    #[actix_web::test]
+    async fn read_by_id_expande_las_relaciones_sin_la_contraseña() {
+        let repos = Repos::memoria();
+        let actor = Actor::sistema();
+        let cliente: Clientes = serde_json::from_value(json!({
+            "cedula": 2001, "direccion": "Calle 1", "email": "ana@correo.co", "nombre": "Ana", "telefono": "300 111 2233",
+        }))
+        .unwrap();
+        let usuario: Usuarios = serde_json::from_value(json!({
+            "cedula": 1000, "email": "cajero@tienda.co", "nombre": "Cajero", "password": "secreto", "usuario": "cajero",
+        }))
+        .unwrap();
+        let venta: Ventas = serde_json::from_value(json!({
+            "codigo": 1, "cedula_cliente": 2001, "cedula_usuario": 1000,
+            "iva_venta": 19.0, "total_venta": 119.0, "valor_venta": 100.0,
+        }))
+        .unwrap();
+        repos.clientes.insertar(&actor, &cliente).await.unwrap();
+        repos.usuarios.insertar(&actor, &usuario).await.unwrap();
+        repos.ventas.insertar(&actor, &venta).await.unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(AppState::new(repos)))
+                .service(web::scope("/ventas").service(read_by_id)),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/ventas/1?expand=detalles,cliente,usuario").to_request();
+        let cuerpo: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(cuerpo["codigo"], 1);
+        assert_eq!(cuerpo["detalles"], json!([]));
+        assert_eq!(cuerpo["cliente"]["nombre"], "Ana");
+        assert_eq!(cuerpo["usuario"]["usuario"], "cajero");
+        assert!(cuerpo["usuario"].get("password").is_none());
+
+        let req = test::TestRequest::get().uri("/ventas/1?expand=productos").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+}
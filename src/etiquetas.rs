@@ -0,0 +1,223 @@
+//! Este archivo genera las etiquetas de los productos: el codigo de barras EAN-13 con sus digitos
+//! debajo, como SVG (con el nombre del producto) o como PNG en escala de grises.
+//! Los codigos se reciben ya validados y normalizados a 13 digitos (ver `codigos_barras::normalizar`).
+
+use std::io::Write;
+
+use flate2::{write::ZlibEncoder, Compression, Crc};
+
+/// Los patrones de los digitos del conjunto L; los del conjunto R son su complemento
+/// y los del conjunto G son los de R al reves
+const L: [u8; 10] = [
+    0b0001101, 0b0011001, 0b0010011, 0b0111101, 0b0100011, 0b0110001, 0b0101111, 0b0111011, 0b0110111, 0b0001011,
+];
+
+/// Los conjuntos de los seis digitos de la izquierda segun el primer digito (1 es G, 0 es L)
+const PARIDAD: [u8; 10] = [0b000000, 0b001011, 0b001101, 0b001110, 0b010011, 0b011001, 0b011100, 0b010101, 0b010110, 0b011010];
+
+/// Los modulos de un EAN-13: 3 de la guarda inicial, 42 de la izquierda, 5 de la guarda
+/// central, 42 de la derecha y 3 de la guarda final
+const MODULOS: usize = 95;
+
+/// Los modulos en blanco antes y despues de las barras (el primer digito se escribe a la izquierda)
+const MARGEN_IZQUIERDO: usize = 11;
+const MARGEN_DERECHO: usize = 7;
+const ANCHO: usize = MARGEN_IZQUIERDO + MODULOS + MARGEN_DERECHO;
+
+/// Los digitos del 0 al 9 en una fuente de 3x5 pixeles, por filas de arriba a abajo
+const FUENTE: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+fn digitos(codigo: &str) -> Vec<usize> {
+    codigo.bytes().map(|b| usize::from(b - b'0')).collect()
+}
+
+/// Los 95 modulos de un EAN-13 (`true` es una barra)
+fn modulos(codigo: &str) -> Vec<bool> {
+    let digitos = digitos(codigo);
+    let mut modulos = Vec::with_capacity(MODULOS);
+    let mut agregar = |patron: u8, largo: u32| {
+        modulos.extend((0..largo).rev().map(|bit| patron >> bit & 1 == 1));
+    };
+    agregar(0b101, 3);
+    for (posicion, &digito) in digitos[1..7].iter().enumerate() {
+        let r = !L[digito] & 0x7f;
+        let patron = if PARIDAD[digitos[0]] >> (5 - posicion) & 1 == 1 { r.reverse_bits() >> 1 } else { L[digito] };
+        agregar(patron, 7);
+    }
+    agregar(0b01010, 5);
+    for &digito in &digitos[7..13] {
+        agregar(!L[digito] & 0x7f, 7);
+    }
+    agregar(0b101, 3);
+    modulos
+}
+
+/// Si un modulo es parte de una guarda (las guardas son mas largas que las demas barras)
+fn es_guarda(modulo: usize) -> bool {
+    modulo < 3 || (45..50).contains(&modulo) || modulo >= 92
+}
+
+/// El modulo (contando el margen) donde empieza el espacio de 7 modulos de cada digito
+fn posicion_digito(indice: usize) -> usize {
+    match indice {
+        0 => MARGEN_IZQUIERDO - 7,
+        1..=6 => MARGEN_IZQUIERDO + 3 + 7 * (indice - 1),
+        _ => MARGEN_IZQUIERDO + 50 + 7 * (indice - 7),
+    }
+}
+
+/// Las barras como rangos de modulos consecutivos: `(inicio, ancho, es_guarda)`
+fn barras(codigo: &str) -> Vec<(usize, usize, bool)> {
+    let mut barras: Vec<(usize, usize, bool)> = Vec::new();
+    for (modulo, barra) in modulos(codigo).into_iter().enumerate() {
+        match barras.last_mut() {
+            _ if !barra => {}
+            Some((inicio, ancho, guarda)) if *inicio + *ancho == modulo && *guarda == es_guarda(modulo) => *ancho += 1,
+            _ => barras.push((modulo, 1, es_guarda(modulo))),
+        }
+    }
+    barras
+}
+
+/// Escapa un texto para incluirlo en un documento XML
+fn escapar(texto: &str) -> String {
+    texto
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// La etiqueta de un producto como SVG: el nombre, las barras y los digitos del codigo.
+/// Cada modulo mide 2 unidades
+/// ### Parametros
+/// * `codigo` - El codigo de barras de 13 digitos
+/// * `nombre` - El nombre del producto
+pub(crate) fn svg(codigo: &str, nombre: &str) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"180\" viewBox=\"0 0 {ANCHO} 90\">\
+         <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\
+         <text x=\"{}\" y=\"10\" font-family=\"sans-serif\" font-size=\"8\" text-anchor=\"middle\">{}</text><g fill=\"black\">",
+        ANCHO * 2,
+        ANCHO / 2,
+        escapar(nombre)
+    );
+    for (inicio, ancho, guarda) in barras(codigo) {
+        let alto = if guarda { 65 } else { 60 };
+        svg.push_str(&format!("<rect x=\"{}\" y=\"15\" width=\"{ancho}\" height=\"{alto}\"/>", MARGEN_IZQUIERDO + inicio));
+    }
+    svg.push_str("</g><g font-family=\"monospace\" font-size=\"9\" text-anchor=\"middle\">");
+    for (indice, digito) in codigo.chars().enumerate() {
+        svg.push_str(&format!("<text x=\"{}\" y=\"84\">{digito}</text>", posicion_digito(indice) as f64 + 3.5));
+    }
+    svg.push_str("</g></svg>");
+    svg
+}
+
+/// Agrega un chunk (largo, tipo, datos y CRC) a un PNG
+fn chunk(png: &mut Vec<u8>, tipo: &[u8; 4], datos: &[u8]) {
+    png.extend_from_slice(&(datos.len() as u32).to_be_bytes());
+    png.extend_from_slice(tipo);
+    png.extend_from_slice(datos);
+    let mut crc = Crc::new();
+    crc.update(tipo);
+    crc.update(datos);
+    png.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+/// La etiqueta de un producto como PNG en escala de grises: las barras y los digitos del codigo.
+/// Cada modulo mide 2 pixeles
+/// ### Parametros
+/// * `codigo` - El codigo de barras de 13 digitos
+pub(crate) fn png(codigo: &str) -> Vec<u8> {
+    const ESCALA: usize = 2;
+    const MARGEN: usize = 10;
+    const ALTO_BARRAS: usize = 100;
+    const PIXEL_FUENTE: usize = 3;
+    let (ancho, alto) = (ANCHO * ESCALA, 2 * MARGEN + ALTO_BARRAS + 4 + 5 * PIXEL_FUENTE);
+    let mut pixeles = vec![0xffu8; ancho * alto];
+    let mut pintar = |x: usize, y: usize, w: usize, h: usize| {
+        for fila in y..y + h {
+            pixeles[fila * ancho + x..fila * ancho + x + w].fill(0);
+        }
+    };
+    for (inicio, largo, guarda) in barras(codigo) {
+        let alto_barra = if guarda { ALTO_BARRAS + 10 } else { ALTO_BARRAS };
+        pintar((MARGEN_IZQUIERDO + inicio) * ESCALA, MARGEN, largo * ESCALA, alto_barra);
+    }
+    for (indice, digito) in digitos(codigo).into_iter().enumerate() {
+        let x = posicion_digito(indice) * ESCALA + (7 * ESCALA - 3 * PIXEL_FUENTE) / 2;
+        for (fila, bits) in FUENTE[digito].iter().enumerate() {
+            for columna in (0..3).filter(|columna| bits >> (2 - columna) & 1 == 1) {
+                let y = MARGEN + ALTO_BARRAS + 4 + fila * PIXEL_FUENTE;
+                pintar(x + columna * PIXEL_FUENTE, y, PIXEL_FUENTE, PIXEL_FUENTE);
+            }
+        }
+    }
+
+    // cada fila empieza con el tipo de filtro (0, sin filtro)
+    let mut filas = Vec::with_capacity((ancho + 1) * alto);
+    for fila in pixeles.chunks(ancho) {
+        filas.push(0);
+        filas.extend_from_slice(fila);
+    }
+    let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+    zlib.write_all(&filas).expect("writing to a Vec does not fail");
+    let comprimido = zlib.finish().expect("writing to a Vec does not fail");
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut cabecera = Vec::with_capacity(13);
+    cabecera.extend_from_slice(&(ancho as u32).to_be_bytes());
+    cabecera.extend_from_slice(&(alto as u32).to_be_bytes());
+    // 8 bits por pixel, escala de grises, compresion y filtros estandar, sin entrelazado
+    cabecera.extend_from_slice(&[8, 0, 0, 0, 0]);
+    chunk(&mut png, b"IHDR", &cabecera);
+    chunk(&mut png, b"IDAT", &comprimido);
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patron(modulos: &[bool]) -> String {
+        modulos.iter().map(|&barra| if barra { '1' } else { '0' }).collect()
+    }
+
+    #[test]
+    fn las_barras_siguen_la_paridad_del_primer_digito() {
+        // 5901234123457: el 5 usa los conjuntos LGGLLG a la izquierda
+        let modulos = modulos("5901234123457");
+        assert_eq!(modulos.len(), MODULOS);
+        assert_eq!(patron(&modulos[..3]), "101");
+        assert_eq!(patron(&modulos[3..10]), "0001011"); // 9 en L
+        assert_eq!(patron(&modulos[10..17]), "0100111"); // 0 en G
+        assert_eq!(patron(&modulos[17..24]), "0110011"); // 1 en G
+        assert_eq!(patron(&modulos[24..31]), "0010011"); // 2 en L
+        assert_eq!(patron(&modulos[45..50]), "01010");
+        assert_eq!(patron(&modulos[50..57]), "1100110"); // 1 en R
+        assert_eq!(patron(&modulos[92..]), "101");
+    }
+
+    #[test]
+    fn el_png_tiene_las_dimensiones_de_la_etiqueta() {
+        let png = png("5901234123457");
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), (ANCHO * 2) as u32);
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+    }
+}
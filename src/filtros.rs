@@ -0,0 +1,83 @@
+//! Este archivo contiene los parametros de consulta (query string)
+//! compartidos por varios servicios REST.
+
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use serde::Deserialize;
+
+/// Filtro por rango de fechas, ambos extremos inclusivos y opcionales.
+/// Ejemplo: `?desde=2023-11-01&hasta=2023-11-30`
+#[derive(Debug, Deserialize)]
+pub struct RangoFechas {
+    pub desde: Option<NaiveDate>,
+    pub hasta: Option<NaiveDate>,
+}
+
+impl RangoFechas {
+    /// El primer instante (UTC) incluido en el rango
+    pub fn inicio(&self) -> Option<DateTime<Utc>> {
+        self.desde.map(|d| d.and_time(Default::default()).and_utc())
+    }
+
+    /// El primer instante (UTC) que ya no pertenece al rango,
+    /// es decir el inicio del dia siguiente a `hasta`
+    pub fn fin(&self) -> Option<DateTime<Utc>> {
+        self.hasta
+            .and_then(|d| d.checked_add_days(Days::new(1)))
+            .map(|d| d.and_time(Default::default()).and_utc())
+    }
+}
+
+/// Filtro por la categoria de los productos, que incluye sus subcategorias.
+/// Ejemplo: `?categoria=3`
+#[derive(Debug, Deserialize)]
+pub struct FiltroCategoria {
+    pub categoria: Option<i64>,
+}
+
+/// Filtro por la sucursal de las ventas, las existencias y demas datos de cada sucursal.
+/// Ejemplo: `?sucursal=2`
+#[derive(Debug, Deserialize)]
+pub struct FiltroSucursal {
+    pub sucursal: Option<i64>,
+}
+
+/// Filtro por el estado de las filas de un proceso (ordenes de compra, conteos).
+/// Ejemplo: `?estado=pendiente`
+#[derive(Debug, Deserialize)]
+pub struct FiltroEstado {
+    pub estado: Option<String>,
+}
+
+/// Paginacion de los listados anidados.
+/// Ejemplo: `?pagina=2&por_pagina=20` (por defecto la primera pagina de 50 filas)
+#[derive(Debug, Deserialize)]
+pub struct Paginacion {
+    pub pagina: Option<i64>,
+    pub por_pagina: Option<i64>,
+}
+
+impl Paginacion {
+    /// La cantidad de filas por pagina, entre 1 y 200
+    pub fn limite(&self) -> i64 {
+        self.por_pagina.unwrap_or(50).clamp(1, 200)
+    }
+
+    /// La cantidad de filas a saltar para llegar a la pagina pedida. Una pagina enorme no desborda:
+    /// el desplazamiento se satura y el listado queda vacio
+    pub fn desplazamiento(&self) -> i64 {
+        (self.pagina.unwrap_or(1).max(1) - 1).saturating_mul(self.limite())
+    }
+}
+
+/// Los listados ocultan las filas borradas (soft delete) a menos que se pida
+/// `?incluir_borrados=true`
+#[derive(Debug, Deserialize)]
+pub struct Borrados {
+    pub incluir_borrados: Option<bool>,
+}
+
+impl Borrados {
+    pub fn incluir(&self) -> bool {
+        self.incluir_borrados.unwrap_or(false)
+    }
+}
@@ -0,0 +1,212 @@
+//! El backend de la tienda generica como libreria: las entidades, sus servicios REST
+//! y la capa de almacenamiento. El binario (`main.rs`) solo contiene la linea de comandos
+//! y el servidor http, de modo que las pruebas de integracion (`tests/`) pueden
+//! montar la misma aplicacion sobre una base de datos desechable.
+
+mod auth;
+mod borrado;
+pub mod cli;
+mod entities;
+mod etiquetas;
+mod filtros;
+mod metricas;
+pub mod migraciones;
+pub mod registro;
+mod repos;
+mod salud;
+pub mod tareas;
+mod validacion;
+
+use actix_web::web;
+
+use crate::entities::*;
+pub use crate::entities::puntos::ConfigPuntos;
+pub use crate::repos::{ConfigPool, Db, Repos};
+
+/// Este struct indica la coneccion a la base de datos
+/// por medio de los repositorios de cada entidad, que comparten una picina de conecciones
+/// (de postgres o de SQLite) que permite multiples operaciones concurrentes,
+/// y la configuracion de los puntos de fidelidad
+pub struct AppState {
+    repos: Repos,
+    puntos: ConfigPuntos,
+}
+
+impl AppState {
+    pub fn new(repos: Repos) -> Self {
+        AppState { repos, puntos: ConfigPuntos::default() }
+    }
+
+    /// Cambia la configuracion de los puntos de fidelidad
+    pub fn con_puntos(self, puntos: ConfigPuntos) -> Self {
+        AppState { puntos, ..self }
+    }
+}
+
+/// Los servicios REST de todas las entidades, bajo el scope `/api`, y los servicios de salud
+/// (`/health`, `/ready`, `/version`) y las metricas (`/metrics`). Requiere `Data<AppState>` y `Data<Db>` en la aplicacion
+/// ### Parametros
+/// * `cfg` - La configuracion de la aplicacion de actix
+pub fn rutas(cfg: &mut web::ServiceConfig) {
+    cfg.service(salud::health)
+        .service(salud::ready)
+        .service(salud::version)
+        .service(metricas::metrics);
+    cfg.service(
+        web::scope("/api")
+            .service(
+                web::scope("/clientes")
+                    .service(clientes::read_all)
+                    .service(clientes::read_by_id)
+                    .service(clientes::read_ventas)
+                    .service(puntos::read_by_cliente)
+                    .service(clientes::create)
+                    .service(clientes::delete)
+                    .service(clientes::restore)
+                    .service(clientes::update),
+            )
+            .service(
+                web::scope("/usuarios")
+                    .service(usuarios::auth)
+                    .service(usuarios::read_all)
+                    .service(usuarios::read_by_id)
+                    .service(usuarios::read_ventas)
+                    .service(sucursales::read_by_usuario)
+                    .service(usuarios::create)
+                    .service(sucursales::assign)
+                    .service(sucursales::unassign)
+                    .service(usuarios::delete)
+                    .service(usuarios::restore)
+                    .service(usuarios::update),
+            )
+            .service(
+                web::scope("/proveedores")
+                    .service(proveedores::read_all)
+                    .service(proveedores::read_by_id)
+                    .service(proveedores::read_productos)
+                    .service(proveedores::create)
+                    .service(proveedores::delete)
+                    .service(proveedores::restore)
+                    .service(proveedores::update),
+            )
+            .service(
+                web::scope("/categorias")
+                    .service(categorias::read_all)
+                    .service(categorias::read_by_id)
+                    .service(categorias::create)
+                    .service(categorias::delete)
+                    .service(categorias::restore)
+                    .service(categorias::update),
+            )
+            .service(
+                web::scope("/productos")
+                    .service(codigos_barras::read_by_barcode)
+                    .service(productos::read_all)
+                    .service(productos::read_by_id)
+                    .service(productos::create)
+                    .service(productos::delete)
+                    .service(productos::restore)
+                    .service(productos::update)
+                    .service(productos::create_json)
+                    .service(precios::read_historial)
+                    .service(precios::create_programado)
+                    .service(precios::delete_programado)
+                    .service(codigos_barras::read_by_producto)
+                    .service(codigos_barras::create)
+                    .service(codigos_barras::delete)
+                    .service(codigos_barras::read_etiqueta),
+            )
+            .service(
+                web::scope("/ventas")
+                    .service(ventas::read_all)
+                    .service(categorias::read_ventas)
+                    .service(ventas::read_by_id)
+                    .service(ventas::read_detalles)
+                    .service(pagos::read_all)
+                    .service(ventas::create)
+                    .service(ventas::calculate)
+                    .service(ventas::finalize)
+                    .service(pagos::create)
+                    .service(pagos::delete)
+                    .service(ventas::delete)
+                    .service(ventas::update),
+            )
+            .service(
+                web::scope("/promociones")
+                    .service(promociones::read_all)
+                    .service(promociones::read_by_id)
+                    .service(promociones::create)
+                    .service(promociones::delete)
+                    .service(promociones::restore)
+                    .service(promociones::update),
+            )
+            .service(
+                web::scope("/inventario")
+                    .service(inventario::read_all)
+                    .service(inventario::read_alertas)
+                    .service(inventario::read_sugerencias)
+                    .service(inventario::read_movimientos),
+            )
+            .service(
+                web::scope("/ordenes_compra")
+                    .service(ordenes_compra::read_all)
+                    .service(ordenes_compra::read_by_id)
+                    .service(ordenes_compra::create)
+                    .service(ordenes_compra::create_sugerida)
+                    .service(ordenes_compra::receive)
+                    .service(ordenes_compra::cancel),
+            )
+            .service(
+                web::scope("/conteos")
+                    .service(conteos::read_all)
+                    .service(conteos::read_by_id)
+                    .service(conteos::read_diferencias)
+                    .service(conteos::create)
+                    .service(conteos::create_cantidades)
+                    .service(conteos::create_csv)
+                    .service(conteos::apply)
+                    .service(conteos::cancel),
+            )
+            .service(
+                web::scope("/sucursales")
+                    .service(sucursales::read_all)
+                    .service(sucursales::read_by_id)
+                    .service(sucursales::create)
+                    .service(sucursales::update),
+            )
+            .service(
+                web::scope("/traslados")
+                    .service(traslados::read_all)
+                    .service(traslados::read_by_id)
+                    .service(traslados::create),
+            )
+            .service(
+                web::scope("/cajas")
+                    .service(cajas::read_all)
+                    .service(cajas::read_by_id)
+                    .service(cajas::create)
+                    .service(cajas::update),
+            )
+            .service(
+                web::scope("/turnos")
+                    .service(turnos::read_all)
+                    .service(turnos::read_actual)
+                    .service(turnos::read_by_id)
+                    .service(turnos::read_movimientos)
+                    .service(turnos::read_arqueo)
+                    .service(turnos::create)
+                    .service(turnos::create_movimiento)
+                    .service(turnos::close),
+            )
+            .service(web::scope("/auditoria").service(auditoria::read_all))
+            .service(web::scope("/logs").service(registro::read_nivel).service(registro::update_nivel))
+            .service(
+                web::scope("/detalle_ventas")
+                    .service(detalle_ventas::read_all)
+                    .service(detalle_ventas::read_by_id)
+                    .service(detalle_ventas::create)
+                    .service(detalle_ventas::delete)
+                    .service(detalle_ventas::update),
+            ),
+    );
+}
@@ -0,0 +1,154 @@
+//! Este archivo contiene las metricas del servidor en el formato de Prometheus, expuestas en `/metrics`:
+//! los requests y su latencia por scope de la api, el uso de la picina de conecciones,
+//! las importaciones de CSV y los contadores del negocio (ventas e ingresos).
+//! Los contadores empiezan en cero cada vez que inicia el proceso, como es usual en Prometheus.
+
+use std::sync::LazyLock;
+
+use actix_web::{get, web::Data, HttpResponse, Responder};
+use prometheus::{
+    Counter, Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+
+use crate::repos::Db;
+
+/// Los scopes de la api que se distinguen en las metricas; el resto de rutas se agrupa en `otro`
+const SCOPES: [&str; 21] = [
+    "clientes",
+    "usuarios",
+    "proveedores",
+    "categorias",
+    "productos",
+    "ventas",
+    "detalle_ventas",
+    "promociones",
+    "inventario",
+    "ordenes_compra",
+    "conteos",
+    "sucursales",
+    "traslados",
+    "cajas",
+    "turnos",
+    "auditoria",
+    "logs",
+    "health",
+    "ready",
+    "version",
+    "metrics",
+];
+
+/// Todas las metricas del servidor
+struct Metricas {
+    registro: Registry,
+    requests: IntCounterVec,
+    latencia: HistogramVec,
+    conexiones: IntGaugeVec,
+    conexiones_maximas: IntGauge,
+    filas_importadas: IntCounter,
+    importaciones_fallidas: IntCounter,
+    ventas: IntCounter,
+    ingresos: Counter,
+}
+
+static METRICAS: LazyLock<Metricas> = LazyLock::new(|| {
+    let registro = Registry::new_custom(Some("tienda".to_owned()), None).expect("valid prefix");
+    let requests = IntCounterVec::new(
+        Opts::new("http_requests_total", "Requests atendidos, por scope de la api, metodo y estado"),
+        &["scope", "method", "status"],
+    )
+    .expect("valid metric");
+    let latencia = HistogramVec::new(
+        HistogramOpts::new("http_request_duration_seconds", "Latencia de los requests, por scope de la api"),
+        &["scope"],
+    )
+    .expect("valid metric");
+    let conexiones = IntGaugeVec::new(
+        Opts::new("db_pool_connections", "Conecciones abiertas de la picina, segun si estan en uso o inactivas"),
+        &["state"],
+    )
+    .expect("valid metric");
+    let conexiones_maximas =
+        IntGauge::new("db_pool_max_connections", "Maximo de conecciones de la picina").expect("valid metric");
+    let filas_importadas =
+        IntCounter::new("csv_import_rows_total", "Productos importados desde CSV").expect("valid metric");
+    let importaciones_fallidas =
+        IntCounter::new("csv_import_failures_total", "Importaciones de CSV rechazadas").expect("valid metric");
+    let ventas = IntCounter::new("sales_total", "Ventas finalizadas").expect("valid metric");
+    let ingresos =
+        Counter::new("sales_revenue_total", "Suma del valor total (con IVA) de las ventas finalizadas")
+            .expect("valid metric");
+
+    for metrica in [
+        Box::new(requests.clone()) as Box<dyn prometheus::core::Collector>,
+        Box::new(latencia.clone()),
+        Box::new(conexiones.clone()),
+        Box::new(conexiones_maximas.clone()),
+        Box::new(filas_importadas.clone()),
+        Box::new(importaciones_fallidas.clone()),
+        Box::new(ventas.clone()),
+        Box::new(ingresos.clone()),
+    ] {
+        registro.register(metrica).expect("unique metric");
+    }
+    Metricas {
+        registro,
+        requests,
+        latencia,
+        conexiones,
+        conexiones_maximas,
+        filas_importadas,
+        importaciones_fallidas,
+        ventas,
+        ingresos,
+    }
+});
+
+/// El scope de una ruta: `/api/ventas/1` es `ventas` y `/health` es `health`
+fn scope(ruta: &str) -> &'static str {
+    let mut partes = ruta.trim_start_matches('/').split('/');
+    let primera = partes.next().unwrap_or_default();
+    let nombre = if primera == "api" { partes.next().unwrap_or_default() } else { primera };
+    SCOPES.iter().find(|scope| **scope == nombre).copied().unwrap_or("otro")
+}
+
+/// Registra un request atendido (ver `registro::Solicitudes`)
+pub(crate) fn registrar_request(ruta: &str, metodo: &str, estado: u16, segundos: f64) {
+    let scope = scope(ruta);
+    METRICAS.requests.with_label_values(&[scope, metodo, &estado.to_string()]).inc();
+    METRICAS.latencia.with_label_values(&[scope]).observe(segundos);
+}
+
+/// Registra los productos de una importacion de CSV
+pub(crate) fn registrar_importacion(filas: u64) {
+    METRICAS.filas_importadas.inc_by(filas);
+}
+
+/// Registra una importacion de CSV rechazada
+pub(crate) fn registrar_importacion_fallida() {
+    METRICAS.importaciones_fallidas.inc();
+}
+
+/// Registra una venta finalizada y su valor total guardado
+pub(crate) fn registrar_venta(total_venta: f64) {
+    METRICAS.ventas.inc();
+    METRICAS.ingresos.inc_by(total_venta);
+}
+
+/// Las metricas en el formato de texto de Prometheus
+/// ### Parametros
+/// * `db` - La coneccion a la base de datos, para el uso de la picina de conecciones
+#[get("/metrics")]
+pub async fn metrics(db: Data<Db>) -> impl Responder {
+    let uso = db.uso();
+    METRICAS.conexiones.with_label_values(&["idle"]).set(uso.inactivas as i64);
+    METRICAS.conexiones.with_label_values(&["in_use"]).set(uso.abiertas as i64 - uso.inactivas as i64);
+    METRICAS.conexiones_maximas.set(uso.maximo as i64);
+
+    let encoder = TextEncoder::new();
+    let mut salida = Vec::new();
+    match encoder.encode(&METRICAS.registro.gather(), &mut salida) {
+        Ok(()) => HttpResponse::Ok().content_type(encoder.format_type()).body(salida),
+        Err(e) => HttpResponse::InternalServerError().json(e.to_string()),
+    }
+}
@@ -0,0 +1,121 @@
+//! Este archivo contiene las migraciones de la base de datos, incluidas en el binario
+//! desde la carpeta `migrations/` (una subcarpeta por motor de base de datos),
+//! y las operaciones para aplicarlas o revertirlas sin necesidad de usar psql.
+
+use std::collections::HashSet;
+
+use sqlx::{
+    migrate::{Migrate, MigrateError, Migrator},
+    Database, Pool,
+};
+
+use crate::repos::Db;
+
+/// Las migraciones de la carpeta `migrations/postgres`
+pub static POSTGRES: Migrator = sqlx::migrate!("migrations/postgres");
+
+/// Las migraciones de la carpeta `migrations/sqlite`
+pub static SQLITE: Migrator = sqlx::migrate!("migrations/sqlite");
+
+/// El estado de una migracion en la base de datos
+#[derive(Debug)]
+pub struct EstadoMigracion {
+    pub version: i64,
+    pub descripcion: String,
+    pub aplicada: bool,
+}
+
+/// Aplica todas las migraciones pendientes
+pub async fn subir(db: &Db) -> Result<(), MigrateError> {
+    match db {
+        Db::Postgres(pool) => POSTGRES.run(pool).await,
+        Db::Sqlite(pool) => SQLITE.run(pool).await,
+    }
+}
+
+/// Revierte la ultima migracion aplicada y retorna su version,
+/// o `None` si no habia migraciones aplicadas
+pub async fn bajar(db: &Db) -> Result<Option<i64>, MigrateError> {
+    match db {
+        Db::Postgres(pool) => revertir_ultima(&POSTGRES, pool).await,
+        Db::Sqlite(pool) => revertir_ultima(&SQLITE, pool).await,
+    }
+}
+
+/// Obtiene el estado de cada una de las migraciones
+pub async fn estado(db: &Db) -> Result<Vec<EstadoMigracion>, MigrateError> {
+    let (migrador, aplicadas) = match db {
+        Db::Postgres(pool) => (&POSTGRES, aplicadas(pool).await?),
+        Db::Sqlite(pool) => (&SQLITE, aplicadas(pool).await?),
+    };
+    Ok(estados(migrador, aplicadas))
+}
+
+/// Obtiene el estado de cada una de las migraciones sin modificar la base de datos: a diferencia de
+/// `estado`, no crea la tabla de migraciones si no existe. Lo usan los servicios de salud
+pub async fn consultar(db: &Db) -> Result<Vec<EstadoMigracion>, sqlx::Error> {
+    const VERSIONES: &str = "select version from _sqlx_migrations order by version;";
+    let (migrador, aplicadas) = match db {
+        Db::Postgres(pool) => {
+            let existe = sqlx::query_scalar::<_, bool>("select to_regclass('_sqlx_migrations') is not null;")
+                .fetch_one(pool)
+                .await?;
+            let aplicadas = if existe { sqlx::query_scalar(VERSIONES).fetch_all(pool).await? } else { Vec::new() };
+            (&POSTGRES, aplicadas)
+        }
+        Db::Sqlite(pool) => {
+            let existe = sqlx::query_scalar::<_, bool>(
+                "select count(*) > 0 from sqlite_master where type = 'table' and name = '_sqlx_migrations';",
+            )
+            .fetch_one(pool)
+            .await?;
+            let aplicadas = if existe { sqlx::query_scalar(VERSIONES).fetch_all(pool).await? } else { Vec::new() };
+            (&SQLITE, aplicadas)
+        }
+    };
+    Ok(estados(migrador, aplicadas))
+}
+
+/// El estado de cada migracion del migrador segun las versiones aplicadas
+fn estados(migrador: &Migrator, aplicadas: Vec<i64>) -> Vec<EstadoMigracion> {
+    let aplicadas: HashSet<i64> = aplicadas.into_iter().collect();
+    migrador
+        .iter()
+        .filter(|m| m.migration_type.is_up_migration())
+        .map(|m| EstadoMigracion {
+            version: m.version,
+            descripcion: m.description.to_string(),
+            aplicada: aplicadas.contains(&m.version),
+        })
+        .collect()
+}
+
+async fn revertir_ultima<DB>(migrador: &Migrator, pool: &Pool<DB>) -> Result<Option<i64>, MigrateError>
+where
+    DB: Database,
+    DB::Connection: Migrate,
+{
+    let mut aplicadas = aplicadas(pool).await?;
+    aplicadas.sort_unstable();
+    let Some(ultima) = aplicadas.pop() else {
+        return Ok(None);
+    };
+    migrador.undo(pool, aplicadas.last().copied().unwrap_or(0)).await?;
+    Ok(Some(ultima))
+}
+
+/// Las versiones de las migraciones aplicadas en la base de datos
+async fn aplicadas<DB>(pool: &Pool<DB>) -> Result<Vec<i64>, MigrateError>
+where
+    DB: Database,
+    DB::Connection: Migrate,
+{
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    Ok(conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| m.version)
+        .collect())
+}
@@ -0,0 +1,184 @@
+//! Este archivo contiene los logs del servidor, con `tracing`.
+//! Cada request queda registrado con su metodo, ruta, estado, latencia, usuario autenticado
+//! y un id de request (el header `X-Request-Id`, o uno nuevo si el cliente no lo envia).
+//! El nivel se configura con `RUST_LOG` (por defecto `info`) y un administrador lo puede cambiar sin
+//! reiniciar el servidor desde `/api/logs/nivel`; con `LOG_FORMAT=json` cada linea es un objeto json.
+//! El mismo middleware alimenta las metricas de requests (ver `metricas`).
+
+use std::{
+    future::{ready, Future, Ready},
+    io::IsTerminal,
+    pin::Pin,
+    sync::OnceLock,
+    time::Instant,
+};
+
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    get,
+    http::header::{HeaderName, HeaderValue},
+    patch,
+    web::{Data, Json},
+    Error, HttpResponse, Responder,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{field, Instrument};
+use tracing_subscriber::{fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry};
+
+use crate::{
+    auth::{self, Actor},
+    metricas, AppState,
+};
+
+/// El header con el id de cada request
+const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Permite cambiar el filtro de los logs mientras el servidor esta corriendo
+static FILTRO: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Inicia los logs (en la salida de errores, para no mezclarlos con la salida de la linea de comandos).
+/// Los logs de sqlx (una linea por consulta, en nivel `debug`) se incluyen en el request que las ejecuta
+/// (con SQLite las consultas corren en otro hilo y quedan fuera del request)
+pub fn iniciar() {
+    // los avisos de postgres (como "relation already exists, skipping") no son utiles en nivel `info`
+    let filtro = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info,sqlx::postgres::notice=warn"));
+    let (filtro, manejador) = reload::Layer::new(filtro);
+    let json = std::env::var("LOG_FORMAT").is_ok_and(|formato| formato.eq_ignore_ascii_case("json"));
+    let registro = tracing_subscriber::registry().with(filtro);
+    let iniciado = if json {
+        registro
+            .with(fmt::layer().json().with_current_span(true).with_span_list(false).with_writer(std::io::stderr))
+            .try_init()
+    } else {
+        let colores = std::io::stderr().is_terminal();
+        registro.with(fmt::layer().with_ansi(colores).with_writer(std::io::stderr)).try_init()
+    };
+    if iniciado.is_ok() {
+        let _ = FILTRO.set(manejador);
+    }
+}
+
+/// Registra en el request el usuario autenticado que realiza la operacion
+pub(crate) fn registrar_actor(actor: &Actor) {
+    if let Some(cedula) = actor.cedula {
+        tracing::Span::current().record("cedula_usuario", cedula);
+    }
+}
+
+/// El middleware que registra cada request
+pub struct Solicitudes;
+
+impl<S, B> Transform<S, ServiceRequest> for Solicitudes
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = SolicitudesMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, servicio: S) -> Self::Future {
+        ready(Ok(SolicitudesMiddleware { servicio }))
+    }
+}
+
+pub struct SolicitudesMiddleware<S> {
+    servicio: S,
+}
+
+impl<S, B> Service<ServiceRequest> for SolicitudesMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    actix_web::dev::forward_ready!(servicio);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let inicio = Instant::now();
+        let request_id = req
+            .headers()
+            .get(&REQUEST_ID)
+            .and_then(|id| id.to_str().ok())
+            .filter(|id| !id.is_empty() && id.len() <= 64)
+            .map(str::to_owned)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let (ruta, metodo) = (req.path().to_owned(), req.method().to_string());
+        let span = tracing::info_span!(
+            "request",
+            request_id = %request_id,
+            method = %metodo,
+            path = %ruta,
+            cedula_usuario = field::Empty,
+        );
+        let respuesta = span.in_scope(|| self.servicio.call(req));
+        Box::pin(
+            async move {
+                let mut respuesta = respuesta.await?;
+                let estado = respuesta.status();
+                let segundos = inicio.elapsed().as_secs_f64();
+                metricas::registrar_request(&ruta, &metodo, estado.as_u16(), segundos);
+                let latencia_ms = segundos * 1000.0;
+                let error = respuesta.response().error().map(ToString::to_string);
+                if estado.is_server_error() {
+                    tracing::error!(status = estado.as_u16(), latencia_ms, error, "request");
+                } else if estado.is_client_error() {
+                    tracing::warn!(status = estado.as_u16(), latencia_ms, error, "request");
+                } else {
+                    tracing::info!(status = estado.as_u16(), latencia_ms, "request");
+                }
+                if let Ok(id) = HeaderValue::from_str(&request_id) {
+                    respuesta.headers_mut().insert(REQUEST_ID, id);
+                }
+                Ok(respuesta)
+            }
+            .instrument(span),
+        )
+    }
+}
+
+/// El filtro de los logs, con la sintaxis de `RUST_LOG` (por ejemplo `debug` o `info,sqlx=debug`)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Nivel {
+    filtro: String,
+}
+
+/// Obtiene el filtro actual de los logs. Requiere un usuario autenticado
+/// ### Parametros
+/// * `_actor` - El usuario autenticado
+#[get("/nivel")]
+pub async fn read_nivel(_actor: Actor) -> impl Responder {
+    match FILTRO.get().and_then(|filtro| filtro.with_current(|actual| actual.to_string()).ok()) {
+        Some(filtro) => HttpResponse::Ok().json(Nivel { filtro }),
+        None => HttpResponse::ServiceUnavailable().json("logging is not initialized"),
+    }
+}
+
+/// Cambia el filtro de los logs sin reiniciar el servidor. Solo lo puede cambiar un administrador
+/// ### Parametros
+/// * `state` - La coneccion a la base de datos
+/// * `actor` - El usuario autenticado que realiza la operacion
+/// * `nivel` - Un json en el body del request con el nuevo filtro
+#[patch("/nivel")]
+pub async fn update_nivel(state: Data<AppState>, actor: Actor, nivel: Json<Nivel>) -> impl Responder {
+    if let Err(respuesta) = auth::administrador(&state, &actor).await {
+        return respuesta;
+    }
+    let Some(manejador) = FILTRO.get() else {
+        return HttpResponse::ServiceUnavailable().json("logging is not initialized");
+    };
+    let filtro = match EnvFilter::try_new(&nivel.filtro) {
+        Ok(filtro) => filtro,
+        Err(e) => return HttpResponse::BadRequest().json(e.to_string()),
+    };
+    match manejador.reload(filtro) {
+        Ok(()) => {
+            tracing::info!(filtro = %nivel.filtro, cedula_usuario = actor.cedula, "log level changed");
+            HttpResponse::Ok().json("log level updated")
+        }
+        Err(e) => HttpResponse::InternalServerError().json(e.to_string()),
+    }
+}
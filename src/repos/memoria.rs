@@ -0,0 +1,1879 @@
+//! Este archivo contiene una implementacion de los repositorios en memoria, para probar
+//! los servicios REST y la logica de las entidades sin una base de datos.
+//! Imita las reglas del esquema SQL: llaves primarias unicas, llaves foraneas,
+//! borrado logico y el registro de cada operacion en la auditoria.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Mutex,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::types::Json;
+
+use super::{
+    AuditoriaRepo, CajasRepo, CategoriasRepo, ClientesRepo, CodigosBarrasRepo, ConteosRepo, DetalleVentasRepo, ErrorRepo,
+    InventarioRepo, OrdenesCompraRepo, PagosRepo, PreciosRepo, ProductosRepo, PromocionesRepo, ProveedoresRepo, PuntosRepo,
+    Resultado, SucursalesRepo, TrasladosRepo, TurnosRepo, UsuariosRepo, VentasRepo,
+};
+use crate::{
+    auth::Actor,
+    borrado::InformeBorrado,
+    entities::{
+        auditoria::{
+            self, Accion, Auditoria, FiltroAuditoria, Tabla, CAJAS, CATEGORIAS, CLIENTES, CODIGOS_BARRAS, CONTEOS,
+            DETALLE_CONTEOS, DETALLE_VENTAS, MOVIMIENTOS_CAJA, MOVIMIENTOS_INVENTARIO, MOVIMIENTOS_PUNTOS, ORDENES_COMPRA, PAGOS,
+            PRECIOS_PROGRAMADOS, PRODUCTOS, PROMOCIONES, PROVEEDORES, SUCURSALES, TRASLADOS, TURNOS, USUARIOS,
+            USUARIOS_SUCURSALES, VENTAS,
+        },
+        cajas::Cajas,
+        categorias::{self, Categorias, VentasCategoria},
+        clientes::Clientes,
+        codigos_barras::CodigosBarras,
+        conteos::{self, Conteos, DetalleConteos},
+        detalle_ventas::DetalleVentas,
+        inventario::{self, Existencias, MovimientosInventario, Vendido},
+        ordenes_compra::{self, DetalleOrdenesCompra, OrdenesCompra},
+        pagos::{centavos, Pagos, EFECTIVO, PUNTOS},
+        precios::{self, PreciosHistorial, PreciosProgramados},
+        productos::Productos,
+        promociones::Promociones,
+        proveedores::Proveedores,
+        puntos::{ConfigPuntos, EstadoPuntos, MovimientosPuntos},
+        sucursales::{self, Sucursales, UsuariosSucursales},
+        traslados::{self, LineaTraslado, Traslados},
+        turnos::{MovimientosCaja, TotalesTurno, Turnos, ENTRADA, SALIDA},
+        usuarios::Usuarios,
+        ventas::Ventas,
+    },
+    filtros::{Paginacion, RangoFechas},
+};
+
+/// Las tablas, indexadas por su llave primaria
+#[derive(Debug, Default)]
+struct Tablas {
+    clientes: BTreeMap<i64, Clientes>,
+    usuarios: BTreeMap<i64, Usuarios>,
+    proveedores: BTreeMap<i64, Proveedores>,
+    categorias: BTreeMap<i64, Categorias>,
+    productos: BTreeMap<i64, Productos>,
+    codigos_barras: BTreeMap<i64, CodigosBarras>,
+    precios_historial: BTreeMap<i64, PreciosHistorial>,
+    precios_programados: BTreeMap<i64, PreciosProgramados>,
+    ventas: BTreeMap<i64, Ventas>,
+    detalle_ventas: BTreeMap<i64, DetalleVentas>,
+    promociones: BTreeMap<i64, Promociones>,
+    pagos: BTreeMap<i64, Pagos>,
+    movimientos_puntos: BTreeMap<i64, MovimientosPuntos>,
+    cajas: BTreeMap<i64, Cajas>,
+    turnos: BTreeMap<i64, Turnos>,
+    movimientos_caja: BTreeMap<i64, MovimientosCaja>,
+    movimientos_inventario: BTreeMap<i64, MovimientosInventario>,
+    ordenes_compra: BTreeMap<i64, OrdenesCompra>,
+    detalle_ordenes_compra: BTreeMap<i64, DetalleOrdenesCompra>,
+    conteos: BTreeMap<i64, Conteos>,
+    detalle_conteos: BTreeMap<i64, DetalleConteos>,
+    sucursales: BTreeMap<i64, Sucursales>,
+    usuarios_sucursales: BTreeMap<i64, UsuariosSucursales>,
+    traslados: BTreeMap<i64, Traslados>,
+    auditoria: Vec<Auditoria>,
+}
+
+/// Los repositorios en memoria. Todas las entidades comparten las mismas tablas
+#[derive(Debug)]
+pub struct Memoria {
+    tablas: Mutex<Tablas>,
+}
+
+impl Default for Memoria {
+    /// Las tablas vacias, salvo la sucursal principal (como la crea su migracion)
+    fn default() -> Self {
+        let mut tablas = Tablas::default();
+        let ahora = Some(Utc::now());
+        let principal = Sucursales {
+            codigo: Some(sucursales::PRINCIPAL),
+            nombre: "Principal".to_owned(),
+            direccion: None,
+            created_at: ahora,
+            updated_at: ahora,
+        };
+        tablas.sucursales.insert(sucursales::PRINCIPAL, principal);
+        Memoria { tablas: Mutex::new(tablas) }
+    }
+}
+
+/// El error de una fila sin llave primaria (o sin llave foranea), que en SQL viola un `not null`
+fn llave_nula() -> ErrorRepo {
+    ErrorRepo::Db(sqlx::Error::Protocol("null key".to_owned()))
+}
+
+/// Verifica que una llave foranea exista en su tabla
+fn referencia<T>(tabla: &BTreeMap<i64, T>, llave: Option<i64>) -> Resultado<()> {
+    match llave {
+        Some(llave) if tabla.contains_key(&llave) => Ok(()),
+        Some(_) => Err(ErrorRepo::LlaveForanea),
+        None => Err(llave_nula()),
+    }
+}
+
+/// La siguiente llave de una tabla con llave autoincremental
+fn siguiente<T>(tabla: &BTreeMap<i64, T>) -> Option<i64> {
+    Some(tabla.keys().next_back().map_or(1, |ultima| ultima + 1))
+}
+
+/// Aplica la paginacion a un listado
+fn paginar<T>(filas: impl Iterator<Item = T>, paginacion: &Paginacion) -> Vec<T> {
+    filas
+        .skip(paginacion.desplazamiento() as usize)
+        .take(paginacion.limite() as usize)
+        .collect()
+}
+
+impl Tablas {
+    /// Registra una operacion en la auditoria
+    fn registrar(
+        &mut self,
+        actor: &Actor,
+        tabla: Tabla,
+        llave: Option<i64>,
+        accion: Accion,
+        antes: Option<Value>,
+        despues: Option<Value>,
+    ) {
+        self.auditoria.push(Auditoria {
+            codigo: self.auditoria.len() as i64 + 1,
+            cedula_usuario: actor.cedula,
+            entidad: tabla.nombre.to_owned(),
+            llave,
+            accion: accion.as_str().to_owned(),
+            antes: antes.map(Json),
+            despues: despues.map(Json),
+            fecha: Utc::now(),
+        });
+    }
+
+    /// Inserta una fila nueva y la registra en la auditoria
+    fn insertar<T: Serialize>(
+        &mut self,
+        actor: &Actor,
+        tabla: Tabla,
+        mapa: fn(&mut Tablas) -> &mut BTreeMap<i64, T>,
+        llave: Option<i64>,
+        accion: Accion,
+        fila: T,
+    ) -> Resultado<u64> {
+        let llave = llave.ok_or_else(llave_nula)?;
+        let filas = mapa(self);
+        if filas.contains_key(&llave) {
+            return Err(ErrorRepo::Duplicado);
+        }
+        let despues = auditoria::instantanea(&fila);
+        filas.insert(llave, fila);
+        self.registrar(actor, tabla, Some(llave), accion, None, Some(despues));
+        Ok(1)
+    }
+
+    /// Modifica una fila y la registra en la auditoria. `cambio` retorna `false`
+    /// si la fila no cumple la condicion de la operacion (como el `where` de SQL)
+    fn modificar<T: Serialize>(
+        &mut self,
+        actor: &Actor,
+        tabla: Tabla,
+        mapa: fn(&mut Tablas) -> &mut BTreeMap<i64, T>,
+        llave: i64,
+        accion: Accion,
+        cambio: impl FnOnce(&mut T) -> bool,
+    ) -> u64 {
+        let Some(fila) = mapa(self).get_mut(&llave) else {
+            return 0;
+        };
+        let antes = auditoria::instantanea(fila);
+        if !cambio(fila) {
+            return 0;
+        }
+        let despues = auditoria::instantanea(fila);
+        self.registrar(actor, tabla, Some(llave), accion, Some(antes), Some(despues));
+        1
+    }
+
+    /// Borra una fila de forma fisica y la registra en la auditoria
+    fn quitar<T: Serialize>(
+        &mut self,
+        actor: &Actor,
+        tabla: Tabla,
+        mapa: fn(&mut Tablas) -> &mut BTreeMap<i64, T>,
+        llave: i64,
+    ) -> u64 {
+        let Some(fila) = mapa(self).remove(&llave) else {
+            return 0;
+        };
+        let antes = auditoria::instantanea(&fila);
+        self.registrar(actor, tabla, Some(llave), Accion::Delete, Some(antes), None);
+        1
+    }
+}
+
+impl Memoria {
+    fn tablas(&self) -> std::sync::MutexGuard<'_, Tablas> {
+        self.tablas.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl ClientesRepo for Memoria {
+    async fn insertar(&self, actor: &Actor, cliente: &Clientes) -> Resultado<u64> {
+        let ahora = Some(Utc::now());
+        let fila = Clientes { created_at: ahora, updated_at: ahora, deleted_at: None, ..cliente.clone() };
+        self.tablas().insertar(actor, CLIENTES, |t| &mut t.clientes, cliente.cedula, Accion::Create, fila)
+    }
+
+    async fn listar(&self, incluir_borrados: bool) -> Resultado<Vec<Clientes>> {
+        let tablas = self.tablas();
+        Ok(tablas.clientes.values().filter(|c| incluir_borrados || c.deleted_at.is_none()).cloned().collect())
+    }
+
+    async fn obtener(&self, cedula: i64) -> Resultado<Option<Clientes>> {
+        Ok(self.tablas().clientes.get(&cedula).cloned())
+    }
+
+    async fn actualizar(&self, actor: &Actor, cedula: i64, cliente: &Clientes) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, CLIENTES, |t| &mut t.clientes, cedula, Accion::Update, |fila| {
+            fila.direccion = cliente.direccion.clone();
+            fila.email = cliente.email.clone();
+            fila.nombre = cliente.nombre.clone();
+            fila.telefono = cliente.telefono.clone();
+            fila.updated_at = Some(Utc::now());
+            true
+        }))
+    }
+
+    async fn informe_borrado(&self, cedula: i64) -> Resultado<Option<InformeBorrado>> {
+        let tablas = self.tablas();
+        if tablas.clientes.get(&cedula).is_none_or(|c| c.deleted_at.is_some()) {
+            return Ok(None);
+        }
+        let ventas = tablas.ventas.values().filter(|v| v.cedula_cliente == Some(cedula)).count();
+        Ok(Some(InformeBorrado::new("clientes", cedula).afecta("ventas", ventas as i64)))
+    }
+
+    async fn borrar(&self, actor: &Actor, cedula: i64) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, CLIENTES, |t| &mut t.clientes, cedula, Accion::Delete, |fila| {
+            fila.deleted_at.is_none() && fila.deleted_at.replace(Utc::now()).is_none()
+        }))
+    }
+
+    async fn restaurar(&self, actor: &Actor, cedula: i64) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, CLIENTES, |t| &mut t.clientes, cedula, Accion::Restore, |fila| {
+            fila.deleted_at.take().is_some()
+        }))
+    }
+}
+
+#[async_trait]
+impl UsuariosRepo for Memoria {
+    async fn insertar(&self, actor: &Actor, usuario: &Usuarios) -> Resultado<u64> {
+        let ahora = Some(Utc::now());
+        let fila = Usuarios { created_at: ahora, updated_at: ahora, deleted_at: None, ..usuario.clone() };
+        self.tablas().insertar(actor, USUARIOS, |t| &mut t.usuarios, usuario.cedula, Accion::Create, fila)
+    }
+
+    async fn listar(&self, incluir_borrados: bool, sucursal: Option<i64>) -> Resultado<Vec<Usuarios>> {
+        let tablas = self.tablas();
+        let asignado = |cedula: Option<i64>| {
+            tablas.usuarios_sucursales.values().any(|a| Some(a.cedula_usuario) == cedula && sucursal == Some(a.codigo_sucursal))
+        };
+        Ok(tablas
+            .usuarios
+            .values()
+            .filter(|u| incluir_borrados || u.deleted_at.is_none())
+            .filter(|u| sucursal.is_none() || asignado(u.cedula))
+            .cloned()
+            .collect())
+    }
+
+    async fn obtener(&self, cedula: i64) -> Resultado<Option<Usuarios>> {
+        Ok(self.tablas().usuarios.get(&cedula).cloned())
+    }
+
+    async fn por_usuario(&self, usuario: &str) -> Resultado<Option<Usuarios>> {
+        let tablas = self.tablas();
+        Ok(tablas.usuarios.values().find(|u| u.usuario == usuario && u.deleted_at.is_none()).cloned())
+    }
+
+    async fn actualizar(&self, actor: &Actor, cedula: i64, usuario: &Usuarios) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, USUARIOS, |t| &mut t.usuarios, cedula, Accion::Update, |fila| {
+            fila.email = usuario.email.clone();
+            fila.nombre = usuario.nombre.clone();
+            fila.password = usuario.password.clone();
+            fila.usuario = usuario.usuario.clone();
+            fila.updated_at = Some(Utc::now());
+            true
+        }))
+    }
+
+    async fn informe_borrado(&self, cedula: i64) -> Resultado<Option<InformeBorrado>> {
+        let tablas = self.tablas();
+        if tablas.usuarios.get(&cedula).is_none_or(|u| u.deleted_at.is_some()) {
+            return Ok(None);
+        }
+        let ventas = tablas.ventas.values().filter(|v| v.cedula_usuario == Some(cedula)).count();
+        Ok(Some(InformeBorrado::new("usuarios", cedula).afecta("ventas", ventas as i64)))
+    }
+
+    async fn borrar(&self, actor: &Actor, cedula: i64) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, USUARIOS, |t| &mut t.usuarios, cedula, Accion::Delete, |fila| {
+            fila.deleted_at.is_none() && fila.deleted_at.replace(Utc::now()).is_none()
+        }))
+    }
+
+    async fn restaurar(&self, actor: &Actor, cedula: i64) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, USUARIOS, |t| &mut t.usuarios, cedula, Accion::Restore, |fila| {
+            fila.deleted_at.take().is_some()
+        }))
+    }
+}
+
+#[async_trait]
+impl ProveedoresRepo for Memoria {
+    async fn insertar(&self, actor: &Actor, proveedor: &Proveedores) -> Resultado<u64> {
+        let ahora = Some(Utc::now());
+        let fila = Proveedores { created_at: ahora, updated_at: ahora, deleted_at: None, ..proveedor.clone() };
+        self.tablas().insertar(actor, PROVEEDORES, |t| &mut t.proveedores, proveedor.nit, Accion::Create, fila)
+    }
+
+    async fn listar(&self, incluir_borrados: bool) -> Resultado<Vec<Proveedores>> {
+        let tablas = self.tablas();
+        Ok(tablas.proveedores.values().filter(|p| incluir_borrados || p.deleted_at.is_none()).cloned().collect())
+    }
+
+    async fn obtener(&self, nit: i64) -> Resultado<Option<Proveedores>> {
+        Ok(self.tablas().proveedores.get(&nit).cloned())
+    }
+
+    async fn actualizar(&self, actor: &Actor, nit: i64, proveedor: &Proveedores) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, PROVEEDORES, |t| &mut t.proveedores, nit, Accion::Update, |fila| {
+            fila.ciudad = proveedor.ciudad.clone();
+            fila.direccion = proveedor.direccion.clone();
+            fila.nombre = proveedor.nombre.clone();
+            fila.telefono = proveedor.telefono.clone();
+            fila.updated_at = Some(Utc::now());
+            true
+        }))
+    }
+
+    async fn informe_borrado(&self, nit: i64) -> Resultado<Option<InformeBorrado>> {
+        let tablas = self.tablas();
+        if tablas.proveedores.get(&nit).is_none_or(|p| p.deleted_at.is_some()) {
+            return Ok(None);
+        }
+        let productos = tablas
+            .productos
+            .values()
+            .filter(|p| p.nit_proveedor == Some(nit) && p.deleted_at.is_none())
+            .count();
+        Ok(Some(InformeBorrado::new("proveedores", nit).afecta("productos", productos as i64)))
+    }
+
+    async fn borrar(&self, actor: &Actor, nit: i64) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, PROVEEDORES, |t| &mut t.proveedores, nit, Accion::Delete, |fila| {
+            fila.deleted_at.is_none() && fila.deleted_at.replace(Utc::now()).is_none()
+        }))
+    }
+
+    async fn restaurar(&self, actor: &Actor, nit: i64) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, PROVEEDORES, |t| &mut t.proveedores, nit, Accion::Restore, |fila| {
+            fila.deleted_at.take().is_some()
+        }))
+    }
+}
+
+impl Tablas {
+    /// Los productos de una categoria y de sus subcategorias (`None` si no se filtra por categoria)
+    fn productos_categoria(&self, categoria: Option<i64>) -> Option<BTreeSet<i64>> {
+        let categorias: Vec<_> = self.categorias.values().cloned().collect();
+        let arbol = categorias::descendientes(&categorias, categoria?);
+        Some(
+            self.productos
+                .values()
+                .filter(|p| p.codigo_categoria.is_some_and(|c| arbol.contains(&c)))
+                .filter_map(|p| p.codigo)
+                .collect(),
+        )
+    }
+
+    fn insertar_producto(&mut self, actor: &Actor, producto: &Productos, accion: Accion) -> Resultado<u64> {
+        referencia(&self.proveedores, producto.nit_proveedor)?;
+        if producto.codigo_categoria.is_some() {
+            referencia(&self.categorias, producto.codigo_categoria)?;
+        }
+        let ahora = Some(Utc::now());
+        let fila = Productos { created_at: ahora, updated_at: ahora, deleted_at: None, ..producto.clone() };
+        let filas = self.insertar(actor, PRODUCTOS, |t| &mut t.productos, producto.codigo, accion, fila)?;
+        let origen = if matches!(accion, Accion::Import) { precios::IMPORTACION } else { precios::CREACION };
+        self.registrar_precios(actor, producto.codigo, origen);
+        Ok(filas)
+    }
+
+    /// Registra un movimiento de inventario y lo registra en la auditoria
+    fn insertar_movimiento(&mut self, actor: &Actor, movimiento: MovimientosInventario) -> Resultado<Option<i64>> {
+        referencia(&self.productos, movimiento.codigo_producto)?;
+        if movimiento.codigo_sucursal.is_some() {
+            referencia(&self.sucursales, movimiento.codigo_sucursal)?;
+        }
+        let codigo = siguiente(&self.movimientos_inventario);
+        let fila = MovimientosInventario { codigo, cedula_usuario: actor.cedula, fecha: Some(Utc::now()), ..movimiento };
+        self.insertar(actor, MOVIMIENTOS_INVENTARIO, |t| &mut t.movimientos_inventario, codigo, Accion::Create, fila)?;
+        Ok(codigo)
+    }
+
+    fn insertar_codigo_barras(&mut self, actor: &Actor, codigo_producto: i64, codigo_barras: &str, accion: Accion) -> Resultado<Option<i64>> {
+        referencia(&self.productos, Some(codigo_producto))?;
+        if self.codigos_barras.values().any(|c| c.codigo_barras == codigo_barras) {
+            return Err(ErrorRepo::Duplicado);
+        }
+        let codigo = siguiente(&self.codigos_barras);
+        let fila = CodigosBarras {
+            codigo,
+            codigo_barras: codigo_barras.to_owned(),
+            codigo_producto: Some(codigo_producto),
+            created_at: Some(Utc::now()),
+        };
+        self.insertar(actor, CODIGOS_BARRAS, |t| &mut t.codigos_barras, codigo, accion, fila)?;
+        Ok(codigo)
+    }
+
+    /// Las existencias de un producto en una sucursal
+    fn existencias_en(&self, codigo_producto: Option<i64>, codigo_sucursal: Option<i64>) -> f64 {
+        self.movimientos_inventario
+            .values()
+            .filter(|m| m.codigo_producto == codigo_producto && m.codigo_sucursal == codigo_sucursal)
+            .map(|m| m.cantidad)
+            .sum()
+    }
+
+    /// Descuenta del inventario de su sucursal los productos de una venta que se acaba de finalizar
+    fn descontar_venta(&mut self, actor: &Actor, codigo_venta: i64) -> Resultado<u64> {
+        let codigo_sucursal = self.ventas.get(&codigo_venta).and_then(|v| v.codigo_sucursal);
+        let detalles: Vec<_> = self.detalle_ventas.values().filter(|d| d.codigo_venta == Some(codigo_venta)).cloned().collect();
+        for detalle in &detalles {
+            let movimiento = MovimientosInventario {
+                codigo_venta: Some(codigo_venta),
+                ..MovimientosInventario::new(codigo_sucursal, detalle.codigo_producto, inventario::VENTA, -detalle.cantidad_producto)
+            };
+            self.insertar_movimiento(actor, movimiento)?;
+        }
+        Ok(detalles.len() as u64)
+    }
+
+    /// Acumula al cliente de una venta que se acaba de finalizar los puntos de lo que no se pago con puntos
+    fn acumular_puntos(&mut self, actor: &Actor, codigo_venta: i64, config: &ConfigPuntos) -> Resultado<()> {
+        let Some(venta) = self.ventas.get(&codigo_venta) else {
+            return Ok(());
+        };
+        let con_puntos: f64 = self
+            .pagos
+            .values()
+            .filter(|p| p.codigo_venta == Some(codigo_venta) && p.metodo == PUNTOS)
+            .map(|p| p.valor)
+            .sum();
+        let puntos = config.acumulados(venta.total_venta - con_puntos);
+        if venta.cedula_cliente.is_none() || puntos <= 0 {
+            return Ok(());
+        }
+        let codigo = siguiente(&self.movimientos_puntos);
+        let ahora = Utc::now();
+        let fila = MovimientosPuntos {
+            codigo,
+            cedula_cliente: venta.cedula_cliente,
+            codigo_venta: Some(codigo_venta),
+            codigo_pago: None,
+            puntos,
+            vence: config.vencimiento(ahora.date_naive()),
+            fecha: Some(ahora),
+        };
+        self.insertar(actor, MOVIMIENTOS_PUNTOS, |t| &mut t.movimientos_puntos, codigo, Accion::Create, fila).map(|_| ())
+    }
+
+    /// Registra los precios actuales de un producto en el historial, si cambiaron
+    fn registrar_precios(&mut self, actor: &Actor, codigo_producto: Option<i64>, origen: &str) {
+        let Some(producto) = codigo_producto.and_then(|codigo| self.productos.get(&codigo)) else {
+            return;
+        };
+        let ultimo = self.precios_historial.values().rev().find(|h| h.codigo_producto == codigo_producto.unwrap_or_default());
+        if ultimo.is_some_and(|h| h.precio_compra == producto.precio_compra && h.precio_venta == producto.precio_venta) {
+            return;
+        }
+        let codigo = siguiente(&self.precios_historial);
+        let fila = PreciosHistorial {
+            codigo,
+            codigo_producto: codigo_producto.unwrap_or_default(),
+            precio_compra: producto.precio_compra,
+            precio_venta: producto.precio_venta,
+            origen: origen.to_owned(),
+            cedula_usuario: actor.cedula,
+            fecha: Some(Utc::now()),
+        };
+        self.precios_historial.insert(codigo.unwrap_or_default(), fila);
+    }
+}
+
+#[async_trait]
+impl ProductosRepo for Memoria {
+    async fn insertar(&self, actor: &Actor, producto: &Productos) -> Resultado<u64> {
+        self.tablas().insertar_producto(actor, producto, Accion::Create)
+    }
+
+    async fn importar(&self, actor: &Actor, productos: &[Productos], codigos_barras: &[CodigosBarras]) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        let (respaldo, registros) = (tablas.productos.clone(), tablas.auditoria.len());
+        let (historial, codigos) = (tablas.precios_historial.clone(), tablas.codigos_barras.clone());
+        let resultado = productos
+            .iter()
+            .try_for_each(|producto| tablas.insertar_producto(actor, producto, Accion::Import).map(|_| ()))
+            .and_then(|_| {
+                codigos_barras.iter().try_for_each(|codigo| {
+                    let codigo_producto = codigo.codigo_producto.unwrap_or_default();
+                    tablas.insertar_codigo_barras(actor, codigo_producto, &codigo.codigo_barras, Accion::Import).map(|_| ())
+                })
+            });
+        if let Err(e) = resultado {
+            tablas.productos = respaldo;
+            tablas.precios_historial = historial;
+            tablas.codigos_barras = codigos;
+            tablas.auditoria.truncate(registros);
+            return Err(e);
+        }
+        Ok(productos.len() as u64)
+    }
+
+    async fn listar(&self, incluir_borrados: bool, categoria: Option<i64>) -> Resultado<Vec<Productos>> {
+        let tablas = self.tablas();
+        let en_categoria = tablas.productos_categoria(categoria);
+        Ok(tablas
+            .productos
+            .values()
+            .filter(|p| incluir_borrados || p.deleted_at.is_none())
+            .filter(|p| en_categoria.as_ref().is_none_or(|codigos| p.codigo.is_some_and(|c| codigos.contains(&c))))
+            .cloned()
+            .collect())
+    }
+
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Productos>> {
+        Ok(self.tablas().productos.get(&codigo).cloned())
+    }
+
+    async fn por_proveedor(&self, nit: i64, paginacion: &Paginacion, incluir_borrados: bool) -> Resultado<Vec<Productos>> {
+        let tablas = self.tablas();
+        let productos = tablas
+            .productos
+            .values()
+            .filter(|p| p.nit_proveedor == Some(nit) && (incluir_borrados || p.deleted_at.is_none()))
+            .cloned();
+        Ok(paginar(productos, paginacion))
+    }
+
+    async fn actualizar(&self, actor: &Actor, codigo: i64, producto: &Productos) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        if producto.codigo_categoria.is_some() {
+            referencia(&tablas.categorias, producto.codigo_categoria)?;
+        }
+        let filas = tablas.modificar(actor, PRODUCTOS, |t| &mut t.productos, codigo, Accion::Update, |fila| {
+            fila.iva_compra = producto.iva_compra;
+            fila.nombre_producto = producto.nombre_producto.clone();
+            fila.precio_compra = producto.precio_compra;
+            fila.precio_venta = producto.precio_venta;
+            fila.codigo_categoria = producto.codigo_categoria;
+            fila.unidad_medida = producto.unidad_medida.clone();
+            fila.stock_minimo = producto.stock_minimo;
+            fila.stock_maximo = producto.stock_maximo;
+            fila.updated_at = Some(Utc::now());
+            true
+        });
+        tablas.registrar_precios(actor, Some(codigo), precios::ACTUALIZACION);
+        Ok(filas)
+    }
+
+    async fn informe_borrado(&self, codigo: i64) -> Resultado<Option<InformeBorrado>> {
+        let tablas = self.tablas();
+        if tablas.productos.get(&codigo).is_none_or(|p| p.deleted_at.is_some()) {
+            return Ok(None);
+        }
+        let detalles = tablas.detalle_ventas.values().filter(|d| d.codigo_producto == Some(codigo)).count();
+        Ok(Some(InformeBorrado::new("productos", codigo).afecta("detalle_ventas", detalles as i64)))
+    }
+
+    async fn borrar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, PRODUCTOS, |t| &mut t.productos, codigo, Accion::Delete, |fila| {
+            fila.deleted_at.is_none() && fila.deleted_at.replace(Utc::now()).is_none()
+        }))
+    }
+
+    async fn restaurar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, PRODUCTOS, |t| &mut t.productos, codigo, Accion::Restore, |fila| {
+            fila.deleted_at.take().is_some()
+        }))
+    }
+}
+
+#[async_trait]
+impl CodigosBarrasRepo for Memoria {
+    async fn insertar(&self, actor: &Actor, codigo_producto: i64, codigo_barras: &str) -> Resultado<Option<i64>> {
+        self.tablas().insertar_codigo_barras(actor, codigo_producto, codigo_barras, Accion::Create)
+    }
+
+    async fn por_producto(&self, codigo_producto: i64) -> Resultado<Vec<CodigosBarras>> {
+        let tablas = self.tablas();
+        Ok(tablas.codigos_barras.values().filter(|c| c.codigo_producto == Some(codigo_producto)).cloned().collect())
+    }
+
+    async fn buscar(&self, codigo_barras: &str) -> Resultado<Option<CodigosBarras>> {
+        Ok(self.tablas().codigos_barras.values().find(|c| c.codigo_barras == codigo_barras).cloned())
+    }
+
+    async fn borrar(&self, actor: &Actor, codigo_producto: i64, codigo_barras: &str) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        let asignado = tablas
+            .codigos_barras
+            .values()
+            .find(|c| c.codigo_barras == codigo_barras && c.codigo_producto == Some(codigo_producto))
+            .and_then(|c| c.codigo);
+        match asignado {
+            Some(codigo) => Ok(tablas.quitar(actor, CODIGOS_BARRAS, |t| &mut t.codigos_barras, codigo)),
+            None => Ok(0),
+        }
+    }
+}
+
+#[async_trait]
+impl PreciosRepo for Memoria {
+    async fn historial(&self, codigo_producto: i64) -> Resultado<Vec<PreciosHistorial>> {
+        let tablas = self.tablas();
+        Ok(tablas.precios_historial.values().filter(|h| h.codigo_producto == codigo_producto).cloned().collect())
+    }
+
+    async fn programar(&self, actor: &Actor, codigo_producto: i64, programado: &PreciosProgramados) -> Resultado<Option<i64>> {
+        let mut tablas = self.tablas();
+        referencia(&tablas.productos, Some(codigo_producto))?;
+        let codigo = siguiente(&tablas.precios_programados);
+        let fila = PreciosProgramados {
+            codigo,
+            codigo_producto: Some(codigo_producto),
+            cedula_usuario: actor.cedula,
+            aplicado_at: None,
+            created_at: Some(Utc::now()),
+            ..programado.clone()
+        };
+        tablas.insertar(actor, PRECIOS_PROGRAMADOS, |t| &mut t.precios_programados, codigo, Accion::Create, fila)?;
+        Ok(codigo)
+    }
+
+    async fn programados(&self, codigo_producto: i64) -> Resultado<Vec<PreciosProgramados>> {
+        let tablas = self.tablas();
+        let mut programados: Vec<_> = tablas
+            .precios_programados
+            .values()
+            .filter(|p| p.codigo_producto == Some(codigo_producto) && p.aplicado_at.is_none())
+            .cloned()
+            .collect();
+        programados.sort_by_key(|p| (p.fecha, p.codigo));
+        Ok(programados)
+    }
+
+    async fn cancelar(&self, actor: &Actor, codigo_producto: i64, codigo: i64) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        let pendiente = tablas.precios_programados.get(&codigo);
+        if pendiente.is_none_or(|p| p.codigo_producto != Some(codigo_producto) || p.aplicado_at.is_some()) {
+            return Ok(0);
+        }
+        Ok(tablas.quitar(actor, PRECIOS_PROGRAMADOS, |t| &mut t.precios_programados, codigo))
+    }
+
+    async fn aplicar(&self, hasta: DateTime<Utc>) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        let mut pendientes: Vec<_> = tablas
+            .precios_programados
+            .values()
+            .filter(|p| p.aplicado_at.is_none() && p.fecha <= hasta)
+            .filter(|p| p.codigo_producto.and_then(|codigo| tablas.productos.get(&codigo)).is_some_and(|p| p.deleted_at.is_none()))
+            .cloned()
+            .collect();
+        pendientes.sort_by_key(|p| (p.fecha, p.codigo));
+        for programado in &pendientes {
+            let actor = Actor { cedula: programado.cedula_usuario };
+            let codigo_producto = programado.codigo_producto.unwrap_or_default();
+            tablas.modificar(&actor, PRODUCTOS, |t| &mut t.productos, codigo_producto, Accion::Update, |fila| {
+                fila.precio_compra = programado.precio_compra.unwrap_or(fila.precio_compra);
+                fila.precio_venta = programado.precio_venta.unwrap_or(fila.precio_venta);
+                fila.updated_at = Some(Utc::now());
+                true
+            });
+            tablas.registrar_precios(&actor, Some(codigo_producto), precios::PROGRAMADO);
+            if let Some(fila) = programado.codigo.and_then(|codigo| tablas.precios_programados.get_mut(&codigo)) {
+                fila.aplicado_at = Some(Utc::now());
+            }
+        }
+        Ok(pendientes.len() as u64)
+    }
+}
+
+/// Si una fila de una sucursal pasa el filtro por sucursal (sin filtro pasan todas)
+fn en_sucursal(sucursal: Option<i64>, codigo_sucursal: Option<i64>) -> bool {
+    sucursal.is_none_or(|sucursal| codigo_sucursal == Some(sucursal))
+}
+
+/// Si una fecha pertenece a un rango de fechas
+fn en_rango(rango: &RangoFechas, fecha: Option<chrono::DateTime<Utc>>) -> bool {
+    rango.inicio().is_none_or(|inicio| fecha.is_some_and(|f| f >= inicio))
+        && rango.fin().is_none_or(|fin| fecha.is_some_and(|f| f < fin))
+}
+
+#[async_trait]
+impl VentasRepo for Memoria {
+    async fn insertar(&self, actor: &Actor, venta: &Ventas) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        referencia(&tablas.clientes, venta.cedula_cliente)?;
+        referencia(&tablas.usuarios, venta.cedula_usuario)?;
+        if venta.codigo_sucursal.is_some() {
+            referencia(&tablas.sucursales, venta.codigo_sucursal)?;
+        }
+        let ahora = Some(Utc::now());
+        let codigo_turno = tablas
+            .turnos
+            .values()
+            .find(|t| t.cedula_usuario == venta.cedula_usuario && t.cerrado_at.is_none())
+            .and_then(|t| t.codigo);
+        let fila = Ventas { codigo_turno, fecha: ahora, created_at: ahora, updated_at: ahora, ..venta.clone() };
+        tablas.insertar(actor, VENTAS, |t| &mut t.ventas, venta.codigo, Accion::Create, fila)
+    }
+
+    async fn listar(&self, rango: &RangoFechas, sucursal: Option<i64>) -> Resultado<Vec<Ventas>> {
+        let tablas = self.tablas();
+        let mut ventas: Vec<Ventas> = tablas
+            .ventas
+            .values()
+            .filter(|v| en_rango(rango, v.fecha) && en_sucursal(sucursal, v.codigo_sucursal))
+            .cloned()
+            .collect();
+        ventas.sort_by_key(|v| v.fecha);
+        Ok(ventas)
+    }
+
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Ventas>> {
+        Ok(self.tablas().ventas.get(&codigo).cloned())
+    }
+
+    async fn por_cliente(&self, cedula: i64, paginacion: &Paginacion, sucursal: Option<i64>) -> Resultado<Vec<Ventas>> {
+        let tablas = self.tablas();
+        let ventas =
+            tablas.ventas.values().filter(|v| v.cedula_cliente == Some(cedula) && en_sucursal(sucursal, v.codigo_sucursal)).cloned();
+        Ok(paginar(ventas, paginacion))
+    }
+
+    async fn por_usuario(&self, cedula: i64, paginacion: &Paginacion, sucursal: Option<i64>) -> Resultado<Vec<Ventas>> {
+        let tablas = self.tablas();
+        let ventas =
+            tablas.ventas.values().filter(|v| v.cedula_usuario == Some(cedula) && en_sucursal(sucursal, v.codigo_sucursal)).cloned();
+        Ok(paginar(ventas, paginacion))
+    }
+
+    async fn actualizar(&self, actor: &Actor, codigo: i64, venta: &Ventas) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, VENTAS, |t| &mut t.ventas, codigo, Accion::Update, |fila| {
+            if fila.finalizada_at.is_some() {
+                return false;
+            }
+            fila.iva_venta = venta.iva_venta;
+            fila.total_venta = venta.total_venta;
+            fila.valor_venta = venta.valor_venta;
+            fila.updated_at = Some(Utc::now());
+            true
+        }))
+    }
+
+    async fn finalizar(&self, actor: &Actor, codigo: i64, puntos: &ConfigPuntos) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        let pagado: f64 = tablas.pagos.values().filter(|p| p.codigo_venta == Some(codigo)).map(|p| p.valor).sum();
+        let detallado: f64 =
+            tablas.detalle_ventas.values().filter(|d| d.codigo_venta == Some(codigo)).map(|d| d.valor_total).sum();
+        let filas = tablas.modificar(actor, VENTAS, |t| &mut t.ventas, codigo, Accion::Update, |fila| {
+            let total = centavos(fila.total_venta);
+            if fila.finalizada_at.is_some() || total != centavos(pagado) || total != centavos(detallado) {
+                return false;
+            }
+            fila.finalizada_at = Some(Utc::now());
+            fila.updated_at = fila.finalizada_at;
+            true
+        });
+        if filas > 0 {
+            tablas.descontar_venta(actor, codigo)?;
+            tablas.acumular_puntos(actor, codigo, puntos)?;
+        }
+        Ok(filas)
+    }
+
+    async fn informe_borrado(&self, codigo: i64) -> Resultado<Option<InformeBorrado>> {
+        let tablas = self.tablas();
+        if !tablas.ventas.contains_key(&codigo) {
+            return Ok(None);
+        }
+        let detalles = tablas.detalle_ventas.values().filter(|d| d.codigo_venta == Some(codigo)).count();
+        let pagos = tablas.pagos.values().filter(|p| p.codigo_venta == Some(codigo)).count();
+        let puntos = tablas.movimientos_puntos.values().filter(|m| m.codigo_venta == Some(codigo)).count();
+        let movimientos = tablas.movimientos_inventario.values().filter(|m| m.codigo_venta == Some(codigo)).count();
+        Ok(Some(
+            InformeBorrado::new("ventas", codigo)
+                .bloquea("detalle_ventas", detalles as i64)
+                .bloquea("pagos", pagos as i64)
+                .bloquea("movimientos_puntos", puntos as i64)
+                .bloquea("movimientos_inventario", movimientos as i64),
+        ))
+    }
+
+    async fn borrar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        if !tablas.venta_abierta(Some(codigo)) {
+            return Ok(0);
+        }
+        if tablas.detalle_ventas.values().any(|d| d.codigo_venta == Some(codigo))
+            || tablas.pagos.values().any(|p| p.codigo_venta == Some(codigo))
+            || tablas.movimientos_puntos.values().any(|m| m.codigo_venta == Some(codigo))
+            || tablas.movimientos_inventario.values().any(|m| m.codigo_venta == Some(codigo))
+        {
+            return Err(ErrorRepo::LlaveForanea);
+        }
+        Ok(tablas.quitar(actor, VENTAS, |t| &mut t.ventas, codigo))
+    }
+}
+
+#[async_trait]
+impl DetalleVentasRepo for Memoria {
+    async fn insertar(&self, actor: &Actor, detalle_venta: &DetalleVentas) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        referencia(&tablas.productos, detalle_venta.codigo_producto)?;
+        referencia(&tablas.ventas, detalle_venta.codigo_venta)?;
+        if detalle_venta.codigo_promocion.is_some() {
+            referencia(&tablas.promociones, detalle_venta.codigo_promocion)?;
+        }
+        if !tablas.venta_abierta(detalle_venta.codigo_venta) {
+            return Ok(0);
+        }
+        let ahora = Some(Utc::now());
+        let fila = DetalleVentas { created_at: ahora, updated_at: ahora, ..detalle_venta.clone() };
+        tablas.insertar(actor, DETALLE_VENTAS, |t| &mut t.detalle_ventas, detalle_venta.codigo, Accion::Create, fila)
+    }
+
+    async fn listar(&self, rango: &RangoFechas, categoria: Option<i64>, sucursal: Option<i64>) -> Resultado<Vec<DetalleVentas>> {
+        let tablas = self.tablas();
+        let en_categoria = tablas.productos_categoria(categoria);
+        let mut detalles: Vec<_> = tablas
+            .detalle_ventas
+            .values()
+            .filter(|d| en_categoria.as_ref().is_none_or(|codigos| d.codigo_producto.is_some_and(|c| codigos.contains(&c))))
+            .filter_map(|d| {
+                let venta = tablas.ventas.get(&d.codigo_venta?)?;
+                (en_rango(rango, venta.fecha) && en_sucursal(sucursal, venta.codigo_sucursal)).then(|| (venta.fecha, d.clone()))
+            })
+            .collect();
+        detalles.sort_by_key(|(fecha, _)| *fecha);
+        Ok(detalles.into_iter().map(|(_, d)| d).collect())
+    }
+
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<DetalleVentas>> {
+        Ok(self.tablas().detalle_ventas.get(&codigo).cloned())
+    }
+
+    async fn por_venta(&self, codigo_venta: i64) -> Resultado<Vec<DetalleVentas>> {
+        let tablas = self.tablas();
+        Ok(tablas.detalle_ventas.values().filter(|d| d.codigo_venta == Some(codigo_venta)).cloned().collect())
+    }
+
+    async fn actualizar(&self, actor: &Actor, codigo: i64, detalle_venta: &DetalleVentas) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        if !tablas.venta_abierta(tablas.detalle_ventas.get(&codigo).and_then(|d| d.codigo_venta)) {
+            return Ok(0);
+        }
+        Ok(tablas.modificar(actor, DETALLE_VENTAS, |t| &mut t.detalle_ventas, codigo, Accion::Update, |fila| {
+            fila.cantidad_producto = detalle_venta.cantidad_producto;
+            fila.valor_total = detalle_venta.valor_total;
+            fila.valor_venta = detalle_venta.valor_venta;
+            fila.valor_iva = detalle_venta.valor_iva;
+            fila.codigo_promocion = detalle_venta.codigo_promocion;
+            fila.descuento = detalle_venta.descuento;
+            fila.updated_at = Some(Utc::now());
+            true
+        }))
+    }
+
+    async fn borrar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        if !tablas.venta_abierta(tablas.detalle_ventas.get(&codigo).and_then(|d| d.codigo_venta)) {
+            return Ok(0);
+        }
+        Ok(tablas.quitar(actor, DETALLE_VENTAS, |t| &mut t.detalle_ventas, codigo))
+    }
+}
+
+impl Tablas {
+    /// Si una venta existe y no esta finalizada
+    fn venta_abierta(&self, codigo_venta: Option<i64>) -> bool {
+        codigo_venta.and_then(|codigo| self.ventas.get(&codigo)).is_some_and(|v| v.finalizada_at.is_none())
+    }
+
+    /// Verifica las llaves foraneas opcionales de una promocion
+    fn referencias_promocion(&self, promocion: &Promociones) -> Resultado<()> {
+        if promocion.codigo_producto.is_some() {
+            referencia(&self.productos, promocion.codigo_producto)?;
+        }
+        if promocion.nit_proveedor.is_some() {
+            referencia(&self.proveedores, promocion.nit_proveedor)?;
+        }
+        if promocion.cedula_cliente.is_some() {
+            referencia(&self.clientes, promocion.cedula_cliente)?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CategoriasRepo for Memoria {
+    async fn insertar(&self, actor: &Actor, categoria: &Categorias) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        if categoria.codigo_padre.is_some() {
+            referencia(&tablas.categorias, categoria.codigo_padre)?;
+        }
+        let ahora = Some(Utc::now());
+        let fila = Categorias { created_at: ahora, updated_at: ahora, deleted_at: None, ..categoria.clone() };
+        tablas.insertar(actor, CATEGORIAS, |t| &mut t.categorias, categoria.codigo, Accion::Create, fila)
+    }
+
+    async fn listar(&self, incluir_borrados: bool) -> Resultado<Vec<Categorias>> {
+        let tablas = self.tablas();
+        Ok(tablas.categorias.values().filter(|c| incluir_borrados || c.deleted_at.is_none()).cloned().collect())
+    }
+
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Categorias>> {
+        Ok(self.tablas().categorias.get(&codigo).cloned())
+    }
+
+    async fn actualizar(&self, actor: &Actor, codigo: i64, categoria: &Categorias) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        if categoria.codigo_padre.is_some() {
+            referencia(&tablas.categorias, categoria.codigo_padre)?;
+        }
+        Ok(tablas.modificar(actor, CATEGORIAS, |t| &mut t.categorias, codigo, Accion::Update, |fila| {
+            fila.nombre = categoria.nombre.clone();
+            fila.codigo_padre = categoria.codigo_padre;
+            fila.updated_at = Some(Utc::now());
+            true
+        }))
+    }
+
+    async fn informe_borrado(&self, codigo: i64) -> Resultado<Option<InformeBorrado>> {
+        let tablas = self.tablas();
+        if tablas.categorias.get(&codigo).is_none_or(|c| c.deleted_at.is_some()) {
+            return Ok(None);
+        }
+        let subcategorias =
+            tablas.categorias.values().filter(|c| c.codigo_padre == Some(codigo) && c.deleted_at.is_none()).count();
+        let productos =
+            tablas.productos.values().filter(|p| p.codigo_categoria == Some(codigo) && p.deleted_at.is_none()).count();
+        Ok(Some(
+            InformeBorrado::new("categorias", codigo)
+                .afecta("categorias", subcategorias as i64)
+                .afecta("productos", productos as i64),
+        ))
+    }
+
+    async fn borrar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, CATEGORIAS, |t| &mut t.categorias, codigo, Accion::Delete, |fila| {
+            fila.deleted_at.is_none() && fila.deleted_at.replace(Utc::now()).is_none()
+        }))
+    }
+
+    async fn restaurar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, CATEGORIAS, |t| &mut t.categorias, codigo, Accion::Restore, |fila| {
+            fila.deleted_at.take().is_some()
+        }))
+    }
+
+    async fn ventas(&self, rango: &RangoFechas, categoria: Option<i64>, sucursal: Option<i64>) -> Resultado<Vec<VentasCategoria>> {
+        let tablas = self.tablas();
+        let en_categoria = tablas.productos_categoria(categoria);
+        let mut grupos: BTreeMap<Option<i64>, (VentasCategoria, BTreeSet<i64>)> = BTreeMap::new();
+        for detalle in tablas.detalle_ventas.values() {
+            let Some(venta) = detalle.codigo_venta.and_then(|c| tablas.ventas.get(&c)) else { continue };
+            let Some(producto) = detalle.codigo_producto.and_then(|c| tablas.productos.get(&c)) else { continue };
+            if !en_rango(rango, venta.fecha)
+                || !en_sucursal(sucursal, venta.codigo_sucursal)
+                || en_categoria.as_ref().is_some_and(|codigos| producto.codigo.is_none_or(|c| !codigos.contains(&c)))
+            {
+                continue;
+            }
+            let (grupo, ventas) = grupos.entry(producto.codigo_categoria).or_insert_with(|| {
+                let nombre = producto.codigo_categoria.and_then(|c| tablas.categorias.get(&c)).map(|c| c.nombre.clone());
+                let grupo = VentasCategoria {
+                    codigo_categoria: producto.codigo_categoria,
+                    nombre,
+                    cantidad: 0.0,
+                    ventas: 0,
+                    descuento: 0.0,
+                    valor_venta: 0.0,
+                    valor_iva: 0.0,
+                    valor_total: 0.0,
+                };
+                (grupo, BTreeSet::new())
+            });
+            ventas.extend(venta.codigo);
+            grupo.cantidad += detalle.cantidad_producto;
+            grupo.ventas = ventas.len() as i64;
+            grupo.descuento += detalle.descuento;
+            grupo.valor_venta += detalle.valor_venta;
+            grupo.valor_iva += detalle.valor_iva;
+            grupo.valor_total += detalle.valor_total;
+        }
+        Ok(grupos.into_values().map(|(grupo, _)| grupo).collect())
+    }
+}
+
+#[async_trait]
+impl PromocionesRepo for Memoria {
+    async fn insertar(&self, actor: &Actor, promocion: &Promociones) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        tablas.referencias_promocion(promocion)?;
+        let ahora = Some(Utc::now());
+        let fila = Promociones { created_at: ahora, updated_at: ahora, deleted_at: None, ..promocion.clone() };
+        tablas.insertar(actor, PROMOCIONES, |t| &mut t.promociones, promocion.codigo, Accion::Create, fila)
+    }
+
+    async fn listar(&self, incluir_borrados: bool) -> Resultado<Vec<Promociones>> {
+        let tablas = self.tablas();
+        Ok(tablas.promociones.values().filter(|p| incluir_borrados || p.deleted_at.is_none()).cloned().collect())
+    }
+
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Promociones>> {
+        Ok(self.tablas().promociones.get(&codigo).cloned())
+    }
+
+    async fn vigentes(&self, fecha: NaiveDate) -> Resultado<Vec<Promociones>> {
+        let tablas = self.tablas();
+        Ok(tablas
+            .promociones
+            .values()
+            .filter(|p| p.deleted_at.is_none())
+            .filter(|p| p.desde.is_none_or(|desde| desde <= fecha) && p.hasta.is_none_or(|hasta| fecha <= hasta))
+            .cloned()
+            .collect())
+    }
+
+    async fn actualizar(&self, actor: &Actor, codigo: i64, promocion: &Promociones) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        tablas.referencias_promocion(promocion)?;
+        Ok(tablas.modificar(actor, PROMOCIONES, |t| &mut t.promociones, codigo, Accion::Update, |fila| {
+            *fila = Promociones {
+                codigo: fila.codigo,
+                created_at: fila.created_at,
+                updated_at: Some(Utc::now()),
+                deleted_at: fila.deleted_at,
+                ..promocion.clone()
+            };
+            true
+        }))
+    }
+
+    async fn informe_borrado(&self, codigo: i64) -> Resultado<Option<InformeBorrado>> {
+        let tablas = self.tablas();
+        if tablas.promociones.get(&codigo).is_none_or(|p| p.deleted_at.is_some()) {
+            return Ok(None);
+        }
+        let detalles = tablas.detalle_ventas.values().filter(|d| d.codigo_promocion == Some(codigo)).count();
+        Ok(Some(InformeBorrado::new("promociones", codigo).afecta("detalle_ventas", detalles as i64)))
+    }
+
+    async fn borrar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, PROMOCIONES, |t| &mut t.promociones, codigo, Accion::Delete, |fila| {
+            fila.deleted_at.is_none() && fila.deleted_at.replace(Utc::now()).is_none()
+        }))
+    }
+
+    async fn restaurar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, PROMOCIONES, |t| &mut t.promociones, codigo, Accion::Restore, |fila| {
+            fila.deleted_at.take().is_some()
+        }))
+    }
+}
+
+#[async_trait]
+impl PagosRepo for Memoria {
+    async fn insertar(&self, actor: &Actor, codigo_venta: i64, pago: &Pagos, puntos: &ConfigPuntos) -> Resultado<Option<i64>> {
+        let mut tablas = self.tablas();
+        let Some(venta) = tablas.ventas.get(&codigo_venta).filter(|v| v.finalizada_at.is_none()) else {
+            return Ok(None);
+        };
+        let pagado: f64 = tablas.pagos.values().filter(|p| p.codigo_venta == Some(codigo_venta)).map(|p| p.valor).sum();
+        if centavos(pagado + pago.valor) > centavos(venta.total_venta) {
+            return Ok(None);
+        }
+        let redencion = match (pago.metodo.as_str(), venta.cedula_cliente) {
+            (PUNTOS, None) => return Ok(None),
+            (PUNTOS, Some(cedula_cliente)) => {
+                let movimientos: Vec<_> =
+                    tablas.movimientos_puntos.values().filter(|m| m.cedula_cliente == Some(cedula_cliente)).cloned().collect();
+                let saldo = EstadoPuntos::new(cedula_cliente, &movimientos, Utc::now().date_naive(), puntos).saldo;
+                let necesarios = puntos.para_pagar(pago.valor);
+                if necesarios > saldo {
+                    return Ok(None);
+                }
+                Some((cedula_cliente, necesarios))
+            }
+            _ => None,
+        };
+        let codigo = siguiente(&tablas.pagos);
+        let fila = Pagos { codigo, codigo_venta: Some(codigo_venta), fecha: Some(Utc::now()), ..pago.clone() };
+        tablas.insertar(actor, PAGOS, |t| &mut t.pagos, codigo, Accion::Create, fila)?;
+        if let Some((cedula_cliente, redimidos)) = redencion {
+            let movimiento = siguiente(&tablas.movimientos_puntos);
+            let fila = MovimientosPuntos {
+                codigo: movimiento,
+                cedula_cliente: Some(cedula_cliente),
+                codigo_venta: None,
+                codigo_pago: codigo,
+                puntos: -redimidos,
+                vence: None,
+                fecha: Some(Utc::now()),
+            };
+            tablas.insertar(actor, MOVIMIENTOS_PUNTOS, |t| &mut t.movimientos_puntos, movimiento, Accion::Create, fila)?;
+        }
+        Ok(codigo)
+    }
+
+    async fn por_venta(&self, codigo_venta: i64) -> Resultado<Vec<Pagos>> {
+        let tablas = self.tablas();
+        Ok(tablas.pagos.values().filter(|p| p.codigo_venta == Some(codigo_venta)).cloned().collect())
+    }
+
+    async fn borrar(&self, actor: &Actor, codigo_venta: i64, codigo: i64) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        let abierta = tablas.ventas.get(&codigo_venta).is_some_and(|v| v.finalizada_at.is_none());
+        if !abierta || tablas.pagos.get(&codigo).is_none_or(|p| p.codigo_venta != Some(codigo_venta)) {
+            return Ok(0);
+        }
+        // los puntos redimidos con el pago se borran con el (on delete cascade)
+        tablas.movimientos_puntos.retain(|_, m| m.codigo_pago != Some(codigo));
+        Ok(tablas.quitar(actor, PAGOS, |t| &mut t.pagos, codigo))
+    }
+}
+
+#[async_trait]
+impl InventarioRepo for Memoria {
+    async fn existencias(&self, sucursal: Option<i64>) -> Resultado<Vec<Existencias>> {
+        let tablas = self.tablas();
+        Ok(tablas
+            .productos
+            .values()
+            .filter(|p| p.deleted_at.is_none())
+            .filter_map(|p| {
+                let codigo = p.codigo?;
+                let movimientos = tablas
+                    .movimientos_inventario
+                    .values()
+                    .filter(|m| m.codigo_producto == Some(codigo) && en_sucursal(sucursal, m.codigo_sucursal));
+                Some(Existencias {
+                    codigo_producto: codigo,
+                    nombre_producto: p.nombre_producto.clone(),
+                    nit_proveedor: p.nit_proveedor,
+                    unidad_medida: p.unidad_medida.clone(),
+                    precio_compra: p.precio_compra,
+                    stock_minimo: p.stock_minimo,
+                    stock_maximo: p.stock_maximo,
+                    cantidad: movimientos.map(|m| m.cantidad).sum(),
+                })
+            })
+            .collect())
+    }
+
+    async fn vendido(&self, desde: DateTime<Utc>, sucursal: Option<i64>) -> Resultado<Vec<Vendido>> {
+        let tablas = self.tablas();
+        let mut vendido: BTreeMap<Option<i64>, f64> = BTreeMap::new();
+        for detalle in tablas.detalle_ventas.values() {
+            let Some(venta) = detalle.codigo_venta.and_then(|c| tablas.ventas.get(&c)) else { continue };
+            if venta.fecha.is_some_and(|fecha| fecha >= desde)
+                && venta.finalizada_at.is_some()
+                && en_sucursal(sucursal, venta.codigo_sucursal)
+            {
+                *vendido.entry(detalle.codigo_producto).or_default() += detalle.cantidad_producto;
+            }
+        }
+        Ok(vendido.into_iter().map(|(codigo_producto, cantidad)| Vendido { codigo_producto, cantidad }).collect())
+    }
+
+    async fn movimientos(&self, codigo_producto: i64, sucursal: Option<i64>) -> Resultado<Vec<MovimientosInventario>> {
+        let tablas = self.tablas();
+        Ok(tablas
+            .movimientos_inventario
+            .values()
+            .filter(|m| m.codigo_producto == Some(codigo_producto) && en_sucursal(sucursal, m.codigo_sucursal))
+            .cloned()
+            .collect())
+    }
+}
+
+#[async_trait]
+impl OrdenesCompraRepo for Memoria {
+    async fn insertar(&self, actor: &Actor, orden: &OrdenesCompra, detalles: &[DetalleOrdenesCompra]) -> Resultado<i64> {
+        let mut tablas = self.tablas();
+        referencia(&tablas.proveedores, orden.nit_proveedor)?;
+        if orden.codigo_sucursal.is_some() {
+            referencia(&tablas.sucursales, orden.codigo_sucursal)?;
+        }
+        for detalle in detalles {
+            referencia(&tablas.productos, detalle.codigo_producto)?;
+        }
+        let codigo = siguiente(&tablas.ordenes_compra);
+        let fila = OrdenesCompra { codigo, created_at: Some(Utc::now()), ..orden.clone() };
+        tablas.insertar(actor, ORDENES_COMPRA, |t| &mut t.ordenes_compra, codigo, Accion::Create, fila)?;
+        for detalle in detalles {
+            let llave = siguiente(&tablas.detalle_ordenes_compra).unwrap_or_default();
+            let fila = DetalleOrdenesCompra { codigo: Some(llave), codigo_orden: codigo, ..detalle.clone() };
+            tablas.detalle_ordenes_compra.insert(llave, fila);
+        }
+        Ok(codigo.unwrap_or_default())
+    }
+
+    async fn listar(&self, estado: Option<&str>, sucursal: Option<i64>) -> Resultado<Vec<OrdenesCompra>> {
+        let tablas = self.tablas();
+        Ok(tablas
+            .ordenes_compra
+            .values()
+            .rev()
+            .filter(|o| estado.is_none_or(|estado| o.estado == estado) && en_sucursal(sucursal, o.codigo_sucursal))
+            .cloned()
+            .collect())
+    }
+
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<OrdenesCompra>> {
+        Ok(self.tablas().ordenes_compra.get(&codigo).cloned())
+    }
+
+    async fn detalles(&self, codigo_orden: i64) -> Resultado<Vec<DetalleOrdenesCompra>> {
+        let tablas = self.tablas();
+        Ok(tablas.detalle_ordenes_compra.values().filter(|d| d.codigo_orden == Some(codigo_orden)).cloned().collect())
+    }
+
+    async fn recibir(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        let filas = tablas.modificar(actor, ORDENES_COMPRA, |t| &mut t.ordenes_compra, codigo, Accion::Update, |fila| {
+            if fila.estado != ordenes_compra::PENDIENTE {
+                return false;
+            }
+            fila.estado = ordenes_compra::RECIBIDA.to_owned();
+            fila.recibida_at = Some(Utc::now());
+            true
+        });
+        if filas == 0 {
+            return Ok(0);
+        }
+        let codigo_sucursal = tablas.ordenes_compra.get(&codigo).and_then(|o| o.codigo_sucursal);
+        let detalles: Vec<_> =
+            tablas.detalle_ordenes_compra.values().filter(|d| d.codigo_orden == Some(codigo)).cloned().collect();
+        for detalle in detalles {
+            let movimiento = MovimientosInventario {
+                codigo_orden: Some(codigo),
+                ..MovimientosInventario::new(codigo_sucursal, detalle.codigo_producto, inventario::COMPRA, detalle.cantidad)
+            };
+            tablas.insertar_movimiento(actor, movimiento)?;
+        }
+        Ok(filas)
+    }
+
+    async fn cancelar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, ORDENES_COMPRA, |t| &mut t.ordenes_compra, codigo, Accion::Update, |fila| {
+            if fila.estado != ordenes_compra::PENDIENTE {
+                return false;
+            }
+            fila.estado = ordenes_compra::CANCELADA.to_owned();
+            true
+        }))
+    }
+}
+
+#[async_trait]
+impl ConteosRepo for Memoria {
+    async fn insertar(&self, actor: &Actor, conteo: &Conteos) -> Resultado<i64> {
+        let mut tablas = self.tablas();
+        if conteo.codigo_sucursal.is_some() {
+            referencia(&tablas.sucursales, conteo.codigo_sucursal)?;
+        }
+        let codigo = siguiente(&tablas.conteos);
+        let fila = Conteos { codigo, created_at: Some(Utc::now()), ..conteo.clone() };
+        tablas.insertar(actor, CONTEOS, |t| &mut t.conteos, codigo, Accion::Create, fila)?;
+        Ok(codigo.unwrap_or_default())
+    }
+
+    async fn listar(&self, estado: Option<&str>, sucursal: Option<i64>) -> Resultado<Vec<Conteos>> {
+        let tablas = self.tablas();
+        Ok(tablas
+            .conteos
+            .values()
+            .rev()
+            .filter(|c| estado.is_none_or(|estado| c.estado == estado) && en_sucursal(sucursal, c.codigo_sucursal))
+            .cloned()
+            .collect())
+    }
+
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Conteos>> {
+        Ok(self.tablas().conteos.get(&codigo).cloned())
+    }
+
+    async fn detalles(&self, codigo_conteo: i64) -> Resultado<Vec<DetalleConteos>> {
+        let tablas = self.tablas();
+        Ok(tablas.detalle_conteos.values().filter(|d| d.codigo_conteo == Some(codigo_conteo)).cloned().collect())
+    }
+
+    async fn registrar(&self, actor: &Actor, codigo_conteo: i64, contados: &[DetalleConteos]) -> Resultado<Option<u64>> {
+        let mut tablas = self.tablas();
+        if tablas.conteos.get(&codigo_conteo).is_none_or(|c| c.estado != conteos::ABIERTO) {
+            return Ok(None);
+        }
+        for contado in contados {
+            referencia(&tablas.productos, contado.codigo_producto)?;
+        }
+        for contado in contados {
+            let anterior = tablas
+                .detalle_conteos
+                .values()
+                .find(|d| d.codigo_conteo == Some(codigo_conteo) && d.codigo_producto == contado.codigo_producto)
+                .and_then(|d| d.codigo);
+            match anterior {
+                Some(codigo) => {
+                    tablas.modificar(actor, DETALLE_CONTEOS, |t| &mut t.detalle_conteos, codigo, Accion::Update, |fila| {
+                        fila.cantidad = contado.cantidad;
+                        fila.motivo = contado.motivo.clone();
+                        fila.updated_at = Some(Utc::now());
+                        true
+                    });
+                }
+                None => {
+                    let codigo = siguiente(&tablas.detalle_conteos);
+                    let fila = DetalleConteos {
+                        codigo,
+                        codigo_conteo: Some(codigo_conteo),
+                        updated_at: Some(Utc::now()),
+                        ..contado.clone()
+                    };
+                    tablas.insertar(actor, DETALLE_CONTEOS, |t| &mut t.detalle_conteos, codigo, Accion::Create, fila)?;
+                }
+            }
+        }
+        Ok(Some(contados.len() as u64))
+    }
+
+    async fn aplicar(&self, actor: &Actor, codigo: i64, motivo: &str) -> Resultado<Option<u64>> {
+        let mut tablas = self.tablas();
+        let filas = tablas.modificar(actor, CONTEOS, |t| &mut t.conteos, codigo, Accion::Update, |fila| {
+            if fila.estado != conteos::ABIERTO {
+                return false;
+            }
+            fila.estado = conteos::APLICADO.to_owned();
+            fila.aplicado_at = Some(Utc::now());
+            true
+        });
+        if filas == 0 {
+            return Ok(None);
+        }
+        let codigo_sucursal = tablas.conteos.get(&codigo).and_then(|c| c.codigo_sucursal);
+        let contados: Vec<_> = tablas
+            .detalle_conteos
+            .values()
+            .filter(|d| d.codigo_conteo == Some(codigo))
+            .filter(|d| d.codigo_producto.and_then(|c| tablas.productos.get(&c)).is_some_and(|p| p.deleted_at.is_none()))
+            .cloned()
+            .collect();
+        let mut ajustes = 0;
+        for contado in contados {
+            let sistema = tablas.existencias_en(contado.codigo_producto, codigo_sucursal);
+            let cantidad = conteos::ajuste(sistema, contado.cantidad);
+            if cantidad == 0.0 {
+                continue;
+            }
+            let movimiento = MovimientosInventario {
+                codigo_conteo: Some(codigo),
+                motivo: Some(contado.motivo.unwrap_or_else(|| motivo.to_owned())),
+                ..MovimientosInventario::new(codigo_sucursal, contado.codigo_producto, inventario::AJUSTE, cantidad)
+            };
+            tablas.insertar_movimiento(actor, movimiento)?;
+            ajustes += 1;
+        }
+        Ok(Some(ajustes))
+    }
+
+    async fn cancelar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, CONTEOS, |t| &mut t.conteos, codigo, Accion::Update, |fila| {
+            if fila.estado != conteos::ABIERTO {
+                return false;
+            }
+            fila.estado = conteos::CANCELADO.to_owned();
+            true
+        }))
+    }
+}
+
+#[async_trait]
+impl PuntosRepo for Memoria {
+    async fn movimientos(&self, cedula_cliente: i64) -> Resultado<Vec<MovimientosPuntos>> {
+        let tablas = self.tablas();
+        Ok(tablas.movimientos_puntos.values().filter(|m| m.cedula_cliente == Some(cedula_cliente)).cloned().collect())
+    }
+}
+
+#[async_trait]
+impl SucursalesRepo for Memoria {
+    async fn insertar(&self, actor: &Actor, sucursal: &Sucursales) -> Resultado<u64> {
+        let ahora = Some(Utc::now());
+        let fila = Sucursales { created_at: ahora, updated_at: ahora, ..sucursal.clone() };
+        self.tablas().insertar(actor, SUCURSALES, |t| &mut t.sucursales, sucursal.codigo, Accion::Create, fila)
+    }
+
+    async fn listar(&self) -> Resultado<Vec<Sucursales>> {
+        Ok(self.tablas().sucursales.values().cloned().collect())
+    }
+
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Sucursales>> {
+        Ok(self.tablas().sucursales.get(&codigo).cloned())
+    }
+
+    async fn actualizar(&self, actor: &Actor, codigo: i64, sucursal: &Sucursales) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, SUCURSALES, |t| &mut t.sucursales, codigo, Accion::Update, |fila| {
+            fila.nombre = sucursal.nombre.clone();
+            fila.direccion = sucursal.direccion.clone();
+            fila.updated_at = Some(Utc::now());
+            true
+        }))
+    }
+
+    async fn asignadas(&self, cedula_usuario: i64) -> Resultado<Vec<Sucursales>> {
+        let tablas = self.tablas();
+        Ok(tablas
+            .sucursales
+            .values()
+            .filter(|s| {
+                tablas.usuarios_sucursales.values().any(|a| a.cedula_usuario == cedula_usuario && s.codigo == Some(a.codigo_sucursal))
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn asignar(&self, actor: &Actor, cedula_usuario: i64, codigo_sucursal: i64) -> Resultado<Option<i64>> {
+        let mut tablas = self.tablas();
+        referencia(&tablas.usuarios, Some(cedula_usuario))?;
+        referencia(&tablas.sucursales, Some(codigo_sucursal))?;
+        if tablas.usuarios_sucursales.values().any(|a| a.cedula_usuario == cedula_usuario && a.codigo_sucursal == codigo_sucursal) {
+            return Err(ErrorRepo::Duplicado);
+        }
+        let codigo = siguiente(&tablas.usuarios_sucursales);
+        let fila = UsuariosSucursales { codigo, cedula_usuario, codigo_sucursal, created_at: Some(Utc::now()) };
+        tablas.insertar(actor, USUARIOS_SUCURSALES, |t| &mut t.usuarios_sucursales, codigo, Accion::Create, fila)?;
+        Ok(codigo)
+    }
+
+    async fn desasignar(&self, actor: &Actor, cedula_usuario: i64, codigo_sucursal: i64) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        let codigo = tablas
+            .usuarios_sucursales
+            .values()
+            .find(|a| a.cedula_usuario == cedula_usuario && a.codigo_sucursal == codigo_sucursal)
+            .and_then(|a| a.codigo);
+        Ok(codigo.map_or(0, |codigo| tablas.quitar(actor, USUARIOS_SUCURSALES, |t| &mut t.usuarios_sucursales, codigo)))
+    }
+}
+
+#[async_trait]
+impl TrasladosRepo for Memoria {
+    async fn insertar(&self, actor: &Actor, traslado: &Traslados, productos: &[LineaTraslado]) -> Resultado<Option<i64>> {
+        let mut tablas = self.tablas();
+        let totales = traslados::totales(productos);
+        for (&codigo_producto, &cantidad) in &totales {
+            let existencias = tablas.existencias_en(Some(codigo_producto), Some(traslado.codigo_sucursal_origen));
+            if !traslados::alcanza(existencias, cantidad) {
+                return Ok(None);
+            }
+        }
+        referencia(&tablas.sucursales, Some(traslado.codigo_sucursal_origen))?;
+        referencia(&tablas.sucursales, Some(traslado.codigo_sucursal_destino))?;
+        let codigo = siguiente(&tablas.traslados);
+        let fila = Traslados { codigo, fecha: Some(Utc::now()), ..traslado.clone() };
+        tablas.insertar(actor, TRASLADOS, |t| &mut t.traslados, codigo, Accion::Create, fila)?;
+        for (&codigo_producto, &cantidad) in &totales {
+            for (codigo_sucursal, cantidad) in [(traslado.codigo_sucursal_origen, -cantidad), (traslado.codigo_sucursal_destino, cantidad)]
+            {
+                let movimiento = MovimientosInventario {
+                    codigo_traslado: codigo,
+                    motivo: traslado.motivo.clone(),
+                    ..MovimientosInventario::new(Some(codigo_sucursal), Some(codigo_producto), inventario::TRASLADO, cantidad)
+                };
+                tablas.insertar_movimiento(actor, movimiento)?;
+            }
+        }
+        Ok(codigo)
+    }
+
+    async fn listar(&self, rango: &RangoFechas, sucursal: Option<i64>) -> Resultado<Vec<Traslados>> {
+        let tablas = self.tablas();
+        Ok(tablas
+            .traslados
+            .values()
+            .filter(|t| en_rango(rango, t.fecha))
+            .filter(|t| {
+                en_sucursal(sucursal, Some(t.codigo_sucursal_origen)) || en_sucursal(sucursal, Some(t.codigo_sucursal_destino))
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Traslados>> {
+        Ok(self.tablas().traslados.get(&codigo).cloned())
+    }
+
+    async fn movimientos(&self, codigo_traslado: i64) -> Resultado<Vec<MovimientosInventario>> {
+        let tablas = self.tablas();
+        Ok(tablas.movimientos_inventario.values().filter(|m| m.codigo_traslado == Some(codigo_traslado)).cloned().collect())
+    }
+}
+
+#[async_trait]
+impl CajasRepo for Memoria {
+    async fn insertar(&self, actor: &Actor, caja: &Cajas) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        if caja.codigo_sucursal.is_some() {
+            referencia(&tablas.sucursales, caja.codigo_sucursal)?;
+        }
+        let ahora = Some(Utc::now());
+        let fila = Cajas { created_at: ahora, updated_at: ahora, ..caja.clone() };
+        tablas.insertar(actor, CAJAS, |t| &mut t.cajas, caja.codigo, Accion::Create, fila)
+    }
+
+    async fn listar(&self, sucursal: Option<i64>) -> Resultado<Vec<Cajas>> {
+        Ok(self.tablas().cajas.values().filter(|c| en_sucursal(sucursal, c.codigo_sucursal)).cloned().collect())
+    }
+
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Cajas>> {
+        Ok(self.tablas().cajas.get(&codigo).cloned())
+    }
+
+    async fn actualizar(&self, actor: &Actor, codigo: i64, caja: &Cajas) -> Resultado<u64> {
+        let mut tablas = self.tablas();
+        if caja.codigo_sucursal.is_some() {
+            referencia(&tablas.sucursales, caja.codigo_sucursal)?;
+        }
+        Ok(tablas.modificar(actor, CAJAS, |t| &mut t.cajas, codigo, Accion::Update, |fila| {
+            fila.nombre = caja.nombre.clone();
+            fila.codigo_sucursal = caja.codigo_sucursal.or(fila.codigo_sucursal);
+            fila.updated_at = Some(Utc::now());
+            true
+        }))
+    }
+}
+
+#[async_trait]
+impl TurnosRepo for Memoria {
+    async fn abrir(&self, actor: &Actor, turno: &Turnos) -> Resultado<i64> {
+        let mut tablas = self.tablas();
+        referencia(&tablas.cajas, turno.codigo_caja)?;
+        referencia(&tablas.usuarios, turno.cedula_usuario)?;
+        let abierto = tablas.turnos.values().any(|t| {
+            t.cerrado_at.is_none() && (t.cedula_usuario == turno.cedula_usuario || t.codigo_caja == turno.codigo_caja)
+        });
+        if abierto {
+            return Err(ErrorRepo::Duplicado);
+        }
+        let codigo = siguiente(&tablas.turnos);
+        let fila = Turnos {
+            codigo,
+            efectivo_contado: None,
+            abierto_at: Some(Utc::now()),
+            cerrado_at: None,
+            ..turno.clone()
+        };
+        tablas.insertar(actor, TURNOS, |t| &mut t.turnos, codigo, Accion::Create, fila)?;
+        Ok(codigo.unwrap_or_default())
+    }
+
+    async fn listar(&self, rango: &RangoFechas, sucursal: Option<i64>) -> Resultado<Vec<Turnos>> {
+        let tablas = self.tablas();
+        let de_la_sucursal =
+            |t: &Turnos| en_sucursal(sucursal, t.codigo_caja.and_then(|c| tablas.cajas.get(&c)).and_then(|c| c.codigo_sucursal));
+        Ok(tablas.turnos.values().filter(|t| en_rango(rango, t.abierto_at) && de_la_sucursal(t)).cloned().collect())
+    }
+
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Turnos>> {
+        Ok(self.tablas().turnos.get(&codigo).cloned())
+    }
+
+    async fn abierto(&self, cedula_usuario: i64) -> Resultado<Option<Turnos>> {
+        let tablas = self.tablas();
+        Ok(tablas
+            .turnos
+            .values()
+            .find(|t| t.cedula_usuario == Some(cedula_usuario) && t.cerrado_at.is_none())
+            .cloned())
+    }
+
+    async fn insertar_movimiento(
+        &self,
+        actor: &Actor,
+        codigo_turno: i64,
+        movimiento: &MovimientosCaja,
+    ) -> Resultado<Option<i64>> {
+        let mut tablas = self.tablas();
+        if tablas.turnos.get(&codigo_turno).is_none_or(|t| t.cerrado_at.is_some()) {
+            return Ok(None);
+        }
+        let codigo = siguiente(&tablas.movimientos_caja);
+        let fila = MovimientosCaja {
+            codigo,
+            codigo_turno: Some(codigo_turno),
+            fecha: Some(Utc::now()),
+            ..movimiento.clone()
+        };
+        tablas.insertar(actor, MOVIMIENTOS_CAJA, |t| &mut t.movimientos_caja, codigo, Accion::Create, fila)?;
+        Ok(codigo)
+    }
+
+    async fn movimientos(&self, codigo_turno: i64) -> Resultado<Vec<MovimientosCaja>> {
+        let tablas = self.tablas();
+        Ok(tablas.movimientos_caja.values().filter(|m| m.codigo_turno == Some(codigo_turno)).cloned().collect())
+    }
+
+    async fn totales(&self, codigo_turno: i64) -> Resultado<TotalesTurno> {
+        let tablas = self.tablas();
+        let mut totales = TotalesTurno::default();
+        let del_turno = |v: &Ventas| v.codigo_turno == Some(codigo_turno) && v.finalizada_at.is_some();
+        for venta in tablas.ventas.values().filter(|v| del_turno(v)) {
+            totales.ventas += 1;
+            totales.total_ventas += venta.total_venta;
+        }
+        for pago in tablas.pagos.values().filter(|p| p.metodo == EFECTIVO) {
+            if pago.codigo_venta.and_then(|v| tablas.ventas.get(&v)).is_some_and(del_turno) {
+                totales.efectivo += pago.valor;
+            }
+        }
+        for movimiento in tablas.movimientos_caja.values().filter(|m| m.codigo_turno == Some(codigo_turno)) {
+            match movimiento.tipo.as_str() {
+                ENTRADA => totales.entradas += movimiento.valor,
+                SALIDA => totales.salidas += movimiento.valor,
+                _ => {}
+            }
+        }
+        Ok(totales)
+    }
+
+    async fn cerrar(&self, actor: &Actor, codigo: i64, efectivo_contado: f64) -> Resultado<u64> {
+        Ok(self.tablas().modificar(actor, TURNOS, |t| &mut t.turnos, codigo, Accion::Update, |fila| {
+            if fila.cerrado_at.is_some() {
+                return false;
+            }
+            fila.efectivo_contado = Some(efectivo_contado);
+            fila.cerrado_at = Some(Utc::now());
+            true
+        }))
+    }
+}
+
+#[async_trait]
+impl AuditoriaRepo for Memoria {
+    async fn listar(
+        &self,
+        filtro: &FiltroAuditoria,
+        rango: &RangoFechas,
+        paginacion: &Paginacion,
+    ) -> Resultado<Vec<Auditoria>> {
+        let tablas = self.tablas();
+        let registros = tablas
+            .auditoria
+            .iter()
+            .rev()
+            .filter(|r| filtro.entidad.as_ref().is_none_or(|e| *e == r.entidad))
+            .filter(|r| filtro.llave.is_none_or(|l| Some(l) == r.llave))
+            .filter(|r| filtro.accion.as_ref().is_none_or(|a| *a == r.accion))
+            .filter(|r| filtro.cedula_usuario.is_none_or(|c| Some(c) == r.cedula_usuario))
+            .filter(|r| en_rango(rango, Some(r.fecha)))
+            .cloned();
+        Ok(paginar(registros, paginacion))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::DeserializeOwned;
+    use serde_json::json;
+
+    use super::*;
+
+    fn desde_json<T: DeserializeOwned>(valor: Value) -> T {
+        serde_json::from_value(valor).expect("json de prueba valido")
+    }
+
+    fn cajero() -> Actor {
+        Actor { cedula: Some(1000) }
+    }
+
+    fn sin_filtro() -> FiltroAuditoria {
+        FiltroAuditoria { entidad: None, llave: None, accion: None, cedula_usuario: None }
+    }
+
+    fn sin_rango() -> RangoFechas {
+        RangoFechas { desde: None, hasta: None }
+    }
+
+    fn primera_pagina() -> Paginacion {
+        Paginacion { pagina: None, por_pagina: None }
+    }
+
+    fn cliente(cedula: i64) -> Clientes {
+        desde_json(json!({
+            "cedula": cedula, "direccion": "Calle 1", "email": "ana@correo.co",
+            "nombre": "Ana", "telefono": "300 111 2233",
+        }))
+    }
+
+    fn producto(codigo: i64, nit_proveedor: i64) -> Productos {
+        desde_json(json!({
+            "codigo": codigo, "nit_proveedor": nit_proveedor, "iva_compra": 19.0,
+            "nombre_producto": "Arroz", "precio_compra": 100.0, "precio_venta": 150.0,
+        }))
+    }
+
+    /// Un proveedor, un usuario, un cliente, un producto y una venta con un detalle
+    async fn tienda() -> Memoria {
+        let repo = Memoria::default();
+        let actor = Actor::sistema();
+        let proveedor: Proveedores = desde_json(json!({
+            "nit": 1234, "ciudad": "Bogota", "direccion": "Calle 2", "nombre": "Distribuidora", "telefono": "601 555 1234",
+        }));
+        ProveedoresRepo::insertar(&repo, &actor, &proveedor).await.unwrap();
+        let usuario: Usuarios = desde_json(json!({
+            "cedula": 1000, "email": "cajero@tienda.co", "nombre": "Cajero", "password": "secreto", "usuario": "cajero",
+        }));
+        UsuariosRepo::insertar(&repo, &actor, &usuario).await.unwrap();
+        ClientesRepo::insertar(&repo, &actor, &cliente(2001)).await.unwrap();
+        ProductosRepo::insertar(&repo, &actor, &producto(10, 1234)).await.unwrap();
+        let venta: Ventas = desde_json(json!({
+            "codigo": 1, "cedula_cliente": 2001, "cedula_usuario": 1000,
+            "iva_venta": 28.5, "total_venta": 178.5, "valor_venta": 150.0,
+        }));
+        VentasRepo::insertar(&repo, &actor, &venta).await.unwrap();
+        let detalle: DetalleVentas = desde_json(json!({
+            "codigo": 11, "codigo_producto": 10, "codigo_venta": 1, "cantidad_producto": 1,
+            "valor_total": 178.5, "valor_venta": 150.0, "valor_iva": 28.5,
+        }));
+        DetalleVentasRepo::insertar(&repo, &actor, &detalle).await.unwrap();
+        repo
+    }
+
+    #[actix_web::test]
+    async fn insertar_asigna_fechas_y_rechaza_llaves_duplicadas() {
+        let repo = Memoria::default();
+        assert_eq!(ClientesRepo::insertar(&repo, &cajero(), &cliente(1)).await.unwrap(), 1);
+        let guardado = ClientesRepo::obtener(&repo, 1).await.unwrap().unwrap();
+        assert!(guardado.created_at.is_some() && guardado.deleted_at.is_none());
+        assert!(matches!(
+            ClientesRepo::insertar(&repo, &cajero(), &cliente(1)).await,
+            Err(ErrorRepo::Duplicado)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn las_llaves_foraneas_deben_existir() {
+        let repo = tienda().await;
+        assert!(matches!(
+            ProductosRepo::insertar(&repo, &cajero(), &producto(20, 9999)).await,
+            Err(ErrorRepo::LlaveForanea)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn el_borrado_logico_oculta_y_restaurar_devuelve() {
+        let repo = tienda().await;
+        assert_eq!(ClientesRepo::borrar(&repo, &cajero(), 2001).await.unwrap(), 1);
+        assert_eq!(ClientesRepo::borrar(&repo, &cajero(), 2001).await.unwrap(), 0);
+        assert!(ClientesRepo::listar(&repo, false).await.unwrap().is_empty());
+        assert_eq!(ClientesRepo::listar(&repo, true).await.unwrap().len(), 1);
+        assert_eq!(ClientesRepo::restaurar(&repo, &cajero(), 2001).await.unwrap(), 1);
+        assert_eq!(ClientesRepo::listar(&repo, false).await.unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn una_venta_con_detalles_no_se_puede_borrar() {
+        let repo = tienda().await;
+        let informe = VentasRepo::informe_borrado(&repo, 1).await.unwrap().unwrap();
+        assert_eq!(serde_json::to_value(informe).unwrap()["bloqueantes"]["detalle_ventas"], 1);
+        assert!(matches!(VentasRepo::borrar(&repo, &cajero(), 1).await, Err(ErrorRepo::LlaveForanea)));
+        assert_eq!(DetalleVentasRepo::borrar(&repo, &cajero(), 11).await.unwrap(), 1);
+        assert_eq!(VentasRepo::borrar(&repo, &cajero(), 1).await.unwrap(), 1);
+    }
+
+    #[actix_web::test]
+    async fn lo_pagado_con_puntos_no_acumula_puntos() {
+        let repo = tienda().await;
+        let config = ConfigPuntos { pesos_por_punto: 10.0, ..ConfigPuntos::default() };
+        let saldo = MovimientosPuntos {
+            codigo: Some(1),
+            cedula_cliente: Some(2001),
+            codigo_venta: None,
+            codigo_pago: None,
+            puntos: 10,
+            vence: None,
+            fecha: Some(Utc::now()),
+        };
+        repo.tablas().movimientos_puntos.insert(1, saldo);
+        // los 10 puntos pagan 100 de los 178.5 de la venta
+        let pago = |metodo: &str, valor: f64| -> Pagos { desde_json(json!({ "metodo": metodo, "valor": valor })) };
+        assert!(PagosRepo::insertar(&repo, &cajero(), 1, &pago(PUNTOS, 100.0), &config).await.unwrap().is_some());
+        assert!(PagosRepo::insertar(&repo, &cajero(), 1, &pago("tarjeta", 78.5), &config).await.unwrap().is_some());
+        assert_eq!(VentasRepo::finalizar(&repo, &cajero(), 1, &config).await.unwrap(), 1);
+        let movimientos = PuntosRepo::movimientos(&repo, 2001).await.unwrap();
+        let acumulados: Vec<_> = movimientos.iter().filter(|m| m.codigo_venta == Some(1)).map(|m| m.puntos).collect();
+        assert_eq!(acumulados, [7]);
+    }
+
+    #[actix_web::test]
+    async fn importar_no_inserta_nada_si_una_fila_falla() {
+        let repo = tienda().await;
+        let productos = [producto(20, 1234), producto(21, 9999)];
+        assert!(ProductosRepo::importar(&repo, &cajero(), &productos, &[]).await.is_err());
+        assert!(ProductosRepo::obtener(&repo, 20).await.unwrap().is_none());
+        let importados = AuditoriaRepo::listar(
+            &repo,
+            &FiltroAuditoria { accion: Some("import".to_owned()), ..sin_filtro() },
+            &sin_rango(),
+            &primera_pagina(),
+        )
+        .await
+        .unwrap();
+        assert!(importados.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn la_auditoria_guarda_el_actor_y_oculta_las_contraseñas() {
+        let repo = tienda().await;
+        let usuario: Usuarios = desde_json(json!({
+            "cedula": 1000, "email": "cajero@tienda.co", "nombre": "Cajero", "password": "nueva", "usuario": "cajero",
+        }));
+        UsuariosRepo::actualizar(&repo, &cajero(), 1000, &usuario).await.unwrap();
+        let registros = AuditoriaRepo::listar(
+            &repo,
+            &FiltroAuditoria { entidad: Some("usuarios".to_owned()), ..sin_filtro() },
+            &sin_rango(),
+            &primera_pagina(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(registros.len(), 2);
+        let ultimo = &registros[0];
+        assert_eq!((ultimo.accion.as_str(), ultimo.cedula_usuario), ("update", Some(1000)));
+        let despues = ultimo.despues.as_ref().unwrap();
+        assert!(despues.get("password").is_none());
+        assert_eq!(despues["usuario"], "cajero");
+    }
+}
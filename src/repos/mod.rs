@@ -0,0 +1,625 @@
+//! Este archivo contiene la capa de almacenamiento: un trait (repositorio) por entidad con
+//! las operaciones que usan los servicios REST y la linea de comandos.
+//! Hay una implementacion con SQL (ver `sql`) que funciona tanto con postgres como con SQLite;
+//! el motor se elige segun el esquema de `DATABASE_URL` (`postgres://...` o `sqlite:...`).
+//! Las operaciones que modifican datos quedan registradas en la auditoria, en la misma transaccion.
+
+#[cfg(test)]
+pub mod memoria;
+mod sql;
+
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use sqlx::{
+    postgres::PgPoolOptions,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    PgPool, SqlitePool,
+};
+
+use crate::{
+    auth::Actor,
+    borrado::InformeBorrado,
+    entities::{
+        auditoria::{Auditoria, FiltroAuditoria},
+        cajas::Cajas,
+        categorias::{Categorias, VentasCategoria},
+        clientes::Clientes,
+        codigos_barras::CodigosBarras,
+        conteos::{Conteos, DetalleConteos},
+        detalle_ventas::DetalleVentas,
+        inventario::{Existencias, MovimientosInventario, Vendido},
+        ordenes_compra::{DetalleOrdenesCompra, OrdenesCompra},
+        pagos::Pagos,
+        precios::{PreciosHistorial, PreciosProgramados},
+        productos::Productos,
+        promociones::Promociones,
+        proveedores::Proveedores,
+        puntos::{ConfigPuntos, MovimientosPuntos},
+        sucursales::Sucursales,
+        traslados::{LineaTraslado, Traslados},
+        turnos::{MovimientosCaja, TotalesTurno, Turnos},
+        usuarios::Usuarios,
+        ventas::Ventas,
+    },
+    filtros::{Paginacion, RangoFechas},
+};
+use chrono::{DateTime, NaiveDate, Utc};
+
+use self::sql::Sql;
+
+/// Los errores de la capa de almacenamiento
+#[derive(Debug)]
+pub enum ErrorRepo {
+    /// Una llave foranea no existe, o la fila es referenciada por otras filas
+    LlaveForanea,
+    /// Ya existe una fila con la misma llave primaria
+    Duplicado,
+    /// Cualquier otro error de la base de datos
+    Db(sqlx::Error),
+}
+
+impl std::fmt::Display for ErrorRepo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorRepo::LlaveForanea => write!(f, "foreign key violation"),
+            ErrorRepo::Duplicado => write!(f, "duplicate key"),
+            ErrorRepo::Db(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ErrorRepo {}
+
+impl From<sqlx::Error> for ErrorRepo {
+    fn from(e: sqlx::Error) -> Self {
+        match &e {
+            sqlx::Error::Database(db) if db.is_foreign_key_violation() => ErrorRepo::LlaveForanea,
+            sqlx::Error::Database(db) if db.is_unique_violation() => ErrorRepo::Duplicado,
+            _ => ErrorRepo::Db(e),
+        }
+    }
+}
+
+pub type Resultado<T> = Result<T, ErrorRepo>;
+
+/// Las operaciones de la tabla 'clientes'
+#[async_trait]
+pub trait ClientesRepo: Send + Sync {
+    async fn insertar(&self, actor: &Actor, cliente: &Clientes) -> Resultado<u64>;
+    async fn listar(&self, incluir_borrados: bool) -> Resultado<Vec<Clientes>>;
+    async fn obtener(&self, cedula: i64) -> Resultado<Option<Clientes>>;
+    async fn actualizar(&self, actor: &Actor, cedula: i64, cliente: &Clientes) -> Resultado<u64>;
+    /// El informe de un borrado simulado, o `None` si el cliente no existe
+    async fn informe_borrado(&self, cedula: i64) -> Resultado<Option<InformeBorrado>>;
+    async fn borrar(&self, actor: &Actor, cedula: i64) -> Resultado<u64>;
+    async fn restaurar(&self, actor: &Actor, cedula: i64) -> Resultado<u64>;
+}
+
+/// Las operaciones de la tabla 'usuarios'
+#[async_trait]
+pub trait UsuariosRepo: Send + Sync {
+    async fn insertar(&self, actor: &Actor, usuario: &Usuarios) -> Resultado<u64>;
+    /// Los usuarios, opcionalmente solo los asignados a una sucursal
+    async fn listar(&self, incluir_borrados: bool, sucursal: Option<i64>) -> Resultado<Vec<Usuarios>>;
+    async fn obtener(&self, cedula: i64) -> Resultado<Option<Usuarios>>;
+    /// Busca un usuario (no borrado) por su nombre de usuario, para el login
+    async fn por_usuario(&self, usuario: &str) -> Resultado<Option<Usuarios>>;
+    async fn actualizar(&self, actor: &Actor, cedula: i64, usuario: &Usuarios) -> Resultado<u64>;
+    async fn informe_borrado(&self, cedula: i64) -> Resultado<Option<InformeBorrado>>;
+    async fn borrar(&self, actor: &Actor, cedula: i64) -> Resultado<u64>;
+    async fn restaurar(&self, actor: &Actor, cedula: i64) -> Resultado<u64>;
+}
+
+/// Las operaciones de la tabla 'proveedores'
+#[async_trait]
+pub trait ProveedoresRepo: Send + Sync {
+    async fn insertar(&self, actor: &Actor, proveedor: &Proveedores) -> Resultado<u64>;
+    async fn listar(&self, incluir_borrados: bool) -> Resultado<Vec<Proveedores>>;
+    async fn obtener(&self, nit: i64) -> Resultado<Option<Proveedores>>;
+    async fn actualizar(&self, actor: &Actor, nit: i64, proveedor: &Proveedores) -> Resultado<u64>;
+    async fn informe_borrado(&self, nit: i64) -> Resultado<Option<InformeBorrado>>;
+    async fn borrar(&self, actor: &Actor, nit: i64) -> Resultado<u64>;
+    async fn restaurar(&self, actor: &Actor, nit: i64) -> Resultado<u64>;
+}
+
+/// Las operaciones de la tabla 'categorias'
+#[async_trait]
+pub trait CategoriasRepo: Send + Sync {
+    async fn insertar(&self, actor: &Actor, categoria: &Categorias) -> Resultado<u64>;
+    async fn listar(&self, incluir_borrados: bool) -> Resultado<Vec<Categorias>>;
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Categorias>>;
+    async fn actualizar(&self, actor: &Actor, codigo: i64, categoria: &Categorias) -> Resultado<u64>;
+    async fn informe_borrado(&self, codigo: i64) -> Resultado<Option<InformeBorrado>>;
+    async fn borrar(&self, actor: &Actor, codigo: i64) -> Resultado<u64>;
+    async fn restaurar(&self, actor: &Actor, codigo: i64) -> Resultado<u64>;
+    /// Las ventas de un rango de fechas agrupadas por la categoria de los productos, opcionalmente
+    /// solo las de una categoria y sus subcategorias y las de una sucursal
+    async fn ventas(&self, rango: &RangoFechas, categoria: Option<i64>, sucursal: Option<i64>) -> Resultado<Vec<VentasCategoria>>;
+}
+
+/// Las operaciones de la tabla 'productos'
+#[async_trait]
+pub trait ProductosRepo: Send + Sync {
+    async fn insertar(&self, actor: &Actor, producto: &Productos) -> Resultado<u64>;
+    /// Inserta varios productos y sus codigos de barras en una sola transaccion: si alguno falla no se inserta ninguno
+    async fn importar(&self, actor: &Actor, productos: &[Productos], codigos_barras: &[CodigosBarras]) -> Resultado<u64>;
+    /// Los productos, opcionalmente solo los de una categoria y sus subcategorias
+    async fn listar(&self, incluir_borrados: bool, categoria: Option<i64>) -> Resultado<Vec<Productos>>;
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Productos>>;
+    /// Los productos de un proveedor, de forma paginada
+    async fn por_proveedor(&self, nit: i64, paginacion: &Paginacion, incluir_borrados: bool) -> Resultado<Vec<Productos>>;
+    async fn actualizar(&self, actor: &Actor, codigo: i64, producto: &Productos) -> Resultado<u64>;
+    async fn informe_borrado(&self, codigo: i64) -> Resultado<Option<InformeBorrado>>;
+    async fn borrar(&self, actor: &Actor, codigo: i64) -> Resultado<u64>;
+    async fn restaurar(&self, actor: &Actor, codigo: i64) -> Resultado<u64>;
+}
+
+/// Las operaciones de la tabla 'codigos_barras'. Los codigos ya vienen normalizados a 13 digitos
+#[async_trait]
+pub trait CodigosBarrasRepo: Send + Sync {
+    /// Asigna un codigo de barras a un producto y retorna su codigo; falla con `LlaveForanea` si el producto
+    /// no existe y con `Duplicado` si el codigo de barras ya esta asignado
+    async fn insertar(&self, actor: &Actor, codigo_producto: i64, codigo_barras: &str) -> Resultado<Option<i64>>;
+    /// Los codigos de barras de un producto, en el orden en que se asignaron
+    async fn por_producto(&self, codigo_producto: i64) -> Resultado<Vec<CodigosBarras>>;
+    async fn buscar(&self, codigo_barras: &str) -> Resultado<Option<CodigosBarras>>;
+    async fn borrar(&self, actor: &Actor, codigo_producto: i64, codigo_barras: &str) -> Resultado<u64>;
+}
+
+/// Las operaciones de las tablas 'precios_historial' y 'precios_programados'.
+/// El historial lo escriben las operaciones de `ProductosRepo` que cambian los precios
+#[async_trait]
+pub trait PreciosRepo: Send + Sync {
+    /// El historial de precios de un producto, en orden cronologico
+    async fn historial(&self, codigo_producto: i64) -> Resultado<Vec<PreciosHistorial>>;
+    /// Programa un cambio de precio y retorna su codigo; falla con `LlaveForanea` si el producto no existe
+    async fn programar(&self, actor: &Actor, codigo_producto: i64, programado: &PreciosProgramados) -> Resultado<Option<i64>>;
+    /// Los cambios programados de un producto que aun no se han aplicado, por fecha
+    async fn programados(&self, codigo_producto: i64) -> Resultado<Vec<PreciosProgramados>>;
+    /// Borra un cambio programado que aun no se ha aplicado
+    async fn cancelar(&self, actor: &Actor, codigo_producto: i64, codigo: i64) -> Resultado<u64>;
+    /// Aplica los cambios programados hasta una fecha, en orden, y retorna cuantos aplico.
+    /// Cada cambio queda en la auditoria a nombre del usuario que lo programo; los de productos borrados
+    /// quedan pendientes
+    async fn aplicar(&self, hasta: DateTime<Utc>) -> Resultado<u64>;
+}
+
+/// Las operaciones de la tabla 'ventas'
+#[async_trait]
+pub trait VentasRepo: Send + Sync {
+    async fn insertar(&self, actor: &Actor, venta: &Ventas) -> Resultado<u64>;
+    /// Las ventas de un rango de fechas, opcionalmente solo las de una sucursal, ordenadas por fecha
+    async fn listar(&self, rango: &RangoFechas, sucursal: Option<i64>) -> Resultado<Vec<Ventas>>;
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Ventas>>;
+    /// Las compras de un cliente, opcionalmente solo las de una sucursal, de forma paginada
+    async fn por_cliente(&self, cedula: i64, paginacion: &Paginacion, sucursal: Option<i64>) -> Resultado<Vec<Ventas>>;
+    /// Las ventas registradas por un usuario, opcionalmente solo las de una sucursal, de forma paginada
+    async fn por_usuario(&self, cedula: i64, paginacion: &Paginacion, sucursal: Option<i64>) -> Resultado<Vec<Ventas>>;
+    async fn actualizar(&self, actor: &Actor, codigo: i64, venta: &Ventas) -> Resultado<u64>;
+    /// Finaliza una venta abierta cuyos detalles y pagos suman su total, descuenta sus productos del inventario de su
+    /// sucursal y le acumula al cliente los puntos de la venta segun `puntos`, en una sola transaccion;
+    /// si no, no afecta ninguna fila
+    async fn finalizar(&self, actor: &Actor, codigo: i64, puntos: &ConfigPuntos) -> Resultado<u64>;
+    async fn informe_borrado(&self, codigo: i64) -> Resultado<Option<InformeBorrado>>;
+    /// Borra una venta abierta de forma fisica (una finalizada no afecta ninguna fila); falla con `LlaveForanea`
+    /// si tiene detalles, pagos o movimientos de puntos o de inventario
+    async fn borrar(&self, actor: &Actor, codigo: i64) -> Resultado<u64>;
+}
+
+/// Las operaciones de la tabla 'detalle_ventas'. Los detalles de una venta finalizada no se pueden
+/// insertar, actualizar ni borrar: esas operaciones no afectan ninguna fila
+#[async_trait]
+pub trait DetalleVentasRepo: Send + Sync {
+    async fn insertar(&self, actor: &Actor, detalle_venta: &DetalleVentas) -> Resultado<u64>;
+    /// Los detalles de las ventas de un rango de fechas (segun la `fecha` de la venta),
+    /// opcionalmente solo los de los productos de una categoria y sus subcategorias
+    /// y los de las ventas de una sucursal
+    async fn listar(&self, rango: &RangoFechas, categoria: Option<i64>, sucursal: Option<i64>) -> Resultado<Vec<DetalleVentas>>;
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<DetalleVentas>>;
+    /// Los detalles de una venta, ordenados por codigo
+    async fn por_venta(&self, codigo_venta: i64) -> Resultado<Vec<DetalleVentas>>;
+    async fn actualizar(&self, actor: &Actor, codigo: i64, detalle_venta: &DetalleVentas) -> Resultado<u64>;
+    async fn borrar(&self, actor: &Actor, codigo: i64) -> Resultado<u64>;
+}
+
+/// Las operaciones de la tabla 'promociones'
+#[async_trait]
+pub trait PromocionesRepo: Send + Sync {
+    async fn insertar(&self, actor: &Actor, promocion: &Promociones) -> Resultado<u64>;
+    async fn listar(&self, incluir_borrados: bool) -> Resultado<Vec<Promociones>>;
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Promociones>>;
+    /// Las promociones no borradas cuyo rango de fechas incluye la fecha dada
+    async fn vigentes(&self, fecha: NaiveDate) -> Resultado<Vec<Promociones>>;
+    async fn actualizar(&self, actor: &Actor, codigo: i64, promocion: &Promociones) -> Resultado<u64>;
+    async fn informe_borrado(&self, codigo: i64) -> Resultado<Option<InformeBorrado>>;
+    async fn borrar(&self, actor: &Actor, codigo: i64) -> Resultado<u64>;
+    async fn restaurar(&self, actor: &Actor, codigo: i64) -> Resultado<u64>;
+}
+
+/// Las operaciones de la tabla 'pagos'
+#[async_trait]
+pub trait PagosRepo: Send + Sync {
+    /// Registra un pago y retorna su codigo, o `None` si la venta no existe, ya esta finalizada
+    /// o el pago excede lo que falta por pagar. Un pago con puntos redime, en la misma transaccion,
+    /// los puntos del cliente de la venta segun `puntos`, y retorna `None` si no le alcanzan
+    async fn insertar(&self, actor: &Actor, codigo_venta: i64, pago: &Pagos, puntos: &ConfigPuntos) -> Resultado<Option<i64>>;
+    /// Los pagos de una venta, ordenados por codigo
+    async fn por_venta(&self, codigo_venta: i64) -> Resultado<Vec<Pagos>>;
+    /// Borra un pago de una venta que no esta finalizada
+    async fn borrar(&self, actor: &Actor, codigo_venta: i64, codigo: i64) -> Resultado<u64>;
+}
+
+/// La consulta de la tabla 'movimientos_puntos'. Los puntos se acumulan al finalizar una venta
+/// y se redimen al registrar un pago con puntos (ver `VentasRepo` y `PagosRepo`)
+#[async_trait]
+pub trait PuntosRepo: Send + Sync {
+    /// Los movimientos de puntos de un cliente, en orden cronologico
+    async fn movimientos(&self, cedula_cliente: i64) -> Resultado<Vec<MovimientosPuntos>>;
+}
+
+/// Las operaciones de la tabla 'movimientos_inventario'
+#[async_trait]
+pub trait InventarioRepo: Send + Sync {
+    /// Las existencias de los productos sin borrar (la suma de sus movimientos), por codigo:
+    /// las de una sucursal o, sin sucursal, las de toda la cadena
+    async fn existencias(&self, sucursal: Option<i64>) -> Resultado<Vec<Existencias>>;
+    /// Lo vendido de cada producto en las ventas finalizadas desde una fecha (segun la `fecha` de la venta),
+    /// en una sucursal o en toda la cadena
+    async fn vendido(&self, desde: DateTime<Utc>, sucursal: Option<i64>) -> Resultado<Vec<Vendido>>;
+    /// Los movimientos de un producto, opcionalmente solo los de una sucursal, en orden cronologico
+    async fn movimientos(&self, codigo_producto: i64, sucursal: Option<i64>) -> Resultado<Vec<MovimientosInventario>>;
+}
+
+/// Las operaciones de las tablas 'ordenes_compra' y 'detalle_ordenes_compra'
+#[async_trait]
+pub trait OrdenesCompraRepo: Send + Sync {
+    /// Crea una orden con sus detalles y retorna su codigo; falla con `LlaveForanea`
+    /// si el proveedor o alguno de los productos no existe
+    async fn insertar(&self, actor: &Actor, orden: &OrdenesCompra, detalles: &[DetalleOrdenesCompra]) -> Resultado<i64>;
+    /// Las ordenes, opcionalmente solo las de un estado y las de una sucursal, de la mas reciente a la mas antigua
+    async fn listar(&self, estado: Option<&str>, sucursal: Option<i64>) -> Resultado<Vec<OrdenesCompra>>;
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<OrdenesCompra>>;
+    /// Los detalles de una orden, ordenados por codigo
+    async fn detalles(&self, codigo_orden: i64) -> Resultado<Vec<DetalleOrdenesCompra>>;
+    /// Recibe una orden pendiente y suma sus productos al inventario de su sucursal, en la misma transaccion;
+    /// si la orden no existe o no esta pendiente no afecta ninguna fila
+    async fn recibir(&self, actor: &Actor, codigo: i64) -> Resultado<u64>;
+    /// Cancela una orden pendiente
+    async fn cancelar(&self, actor: &Actor, codigo: i64) -> Resultado<u64>;
+}
+
+/// Las operaciones de las tablas 'conteos' y 'detalle_conteos'
+#[async_trait]
+pub trait ConteosRepo: Send + Sync {
+    /// Abre un conteo y retorna su codigo
+    async fn insertar(&self, actor: &Actor, conteo: &Conteos) -> Resultado<i64>;
+    /// Los conteos, opcionalmente solo los de un estado y los de una sucursal, del mas reciente al mas antiguo
+    async fn listar(&self, estado: Option<&str>, sucursal: Option<i64>) -> Resultado<Vec<Conteos>>;
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Conteos>>;
+    /// Las cantidades contadas de un conteo, ordenadas por codigo
+    async fn detalles(&self, codigo_conteo: i64) -> Resultado<Vec<DetalleConteos>>;
+    /// Registra las cantidades contadas en un conteo abierto (reemplazando las de los productos ya contados)
+    /// y retorna cuantas registro, o `None` si el conteo no existe o no esta abierto;
+    /// falla con `LlaveForanea` si alguno de los productos no existe
+    async fn registrar(&self, actor: &Actor, codigo_conteo: i64, contados: &[DetalleConteos]) -> Resultado<Option<u64>>;
+    /// Aplica un conteo abierto: ajusta las existencias en su sucursal de cada producto contado (sin borrar) a lo contado,
+    /// en la misma transaccion, y retorna cuantos ajustes registro, o `None` si el conteo no existe
+    /// o no esta abierto. `motivo` es el de los productos contados sin motivo propio
+    async fn aplicar(&self, actor: &Actor, codigo: i64, motivo: &str) -> Resultado<Option<u64>>;
+    /// Cancela un conteo abierto
+    async fn cancelar(&self, actor: &Actor, codigo: i64) -> Resultado<u64>;
+}
+
+/// Las operaciones de las tablas 'sucursales' y 'usuarios_sucursales'
+#[async_trait]
+pub trait SucursalesRepo: Send + Sync {
+    async fn insertar(&self, actor: &Actor, sucursal: &Sucursales) -> Resultado<u64>;
+    async fn listar(&self) -> Resultado<Vec<Sucursales>>;
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Sucursales>>;
+    async fn actualizar(&self, actor: &Actor, codigo: i64, sucursal: &Sucursales) -> Resultado<u64>;
+    /// Las sucursales asignadas a un usuario, ordenadas por codigo
+    async fn asignadas(&self, cedula_usuario: i64) -> Resultado<Vec<Sucursales>>;
+    /// Asigna una sucursal a un usuario; falla con `LlaveForanea` si alguno de los dos no existe
+    /// y con `Duplicado` si ya estaba asignada
+    async fn asignar(&self, actor: &Actor, cedula_usuario: i64, codigo_sucursal: i64) -> Resultado<Option<i64>>;
+    async fn desasignar(&self, actor: &Actor, cedula_usuario: i64, codigo_sucursal: i64) -> Resultado<u64>;
+}
+
+/// Las operaciones de la tabla 'traslados'
+#[async_trait]
+pub trait TrasladosRepo: Send + Sync {
+    /// Registra un traslado y sus movimientos de inventario (una salida en el origen y una entrada en
+    /// el destino por producto) en una sola transaccion, y retorna su codigo, o `None` si el origen no
+    /// tiene las existencias de alguno de los productos. Los traslados desde una misma sucursal se
+    /// registran uno a la vez
+    async fn insertar(&self, actor: &Actor, traslado: &Traslados, productos: &[LineaTraslado]) -> Resultado<Option<i64>>;
+    /// Los traslados de un rango de fechas, opcionalmente solo los que salen o llegan a una sucursal,
+    /// ordenados por fecha
+    async fn listar(&self, rango: &RangoFechas, sucursal: Option<i64>) -> Resultado<Vec<Traslados>>;
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Traslados>>;
+    /// Los movimientos de inventario de un traslado, ordenados por codigo
+    async fn movimientos(&self, codigo_traslado: i64) -> Resultado<Vec<MovimientosInventario>>;
+}
+
+/// Las operaciones de la tabla 'cajas'
+#[async_trait]
+pub trait CajasRepo: Send + Sync {
+    async fn insertar(&self, actor: &Actor, caja: &Cajas) -> Resultado<u64>;
+    /// Las cajas, opcionalmente solo las de una sucursal
+    async fn listar(&self, sucursal: Option<i64>) -> Resultado<Vec<Cajas>>;
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Cajas>>;
+    async fn actualizar(&self, actor: &Actor, codigo: i64, caja: &Cajas) -> Resultado<u64>;
+}
+
+/// Las operaciones de las tablas 'turnos' y 'movimientos_caja'
+#[async_trait]
+pub trait TurnosRepo: Send + Sync {
+    /// Abre un turno y retorna su codigo; falla con `Duplicado` si el usuario
+    /// o la caja ya tienen un turno abierto
+    async fn abrir(&self, actor: &Actor, turno: &Turnos) -> Resultado<i64>;
+    /// Los turnos abiertos en un rango de fechas, opcionalmente solo los de las cajas de una sucursal,
+    /// ordenados por fecha de apertura
+    async fn listar(&self, rango: &RangoFechas, sucursal: Option<i64>) -> Resultado<Vec<Turnos>>;
+    async fn obtener(&self, codigo: i64) -> Resultado<Option<Turnos>>;
+    /// El turno abierto de un usuario
+    async fn abierto(&self, cedula_usuario: i64) -> Resultado<Option<Turnos>>;
+    /// Registra un movimiento de efectivo y retorna su codigo, o `None` si el turno no esta abierto
+    async fn insertar_movimiento(
+        &self,
+        actor: &Actor,
+        codigo_turno: i64,
+        movimiento: &MovimientosCaja,
+    ) -> Resultado<Option<i64>>;
+    /// Los movimientos de efectivo de un turno, ordenados por codigo
+    async fn movimientos(&self, codigo_turno: i64) -> Resultado<Vec<MovimientosCaja>>;
+    /// Las ventas finalizadas y los movimientos de efectivo de un turno, para el arqueo; las ventas abiertas
+    /// y sus pagos no se cuentan
+    async fn totales(&self, codigo_turno: i64) -> Resultado<TotalesTurno>;
+    /// Cierra un turno abierto; no afecta ninguna fila si el turno no existe o ya esta cerrado
+    async fn cerrar(&self, actor: &Actor, codigo: i64, efectivo_contado: f64) -> Resultado<u64>;
+}
+
+/// La consulta de la tabla 'auditoria'
+#[async_trait]
+pub trait AuditoriaRepo: Send + Sync {
+    /// Los registros de la auditoria, del mas reciente al mas antiguo
+    async fn listar(
+        &self,
+        filtro: &FiltroAuditoria,
+        rango: &RangoFechas,
+        paginacion: &Paginacion,
+    ) -> Resultado<Vec<Auditoria>>;
+}
+
+/// Los repositorios de todas las entidades, compartidos por los servicios REST por medio de `AppState`
+#[derive(Clone)]
+pub struct Repos {
+    pub(crate) clientes: Arc<dyn ClientesRepo>,
+    pub(crate) usuarios: Arc<dyn UsuariosRepo>,
+    pub(crate) proveedores: Arc<dyn ProveedoresRepo>,
+    pub(crate) categorias: Arc<dyn CategoriasRepo>,
+    pub(crate) productos: Arc<dyn ProductosRepo>,
+    pub(crate) codigos_barras: Arc<dyn CodigosBarrasRepo>,
+    pub(crate) precios: Arc<dyn PreciosRepo>,
+    pub(crate) ventas: Arc<dyn VentasRepo>,
+    pub(crate) detalle_ventas: Arc<dyn DetalleVentasRepo>,
+    pub(crate) promociones: Arc<dyn PromocionesRepo>,
+    pub(crate) pagos: Arc<dyn PagosRepo>,
+    pub(crate) puntos: Arc<dyn PuntosRepo>,
+    pub(crate) inventario: Arc<dyn InventarioRepo>,
+    pub(crate) ordenes_compra: Arc<dyn OrdenesCompraRepo>,
+    pub(crate) conteos: Arc<dyn ConteosRepo>,
+    pub(crate) sucursales: Arc<dyn SucursalesRepo>,
+    pub(crate) traslados: Arc<dyn TrasladosRepo>,
+    pub(crate) cajas: Arc<dyn CajasRepo>,
+    pub(crate) turnos: Arc<dyn TurnosRepo>,
+    pub(crate) auditoria: Arc<dyn AuditoriaRepo>,
+}
+
+impl Repos {
+    /// Usa el mismo repositorio para todas las entidades
+    fn todos<R>(repo: R) -> Self
+    where
+        R: ClientesRepo
+            + UsuariosRepo
+            + ProveedoresRepo
+            + CategoriasRepo
+            + ProductosRepo
+            + CodigosBarrasRepo
+            + PreciosRepo
+            + VentasRepo
+            + DetalleVentasRepo
+            + PromocionesRepo
+            + PagosRepo
+            + PuntosRepo
+            + InventarioRepo
+            + OrdenesCompraRepo
+            + ConteosRepo
+            + SucursalesRepo
+            + TrasladosRepo
+            + CajasRepo
+            + TurnosRepo
+            + AuditoriaRepo
+            + 'static,
+    {
+        let repo = Arc::new(repo);
+        Repos {
+            clientes: repo.clone(),
+            usuarios: repo.clone(),
+            proveedores: repo.clone(),
+            categorias: repo.clone(),
+            productos: repo.clone(),
+            codigos_barras: repo.clone(),
+            precios: repo.clone(),
+            ventas: repo.clone(),
+            detalle_ventas: repo.clone(),
+            promociones: repo.clone(),
+            pagos: repo.clone(),
+            puntos: repo.clone(),
+            inventario: repo.clone(),
+            ordenes_compra: repo.clone(),
+            conteos: repo.clone(),
+            sucursales: repo.clone(),
+            traslados: repo.clone(),
+            cajas: repo.clone(),
+            turnos: repo.clone(),
+            auditoria: repo,
+        }
+    }
+
+    /// Repositorios en memoria, vacios, para las pruebas
+    #[cfg(test)]
+    pub fn memoria() -> Self {
+        Repos::todos(memoria::Memoria::default())
+    }
+}
+
+/// El uso de la picina de conecciones en un momento dado
+#[derive(Debug, Clone, Copy)]
+pub struct UsoPool {
+    pub abiertas: u32,
+    pub inactivas: usize,
+    pub maximo: u32,
+}
+
+/// Lee una variable de entorno numerica, o `None` si no esta definida
+pub(crate) fn variable<T: FromStr>(nombre: &str) -> Result<Option<T>, String> {
+    match std::env::var(nombre) {
+        Ok(valor) => valor
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("{nombre} must be a positive number, got '{valor}'")),
+        Err(_) => Ok(None),
+    }
+}
+
+/// La configuracion de la picina de conecciones. Se toma de las variables de entorno
+/// `DB_MAX_CONNECTIONS`, `DB_MIN_CONNECTIONS`, `DB_ACQUIRE_TIMEOUT` y `DB_IDLE_TIMEOUT`
+/// (los tiempos en segundos); las que no esten definidas usan el valor predeterminado
+#[derive(Debug, Clone)]
+pub struct ConfigPool {
+    /// El maximo de conecciones abiertas
+    pub maximo: u32,
+    /// Las conecciones que se mantienen abiertas aunque no se usen
+    pub minimo: u32,
+    /// Cuanto espera un request por una coneccion libre antes de fallar
+    pub espera: Duration,
+    /// Despues de cuanto tiempo sin usarse se cierra una coneccion (por encima del minimo)
+    pub inactividad: Duration,
+}
+
+impl Default for ConfigPool {
+    fn default() -> Self {
+        ConfigPool {
+            maximo: 10,
+            minimo: 1,
+            espera: Duration::from_secs(5),
+            inactividad: Duration::from_secs(600),
+        }
+    }
+}
+
+impl ConfigPool {
+    /// Lee la configuracion de las variables de entorno
+    pub fn desde_entorno() -> Result<Self, String> {
+        let predeterminada = ConfigPool::default();
+        let config = ConfigPool {
+            maximo: variable("DB_MAX_CONNECTIONS")?.unwrap_or(predeterminada.maximo),
+            minimo: variable("DB_MIN_CONNECTIONS")?.unwrap_or(predeterminada.minimo),
+            espera: variable("DB_ACQUIRE_TIMEOUT")?.map_or(predeterminada.espera, Duration::from_secs),
+            inactividad: variable("DB_IDLE_TIMEOUT")?.map_or(predeterminada.inactividad, Duration::from_secs),
+        };
+        if config.maximo == 0 || config.minimo > config.maximo {
+            return Err(format!(
+                "DB_MIN_CONNECTIONS ({}) must not exceed DB_MAX_CONNECTIONS ({}), which must be at least 1",
+                config.minimo, config.maximo
+            ));
+        }
+        Ok(config)
+    }
+}
+
+/// La picina de conecciones a la base de datos, segun el motor elegido en `DATABASE_URL`
+#[derive(Debug, Clone)]
+pub enum Db {
+    Postgres(PgPool),
+    Sqlite(SqlitePool),
+}
+
+impl Db {
+    /// Se conecta a la base de datos con la configuracion predeterminada de la picina
+    pub async fn conectar(url: &str) -> Result<Db, sqlx::Error> {
+        Db::conectar_con(url, &ConfigPool::default()).await
+    }
+
+    /// Se conecta a la base de datos. Las url `sqlite:tienda.db` (o `sqlite::memory:`) usan SQLite
+    /// y crean el archivo si no existe; cualquier otra se trata como una url de postgres
+    pub async fn conectar_con(url: &str, config: &ConfigPool) -> Result<Db, sqlx::Error> {
+        if url.starts_with("sqlite:") {
+            let opciones = SqliteConnectOptions::from_str(url)?
+                .create_if_missing(true)
+                .journal_mode(SqliteJournalMode::Wal);
+            // al menos una coneccion siempre abierta para que `sqlite::memory:` no se pierda
+            let pool = SqlitePoolOptions::new()
+                .max_connections(config.maximo)
+                .min_connections(config.minimo.max(1))
+                .acquire_timeout(config.espera)
+                .idle_timeout(config.inactividad)
+                .connect_with(opciones)
+                .await?;
+            Ok(Db::Sqlite(pool))
+        } else {
+            let pool = PgPoolOptions::new()
+                .max_connections(config.maximo)
+                .min_connections(config.minimo)
+                .acquire_timeout(config.espera)
+                .idle_timeout(config.inactividad)
+                .connect(url)
+                .await?;
+            Ok(Db::Postgres(pool))
+        }
+    }
+
+    /// Cierra la picina: espera a que se devuelvan las conecciones en uso
+    /// (las transacciones en curso terminan) y cierra todas las conecciones
+    pub async fn cerrar(&self) {
+        match self {
+            Db::Postgres(pool) => pool.close().await,
+            Db::Sqlite(pool) => pool.close().await,
+        }
+    }
+
+    /// Verifica que la base de datos responda, con un `select 1`
+    pub async fn ping(&self) -> Result<(), sqlx::Error> {
+        match self {
+            Db::Postgres(pool) => sqlx::query("select 1").execute(pool).await.map(|_| ()),
+            Db::Sqlite(pool) => sqlx::query("select 1").execute(pool).await.map(|_| ()),
+        }
+    }
+
+    /// Las conecciones abiertas, inactivas y el maximo de la picina
+    pub fn uso(&self) -> UsoPool {
+        match self {
+            Db::Postgres(pool) => UsoPool {
+                abiertas: pool.size(),
+                inactivas: pool.num_idle(),
+                maximo: pool.options().get_max_connections(),
+            },
+            Db::Sqlite(pool) => UsoPool {
+                abiertas: pool.size(),
+                inactivas: pool.num_idle(),
+                maximo: pool.options().get_max_connections(),
+            },
+        }
+    }
+
+    /// Los repositorios de las entidades sobre esta base de datos
+    pub fn repos(&self) -> Repos {
+        match self {
+            Db::Postgres(pool) => Repos::todos(Sql::new(pool.clone())),
+            Db::Sqlite(pool) => Repos::todos(Sql::new(pool.clone())),
+        }
+    }
+}
@@ -0,0 +1,2170 @@
+//! Este archivo contiene la implementacion SQL de los repositorios.
+//! Las consultas se escriben una sola vez (en el macro `repos_sql!`) y se compilan para
+//! postgres y para SQLite: solo usan SQL comun a ambos motores, y las fechas se asignan
+//! desde Rust en lugar de usar `now()`. La unica consulta propia de cada motor es la que bloquea
+//! una fila (ver `bloquear`).
+//! Cada operacion tiene un span de `tracing` (en nivel `debug`) que agrupa sus consultas,
+//! y los errores de la base de datos quedan en los logs (en nivel `warn`; el request que
+//! falla por ellos queda en nivel `error` si responde con un error 5xx).
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{
+    database::HasArguments,
+    query::{Query, QueryScalar},
+    types::Json,
+    Database, FromRow, Pool, Postgres, Sqlite,
+};
+
+use super::{
+    AuditoriaRepo, CajasRepo, CategoriasRepo, ClientesRepo, CodigosBarrasRepo, ConteosRepo, DetalleVentasRepo, InventarioRepo,
+    OrdenesCompraRepo, PagosRepo, PreciosRepo, ProductosRepo, PromocionesRepo, ProveedoresRepo, PuntosRepo, Resultado,
+    SucursalesRepo, TrasladosRepo, TurnosRepo, UsuariosRepo, VentasRepo,
+};
+use crate::{
+    auth::Actor,
+    borrado::InformeBorrado,
+    entities::{
+        auditoria::{
+            self, Accion, Auditoria, FiltroAuditoria, Tabla, CAJAS, CATEGORIAS, CLIENTES, CODIGOS_BARRAS, CONTEOS,
+            DETALLE_CONTEOS, DETALLE_VENTAS, MOVIMIENTOS_CAJA, MOVIMIENTOS_INVENTARIO, MOVIMIENTOS_PUNTOS, ORDENES_COMPRA, PAGOS,
+            PRECIOS_PROGRAMADOS, PRODUCTOS, PROMOCIONES, PROVEEDORES, SUCURSALES, TRASLADOS, TURNOS, USUARIOS,
+            USUARIOS_SUCURSALES, VENTAS,
+        },
+        cajas::Cajas,
+        categorias::{Categorias, VentasCategoria},
+        clientes::Clientes,
+        codigos_barras::CodigosBarras,
+        conteos::{self, Conteos, DetalleConteos},
+        detalle_ventas::DetalleVentas,
+        inventario::{self, Existencias, MovimientosInventario, Vendido},
+        ordenes_compra::{self, DetalleOrdenesCompra, OrdenesCompra},
+        pagos::{Pagos, EFECTIVO, PUNTOS},
+        precios::{self, PreciosHistorial, PreciosProgramados},
+        productos::Productos,
+        promociones::Promociones,
+        proveedores::Proveedores,
+        puntos::{ConfigPuntos, EstadoPuntos, MovimientosPuntos},
+        sucursales::{Sucursales, UsuariosSucursales},
+        traslados::{self, LineaTraslado, Traslados},
+        turnos::{MovimientosCaja, TotalesTurno, Turnos, ENTRADA, SALIDA},
+        usuarios::Usuarios,
+        ventas::Ventas,
+    },
+    filtros::{Paginacion, RangoFechas},
+};
+
+/// Los repositorios sobre una picina de conecciones de postgres o de SQLite
+pub struct Sql<DB: Database> {
+    pool: Pool<DB>,
+}
+
+impl<DB: Database> Sql<DB> {
+    pub fn new(pool: Pool<DB>) -> Self {
+        Sql { pool }
+    }
+}
+
+const INSERTAR_PRODUCTO: &str = "insert into productos \
+    (codigo, nit_proveedor, iva_compra, nombre_producto, precio_compra, precio_venta, codigo_categoria, unidad_medida, \
+    stock_minimo, stock_maximo, created_at, updated_at) values ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$11);";
+
+/// Las categorias de un arbol: la categoria `$n` y sus subcategorias (`union` evita los ciclos)
+macro_rules! arbol {
+    ($n:literal) => {
+        concat!(
+            "with recursive arbol(codigo) as (select codigo from categorias where codigo = $", $n,
+            " union select c.codigo from categorias c join arbol a on c.codigo_padre = a.codigo) "
+        )
+    };
+}
+
+const INSERTAR_CODIGO_BARRAS: &str =
+    "insert into codigos_barras (codigo_barras, codigo_producto, created_at) values ($1,$2,$3) returning codigo;";
+
+macro_rules! repos_sql {
+    ($db:ty, $bloqueo:literal) => {
+        impl Sql<$db> {
+            /// Obtiene una fila como json (sin contraseñas), para guardarla en la auditoria
+            async fn instantanea<T>(
+                conn: &mut <$db as Database>::Connection,
+                tabla: Tabla,
+                llave: i64,
+            ) -> Result<Option<Value>, sqlx::Error>
+            where
+                T: for<'r> FromRow<'r, <$db as Database>::Row> + Serialize + Send + Unpin,
+            {
+                let fila = sqlx::query_as::<_, T>(&format!(
+                    "select * from {} where {} = $1;",
+                    tabla.nombre, tabla.llave
+                ))
+                .bind(llave)
+                .fetch_optional(conn)
+                .await?;
+                Ok(fila.as_ref().map(auditoria::instantanea))
+            }
+
+            /// Registra una operacion en la auditoria, dentro de la transaccion de la operacion
+            async fn registrar(
+                conn: &mut <$db as Database>::Connection,
+                actor: &Actor,
+                tabla: Tabla,
+                llave: Option<i64>,
+                accion: Accion,
+                antes: Option<Value>,
+                despues: Option<Value>,
+            ) -> Result<(), sqlx::Error> {
+                sqlx::query(
+                    "insert into auditoria (cedula_usuario, entidad, llave, accion, antes, despues, fecha) \
+                     values ($1,$2,$3,$4,$5,$6,$7);",
+                )
+                .bind(actor.cedula)
+                .bind(tabla.nombre)
+                .bind(llave)
+                .bind(accion.as_str())
+                .bind(antes.map(Json))
+                .bind(despues.map(Json))
+                .bind(Utc::now())
+                .execute(conn)
+                .await
+                .map(|_| ())
+            }
+
+            /// Ejecuta una consulta que modifica una fila de tipo `T` y la registra en la auditoria,
+            /// guardando la fila antes y despues del cambio. Todo ocurre en una sola transaccion,
+            /// por lo que si la auditoria falla la operacion tampoco se aplica.
+            /// Si la consulta no afecta ninguna fila no se registra nada.
+            async fn ejecutar<'q, T>(
+                &self,
+                actor: &Actor,
+                tabla: Tabla,
+                llave: Option<i64>,
+                accion: Accion,
+                consulta: Query<'q, $db, <$db as HasArguments<'q>>::Arguments>,
+            ) -> Resultado<u64>
+            where
+                T: for<'r> FromRow<'r, <$db as Database>::Row> + Serialize + Send + Unpin,
+            {
+                let mut transaction = self.pool.begin().await?;
+                let filas = Self::ejecutar_en::<T>(&mut transaction, actor, tabla, llave, accion, consulta).await;
+                Self::terminar(transaction, filas).await
+            }
+
+            /// Como `ejecutar`, para una fila que pertenece a la venta `codigo_venta`: bloquea antes la venta,
+            /// para que la escritura espere a la finalizacion concurrente de la venta y no la cambie despues
+            async fn ejecutar_en_venta<'q, T>(
+                &self,
+                actor: &Actor,
+                codigo_venta: Option<i64>,
+                tabla: Tabla,
+                llave: Option<i64>,
+                accion: Accion,
+                consulta: Query<'q, $db, <$db as HasArguments<'q>>::Arguments>,
+            ) -> Resultado<u64>
+            where
+                T: for<'r> FromRow<'r, <$db as Database>::Row> + Serialize + Send + Unpin,
+            {
+                let mut transaction = self.pool.begin().await?;
+                let filas = async {
+                    if let Some(codigo_venta) = codigo_venta {
+                        Self::bloquear(&mut transaction, VENTAS, codigo_venta).await?;
+                    }
+                    Self::ejecutar_en::<T>(&mut transaction, actor, tabla, llave, accion, consulta).await
+                }
+                .await;
+                Self::terminar(transaction, filas).await
+            }
+
+            /// Confirma la transaccion si la operacion tuvo exito, y si no la revierte de inmediato:
+            /// en SQLite una transaccion que solo se suelta sigue bloqueando la escritura
+            /// hasta que su coneccion se vuelve a usar
+            async fn terminar<R>(
+                transaction: sqlx::Transaction<'_, $db>,
+                resultado: Result<R, sqlx::Error>,
+            ) -> Resultado<R> {
+                match resultado {
+                    Ok(valor) => {
+                        transaction.commit().await?;
+                        Ok(valor)
+                    }
+                    Err(error) => {
+                        transaction.rollback().await?;
+                        Err(error.into())
+                    }
+                }
+            }
+
+            /// Como `ejecutar`, dentro de una transaccion abierta por quien lo llama
+            async fn ejecutar_en<'q, T>(
+                conn: &mut <$db as Database>::Connection,
+                actor: &Actor,
+                tabla: Tabla,
+                llave: Option<i64>,
+                accion: Accion,
+                consulta: Query<'q, $db, <$db as HasArguments<'q>>::Arguments>,
+            ) -> Result<u64, sqlx::Error>
+            where
+                T: for<'r> FromRow<'r, <$db as Database>::Row> + Serialize + Send + Unpin,
+            {
+                let antes = match llave {
+                    Some(llave) => Self::instantanea::<T>(conn, tabla, llave).await?,
+                    None => None,
+                };
+                let filas = consulta.execute(&mut *conn).await?.rows_affected();
+                if filas > 0 {
+                    let despues = match llave {
+                        Some(llave) => Self::instantanea::<T>(conn, tabla, llave).await?,
+                        None => None,
+                    };
+                    Self::registrar(conn, actor, tabla, llave, accion, antes, despues).await?;
+                }
+                Ok(filas)
+            }
+
+            /// Registra los precios actuales de un producto en 'precios_historial',
+            /// si son distintos de los del ultimo registro
+            async fn registrar_precios(
+                conn: &mut <$db as Database>::Connection,
+                actor: &Actor,
+                codigo_producto: i64,
+                origen: &str,
+            ) -> Result<(), sqlx::Error> {
+                sqlx::query(
+                    "insert into precios_historial (codigo_producto, precio_compra, precio_venta, origen, cedula_usuario, fecha) \
+                     select p.codigo, p.precio_compra, p.precio_venta, $2, $3, $4 from productos p where p.codigo = $1 \
+                     and not exists (select 1 from precios_historial h where h.codigo_producto = p.codigo \
+                     and h.precio_compra = p.precio_compra and h.precio_venta = p.precio_venta \
+                     and h.codigo = (select max(codigo) from precios_historial where codigo_producto = p.codigo));",
+                )
+                .bind(codigo_producto)
+                .bind(origen)
+                .bind(actor.cedula)
+                .bind(Utc::now())
+                .execute(conn)
+                .await
+                .map(|_| ())
+            }
+
+            /// Como `ejecutar`, para una consulta que modifica un producto: en la misma transaccion
+            /// registra sus precios en el historial si cambiaron
+            async fn ejecutar_producto<'q>(
+                &self,
+                actor: &Actor,
+                codigo: Option<i64>,
+                accion: Accion,
+                origen: &str,
+                consulta: Query<'q, $db, <$db as HasArguments<'q>>::Arguments>,
+            ) -> Resultado<u64> {
+                let mut transaction = self.pool.begin().await?;
+                let filas = Self::ejecutar_en::<Productos>(&mut transaction, actor, PRODUCTOS, codigo, accion, consulta).await;
+                let resultado = match (filas, codigo) {
+                    (Ok(filas @ 1..), Some(codigo)) => {
+                        Self::registrar_precios(&mut transaction, actor, codigo, origen).await.map(|_| filas)
+                    }
+                    (filas, _) => filas,
+                };
+                Self::terminar(transaction, resultado).await
+            }
+
+            /// Ejecuta un `insert ... returning codigo` de una fila de tipo `T` cuya llave la asigna
+            /// la base de datos, y la registra en la auditoria en la misma transaccion.
+            /// Retorna la llave de la fila, o `None` si la consulta no inserta nada
+            async fn crear<'q, T>(
+                &self,
+                actor: &Actor,
+                tabla: Tabla,
+                consulta: QueryScalar<'q, $db, i64, <$db as HasArguments<'q>>::Arguments>,
+            ) -> Resultado<Option<i64>>
+            where
+                T: for<'r> FromRow<'r, <$db as Database>::Row> + Serialize + Send + Unpin,
+            {
+                let mut transaction = self.pool.begin().await?;
+                let llave = Self::crear_en::<T>(&mut transaction, actor, tabla, consulta).await;
+                Self::terminar(transaction, llave).await
+            }
+
+            /// Como `crear`, dentro de una transaccion abierta por quien lo llama
+            async fn crear_en<'q, T>(
+                conn: &mut <$db as Database>::Connection,
+                actor: &Actor,
+                tabla: Tabla,
+                consulta: QueryScalar<'q, $db, i64, <$db as HasArguments<'q>>::Arguments>,
+            ) -> Result<Option<i64>, sqlx::Error>
+            where
+                T: for<'r> FromRow<'r, <$db as Database>::Row> + Serialize + Send + Unpin,
+            {
+                let Some(llave) = consulta.fetch_optional(&mut *conn).await? else {
+                    return Ok(None);
+                };
+                let despues = Self::instantanea::<T>(conn, tabla, llave).await?;
+                Self::registrar(conn, actor, tabla, Some(llave), Accion::Create, None, despues).await?;
+                Ok(Some(llave))
+            }
+
+            /// Bloquea una fila hasta que termine la transaccion, para que las operaciones concurrentes
+            /// que la leen y luego escriben segun lo leido esperen su turno. Retorna si la fila existe
+            async fn bloquear(conn: &mut <$db as Database>::Connection, tabla: Tabla, llave: i64) -> Result<bool, sqlx::Error> {
+                let sql = format!($bloqueo, tabla = tabla.nombre, llave = tabla.llave);
+                Ok(sqlx::query(&sql).bind(llave).execute(conn).await?.rows_affected() > 0)
+            }
+
+            /// Registra un movimiento de inventario y lo registra en la auditoria,
+            /// dentro de la transaccion de la operacion que lo origina
+            async fn insertar_movimiento(
+                conn: &mut <$db as Database>::Connection,
+                actor: &Actor,
+                movimiento: &MovimientosInventario,
+            ) -> Result<i64, sqlx::Error> {
+                let codigo = sqlx::query_scalar::<_, i64>(
+                    "insert into movimientos_inventario \
+                     (codigo_producto, codigo_sucursal, tipo, cantidad, codigo_venta, codigo_orden, codigo_conteo, codigo_traslado, \
+                     motivo, cedula_usuario, fecha) values ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11) returning codigo;",
+                )
+                .bind(movimiento.codigo_producto)
+                .bind(movimiento.codigo_sucursal)
+                .bind(movimiento.tipo.as_str())
+                .bind(movimiento.cantidad)
+                .bind(movimiento.codigo_venta)
+                .bind(movimiento.codigo_orden)
+                .bind(movimiento.codigo_conteo)
+                .bind(movimiento.codigo_traslado)
+                .bind(movimiento.motivo.as_deref())
+                .bind(actor.cedula)
+                .bind(Utc::now())
+                .fetch_one(&mut *conn)
+                .await?;
+                let despues = Self::instantanea::<MovimientosInventario>(conn, MOVIMIENTOS_INVENTARIO, codigo).await?;
+                Self::registrar(conn, actor, MOVIMIENTOS_INVENTARIO, Some(codigo), Accion::Create, None, despues).await?;
+                Ok(codigo)
+            }
+
+            /// Las existencias de un producto en una sucursal, dentro de la transaccion de la operacion
+            async fn existencias_en(
+                conn: &mut <$db as Database>::Connection,
+                codigo_producto: Option<i64>,
+                codigo_sucursal: Option<i64>,
+            ) -> Result<f64, sqlx::Error> {
+                sqlx::query_scalar::<_, f64>(
+                    "select coalesce(sum(cantidad), 0.0) from movimientos_inventario \
+                     where codigo_producto = $1 and codigo_sucursal = $2;",
+                )
+                .bind(codigo_producto)
+                .bind(codigo_sucursal)
+                .fetch_one(conn)
+                .await
+            }
+
+            /// Descuenta del inventario de su sucursal los productos de una venta que se acaba de finalizar,
+            /// dentro de la transaccion que la finaliza, y retorna cuantos movimientos registro
+            async fn descontar_venta(
+                conn: &mut <$db as Database>::Connection,
+                actor: &Actor,
+                codigo_venta: i64,
+            ) -> Result<u64, sqlx::Error> {
+                let codigo_sucursal = sqlx::query_scalar::<_, Option<i64>>("select codigo_sucursal from ventas where codigo = $1;")
+                    .bind(codigo_venta)
+                    .fetch_optional(&mut *conn)
+                    .await?
+                    .flatten();
+                let detalles =
+                    sqlx::query_as::<_, DetalleVentas>("select * from detalle_ventas where codigo_venta = $1 order by codigo;")
+                        .bind(codigo_venta)
+                        .fetch_all(&mut *conn)
+                        .await?;
+                for detalle in &detalles {
+                    let movimiento = MovimientosInventario {
+                        codigo_venta: Some(codigo_venta),
+                        ..MovimientosInventario::new(codigo_sucursal, detalle.codigo_producto, inventario::VENTA, -detalle.cantidad_producto)
+                    };
+                    Self::insertar_movimiento(conn, actor, &movimiento).await?;
+                }
+                Ok(detalles.len() as u64)
+            }
+
+            /// Acumula al cliente de una venta que se acaba de finalizar los puntos de lo que no se pago con puntos,
+            /// dentro de la transaccion que la finaliza
+            async fn acumular_puntos(
+                conn: &mut <$db as Database>::Connection,
+                actor: &Actor,
+                codigo_venta: i64,
+                config: &ConfigPuntos,
+            ) -> Result<(), sqlx::Error> {
+                let venta = sqlx::query_as::<_, Ventas>("select * from ventas where codigo = $1;")
+                    .bind(codigo_venta)
+                    .fetch_one(&mut *conn)
+                    .await?;
+                let con_puntos = sqlx::query_scalar::<_, f64>(
+                    "select coalesce(sum(valor), 0.0) from pagos where codigo_venta = $1 and metodo = $2;",
+                )
+                .bind(codigo_venta)
+                .bind(PUNTOS)
+                .fetch_one(&mut *conn)
+                .await?;
+                let puntos = config.acumulados(venta.total_venta - con_puntos);
+                if venta.cedula_cliente.is_none() || puntos <= 0 {
+                    return Ok(());
+                }
+                let ahora = Utc::now();
+                let codigo = sqlx::query_scalar::<_, i64>(
+                    "insert into movimientos_puntos (cedula_cliente, codigo_venta, puntos, vence, fecha) \
+                     values ($1,$2,$3,$4,$5) returning codigo;",
+                )
+                .bind(venta.cedula_cliente)
+                .bind(codigo_venta)
+                .bind(puntos)
+                .bind(config.vencimiento(ahora.date_naive()))
+                .bind(ahora)
+                .fetch_one(&mut *conn)
+                .await?;
+                let despues = Self::instantanea::<MovimientosPuntos>(conn, MOVIMIENTOS_PUNTOS, codigo).await?;
+                Self::registrar(conn, actor, MOVIMIENTOS_PUNTOS, Some(codigo), Accion::Create, None, despues).await
+            }
+
+            /// Los puntos que redime un pago con puntos de una venta: el cliente de la venta y cuantos puntos,
+            /// o `None` si la venta no tiene cliente o al cliente no le alcanzan. Bloquea al cliente,
+            /// para que sus redenciones concurrentes esperen a que termine la transaccion
+            async fn redencion(
+                conn: &mut <$db as Database>::Connection,
+                codigo_venta: i64,
+                valor: f64,
+                config: &ConfigPuntos,
+            ) -> Result<Option<(i64, i64)>, sqlx::Error> {
+                let cedula_cliente = sqlx::query_scalar::<_, Option<i64>>("select cedula_cliente from ventas where codigo = $1;")
+                    .bind(codigo_venta)
+                    .fetch_optional(&mut *conn)
+                    .await?
+                    .flatten();
+                let Some(cedula_cliente) = cedula_cliente else {
+                    return Ok(None);
+                };
+                Self::bloquear(conn, CLIENTES, cedula_cliente).await?;
+                let movimientos = sqlx::query_as::<_, MovimientosPuntos>(
+                    "select * from movimientos_puntos where cedula_cliente = $1 order by fecha, codigo;",
+                )
+                .bind(cedula_cliente)
+                .fetch_all(&mut *conn)
+                .await?;
+                let saldo = EstadoPuntos::new(cedula_cliente, &movimientos, Utc::now().date_naive(), config).saldo;
+                let necesarios = config.para_pagar(valor);
+                Ok((necesarios <= saldo).then_some((cedula_cliente, necesarios)))
+            }
+
+            /// Cuenta las filas de una consulta `select count(*) ... where ... = $1`
+            async fn contar(&self, sql: &str, id: i64) -> Result<i64, sqlx::Error> {
+                sqlx::query_scalar::<_, i64>(sql).bind(id).fetch_one(&self.pool).await
+            }
+        }
+
+        #[async_trait]
+        impl ClientesRepo for Sql<$db> {
+            #[tracing::instrument(name = "clientes.insertar", level = "debug", skip(self, actor, cliente), err(Display, level = "warn"))]
+            async fn insertar(&self, actor: &Actor, cliente: &Clientes) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "insert into clientes (cedula, direccion, email, nombre, telefono, created_at, updated_at) \
+                     values ($1,$2,$3,$4,$5,$6,$6);",
+                )
+                .bind(cliente.cedula)
+                .bind(cliente.direccion.as_str())
+                .bind(cliente.email.as_str())
+                .bind(cliente.nombre.as_str())
+                .bind(cliente.telefono.as_str())
+                .bind(Utc::now());
+                self.ejecutar::<Clientes>(actor, CLIENTES, cliente.cedula, Accion::Create, consulta).await
+            }
+
+            #[tracing::instrument(name = "clientes.listar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn listar(&self, incluir_borrados: bool) -> Resultado<Vec<Clientes>> {
+                Ok(sqlx::query_as::<_, Clientes>("select * from clientes where $1 or deleted_at is null order by cedula;")
+                    .bind(incluir_borrados)
+                    .fetch_all(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "clientes.obtener", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn obtener(&self, cedula: i64) -> Resultado<Option<Clientes>> {
+                Ok(sqlx::query_as::<_, Clientes>("select * from clientes where cedula = $1;")
+                    .bind(cedula)
+                    .fetch_optional(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "clientes.actualizar", level = "debug", skip(self, actor, cliente), err(Display, level = "warn"))]
+            async fn actualizar(&self, actor: &Actor, cedula: i64, cliente: &Clientes) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "update clientes set direccion = $1, email = $2, nombre = $3, telefono = $4, updated_at = $5 where cedula = $6;",
+                )
+                .bind(cliente.direccion.as_str())
+                .bind(cliente.email.as_str())
+                .bind(cliente.nombre.as_str())
+                .bind(cliente.telefono.as_str())
+                .bind(Utc::now())
+                .bind(cedula);
+                self.ejecutar::<Clientes>(actor, CLIENTES, Some(cedula), Accion::Update, consulta).await
+            }
+
+            #[tracing::instrument(name = "clientes.informe_borrado", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn informe_borrado(&self, cedula: i64) -> Resultado<Option<InformeBorrado>> {
+                if self.contar("select count(*) from clientes where cedula = $1 and deleted_at is null;", cedula).await? == 0 {
+                    return Ok(None);
+                }
+                let ventas = self.contar("select count(*) from ventas where cedula_cliente = $1;", cedula).await?;
+                Ok(Some(InformeBorrado::new("clientes", cedula).afecta("ventas", ventas)))
+            }
+
+            #[tracing::instrument(name = "clientes.borrar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn borrar(&self, actor: &Actor, cedula: i64) -> Resultado<u64> {
+                let consulta = sqlx::query("update clientes set deleted_at = $1 where cedula = $2 and deleted_at is null;")
+                    .bind(Utc::now())
+                    .bind(cedula);
+                self.ejecutar::<Clientes>(actor, CLIENTES, Some(cedula), Accion::Delete, consulta).await
+            }
+
+            #[tracing::instrument(name = "clientes.restaurar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn restaurar(&self, actor: &Actor, cedula: i64) -> Resultado<u64> {
+                let consulta = sqlx::query("update clientes set deleted_at = null where cedula = $1 and deleted_at is not null;")
+                    .bind(cedula);
+                self.ejecutar::<Clientes>(actor, CLIENTES, Some(cedula), Accion::Restore, consulta).await
+            }
+        }
+
+        #[async_trait]
+        impl UsuariosRepo for Sql<$db> {
+            #[tracing::instrument(name = "usuarios.insertar", level = "debug", skip(self, actor, usuario), err(Display, level = "warn"))]
+            async fn insertar(&self, actor: &Actor, usuario: &Usuarios) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "insert into usuarios (cedula, email, nombre, password, usuario, admin, created_at, updated_at) \
+                     values ($1,$2,$3,$4,$5,$6,$7,$7);",
+                )
+                .bind(usuario.cedula)
+                .bind(usuario.email.as_deref())
+                .bind(usuario.nombre.as_deref())
+                .bind(usuario.password.as_str())
+                .bind(usuario.usuario.as_str())
+                .bind(usuario.admin)
+                .bind(Utc::now());
+                self.ejecutar::<Usuarios>(actor, USUARIOS, usuario.cedula, Accion::Create, consulta).await
+            }
+
+            #[tracing::instrument(name = "usuarios.listar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn listar(&self, incluir_borrados: bool, sucursal: Option<i64>) -> Resultado<Vec<Usuarios>> {
+                Ok(sqlx::query_as::<_, Usuarios>(
+                    "select * from usuarios where ($1 or deleted_at is null) and ($2 is null or cedula in \
+                     (select cedula_usuario from usuarios_sucursales where codigo_sucursal = $2)) order by cedula;",
+                )
+                .bind(incluir_borrados)
+                .bind(sucursal)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "usuarios.obtener", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn obtener(&self, cedula: i64) -> Resultado<Option<Usuarios>> {
+                Ok(sqlx::query_as::<_, Usuarios>("select * from usuarios where cedula = $1;")
+                    .bind(cedula)
+                    .fetch_optional(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "usuarios.por_usuario", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn por_usuario(&self, usuario: &str) -> Resultado<Option<Usuarios>> {
+                Ok(sqlx::query_as::<_, Usuarios>("select * from usuarios where usuario = $1 and deleted_at is null;")
+                    .bind(usuario)
+                    .fetch_optional(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "usuarios.actualizar", level = "debug", skip(self, actor, usuario), err(Display, level = "warn"))]
+            async fn actualizar(&self, actor: &Actor, cedula: i64, usuario: &Usuarios) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "update usuarios set email = $1, nombre = $2, password = $3, usuario = $4, updated_at = $5 where cedula = $6;",
+                )
+                .bind(usuario.email.as_deref())
+                .bind(usuario.nombre.as_deref())
+                .bind(usuario.password.as_str())
+                .bind(usuario.usuario.as_str())
+                .bind(Utc::now())
+                .bind(cedula);
+                self.ejecutar::<Usuarios>(actor, USUARIOS, Some(cedula), Accion::Update, consulta).await
+            }
+
+            #[tracing::instrument(name = "usuarios.informe_borrado", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn informe_borrado(&self, cedula: i64) -> Resultado<Option<InformeBorrado>> {
+                if self.contar("select count(*) from usuarios where cedula = $1 and deleted_at is null;", cedula).await? == 0 {
+                    return Ok(None);
+                }
+                let ventas = self.contar("select count(*) from ventas where cedula_usuario = $1;", cedula).await?;
+                Ok(Some(InformeBorrado::new("usuarios", cedula).afecta("ventas", ventas)))
+            }
+
+            #[tracing::instrument(name = "usuarios.borrar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn borrar(&self, actor: &Actor, cedula: i64) -> Resultado<u64> {
+                let consulta = sqlx::query("update usuarios set deleted_at = $1 where cedula = $2 and deleted_at is null;")
+                    .bind(Utc::now())
+                    .bind(cedula);
+                self.ejecutar::<Usuarios>(actor, USUARIOS, Some(cedula), Accion::Delete, consulta).await
+            }
+
+            #[tracing::instrument(name = "usuarios.restaurar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn restaurar(&self, actor: &Actor, cedula: i64) -> Resultado<u64> {
+                let consulta = sqlx::query("update usuarios set deleted_at = null where cedula = $1 and deleted_at is not null;")
+                    .bind(cedula);
+                self.ejecutar::<Usuarios>(actor, USUARIOS, Some(cedula), Accion::Restore, consulta).await
+            }
+        }
+
+        #[async_trait]
+        impl ProveedoresRepo for Sql<$db> {
+            #[tracing::instrument(name = "proveedores.insertar", level = "debug", skip(self, actor, proveedor), err(Display, level = "warn"))]
+            async fn insertar(&self, actor: &Actor, proveedor: &Proveedores) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "insert into proveedores (nit, ciudad, direccion, nombre, telefono, created_at, updated_at) \
+                     values ($1,$2,$3,$4,$5,$6,$6);",
+                )
+                .bind(proveedor.nit)
+                .bind(proveedor.ciudad.as_str())
+                .bind(proveedor.direccion.as_str())
+                .bind(proveedor.nombre.as_str())
+                .bind(proveedor.telefono.as_str())
+                .bind(Utc::now());
+                self.ejecutar::<Proveedores>(actor, PROVEEDORES, proveedor.nit, Accion::Create, consulta).await
+            }
+
+            #[tracing::instrument(name = "proveedores.listar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn listar(&self, incluir_borrados: bool) -> Resultado<Vec<Proveedores>> {
+                Ok(sqlx::query_as::<_, Proveedores>("select * from proveedores where $1 or deleted_at is null order by nit;")
+                    .bind(incluir_borrados)
+                    .fetch_all(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "proveedores.obtener", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn obtener(&self, nit: i64) -> Resultado<Option<Proveedores>> {
+                Ok(sqlx::query_as::<_, Proveedores>("select * from proveedores where nit = $1;")
+                    .bind(nit)
+                    .fetch_optional(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "proveedores.actualizar", level = "debug", skip(self, actor, proveedor), err(Display, level = "warn"))]
+            async fn actualizar(&self, actor: &Actor, nit: i64, proveedor: &Proveedores) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "update proveedores set ciudad = $1, direccion = $2, nombre = $3, telefono = $4, updated_at = $5 where nit = $6;",
+                )
+                .bind(proveedor.ciudad.as_str())
+                .bind(proveedor.direccion.as_str())
+                .bind(proveedor.nombre.as_str())
+                .bind(proveedor.telefono.as_str())
+                .bind(Utc::now())
+                .bind(nit);
+                self.ejecutar::<Proveedores>(actor, PROVEEDORES, Some(nit), Accion::Update, consulta).await
+            }
+
+            #[tracing::instrument(name = "proveedores.informe_borrado", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn informe_borrado(&self, nit: i64) -> Resultado<Option<InformeBorrado>> {
+                if self.contar("select count(*) from proveedores where nit = $1 and deleted_at is null;", nit).await? == 0 {
+                    return Ok(None);
+                }
+                let productos = self
+                    .contar("select count(*) from productos where nit_proveedor = $1 and deleted_at is null;", nit)
+                    .await?;
+                Ok(Some(InformeBorrado::new("proveedores", nit).afecta("productos", productos)))
+            }
+
+            #[tracing::instrument(name = "proveedores.borrar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn borrar(&self, actor: &Actor, nit: i64) -> Resultado<u64> {
+                let consulta = sqlx::query("update proveedores set deleted_at = $1 where nit = $2 and deleted_at is null;")
+                    .bind(Utc::now())
+                    .bind(nit);
+                self.ejecutar::<Proveedores>(actor, PROVEEDORES, Some(nit), Accion::Delete, consulta).await
+            }
+
+            #[tracing::instrument(name = "proveedores.restaurar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn restaurar(&self, actor: &Actor, nit: i64) -> Resultado<u64> {
+                let consulta = sqlx::query("update proveedores set deleted_at = null where nit = $1 and deleted_at is not null;")
+                    .bind(nit);
+                self.ejecutar::<Proveedores>(actor, PROVEEDORES, Some(nit), Accion::Restore, consulta).await
+            }
+        }
+
+        #[async_trait]
+        impl CategoriasRepo for Sql<$db> {
+            #[tracing::instrument(name = "categorias.insertar", level = "debug", skip(self, actor, categoria), err(Display, level = "warn"))]
+            async fn insertar(&self, actor: &Actor, categoria: &Categorias) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "insert into categorias (codigo, nombre, codigo_padre, created_at, updated_at) values ($1,$2,$3,$4,$4);",
+                )
+                .bind(categoria.codigo)
+                .bind(categoria.nombre.as_str())
+                .bind(categoria.codigo_padre)
+                .bind(Utc::now());
+                self.ejecutar::<Categorias>(actor, CATEGORIAS, categoria.codigo, Accion::Create, consulta).await
+            }
+
+            #[tracing::instrument(name = "categorias.listar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn listar(&self, incluir_borrados: bool) -> Resultado<Vec<Categorias>> {
+                Ok(sqlx::query_as::<_, Categorias>("select * from categorias where $1 or deleted_at is null order by codigo;")
+                    .bind(incluir_borrados)
+                    .fetch_all(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "categorias.obtener", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn obtener(&self, codigo: i64) -> Resultado<Option<Categorias>> {
+                Ok(sqlx::query_as::<_, Categorias>("select * from categorias where codigo = $1;")
+                    .bind(codigo)
+                    .fetch_optional(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "categorias.actualizar", level = "debug", skip(self, actor, categoria), err(Display, level = "warn"))]
+            async fn actualizar(&self, actor: &Actor, codigo: i64, categoria: &Categorias) -> Resultado<u64> {
+                let consulta = sqlx::query("update categorias set nombre = $1, codigo_padre = $2, updated_at = $3 where codigo = $4;")
+                    .bind(categoria.nombre.as_str())
+                    .bind(categoria.codigo_padre)
+                    .bind(Utc::now())
+                    .bind(codigo);
+                self.ejecutar::<Categorias>(actor, CATEGORIAS, Some(codigo), Accion::Update, consulta).await
+            }
+
+            #[tracing::instrument(name = "categorias.informe_borrado", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn informe_borrado(&self, codigo: i64) -> Resultado<Option<InformeBorrado>> {
+                if self.contar("select count(*) from categorias where codigo = $1 and deleted_at is null;", codigo).await? == 0 {
+                    return Ok(None);
+                }
+                let subcategorias = self
+                    .contar("select count(*) from categorias where codigo_padre = $1 and deleted_at is null;", codigo)
+                    .await?;
+                let productos = self
+                    .contar("select count(*) from productos where codigo_categoria = $1 and deleted_at is null;", codigo)
+                    .await?;
+                Ok(Some(
+                    InformeBorrado::new("categorias", codigo).afecta("categorias", subcategorias).afecta("productos", productos),
+                ))
+            }
+
+            #[tracing::instrument(name = "categorias.borrar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn borrar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+                let consulta = sqlx::query("update categorias set deleted_at = $1 where codigo = $2 and deleted_at is null;")
+                    .bind(Utc::now())
+                    .bind(codigo);
+                self.ejecutar::<Categorias>(actor, CATEGORIAS, Some(codigo), Accion::Delete, consulta).await
+            }
+
+            #[tracing::instrument(name = "categorias.restaurar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn restaurar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+                let consulta =
+                    sqlx::query("update categorias set deleted_at = null where codigo = $1 and deleted_at is not null;").bind(codigo);
+                self.ejecutar::<Categorias>(actor, CATEGORIAS, Some(codigo), Accion::Restore, consulta).await
+            }
+
+            #[tracing::instrument(name = "categorias.ventas", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn ventas(&self, rango: &RangoFechas, categoria: Option<i64>, sucursal: Option<i64>) -> Resultado<Vec<VentasCategoria>> {
+                Ok(sqlx::query_as::<_, VentasCategoria>(concat!(
+                    arbol!(3),
+                    "select p.codigo_categoria, c.nombre, sum(d.cantidad_producto) as cantidad, \
+                     count(distinct d.codigo_venta) as ventas, sum(d.descuento) as descuento, sum(d.valor_venta) as valor_venta, \
+                     sum(d.valor_iva) as valor_iva, sum(d.valor_total) as valor_total \
+                     from detalle_ventas d join ventas v on v.codigo = d.codigo_venta \
+                     join productos p on p.codigo = d.codigo_producto left join categorias c on c.codigo = p.codigo_categoria \
+                     where ($1 is null or v.fecha >= $1) and ($2 is null or v.fecha < $2) \
+                     and ($3 is null or p.codigo_categoria in (select codigo from arbol)) \
+                     and ($4 is null or v.codigo_sucursal = $4) \
+                     group by p.codigo_categoria, c.nombre order by p.codigo_categoria;"
+                ))
+                .bind(rango.inicio())
+                .bind(rango.fin())
+                .bind(categoria)
+                .bind(sucursal)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+        }
+
+        #[async_trait]
+        impl ProductosRepo for Sql<$db> {
+            #[tracing::instrument(name = "productos.insertar", level = "debug", skip(self, actor, producto), err(Display, level = "warn"))]
+            async fn insertar(&self, actor: &Actor, producto: &Productos) -> Resultado<u64> {
+                let consulta = sqlx::query(INSERTAR_PRODUCTO)
+                    .bind(producto.codigo)
+                    .bind(producto.nit_proveedor)
+                    .bind(producto.iva_compra)
+                    .bind(producto.nombre_producto.as_str())
+                    .bind(producto.precio_compra)
+                    .bind(producto.precio_venta)
+                    .bind(producto.codigo_categoria)
+                    .bind(producto.unidad_medida.as_str())
+                    .bind(producto.stock_minimo)
+                    .bind(producto.stock_maximo)
+                    .bind(Utc::now());
+                self.ejecutar_producto(actor, producto.codigo, Accion::Create, precios::CREACION, consulta).await
+            }
+
+            #[tracing::instrument(name = "productos.importar", level = "debug", skip(self, actor, productos, codigos_barras), err(Display, level = "warn"))]
+            async fn importar(&self, actor: &Actor, productos: &[Productos], codigos_barras: &[CodigosBarras]) -> Resultado<u64> {
+                let mut transaction = self.pool.begin().await?;
+                let ahora = Utc::now();
+                let resultado = async {
+                    for producto in productos {
+                        sqlx::query(INSERTAR_PRODUCTO)
+                            .bind(producto.codigo)
+                            .bind(producto.nit_proveedor)
+                            .bind(producto.iva_compra)
+                            .bind(producto.nombre_producto.as_str())
+                            .bind(producto.precio_compra)
+                            .bind(producto.precio_venta)
+                            .bind(producto.codigo_categoria)
+                            .bind(producto.unidad_medida.as_str())
+                            .bind(producto.stock_minimo)
+                            .bind(producto.stock_maximo)
+                            .bind(ahora)
+                            .execute(&mut *transaction)
+                            .await?;
+                        if let Some(codigo) = producto.codigo {
+                            let despues = Self::instantanea::<Productos>(&mut transaction, PRODUCTOS, codigo).await?;
+                            Self::registrar(&mut transaction, actor, PRODUCTOS, Some(codigo), Accion::Import, None, despues)
+                                .await?;
+                            Self::registrar_precios(&mut transaction, actor, codigo, precios::IMPORTACION).await?;
+                        }
+                    }
+                    for codigo_barras in codigos_barras {
+                        let codigo = sqlx::query_scalar::<_, i64>(INSERTAR_CODIGO_BARRAS)
+                            .bind(codigo_barras.codigo_barras.as_str())
+                            .bind(codigo_barras.codigo_producto)
+                            .bind(ahora)
+                            .fetch_one(&mut *transaction)
+                            .await?;
+                        let despues = Self::instantanea::<CodigosBarras>(&mut transaction, CODIGOS_BARRAS, codigo).await?;
+                        Self::registrar(&mut transaction, actor, CODIGOS_BARRAS, Some(codigo), Accion::Import, None, despues)
+                            .await?;
+                    }
+                    Ok(productos.len() as u64)
+                }
+                .await;
+                Self::terminar(transaction, resultado).await
+            }
+
+            #[tracing::instrument(name = "productos.listar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn listar(&self, incluir_borrados: bool, categoria: Option<i64>) -> Resultado<Vec<Productos>> {
+                Ok(sqlx::query_as::<_, Productos>(concat!(
+                    arbol!(2),
+                    "select * from productos where ($1 or deleted_at is null) \
+                     and ($2 is null or codigo_categoria in (select codigo from arbol)) order by codigo;"
+                ))
+                .bind(incluir_borrados)
+                .bind(categoria)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "productos.obtener", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn obtener(&self, codigo: i64) -> Resultado<Option<Productos>> {
+                Ok(sqlx::query_as::<_, Productos>("select * from productos where codigo = $1;")
+                    .bind(codigo)
+                    .fetch_optional(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "productos.por_proveedor", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn por_proveedor(
+                &self,
+                nit: i64,
+                paginacion: &Paginacion,
+                incluir_borrados: bool,
+            ) -> Resultado<Vec<Productos>> {
+                Ok(sqlx::query_as::<_, Productos>(
+                    "select * from productos where nit_proveedor = $1 and ($4 or deleted_at is null) \
+                     order by codigo limit $2 offset $3;",
+                )
+                .bind(nit)
+                .bind(paginacion.limite())
+                .bind(paginacion.desplazamiento())
+                .bind(incluir_borrados)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "productos.actualizar", level = "debug", skip(self, actor, producto), err(Display, level = "warn"))]
+            async fn actualizar(&self, actor: &Actor, codigo: i64, producto: &Productos) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "update productos set iva_compra = $1, nombre_producto = $2, precio_compra = $3, precio_venta = $4, \
+                     codigo_categoria = $5, unidad_medida = $6, stock_minimo = $7, stock_maximo = $8, updated_at = $9 \
+                     where codigo = $10;",
+                )
+                .bind(producto.iva_compra)
+                .bind(producto.nombre_producto.as_str())
+                .bind(producto.precio_compra)
+                .bind(producto.precio_venta)
+                .bind(producto.codigo_categoria)
+                .bind(producto.unidad_medida.as_str())
+                .bind(producto.stock_minimo)
+                .bind(producto.stock_maximo)
+                .bind(Utc::now())
+                .bind(codigo);
+                self.ejecutar_producto(actor, Some(codigo), Accion::Update, precios::ACTUALIZACION, consulta).await
+            }
+
+            #[tracing::instrument(name = "productos.informe_borrado", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn informe_borrado(&self, codigo: i64) -> Resultado<Option<InformeBorrado>> {
+                if self.contar("select count(*) from productos where codigo = $1 and deleted_at is null;", codigo).await? == 0 {
+                    return Ok(None);
+                }
+                let detalles = self
+                    .contar("select count(*) from detalle_ventas where codigo_producto = $1;", codigo)
+                    .await?;
+                Ok(Some(InformeBorrado::new("productos", codigo).afecta("detalle_ventas", detalles)))
+            }
+
+            #[tracing::instrument(name = "productos.borrar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn borrar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+                let consulta = sqlx::query("update productos set deleted_at = $1 where codigo = $2 and deleted_at is null;")
+                    .bind(Utc::now())
+                    .bind(codigo);
+                self.ejecutar::<Productos>(actor, PRODUCTOS, Some(codigo), Accion::Delete, consulta).await
+            }
+
+            #[tracing::instrument(name = "productos.restaurar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn restaurar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+                let consulta = sqlx::query("update productos set deleted_at = null where codigo = $1 and deleted_at is not null;")
+                    .bind(codigo);
+                self.ejecutar::<Productos>(actor, PRODUCTOS, Some(codigo), Accion::Restore, consulta).await
+            }
+        }
+
+        #[async_trait]
+        impl CodigosBarrasRepo for Sql<$db> {
+            #[tracing::instrument(name = "codigos_barras.insertar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn insertar(&self, actor: &Actor, codigo_producto: i64, codigo_barras: &str) -> Resultado<Option<i64>> {
+                let consulta = sqlx::query_scalar(INSERTAR_CODIGO_BARRAS)
+                    .bind(codigo_barras)
+                    .bind(codigo_producto)
+                    .bind(Utc::now());
+                self.crear::<CodigosBarras>(actor, CODIGOS_BARRAS, consulta).await
+            }
+
+            #[tracing::instrument(name = "codigos_barras.por_producto", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn por_producto(&self, codigo_producto: i64) -> Resultado<Vec<CodigosBarras>> {
+                Ok(sqlx::query_as::<_, CodigosBarras>("select * from codigos_barras where codigo_producto = $1 order by codigo;")
+                    .bind(codigo_producto)
+                    .fetch_all(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "codigos_barras.buscar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn buscar(&self, codigo_barras: &str) -> Resultado<Option<CodigosBarras>> {
+                Ok(sqlx::query_as::<_, CodigosBarras>("select * from codigos_barras where codigo_barras = $1;")
+                    .bind(codigo_barras)
+                    .fetch_optional(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "codigos_barras.borrar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn borrar(&self, actor: &Actor, codigo_producto: i64, codigo_barras: &str) -> Resultado<u64> {
+                let codigo = sqlx::query_scalar::<_, i64>(
+                    "select codigo from codigos_barras where codigo_barras = $1 and codigo_producto = $2;",
+                )
+                .bind(codigo_barras)
+                .bind(codigo_producto)
+                .fetch_optional(&self.pool)
+                .await?;
+                let Some(codigo) = codigo else {
+                    return Ok(0);
+                };
+                let consulta = sqlx::query("delete from codigos_barras where codigo = $1;").bind(codigo);
+                self.ejecutar::<CodigosBarras>(actor, CODIGOS_BARRAS, Some(codigo), Accion::Delete, consulta).await
+            }
+        }
+
+        #[async_trait]
+        impl PreciosRepo for Sql<$db> {
+            #[tracing::instrument(name = "precios.historial", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn historial(&self, codigo_producto: i64) -> Resultado<Vec<PreciosHistorial>> {
+                Ok(sqlx::query_as::<_, PreciosHistorial>(
+                    "select * from precios_historial where codigo_producto = $1 order by fecha, codigo;",
+                )
+                .bind(codigo_producto)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "precios.programar", level = "debug", skip(self, actor, programado), err(Display, level = "warn"))]
+            async fn programar(&self, actor: &Actor, codigo_producto: i64, programado: &PreciosProgramados) -> Resultado<Option<i64>> {
+                let consulta = sqlx::query_scalar(
+                    "insert into precios_programados (codigo_producto, precio_compra, precio_venta, fecha, cedula_usuario, created_at) \
+                     values ($1,$2,$3,$4,$5,$6) returning codigo;",
+                )
+                .bind(codigo_producto)
+                .bind(programado.precio_compra)
+                .bind(programado.precio_venta)
+                .bind(programado.fecha)
+                .bind(actor.cedula)
+                .bind(Utc::now());
+                self.crear::<PreciosProgramados>(actor, PRECIOS_PROGRAMADOS, consulta).await
+            }
+
+            #[tracing::instrument(name = "precios.programados", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn programados(&self, codigo_producto: i64) -> Resultado<Vec<PreciosProgramados>> {
+                Ok(sqlx::query_as::<_, PreciosProgramados>(
+                    "select * from precios_programados where codigo_producto = $1 and aplicado_at is null order by fecha, codigo;",
+                )
+                .bind(codigo_producto)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "precios.cancelar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn cancelar(&self, actor: &Actor, codigo_producto: i64, codigo: i64) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "delete from precios_programados where codigo = $1 and codigo_producto = $2 and aplicado_at is null;",
+                )
+                .bind(codigo)
+                .bind(codigo_producto);
+                self.ejecutar::<PreciosProgramados>(actor, PRECIOS_PROGRAMADOS, Some(codigo), Accion::Delete, consulta).await
+            }
+
+            #[tracing::instrument(name = "precios.aplicar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn aplicar(&self, hasta: DateTime<Utc>) -> Resultado<u64> {
+                // los precios de los productos borrados quedan pendientes hasta que se restauren
+                let pendientes = sqlx::query_as::<_, PreciosProgramados>(
+                    "select * from precios_programados where aplicado_at is null and fecha <= $1 \
+                     and codigo_producto in (select codigo from productos where deleted_at is null) order by fecha, codigo;",
+                )
+                .bind(hasta)
+                .fetch_all(&self.pool)
+                .await?;
+                let mut aplicados = 0;
+                for programado in pendientes {
+                    let (Some(codigo), Some(codigo_producto)) = (programado.codigo, programado.codigo_producto) else {
+                        continue;
+                    };
+                    let actor = Actor { cedula: programado.cedula_usuario };
+                    let ahora = Utc::now();
+                    let mut transaction = self.pool.begin().await?;
+                    let resultado = async {
+                        // otra instancia del servidor pudo aplicarlo primero
+                        let marcado =
+                            sqlx::query("update precios_programados set aplicado_at = $1 where codigo = $2 and aplicado_at is null;")
+                                .bind(ahora)
+                                .bind(codigo)
+                                .execute(&mut *transaction)
+                                .await?
+                                .rows_affected();
+                        if marcado == 0 {
+                            return Ok(false);
+                        }
+                        let consulta = sqlx::query(
+                            "update productos set precio_compra = coalesce($1, precio_compra), \
+                             precio_venta = coalesce($2, precio_venta), updated_at = $3 where codigo = $4;",
+                        )
+                        .bind(programado.precio_compra)
+                        .bind(programado.precio_venta)
+                        .bind(ahora)
+                        .bind(codigo_producto);
+                        Self::ejecutar_en::<Productos>(&mut transaction, &actor, PRODUCTOS, Some(codigo_producto), Accion::Update, consulta)
+                            .await?;
+                        Self::registrar_precios(&mut transaction, &actor, codigo_producto, precios::PROGRAMADO).await?;
+                        Ok(true)
+                    }
+                    .await;
+                    if Self::terminar(transaction, resultado).await? {
+                        aplicados += 1;
+                    }
+                }
+                Ok(aplicados)
+            }
+        }
+
+        #[async_trait]
+        impl VentasRepo for Sql<$db> {
+            #[tracing::instrument(name = "ventas.insertar", level = "debug", skip(self, actor, venta), err(Display, level = "warn"))]
+            async fn insertar(&self, actor: &Actor, venta: &Ventas) -> Resultado<u64> {
+                // la venta queda en el turno abierto del usuario, si tiene uno
+                let consulta = sqlx::query(
+                    "insert into ventas \
+                     (codigo, cedula_cliente, cedula_usuario, iva_venta, total_venta, valor_venta, codigo_sucursal, codigo_turno, \
+                     fecha, created_at, updated_at) \
+                     values ($1,$2,$3,$4,$5,$6,$7, \
+                     (select codigo from turnos where cedula_usuario = $3 and cerrado_at is null),$8,$8,$8);",
+                )
+                .bind(venta.codigo)
+                .bind(venta.cedula_cliente)
+                .bind(venta.cedula_usuario)
+                .bind(venta.iva_venta)
+                .bind(venta.total_venta)
+                .bind(venta.valor_venta)
+                .bind(venta.codigo_sucursal)
+                .bind(Utc::now());
+                self.ejecutar::<Ventas>(actor, VENTAS, venta.codigo, Accion::Create, consulta).await
+            }
+
+            #[tracing::instrument(name = "ventas.listar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn listar(&self, rango: &RangoFechas, sucursal: Option<i64>) -> Resultado<Vec<Ventas>> {
+                Ok(sqlx::query_as::<_, Ventas>(
+                    "select * from ventas where ($1 is null or fecha >= $1) and ($2 is null or fecha < $2) \
+                     and ($3 is null or codigo_sucursal = $3) order by fecha;",
+                )
+                .bind(rango.inicio())
+                .bind(rango.fin())
+                .bind(sucursal)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "ventas.obtener", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn obtener(&self, codigo: i64) -> Resultado<Option<Ventas>> {
+                Ok(sqlx::query_as::<_, Ventas>("select * from ventas where codigo = $1;")
+                    .bind(codigo)
+                    .fetch_optional(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "ventas.por_cliente", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn por_cliente(&self, cedula: i64, paginacion: &Paginacion, sucursal: Option<i64>) -> Resultado<Vec<Ventas>> {
+                Ok(sqlx::query_as::<_, Ventas>(
+                    "select * from ventas where cedula_cliente = $1 and ($4 is null or codigo_sucursal = $4) \
+                     order by codigo limit $2 offset $3;",
+                )
+                .bind(cedula)
+                .bind(paginacion.limite())
+                .bind(paginacion.desplazamiento())
+                .bind(sucursal)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "ventas.por_usuario", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn por_usuario(&self, cedula: i64, paginacion: &Paginacion, sucursal: Option<i64>) -> Resultado<Vec<Ventas>> {
+                Ok(sqlx::query_as::<_, Ventas>(
+                    "select * from ventas where cedula_usuario = $1 and ($4 is null or codigo_sucursal = $4) \
+                     order by codigo limit $2 offset $3;",
+                )
+                .bind(cedula)
+                .bind(paginacion.limite())
+                .bind(paginacion.desplazamiento())
+                .bind(sucursal)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "ventas.actualizar", level = "debug", skip(self, actor, venta), err(Display, level = "warn"))]
+            async fn actualizar(&self, actor: &Actor, codigo: i64, venta: &Ventas) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "update ventas set iva_venta = $1, total_venta = $2, valor_venta = $3, updated_at = $4 \
+                     where codigo = $5 and finalizada_at is null;",
+                )
+                .bind(venta.iva_venta)
+                .bind(venta.total_venta)
+                .bind(venta.valor_venta)
+                .bind(Utc::now())
+                .bind(codigo);
+                self.ejecutar::<Ventas>(actor, VENTAS, Some(codigo), Accion::Update, consulta).await
+            }
+
+            #[tracing::instrument(name = "ventas.finalizar", level = "debug", skip(self, actor, puntos), err(Display, level = "warn"))]
+            async fn finalizar(&self, actor: &Actor, codigo: i64, puntos: &ConfigPuntos) -> Resultado<u64> {
+                // los pagos y los detalles se comparan con el total en centavos
+                let consulta = sqlx::query(
+                    "update ventas set finalizada_at = $1, updated_at = $1 where codigo = $2 and finalizada_at is null \
+                     and abs(total_venta - (select coalesce(sum(valor), 0.0) from pagos where codigo_venta = $2)) < 0.005 \
+                     and abs(total_venta - (select coalesce(sum(valor_total), 0.0) from detalle_ventas \
+                     where codigo_venta = $2)) < 0.005;",
+                )
+                .bind(Utc::now())
+                .bind(codigo);
+                let mut transaction = self.pool.begin().await?;
+                let resultado = async {
+                    // la venta se bloquea antes de sumar sus pagos y detalles, para que la suma vea los cambios
+                    // que otra transaccion hizo mientras esta esperaba
+                    Self::bloquear(&mut transaction, VENTAS, codigo).await?;
+                    let filas = Self::ejecutar_en::<Ventas>(&mut transaction, actor, VENTAS, Some(codigo), Accion::Update, consulta).await?;
+                    if filas > 0 {
+                        Self::descontar_venta(&mut transaction, actor, codigo).await?;
+                        Self::acumular_puntos(&mut transaction, actor, codigo, puntos).await?;
+                    }
+                    Ok(filas)
+                }
+                .await;
+                Self::terminar(transaction, resultado).await
+            }
+
+            #[tracing::instrument(name = "ventas.informe_borrado", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn informe_borrado(&self, codigo: i64) -> Resultado<Option<InformeBorrado>> {
+                if self.contar("select count(*) from ventas where codigo = $1;", codigo).await? == 0 {
+                    return Ok(None);
+                }
+                let detalles = self
+                    .contar("select count(*) from detalle_ventas where codigo_venta = $1;", codigo)
+                    .await?;
+                let pagos = self.contar("select count(*) from pagos where codigo_venta = $1;", codigo).await?;
+                let puntos = self.contar("select count(*) from movimientos_puntos where codigo_venta = $1;", codigo).await?;
+                let movimientos = self
+                    .contar("select count(*) from movimientos_inventario where codigo_venta = $1;", codigo)
+                    .await?;
+                Ok(Some(
+                    InformeBorrado::new("ventas", codigo)
+                        .bloquea("detalle_ventas", detalles)
+                        .bloquea("pagos", pagos)
+                        .bloquea("movimientos_puntos", puntos)
+                        .bloquea("movimientos_inventario", movimientos),
+                ))
+            }
+
+            #[tracing::instrument(name = "ventas.borrar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn borrar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+                let consulta = sqlx::query("delete from ventas where codigo = $1 and finalizada_at is null;").bind(codigo);
+                self.ejecutar::<Ventas>(actor, VENTAS, Some(codigo), Accion::Delete, consulta).await
+            }
+        }
+
+        #[async_trait]
+        impl DetalleVentasRepo for Sql<$db> {
+            #[tracing::instrument(name = "detalle_ventas.insertar", level = "debug", skip(self, actor, detalle_venta), err(Display, level = "warn"))]
+            async fn insertar(&self, actor: &Actor, detalle_venta: &DetalleVentas) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "insert into detalle_ventas \
+                     (codigo, codigo_producto, codigo_venta, cantidad_producto, valor_total, valor_venta, valor_iva, \
+                     codigo_promocion, descuento, created_at, updated_at) \
+                     select $1,$2,codigo,$4,$5,$6,$7,$8,$9,$10,$10 from ventas where codigo = $3 and finalizada_at is null;",
+                )
+                .bind(detalle_venta.codigo)
+                .bind(detalle_venta.codigo_producto)
+                .bind(detalle_venta.codigo_venta)
+                .bind(detalle_venta.cantidad_producto)
+                .bind(detalle_venta.valor_total)
+                .bind(detalle_venta.valor_venta)
+                .bind(detalle_venta.valor_iva)
+                .bind(detalle_venta.codigo_promocion)
+                .bind(detalle_venta.descuento)
+                .bind(Utc::now());
+                self.ejecutar_en_venta::<DetalleVentas>(
+                    actor,
+                    detalle_venta.codigo_venta,
+                    DETALLE_VENTAS,
+                    detalle_venta.codigo,
+                    Accion::Create,
+                    consulta,
+                )
+                .await
+            }
+
+            #[tracing::instrument(name = "detalle_ventas.listar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn listar(&self, rango: &RangoFechas, categoria: Option<i64>, sucursal: Option<i64>) -> Resultado<Vec<DetalleVentas>> {
+                Ok(sqlx::query_as::<_, DetalleVentas>(concat!(
+                    arbol!(3),
+                    "select d.* from detalle_ventas d join ventas v on v.codigo = d.codigo_venta \
+                     join productos p on p.codigo = d.codigo_producto \
+                     where ($1 is null or v.fecha >= $1) and ($2 is null or v.fecha < $2) \
+                     and ($3 is null or p.codigo_categoria in (select codigo from arbol)) \
+                     and ($4 is null or v.codigo_sucursal = $4) order by v.fecha;"
+                ))
+                .bind(rango.inicio())
+                .bind(rango.fin())
+                .bind(categoria)
+                .bind(sucursal)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "detalle_ventas.obtener", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn obtener(&self, codigo: i64) -> Resultado<Option<DetalleVentas>> {
+                Ok(sqlx::query_as::<_, DetalleVentas>("select * from detalle_ventas where codigo = $1;")
+                    .bind(codigo)
+                    .fetch_optional(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "detalle_ventas.por_venta", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn por_venta(&self, codigo_venta: i64) -> Resultado<Vec<DetalleVentas>> {
+                Ok(sqlx::query_as::<_, DetalleVentas>(
+                    "select * from detalle_ventas where codigo_venta = $1 order by codigo;",
+                )
+                .bind(codigo_venta)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "detalle_ventas.actualizar", level = "debug", skip(self, actor, detalle_venta), err(Display, level = "warn"))]
+            async fn actualizar(&self, actor: &Actor, codigo: i64, detalle_venta: &DetalleVentas) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "update detalle_ventas set cantidad_producto = $1, valor_total = $2, valor_venta = $3, valor_iva = $4, \
+                     codigo_promocion = $5, descuento = $6, updated_at = $7 where codigo = $8 \
+                     and codigo_venta in (select codigo from ventas where finalizada_at is null);",
+                )
+                .bind(detalle_venta.cantidad_producto)
+                .bind(detalle_venta.valor_total)
+                .bind(detalle_venta.valor_venta)
+                .bind(detalle_venta.valor_iva)
+                .bind(detalle_venta.codigo_promocion)
+                .bind(detalle_venta.descuento)
+                .bind(Utc::now())
+                .bind(codigo);
+                // un detalle no cambia de venta, por lo que se puede leer antes de la transaccion
+                let codigo_venta = DetalleVentasRepo::obtener(self, codigo).await?.and_then(|d| d.codigo_venta);
+                self.ejecutar_en_venta::<DetalleVentas>(actor, codigo_venta, DETALLE_VENTAS, Some(codigo), Accion::Update, consulta)
+                    .await
+            }
+
+            #[tracing::instrument(name = "detalle_ventas.borrar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn borrar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "delete from detalle_ventas where codigo = $1 \
+                     and codigo_venta in (select codigo from ventas where finalizada_at is null);",
+                )
+                .bind(codigo);
+                let codigo_venta = DetalleVentasRepo::obtener(self, codigo).await?.and_then(|d| d.codigo_venta);
+                self.ejecutar_en_venta::<DetalleVentas>(actor, codigo_venta, DETALLE_VENTAS, Some(codigo), Accion::Delete, consulta)
+                    .await
+            }
+        }
+
+        #[async_trait]
+        impl PromocionesRepo for Sql<$db> {
+            #[tracing::instrument(name = "promociones.insertar", level = "debug", skip(self, actor, promocion), err(Display, level = "warn"))]
+            async fn insertar(&self, actor: &Actor, promocion: &Promociones) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "insert into promociones (codigo, nombre, tipo, valor, lleve, pague, codigo_producto, nit_proveedor, \
+                     cedula_cliente, desde, hasta, created_at, updated_at) \
+                     values ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$12);",
+                )
+                .bind(promocion.codigo)
+                .bind(promocion.nombre.as_str())
+                .bind(promocion.tipo.as_str())
+                .bind(promocion.valor)
+                .bind(promocion.lleve)
+                .bind(promocion.pague)
+                .bind(promocion.codigo_producto)
+                .bind(promocion.nit_proveedor)
+                .bind(promocion.cedula_cliente)
+                .bind(promocion.desde)
+                .bind(promocion.hasta)
+                .bind(Utc::now());
+                self.ejecutar::<Promociones>(actor, PROMOCIONES, promocion.codigo, Accion::Create, consulta).await
+            }
+
+            #[tracing::instrument(name = "promociones.listar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn listar(&self, incluir_borrados: bool) -> Resultado<Vec<Promociones>> {
+                Ok(sqlx::query_as::<_, Promociones>(
+                    "select * from promociones where $1 or deleted_at is null order by codigo;",
+                )
+                .bind(incluir_borrados)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "promociones.obtener", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn obtener(&self, codigo: i64) -> Resultado<Option<Promociones>> {
+                Ok(sqlx::query_as::<_, Promociones>("select * from promociones where codigo = $1;")
+                    .bind(codigo)
+                    .fetch_optional(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "promociones.vigentes", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn vigentes(&self, fecha: NaiveDate) -> Resultado<Vec<Promociones>> {
+                Ok(sqlx::query_as::<_, Promociones>(
+                    "select * from promociones where deleted_at is null \
+                     and (desde is null or desde <= $1) and (hasta is null or hasta >= $1) order by codigo;",
+                )
+                .bind(fecha)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "promociones.actualizar", level = "debug", skip(self, actor, promocion), err(Display, level = "warn"))]
+            async fn actualizar(&self, actor: &Actor, codigo: i64, promocion: &Promociones) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "update promociones set nombre = $1, tipo = $2, valor = $3, lleve = $4, pague = $5, codigo_producto = $6, \
+                     nit_proveedor = $7, cedula_cliente = $8, desde = $9, hasta = $10, updated_at = $11 where codigo = $12;",
+                )
+                .bind(promocion.nombre.as_str())
+                .bind(promocion.tipo.as_str())
+                .bind(promocion.valor)
+                .bind(promocion.lleve)
+                .bind(promocion.pague)
+                .bind(promocion.codigo_producto)
+                .bind(promocion.nit_proveedor)
+                .bind(promocion.cedula_cliente)
+                .bind(promocion.desde)
+                .bind(promocion.hasta)
+                .bind(Utc::now())
+                .bind(codigo);
+                self.ejecutar::<Promociones>(actor, PROMOCIONES, Some(codigo), Accion::Update, consulta).await
+            }
+
+            #[tracing::instrument(name = "promociones.informe_borrado", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn informe_borrado(&self, codigo: i64) -> Resultado<Option<InformeBorrado>> {
+                if self.contar("select count(*) from promociones where codigo = $1 and deleted_at is null;", codigo).await? == 0 {
+                    return Ok(None);
+                }
+                let detalles = self
+                    .contar("select count(*) from detalle_ventas where codigo_promocion = $1;", codigo)
+                    .await?;
+                Ok(Some(InformeBorrado::new("promociones", codigo).afecta("detalle_ventas", detalles)))
+            }
+
+            #[tracing::instrument(name = "promociones.borrar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn borrar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+                let consulta = sqlx::query("update promociones set deleted_at = $1 where codigo = $2 and deleted_at is null;")
+                    .bind(Utc::now())
+                    .bind(codigo);
+                self.ejecutar::<Promociones>(actor, PROMOCIONES, Some(codigo), Accion::Delete, consulta).await
+            }
+
+            #[tracing::instrument(name = "promociones.restaurar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn restaurar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+                let consulta =
+                    sqlx::query("update promociones set deleted_at = null where codigo = $1 and deleted_at is not null;")
+                        .bind(codigo);
+                self.ejecutar::<Promociones>(actor, PROMOCIONES, Some(codigo), Accion::Restore, consulta).await
+            }
+        }
+
+        #[async_trait]
+        impl PagosRepo for Sql<$db> {
+            #[tracing::instrument(name = "pagos.insertar", level = "debug", skip(self, actor, pago, puntos), err(Display, level = "warn"))]
+            async fn insertar(&self, actor: &Actor, codigo_venta: i64, pago: &Pagos, puntos: &ConfigPuntos) -> Resultado<Option<i64>> {
+                let consulta = sqlx::query_scalar(
+                    "insert into pagos (codigo_venta, metodo, valor, recibido, cambio, referencia, fecha) \
+                     select codigo, $2, $3, $4, $5, $6, $7 from ventas where codigo = $1 and finalizada_at is null \
+                     and (select coalesce(sum(valor), 0.0) from pagos where codigo_venta = $1) + $3 < total_venta + 0.005 \
+                     returning codigo;",
+                )
+                .bind(codigo_venta)
+                .bind(pago.metodo.as_str())
+                .bind(pago.valor)
+                .bind(pago.recibido)
+                .bind(pago.cambio)
+                .bind(pago.referencia.as_deref())
+                .bind(Utc::now());
+                let mut transaction = self.pool.begin().await?;
+                let resultado = async {
+                    // los pagos concurrentes de la misma venta esperan a que este termine para sumar lo pagado
+                    Self::bloquear(&mut transaction, VENTAS, codigo_venta).await?;
+                    let redencion = match pago.metodo.as_str() {
+                        PUNTOS => match Self::redencion(&mut transaction, codigo_venta, pago.valor, puntos).await? {
+                            Some(redencion) => Some(redencion),
+                            None => return Ok(None),
+                        },
+                        _ => None,
+                    };
+                    let Some(codigo) = Self::crear_en::<Pagos>(&mut transaction, actor, PAGOS, consulta).await? else {
+                        return Ok(None);
+                    };
+                    if let Some((cedula_cliente, redimidos)) = redencion {
+                        let consulta = sqlx::query_scalar(
+                            "insert into movimientos_puntos (cedula_cliente, codigo_pago, puntos, fecha) \
+                             values ($1,$2,$3,$4) returning codigo;",
+                        )
+                        .bind(cedula_cliente)
+                        .bind(codigo)
+                        .bind(-redimidos)
+                        .bind(Utc::now());
+                        Self::crear_en::<MovimientosPuntos>(&mut transaction, actor, MOVIMIENTOS_PUNTOS, consulta).await?;
+                    }
+                    Ok(Some(codigo))
+                }
+                .await;
+                Self::terminar(transaction, resultado).await
+            }
+
+            #[tracing::instrument(name = "pagos.por_venta", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn por_venta(&self, codigo_venta: i64) -> Resultado<Vec<Pagos>> {
+                Ok(sqlx::query_as::<_, Pagos>("select * from pagos where codigo_venta = $1 order by codigo;")
+                    .bind(codigo_venta)
+                    .fetch_all(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "pagos.borrar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn borrar(&self, actor: &Actor, codigo_venta: i64, codigo: i64) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "delete from pagos where codigo = $1 and codigo_venta = $2 \
+                     and codigo_venta in (select codigo from ventas where finalizada_at is null);",
+                )
+                .bind(codigo)
+                .bind(codigo_venta);
+                self.ejecutar_en_venta::<Pagos>(actor, Some(codigo_venta), PAGOS, Some(codigo), Accion::Delete, consulta)
+                    .await
+            }
+        }
+
+        #[async_trait]
+        impl PuntosRepo for Sql<$db> {
+            #[tracing::instrument(name = "puntos.movimientos", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn movimientos(&self, cedula_cliente: i64) -> Resultado<Vec<MovimientosPuntos>> {
+                Ok(sqlx::query_as::<_, MovimientosPuntos>(
+                    "select * from movimientos_puntos where cedula_cliente = $1 order by fecha, codigo;",
+                )
+                .bind(cedula_cliente)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+        }
+
+        #[async_trait]
+        impl InventarioRepo for Sql<$db> {
+            #[tracing::instrument(name = "inventario.existencias", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn existencias(&self, sucursal: Option<i64>) -> Resultado<Vec<Existencias>> {
+                Ok(sqlx::query_as::<_, Existencias>(
+                    "select p.codigo as codigo_producto, p.nombre_producto, p.nit_proveedor, p.unidad_medida, p.precio_compra, \
+                     p.stock_minimo, p.stock_maximo, coalesce(sum(m.cantidad), 0.0) as cantidad \
+                     from productos p left join movimientos_inventario m on m.codigo_producto = p.codigo \
+                     and ($1 is null or m.codigo_sucursal = $1) \
+                     where p.deleted_at is null group by p.codigo order by p.codigo;",
+                )
+                .bind(sucursal)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "inventario.vendido", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn vendido(&self, desde: DateTime<Utc>, sucursal: Option<i64>) -> Resultado<Vec<Vendido>> {
+                Ok(sqlx::query_as::<_, Vendido>(
+                    "select d.codigo_producto, sum(d.cantidad_producto) as cantidad \
+                     from detalle_ventas d join ventas v on v.codigo = d.codigo_venta \
+                     where v.fecha >= $1 and v.finalizada_at is not null and ($2 is null or v.codigo_sucursal = $2) \
+                     group by d.codigo_producto;",
+                )
+                .bind(desde)
+                .bind(sucursal)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "inventario.movimientos", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn movimientos(&self, codigo_producto: i64, sucursal: Option<i64>) -> Resultado<Vec<MovimientosInventario>> {
+                Ok(sqlx::query_as::<_, MovimientosInventario>(
+                    "select * from movimientos_inventario where codigo_producto = $1 and ($2 is null or codigo_sucursal = $2) \
+                     order by fecha, codigo;",
+                )
+                .bind(codigo_producto)
+                .bind(sucursal)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+        }
+
+        #[async_trait]
+        impl OrdenesCompraRepo for Sql<$db> {
+            #[tracing::instrument(name = "ordenes_compra.insertar", level = "debug", skip(self, actor, orden, detalles), err(Display, level = "warn"))]
+            async fn insertar(&self, actor: &Actor, orden: &OrdenesCompra, detalles: &[DetalleOrdenesCompra]) -> Resultado<i64> {
+                let mut transaction = self.pool.begin().await?;
+                let resultado = async {
+                    let codigo = sqlx::query_scalar::<_, i64>(
+                        "insert into ordenes_compra (nit_proveedor, codigo_sucursal, estado, cedula_usuario, created_at) \
+                         values ($1,$2,$3,$4,$5) returning codigo;",
+                    )
+                    .bind(orden.nit_proveedor)
+                    .bind(orden.codigo_sucursal)
+                    .bind(orden.estado.as_str())
+                    .bind(orden.cedula_usuario)
+                    .bind(Utc::now())
+                    .fetch_one(&mut *transaction)
+                    .await?;
+                    for detalle in detalles {
+                        sqlx::query(
+                            "insert into detalle_ordenes_compra (codigo_orden, codigo_producto, cantidad, precio_compra) \
+                             values ($1,$2,$3,$4);",
+                        )
+                        .bind(codigo)
+                        .bind(detalle.codigo_producto)
+                        .bind(detalle.cantidad)
+                        .bind(detalle.precio_compra)
+                        .execute(&mut *transaction)
+                        .await?;
+                    }
+                    let despues = Self::instantanea::<OrdenesCompra>(&mut transaction, ORDENES_COMPRA, codigo).await?;
+                    Self::registrar(&mut transaction, actor, ORDENES_COMPRA, Some(codigo), Accion::Create, None, despues).await?;
+                    Ok(codigo)
+                }
+                .await;
+                Self::terminar(transaction, resultado).await
+            }
+
+            #[tracing::instrument(name = "ordenes_compra.listar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn listar(&self, estado: Option<&str>, sucursal: Option<i64>) -> Resultado<Vec<OrdenesCompra>> {
+                Ok(sqlx::query_as::<_, OrdenesCompra>(
+                    "select * from ordenes_compra where ($1 is null or estado = $1) and ($2 is null or codigo_sucursal = $2) \
+                     order by created_at desc, codigo desc;",
+                )
+                .bind(estado)
+                .bind(sucursal)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "ordenes_compra.obtener", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn obtener(&self, codigo: i64) -> Resultado<Option<OrdenesCompra>> {
+                Ok(sqlx::query_as::<_, OrdenesCompra>("select * from ordenes_compra where codigo = $1;")
+                    .bind(codigo)
+                    .fetch_optional(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "ordenes_compra.detalles", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn detalles(&self, codigo_orden: i64) -> Resultado<Vec<DetalleOrdenesCompra>> {
+                Ok(sqlx::query_as::<_, DetalleOrdenesCompra>(
+                    "select * from detalle_ordenes_compra where codigo_orden = $1 order by codigo;",
+                )
+                .bind(codigo_orden)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "ordenes_compra.recibir", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn recibir(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+                let mut transaction = self.pool.begin().await?;
+                let resultado = async {
+                    let consulta = sqlx::query(
+                        "update ordenes_compra set estado = $1, recibida_at = $2 where codigo = $3 and estado = $4;",
+                    )
+                    .bind(ordenes_compra::RECIBIDA)
+                    .bind(Utc::now())
+                    .bind(codigo)
+                    .bind(ordenes_compra::PENDIENTE);
+                    let filas = Self::ejecutar_en::<OrdenesCompra>(
+                        &mut transaction, actor, ORDENES_COMPRA, Some(codigo), Accion::Update, consulta,
+                    )
+                    .await?;
+                    if filas == 0 {
+                        return Ok(0);
+                    }
+                    let codigo_sucursal =
+                        sqlx::query_scalar::<_, Option<i64>>("select codigo_sucursal from ordenes_compra where codigo = $1;")
+                            .bind(codigo)
+                            .fetch_one(&mut *transaction)
+                            .await?;
+                    let detalles = sqlx::query_as::<_, DetalleOrdenesCompra>(
+                        "select * from detalle_ordenes_compra where codigo_orden = $1 order by codigo;",
+                    )
+                    .bind(codigo)
+                    .fetch_all(&mut *transaction)
+                    .await?;
+                    for detalle in &detalles {
+                        let movimiento = MovimientosInventario {
+                            codigo_orden: Some(codigo),
+                            ..MovimientosInventario::new(codigo_sucursal, detalle.codigo_producto, inventario::COMPRA, detalle.cantidad)
+                        };
+                        Self::insertar_movimiento(&mut transaction, actor, &movimiento).await?;
+                    }
+                    Ok(filas)
+                }
+                .await;
+                Self::terminar(transaction, resultado).await
+            }
+
+            #[tracing::instrument(name = "ordenes_compra.cancelar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn cancelar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+                let consulta = sqlx::query("update ordenes_compra set estado = $1 where codigo = $2 and estado = $3;")
+                    .bind(ordenes_compra::CANCELADA)
+                    .bind(codigo)
+                    .bind(ordenes_compra::PENDIENTE);
+                self.ejecutar::<OrdenesCompra>(actor, ORDENES_COMPRA, Some(codigo), Accion::Update, consulta).await
+            }
+        }
+
+        #[async_trait]
+        impl ConteosRepo for Sql<$db> {
+            #[tracing::instrument(name = "conteos.insertar", level = "debug", skip(self, actor, conteo), err(Display, level = "warn"))]
+            async fn insertar(&self, actor: &Actor, conteo: &Conteos) -> Resultado<i64> {
+                let consulta = sqlx::query_scalar(
+                    "insert into conteos (descripcion, codigo_sucursal, estado, cedula_usuario, created_at) \
+                     values ($1,$2,$3,$4,$5) returning codigo;",
+                )
+                .bind(conteo.descripcion.as_deref())
+                .bind(conteo.codigo_sucursal)
+                .bind(conteo.estado.as_str())
+                .bind(conteo.cedula_usuario)
+                .bind(Utc::now());
+                let codigo = self.crear::<Conteos>(actor, CONTEOS, consulta).await?;
+                Ok(codigo.expect("insert returns the new codigo"))
+            }
+
+            #[tracing::instrument(name = "conteos.listar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn listar(&self, estado: Option<&str>, sucursal: Option<i64>) -> Resultado<Vec<Conteos>> {
+                Ok(sqlx::query_as::<_, Conteos>(
+                    "select * from conteos where ($1 is null or estado = $1) and ($2 is null or codigo_sucursal = $2) \
+                     order by created_at desc, codigo desc;",
+                )
+                .bind(estado)
+                .bind(sucursal)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "conteos.obtener", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn obtener(&self, codigo: i64) -> Resultado<Option<Conteos>> {
+                Ok(sqlx::query_as::<_, Conteos>("select * from conteos where codigo = $1;")
+                    .bind(codigo)
+                    .fetch_optional(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "conteos.detalles", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn detalles(&self, codigo_conteo: i64) -> Resultado<Vec<DetalleConteos>> {
+                Ok(sqlx::query_as::<_, DetalleConteos>(
+                    "select * from detalle_conteos where codigo_conteo = $1 order by codigo;",
+                )
+                .bind(codigo_conteo)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "conteos.registrar", level = "debug", skip(self, actor, contados), err(Display, level = "warn"))]
+            async fn registrar(&self, actor: &Actor, codigo_conteo: i64, contados: &[DetalleConteos]) -> Resultado<Option<u64>> {
+                let mut transaction = self.pool.begin().await?;
+                let resultado = async {
+                    let estado = sqlx::query_scalar::<_, String>("select estado from conteos where codigo = $1;")
+                        .bind(codigo_conteo)
+                        .fetch_optional(&mut *transaction)
+                        .await?;
+                    if estado.as_deref() != Some(conteos::ABIERTO) {
+                        return Ok(None);
+                    }
+                    for contado in contados {
+                        let anterior = sqlx::query_scalar::<_, i64>(
+                            "select codigo from detalle_conteos where codigo_conteo = $1 and codigo_producto = $2;",
+                        )
+                        .bind(codigo_conteo)
+                        .bind(contado.codigo_producto)
+                        .fetch_optional(&mut *transaction)
+                        .await?;
+                        let antes = match anterior {
+                            Some(codigo) => Self::instantanea::<DetalleConteos>(&mut transaction, DETALLE_CONTEOS, codigo).await?,
+                            None => None,
+                        };
+                        let codigo = sqlx::query_scalar::<_, i64>(
+                            "insert into detalle_conteos (codigo_conteo, codigo_producto, cantidad, motivo, updated_at) \
+                             values ($1,$2,$3,$4,$5) on conflict (codigo_conteo, codigo_producto) \
+                             do update set cantidad = excluded.cantidad, motivo = excluded.motivo, updated_at = excluded.updated_at \
+                             returning codigo;",
+                        )
+                        .bind(codigo_conteo)
+                        .bind(contado.codigo_producto)
+                        .bind(contado.cantidad)
+                        .bind(contado.motivo.as_deref())
+                        .bind(Utc::now())
+                        .fetch_one(&mut *transaction)
+                        .await?;
+                        let accion = if anterior.is_some() { Accion::Update } else { Accion::Create };
+                        let despues = Self::instantanea::<DetalleConteos>(&mut transaction, DETALLE_CONTEOS, codigo).await?;
+                        Self::registrar(&mut transaction, actor, DETALLE_CONTEOS, Some(codigo), accion, antes, despues).await?;
+                    }
+                    Ok(Some(contados.len() as u64))
+                }
+                .await;
+                Self::terminar(transaction, resultado).await
+            }
+
+            #[tracing::instrument(name = "conteos.aplicar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn aplicar(&self, actor: &Actor, codigo: i64, motivo: &str) -> Resultado<Option<u64>> {
+                let mut transaction = self.pool.begin().await?;
+                let resultado = async {
+                    let consulta = sqlx::query("update conteos set estado = $1, aplicado_at = $2 where codigo = $3 and estado = $4;")
+                        .bind(conteos::APLICADO)
+                        .bind(Utc::now())
+                        .bind(codigo)
+                        .bind(conteos::ABIERTO);
+                    let filas =
+                        Self::ejecutar_en::<Conteos>(&mut transaction, actor, CONTEOS, Some(codigo), Accion::Update, consulta).await?;
+                    if filas == 0 {
+                        return Ok(None);
+                    }
+                    let codigo_sucursal =
+                        sqlx::query_scalar::<_, Option<i64>>("select codigo_sucursal from conteos where codigo = $1;")
+                            .bind(codigo)
+                            .fetch_one(&mut *transaction)
+                            .await?;
+                    let contados = sqlx::query_as::<_, DetalleConteos>(
+                        "select d.* from detalle_conteos d join productos p on p.codigo = d.codigo_producto \
+                         where d.codigo_conteo = $1 and p.deleted_at is null order by d.codigo;",
+                    )
+                    .bind(codigo)
+                    .fetch_all(&mut *transaction)
+                    .await?;
+                    let mut ajustes = 0;
+                    for contado in &contados {
+                        let sistema = Self::existencias_en(&mut transaction, contado.codigo_producto, codigo_sucursal).await?;
+                        let cantidad = conteos::ajuste(sistema, contado.cantidad);
+                        if cantidad == 0.0 {
+                            continue;
+                        }
+                        let movimiento = MovimientosInventario {
+                            codigo_conteo: Some(codigo),
+                            motivo: Some(contado.motivo.clone().unwrap_or_else(|| motivo.to_owned())),
+                            ..MovimientosInventario::new(codigo_sucursal, contado.codigo_producto, inventario::AJUSTE, cantidad)
+                        };
+                        Self::insertar_movimiento(&mut transaction, actor, &movimiento).await?;
+                        ajustes += 1;
+                    }
+                    Ok(Some(ajustes))
+                }
+                .await;
+                Self::terminar(transaction, resultado).await
+            }
+
+            #[tracing::instrument(name = "conteos.cancelar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn cancelar(&self, actor: &Actor, codigo: i64) -> Resultado<u64> {
+                let consulta = sqlx::query("update conteos set estado = $1 where codigo = $2 and estado = $3;")
+                    .bind(conteos::CANCELADO)
+                    .bind(codigo)
+                    .bind(conteos::ABIERTO);
+                self.ejecutar::<Conteos>(actor, CONTEOS, Some(codigo), Accion::Update, consulta).await
+            }
+        }
+
+        #[async_trait]
+        impl SucursalesRepo for Sql<$db> {
+            #[tracing::instrument(name = "sucursales.insertar", level = "debug", skip(self, actor, sucursal), err(Display, level = "warn"))]
+            async fn insertar(&self, actor: &Actor, sucursal: &Sucursales) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "insert into sucursales (codigo, nombre, direccion, created_at, updated_at) values ($1,$2,$3,$4,$4);",
+                )
+                .bind(sucursal.codigo)
+                .bind(sucursal.nombre.as_str())
+                .bind(sucursal.direccion.as_deref())
+                .bind(Utc::now());
+                self.ejecutar::<Sucursales>(actor, SUCURSALES, sucursal.codigo, Accion::Create, consulta).await
+            }
+
+            #[tracing::instrument(name = "sucursales.listar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn listar(&self) -> Resultado<Vec<Sucursales>> {
+                Ok(sqlx::query_as::<_, Sucursales>("select * from sucursales order by codigo;")
+                    .fetch_all(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "sucursales.obtener", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn obtener(&self, codigo: i64) -> Resultado<Option<Sucursales>> {
+                Ok(sqlx::query_as::<_, Sucursales>("select * from sucursales where codigo = $1;")
+                    .bind(codigo)
+                    .fetch_optional(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "sucursales.actualizar", level = "debug", skip(self, actor, sucursal), err(Display, level = "warn"))]
+            async fn actualizar(&self, actor: &Actor, codigo: i64, sucursal: &Sucursales) -> Resultado<u64> {
+                let consulta = sqlx::query("update sucursales set nombre = $1, direccion = $2, updated_at = $3 where codigo = $4;")
+                    .bind(sucursal.nombre.as_str())
+                    .bind(sucursal.direccion.as_deref())
+                    .bind(Utc::now())
+                    .bind(codigo);
+                self.ejecutar::<Sucursales>(actor, SUCURSALES, Some(codigo), Accion::Update, consulta).await
+            }
+
+            #[tracing::instrument(name = "sucursales.asignadas", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn asignadas(&self, cedula_usuario: i64) -> Resultado<Vec<Sucursales>> {
+                Ok(sqlx::query_as::<_, Sucursales>(
+                    "select s.* from sucursales s join usuarios_sucursales u on u.codigo_sucursal = s.codigo \
+                     where u.cedula_usuario = $1 order by s.codigo;",
+                )
+                .bind(cedula_usuario)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "sucursales.asignar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn asignar(&self, actor: &Actor, cedula_usuario: i64, codigo_sucursal: i64) -> Resultado<Option<i64>> {
+                let consulta = sqlx::query_scalar(
+                    "insert into usuarios_sucursales (cedula_usuario, codigo_sucursal, created_at) values ($1,$2,$3) returning codigo;",
+                )
+                .bind(cedula_usuario)
+                .bind(codigo_sucursal)
+                .bind(Utc::now());
+                self.crear::<UsuariosSucursales>(actor, USUARIOS_SUCURSALES, consulta).await
+            }
+
+            #[tracing::instrument(name = "sucursales.desasignar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn desasignar(&self, actor: &Actor, cedula_usuario: i64, codigo_sucursal: i64) -> Resultado<u64> {
+                let codigo = sqlx::query_scalar::<_, i64>(
+                    "select codigo from usuarios_sucursales where cedula_usuario = $1 and codigo_sucursal = $2;",
+                )
+                .bind(cedula_usuario)
+                .bind(codigo_sucursal)
+                .fetch_optional(&self.pool)
+                .await?;
+                let Some(codigo) = codigo else {
+                    return Ok(0);
+                };
+                let consulta = sqlx::query("delete from usuarios_sucursales where codigo = $1;").bind(codigo);
+                self.ejecutar::<UsuariosSucursales>(actor, USUARIOS_SUCURSALES, Some(codigo), Accion::Delete, consulta).await
+            }
+        }
+
+        #[async_trait]
+        impl TrasladosRepo for Sql<$db> {
+            #[tracing::instrument(name = "traslados.insertar", level = "debug", skip(self, actor, traslado, productos), err(Display, level = "warn"))]
+            async fn insertar(&self, actor: &Actor, traslado: &Traslados, productos: &[LineaTraslado]) -> Resultado<Option<i64>> {
+                let mut transaction = self.pool.begin().await?;
+                let resultado = async {
+                    // los traslados desde una misma sucursal esperan su turno, para que dos traslados
+                    // concurrentes no lean las mismas existencias y las dejen negativas
+                    Self::bloquear(&mut transaction, SUCURSALES, traslado.codigo_sucursal_origen).await?;
+                    let totales = traslados::totales(productos);
+                    for (&codigo_producto, &cantidad) in &totales {
+                        let existencias =
+                            Self::existencias_en(&mut transaction, Some(codigo_producto), Some(traslado.codigo_sucursal_origen))
+                                .await?;
+                        if !traslados::alcanza(existencias, cantidad) {
+                            return Ok(None);
+                        }
+                    }
+                    let codigo = sqlx::query_scalar::<_, i64>(
+                        "insert into traslados (codigo_sucursal_origen, codigo_sucursal_destino, motivo, cedula_usuario, fecha) \
+                         values ($1,$2,$3,$4,$5) returning codigo;",
+                    )
+                    .bind(traslado.codigo_sucursal_origen)
+                    .bind(traslado.codigo_sucursal_destino)
+                    .bind(traslado.motivo.as_deref())
+                    .bind(traslado.cedula_usuario)
+                    .bind(Utc::now())
+                    .fetch_one(&mut *transaction)
+                    .await?;
+                    let despues = Self::instantanea::<Traslados>(&mut transaction, TRASLADOS, codigo).await?;
+                    Self::registrar(&mut transaction, actor, TRASLADOS, Some(codigo), Accion::Create, None, despues).await?;
+                    for (&codigo_producto, &cantidad) in &totales {
+                        for (codigo_sucursal, cantidad) in
+                            [(traslado.codigo_sucursal_origen, -cantidad), (traslado.codigo_sucursal_destino, cantidad)]
+                        {
+                            let movimiento = MovimientosInventario {
+                                codigo_traslado: Some(codigo),
+                                motivo: traslado.motivo.clone(),
+                                ..MovimientosInventario::new(Some(codigo_sucursal), Some(codigo_producto), inventario::TRASLADO, cantidad)
+                            };
+                            Self::insertar_movimiento(&mut transaction, actor, &movimiento).await?;
+                        }
+                    }
+                    Ok(Some(codigo))
+                }
+                .await;
+                Self::terminar(transaction, resultado).await
+            }
+
+            #[tracing::instrument(name = "traslados.listar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn listar(&self, rango: &RangoFechas, sucursal: Option<i64>) -> Resultado<Vec<Traslados>> {
+                Ok(sqlx::query_as::<_, Traslados>(
+                    "select * from traslados where ($1 is null or fecha >= $1) and ($2 is null or fecha < $2) \
+                     and ($3 is null or codigo_sucursal_origen = $3 or codigo_sucursal_destino = $3) order by fecha, codigo;",
+                )
+                .bind(rango.inicio())
+                .bind(rango.fin())
+                .bind(sucursal)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "traslados.obtener", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn obtener(&self, codigo: i64) -> Resultado<Option<Traslados>> {
+                Ok(sqlx::query_as::<_, Traslados>("select * from traslados where codigo = $1;")
+                    .bind(codigo)
+                    .fetch_optional(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "traslados.movimientos", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn movimientos(&self, codigo_traslado: i64) -> Resultado<Vec<MovimientosInventario>> {
+                Ok(sqlx::query_as::<_, MovimientosInventario>(
+                    "select * from movimientos_inventario where codigo_traslado = $1 order by codigo;",
+                )
+                .bind(codigo_traslado)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+        }
+
+        #[async_trait]
+        impl CajasRepo for Sql<$db> {
+            #[tracing::instrument(name = "cajas.insertar", level = "debug", skip(self, actor, caja), err(Display, level = "warn"))]
+            async fn insertar(&self, actor: &Actor, caja: &Cajas) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "insert into cajas (codigo, nombre, codigo_sucursal, created_at, updated_at) values ($1,$2,$3,$4,$4);",
+                )
+                .bind(caja.codigo)
+                .bind(caja.nombre.as_str())
+                .bind(caja.codigo_sucursal)
+                .bind(Utc::now());
+                self.ejecutar::<Cajas>(actor, CAJAS, caja.codigo, Accion::Create, consulta).await
+            }
+
+            #[tracing::instrument(name = "cajas.listar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn listar(&self, sucursal: Option<i64>) -> Resultado<Vec<Cajas>> {
+                Ok(sqlx::query_as::<_, Cajas>("select * from cajas where $1 is null or codigo_sucursal = $1 order by codigo;")
+                    .bind(sucursal)
+                    .fetch_all(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "cajas.obtener", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn obtener(&self, codigo: i64) -> Resultado<Option<Cajas>> {
+                Ok(sqlx::query_as::<_, Cajas>("select * from cajas where codigo = $1;")
+                    .bind(codigo)
+                    .fetch_optional(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "cajas.actualizar", level = "debug", skip(self, actor, caja), err(Display, level = "warn"))]
+            async fn actualizar(&self, actor: &Actor, codigo: i64, caja: &Cajas) -> Resultado<u64> {
+                let consulta = sqlx::query("update cajas set nombre = $1, codigo_sucursal = coalesce($2, codigo_sucursal), updated_at = $3 where codigo = $4;")
+                    .bind(caja.nombre.as_str())
+                    .bind(caja.codigo_sucursal)
+                    .bind(Utc::now())
+                    .bind(codigo);
+                self.ejecutar::<Cajas>(actor, CAJAS, Some(codigo), Accion::Update, consulta).await
+            }
+        }
+
+        #[async_trait]
+        impl TurnosRepo for Sql<$db> {
+            #[tracing::instrument(name = "turnos.abrir", level = "debug", skip(self, actor, turno), err(Display, level = "warn"))]
+            async fn abrir(&self, actor: &Actor, turno: &Turnos) -> Resultado<i64> {
+                let consulta = sqlx::query_scalar(
+                    "insert into turnos (codigo_caja, cedula_usuario, efectivo_inicial, abierto_at) \
+                     values ($1,$2,$3,$4) returning codigo;",
+                )
+                .bind(turno.codigo_caja)
+                .bind(turno.cedula_usuario)
+                .bind(turno.efectivo_inicial)
+                .bind(Utc::now());
+                let codigo = self.crear::<Turnos>(actor, TURNOS, consulta).await?;
+                Ok(codigo.expect("insert returns the new codigo"))
+            }
+
+            #[tracing::instrument(name = "turnos.listar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn listar(&self, rango: &RangoFechas, sucursal: Option<i64>) -> Resultado<Vec<Turnos>> {
+                Ok(sqlx::query_as::<_, Turnos>(
+                    "select * from turnos where ($1 is null or abierto_at >= $1) and ($2 is null or abierto_at < $2) \
+                     and ($3 is null or codigo_caja in (select codigo from cajas where codigo_sucursal = $3)) order by abierto_at;",
+                )
+                .bind(rango.inicio())
+                .bind(rango.fin())
+                .bind(sucursal)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "turnos.obtener", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn obtener(&self, codigo: i64) -> Resultado<Option<Turnos>> {
+                Ok(sqlx::query_as::<_, Turnos>("select * from turnos where codigo = $1;")
+                    .bind(codigo)
+                    .fetch_optional(&self.pool)
+                    .await?)
+            }
+
+            #[tracing::instrument(name = "turnos.abierto", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn abierto(&self, cedula_usuario: i64) -> Resultado<Option<Turnos>> {
+                Ok(sqlx::query_as::<_, Turnos>(
+                    "select * from turnos where cedula_usuario = $1 and cerrado_at is null;",
+                )
+                .bind(cedula_usuario)
+                .fetch_optional(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "turnos.insertar_movimiento", level = "debug", skip(self, actor, movimiento), err(Display, level = "warn"))]
+            async fn insertar_movimiento(
+                &self,
+                actor: &Actor,
+                codigo_turno: i64,
+                movimiento: &MovimientosCaja,
+            ) -> Resultado<Option<i64>> {
+                let consulta = sqlx::query_scalar(
+                    "insert into movimientos_caja (codigo_turno, tipo, valor, motivo, fecha) \
+                     select codigo, $2, $3, $4, $5 from turnos where codigo = $1 and cerrado_at is null \
+                     returning codigo;",
+                )
+                .bind(codigo_turno)
+                .bind(movimiento.tipo.as_str())
+                .bind(movimiento.valor)
+                .bind(movimiento.motivo.as_str())
+                .bind(Utc::now());
+                self.crear::<MovimientosCaja>(actor, MOVIMIENTOS_CAJA, consulta).await
+            }
+
+            #[tracing::instrument(name = "turnos.movimientos", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn movimientos(&self, codigo_turno: i64) -> Resultado<Vec<MovimientosCaja>> {
+                Ok(sqlx::query_as::<_, MovimientosCaja>(
+                    "select * from movimientos_caja where codigo_turno = $1 order by codigo;",
+                )
+                .bind(codigo_turno)
+                .fetch_all(&self.pool)
+                .await?)
+            }
+
+            #[tracing::instrument(name = "turnos.totales", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn totales(&self, codigo_turno: i64) -> Resultado<TotalesTurno> {
+                let (ventas, total_ventas) = sqlx::query_as::<_, (i64, f64)>(
+                    "select count(*), coalesce(sum(total_venta), 0.0) from ventas \
+                     where codigo_turno = $1 and finalizada_at is not null;",
+                )
+                .bind(codigo_turno)
+                .fetch_one(&self.pool)
+                .await?;
+                let efectivo = sqlx::query_scalar::<_, f64>(
+                    "select coalesce(sum(p.valor), 0.0) from pagos p join ventas v on v.codigo = p.codigo_venta \
+                     where v.codigo_turno = $1 and v.finalizada_at is not null and p.metodo = $2;",
+                )
+                .bind(codigo_turno)
+                .bind(EFECTIVO)
+                .fetch_one(&self.pool)
+                .await?;
+                let (entradas, salidas) = sqlx::query_as::<_, (f64, f64)>(
+                    "select coalesce(sum(case when tipo = $2 then valor else 0.0 end), 0.0), \
+                     coalesce(sum(case when tipo = $3 then valor else 0.0 end), 0.0) \
+                     from movimientos_caja where codigo_turno = $1;",
+                )
+                .bind(codigo_turno)
+                .bind(ENTRADA)
+                .bind(SALIDA)
+                .fetch_one(&self.pool)
+                .await?;
+                Ok(TotalesTurno { ventas, total_ventas, efectivo, entradas, salidas })
+            }
+
+            #[tracing::instrument(name = "turnos.cerrar", level = "debug", skip(self, actor), err(Display, level = "warn"))]
+            async fn cerrar(&self, actor: &Actor, codigo: i64, efectivo_contado: f64) -> Resultado<u64> {
+                let consulta = sqlx::query(
+                    "update turnos set efectivo_contado = $1, cerrado_at = $2 where codigo = $3 and cerrado_at is null;",
+                )
+                .bind(efectivo_contado)
+                .bind(Utc::now())
+                .bind(codigo);
+                self.ejecutar::<Turnos>(actor, TURNOS, Some(codigo), Accion::Update, consulta).await
+            }
+        }
+
+        #[async_trait]
+        impl AuditoriaRepo for Sql<$db> {
+            #[tracing::instrument(name = "auditoria.listar", level = "debug", skip(self), err(Display, level = "warn"))]
+            async fn listar(
+                &self,
+                filtro: &FiltroAuditoria,
+                rango: &RangoFechas,
+                paginacion: &Paginacion,
+            ) -> Resultado<Vec<Auditoria>> {
+                Ok(sqlx::query_as::<_, Auditoria>(
+                    "select * from auditoria \
+                     where ($1 is null or entidad = $1) and ($2 is null or llave = $2) \
+                     and ($3 is null or accion = $3) and ($4 is null or cedula_usuario = $4) \
+                     and ($5 is null or fecha >= $5) and ($6 is null or fecha < $6) \
+                     order by codigo desc limit $7 offset $8;",
+                )
+                .bind(filtro.entidad.as_deref())
+                .bind(filtro.llave)
+                .bind(filtro.accion.as_deref())
+                .bind(filtro.cedula_usuario)
+                .bind(rango.inicio())
+                .bind(rango.fin())
+                .bind(paginacion.limite())
+                .bind(paginacion.desplazamiento())
+                .fetch_all(&self.pool)
+                .await?)
+            }
+        }
+    };
+}
+
+// postgres bloquea solo la fila; SQLite no tiene bloqueos por fila, pero una escritura
+// (aunque no cambie nada) toma el bloqueo de escritura de toda la base
+repos_sql!(Postgres, "select 1 from {tabla} where {llave} = $1 for update;");
+repos_sql!(Sqlite, "update {tabla} set {llave} = {llave} where {llave} = $1;");
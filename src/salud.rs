@@ -0,0 +1,66 @@
+//! Este archivo contiene los servicios de salud del servidor, para los supervisores de procesos
+//! y balanceadores de carga. Estan fuera del scope `/api`, no requieren autenticacion y solo leen
+//! la base de datos.
+
+use actix_web::{get, web::Data, HttpResponse, Responder};
+use serde::Serialize;
+
+use crate::{migraciones, repos::Db};
+
+/// El estado de la base de datos segun `/ready`
+#[derive(Debug, Serialize)]
+struct Preparacion {
+    db: bool,
+    migraciones_pendientes: Option<usize>,
+}
+
+/// La version del servidor segun `/version`
+#[derive(Debug, Serialize)]
+struct Version {
+    version: &'static str,
+    git_hash: &'static str,
+    migracion: Option<i64>,
+}
+
+/// Indica que el proceso esta vivo (no consulta la base de datos)
+#[get("/health")]
+pub async fn health() -> impl Responder {
+    HttpResponse::Ok().json("ok")
+}
+
+/// Indica si el servidor puede atender requests: la base de datos responde
+/// y no hay migraciones pendientes. Si no, responde `503 Service Unavailable`
+/// ### Parametros
+/// * `db` - La coneccion a la base de datos
+#[get("/ready")]
+pub async fn ready(db: Data<Db>) -> impl Responder {
+    let pendientes = match db.ping().await {
+        Ok(()) => migraciones::consultar(&db)
+            .await
+            .ok()
+            .map(|estado| estado.iter().filter(|m| !m.aplicada).count()),
+        Err(_) => None,
+    };
+    let preparacion = Preparacion { db: pendientes.is_some(), migraciones_pendientes: pendientes };
+    match pendientes {
+        Some(0) => HttpResponse::Ok().json(preparacion),
+        _ => HttpResponse::ServiceUnavailable().json(preparacion),
+    }
+}
+
+/// La version del crate, el commit de git con el que se compilo
+/// y la ultima migracion aplicada en la base de datos
+/// ### Parametros
+/// * `db` - La coneccion a la base de datos
+#[get("/version")]
+pub async fn version(db: Data<Db>) -> impl Responder {
+    let migracion = match migraciones::consultar(&db).await {
+        Ok(estado) => estado.iter().filter(|m| m.aplicada).map(|m| m.version).max(),
+        Err(_) => None,
+    };
+    HttpResponse::Ok().json(Version {
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: env!("GIT_HASH"),
+        migracion,
+    })
+}
@@ -0,0 +1,36 @@
+//! Las tareas periodicas del servidor, que corren junto al servidor http:
+//! por ahora, aplicar los cambios de precio programados cuando llega su fecha.
+
+use std::time::Duration;
+
+use actix_web::rt::{self, task::JoinHandle, time};
+
+use crate::{entities::precios, Db};
+
+/// Cada cuanto se buscan cambios de precio programados por aplicar
+const INTERVALO: Duration = Duration::from_secs(60);
+
+/// Inicia las tareas periodicas en el runtime de actix. La primera ejecucion es inmediata,
+/// de modo que al iniciar el servidor se aplican los cambios que vencieron mientras estaba apagado.
+/// Las tareas se detienen con `abort` sobre el handle retornado
+/// ### Parametros
+/// * `db` - La picina de conecciones a la base de datos
+pub fn iniciar(db: &Db) -> JoinHandle<()> {
+    let db = db.clone();
+    rt::spawn(async move {
+        let mut intervalo = time::interval(INTERVALO);
+        loop {
+            intervalo.tick().await;
+            ejecutar(&db).await;
+        }
+    })
+}
+
+/// Ejecuta una vez todas las tareas periodicas; los errores quedan en los logs
+/// ### Parametros
+/// * `db` - La picina de conecciones a la base de datos
+pub async fn ejecutar(db: &Db) {
+    if let Err(error) = precios::aplicar_programados(&db.repos()).await {
+        tracing::warn!(%error, "could not apply scheduled price changes");
+    }
+}
@@ -0,0 +1,72 @@
+//! Este archivo contiene las validaciones compartidas por los structs de las entidades.
+//! Cada struct declara sus reglas con `#[derive(Validate)]` (los largos coinciden con los
+//! `varchar(40)` de las migraciones) y los servicios REST las aplican antes de ejecutar SQL.
+
+use std::collections::BTreeMap;
+
+use actix_web::HttpResponse;
+use validator::{ValidationError, ValidationErrors, ValidationErrorsKind};
+
+/// Valida un numero de telefono: digitos con `+`, espacios, guiones o parentesis
+/// opcionales, y entre 7 y 15 digitos en total
+pub fn telefono(valor: &str) -> Result<(), ValidationError> {
+    let digitos = valor.chars().filter(char::is_ascii_digit).count();
+    let permitidos = valor
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '+' | ' ' | '-' | '(' | ')'));
+    if permitidos && (7..=15).contains(&digitos) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("telefono"))
+    }
+}
+
+/// Describe un error de validacion de un campo
+fn mensaje(error: &ValidationError) -> String {
+    if let Some(mensaje) = &error.message {
+        return mensaje.to_string();
+    }
+    let param = |nombre: &str| {
+        error.params.get(nombre).map(|v| match v.as_f64() {
+            Some(n) if n.fract() == 0.0 => format!("{}", n as i64),
+            _ => v.to_string(),
+        })
+    };
+    match error.code.as_ref() {
+        "length" => match (param("min"), param("max")) {
+            (Some(min), Some(max)) => format!("must have between {min} and {max} characters"),
+            (None, Some(max)) => format!("must have at most {max} characters"),
+            (Some(min), None) => format!("must have at least {min} characters"),
+            (None, None) => "invalid length".to_owned(),
+        },
+        "range" => match (param("min"), param("max")) {
+            (Some(min), Some(max)) => format!("must be between {min} and {max}"),
+            (Some(min), None) => format!("must be greater than or equal to {min}"),
+            (None, Some(max)) => format!("must be less than or equal to {max}"),
+            (None, None) => "out of range".to_owned(),
+        },
+        "email" => "must be a valid email".to_owned(),
+        "telefono" => "must be a valid phone number".to_owned(),
+        otro => otro.to_owned(),
+    }
+}
+
+/// Convierte los errores de validacion en una lista de errores por campo,
+/// por ejemplo `{"email": ["must be a valid email"]}`
+pub fn por_campo(errores: &ValidationErrors) -> BTreeMap<String, Vec<String>> {
+    errores
+        .errors()
+        .iter()
+        .filter_map(|(campo, tipo)| match tipo {
+            ValidationErrorsKind::Field(errores) => {
+                Some((campo.to_string(), errores.iter().map(mensaje).collect()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// La respuesta 400 de un json que no cumple las validaciones
+pub fn rechazar(errores: ValidationErrors) -> HttpResponse {
+    HttpResponse::BadRequest().json(por_campo(&errores))
+}
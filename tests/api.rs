@@ -0,0 +1,1125 @@
+//! Pruebas de integracion de la API web: montan la misma aplicacion que el servidor
+//! (`rutas`) sobre una base de datos SQLite desechable, migrada desde cero en cada prueba.
+//! Con `TEST_DATABASE_URL` definida usan en cambio un schema nuevo de esa base de datos postgres,
+//! para probar tambien las consultas propias de postgres (como los bloqueos con `for update`).
+
+use actix_web::{
+    body::MessageBody,
+    dev::ServiceResponse,
+    http::{header, StatusCode},
+    test::{self, TestRequest},
+    web::Data,
+    App,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+use proyecto_final_sd::{
+    cli::{self, Comando},
+    migraciones, registro, rutas, tareas, AppState, Db,
+};
+
+/// Las credenciales del administrador creado en cada base de datos de prueba
+const ADMIN: (&str, &str) = ("admin", "secreto");
+
+/// Donde vive la base de datos de una prueba; se borra al soltarla
+struct Desechable {
+    /// El directorio temporal del archivo de SQLite
+    _directorio: Option<TempDir>,
+    /// La url de la base de datos postgres de `TEST_DATABASE_URL` y el nombre del schema de la prueba
+    schema: Option<(String, String)>,
+}
+
+impl Drop for Desechable {
+    fn drop(&mut self) {
+        let Some((url, nombre)) = &self.schema else {
+            return;
+        };
+        let (url, sql) = (url.clone(), format!("drop schema {nombre} cascade;"));
+        // el runtime de la prueba puede estar terminando, asi que el schema se borra desde otro
+        let borrado = std::thread::spawn(move || {
+            actix_web::rt::System::new().block_on(async move {
+                let db = Db::conectar(&url).await.expect("coneccion a postgres");
+                ejecutar_sql(&db, &sql).await;
+            })
+        });
+        if borrado.join().is_err() {
+            eprintln!("could not drop the test schema {nombre}");
+        }
+    }
+}
+
+/// Una base de datos nueva, migrada y con un administrador: un archivo de SQLite en un directorio temporal,
+/// o un schema nuevo si `TEST_DATABASE_URL` esta definida
+async fn base_de_datos() -> (Desechable, Db) {
+    let (desechable, url) = match std::env::var("TEST_DATABASE_URL") {
+        Ok(url) => {
+            let nombre = format!("prueba_{}", uuid::Uuid::new_v4().simple());
+            let db = Db::conectar(&url).await.expect("coneccion a postgres");
+            ejecutar_sql(&db, &format!("create schema {nombre};")).await;
+            let separador = if url.contains('?') { '&' } else { '?' };
+            let en_schema = format!("{url}{separador}options=-c%20search_path%3D{nombre}");
+            (Desechable { _directorio: None, schema: Some((url, nombre)) }, en_schema)
+        }
+        Err(_) => {
+            let directorio = tempfile::tempdir().expect("directorio temporal");
+            let url = format!("sqlite:{}", directorio.path().join("tienda.db").display());
+            (Desechable { _directorio: Some(directorio), schema: None }, url)
+        }
+    };
+    let db = Db::conectar(&url).await.expect("coneccion a la base de datos");
+    migraciones::subir(&db).await.expect("migraciones");
+    let admin = Comando::CreateAdmin {
+        cedula: 1,
+        usuario: ADMIN.0.to_owned(),
+        password: ADMIN.1.to_owned(),
+        email: "admin@tienda.co".to_owned(),
+        nombre: "Administrador".to_owned(),
+    };
+    cli::ejecutar(&db, admin).await.expect("usuario administrador");
+    (desechable, db)
+}
+
+/// Ejecuta una sentencia directamente sobre la base de datos, para preparar un caso que la api no permite
+async fn ejecutar_sql(db: &Db, sql: &str) {
+    let resultado = match db {
+        Db::Postgres(pool) => sqlx::query(sql).execute(pool).await.map(|_| ()),
+        Db::Sqlite(pool) => sqlx::query(sql).execute(pool).await.map(|_| ()),
+    };
+    resultado.expect("sentencia de prueba");
+}
+
+/// Monta la aplicacion sobre la base de datos
+macro_rules! aplicacion {
+    ($db:expr) => {
+        test::init_service(
+            App::new()
+                .app_data(Data::new(AppState::new($db.repos())))
+                .app_data(Data::new($db.clone()))
+                .wrap(registro::Solicitudes)
+                .configure(rutas),
+        )
+        .await
+    };
+}
+
+/// El header `Authorization` del administrador
+fn autorizacion() -> (header::HeaderName, String) {
+    let (usuario, password) = ADMIN;
+    credenciales(usuario, password)
+}
+
+/// El header `Authorization` de un usuario
+fn credenciales(usuario: &str, password: &str) -> (header::HeaderName, String) {
+    (header::AUTHORIZATION, format!("Basic {}", STANDARD.encode(format!("{usuario}:{password}"))))
+}
+
+/// Envia un request y retorna el estado y el cuerpo (ver `leer`)
+macro_rules! llamar {
+    ($app:expr, $req:expr) => {
+        leer(test::call_service($app, $req.to_request()).await)
+    };
+}
+
+/// El estado y el cuerpo de una respuesta (como json, o como texto si no es json)
+async fn leer<B: MessageBody>(respuesta: ServiceResponse<B>) -> (StatusCode, Value) {
+    let estado = respuesta.status();
+    let cuerpo = test::read_body(respuesta).await;
+    let valor = serde_json::from_slice(&cuerpo)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&cuerpo).into_owned()));
+    (estado, valor)
+}
+
+fn get(uri: &str) -> TestRequest {
+    TestRequest::get().uri(uri)
+}
+
+fn post(uri: &str, cuerpo: Value) -> TestRequest {
+    TestRequest::post().uri(uri).insert_header(autorizacion()).set_json(cuerpo)
+}
+
+fn patch(uri: &str, cuerpo: Value) -> TestRequest {
+    TestRequest::patch().uri(uri).insert_header(autorizacion()).set_json(cuerpo)
+}
+
+fn delete(uri: &str) -> TestRequest {
+    TestRequest::delete().uri(uri).insert_header(autorizacion())
+}
+
+fn restaurar(uri: &str) -> TestRequest {
+    TestRequest::post().uri(uri).insert_header(autorizacion())
+}
+
+/// Un formulario multipart con un solo archivo, como lo envia el frontend al importar productos
+fn formulario_csv(uri: &str, csv: &str) -> TestRequest {
+    let limite = "----limite-de-prueba";
+    let cuerpo = format!(
+        "--{limite}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"prod.csv\"\r\n\
+         Content-Type: text/csv\r\n\r\n{csv}\r\n--{limite}--\r\n"
+    );
+    TestRequest::post()
+        .uri(uri)
+        .insert_header(autorizacion())
+        .insert_header((header::CONTENT_TYPE, format!("multipart/form-data; boundary={limite}")))
+        .set_payload(cuerpo)
+}
+
+fn proveedor() -> Value {
+    json!({ "nit": 1234, "ciudad": "Bogota", "direccion": "Calle 1 # 2-3", "nombre": "Distribuidora", "telefono": "601 555 1234" })
+}
+
+fn producto() -> Value {
+    json!({
+        "codigo": 77, "nit_proveedor": 1234, "iva_compra": 19.0,
+        "nombre_producto": "Arroz", "precio_compra": 100.0, "precio_venta": 150.0,
+    })
+}
+
+fn cliente() -> Value {
+    json!({ "cedula": 2001, "direccion": "Calle 9", "email": "ana@correo.co", "nombre": "Ana", "telefono": "300 111 2233" })
+}
+
+/// Un detalle de una unidad del producto de prueba, por el total (con IVA) de su venta
+fn detalle(codigo: i64, codigo_venta: i64, valor_total: f64) -> Value {
+    json!({
+        "codigo": codigo, "codigo_producto": 77, "codigo_venta": codigo_venta, "cantidad_producto": 1,
+        "valor_total": valor_total, "valor_venta": valor_total, "valor_iva": 0.0,
+    })
+}
+
+#[actix_web::test]
+async fn clientes_crud() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+
+    let (estado, _) = llamar!(&app, TestRequest::post().uri("/api/clientes/").set_json(cliente())).await;
+    assert_eq!(estado, StatusCode::UNAUTHORIZED);
+    assert_eq!(llamar!(&app, post("/api/clientes/", cliente())).await.0, StatusCode::CREATED);
+    let (estado, _) = llamar!(&app, post("/api/clientes/", json!({ "cedula": 2002, "email": "no es un correo" }))).await;
+    assert_eq!(estado, StatusCode::BAD_REQUEST);
+
+    let (estado, leido) = llamar!(&app, get("/api/clientes/2001")).await;
+    assert_eq!((estado, &leido["nombre"]), (StatusCode::OK, &json!("Ana")));
+    assert_eq!(llamar!(&app, get("/api/clientes/9999")).await.0, StatusCode::NOT_FOUND);
+
+    let cambios = json!({ "direccion": "Carrera 7", "email": "ana@correo.co", "nombre": "Ana Maria", "telefono": "300 111 2233" });
+    assert_eq!(llamar!(&app, patch("/api/clientes/2001", cambios)).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/clientes/2001")).await.1["nombre"], "Ana Maria");
+
+    assert_eq!(llamar!(&app, delete("/api/clientes/2001")).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/clientes/")).await.1, json!([]));
+    let (_, todos) = llamar!(&app, get("/api/clientes/?incluir_borrados=true")).await;
+    assert_eq!(todos.as_array().map(Vec::len), Some(1));
+    assert_eq!(llamar!(&app, restaurar("/api/clientes/2001/restaurar")).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/clientes/")).await.1.as_array().map(Vec::len), Some(1));
+}
+
+#[actix_web::test]
+async fn usuarios_crud_y_login() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+
+    // solo el comando `create-admin` crea administradores
+    let cajero = json!({ "cedula": 1000, "email": "cajero@tienda.co", "nombre": "Cajero", "password": "caja", "usuario": "cajero", "admin": true });
+    assert_eq!(llamar!(&app, post("/api/usuarios/", cajero)).await.0, StatusCode::CREATED);
+    let (estado, leido) = llamar!(&app, get("/api/usuarios/1000").insert_header(autorizacion())).await;
+    assert_eq!((estado, &leido["usuario"], &leido["admin"]), (StatusCode::OK, &json!("cajero"), &json!(false)));
+    assert_eq!(llamar!(&app, get("/api/usuarios/1").insert_header(autorizacion())).await.1["admin"], true);
+
+    // los usuarios solo se leen autenticado, y nunca con su contraseña
+    assert_eq!(llamar!(&app, get("/api/usuarios/")).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(llamar!(&app, get("/api/usuarios/1")).await.0, StatusCode::UNAUTHORIZED);
+    let (_, usuarios) = llamar!(&app, get("/api/usuarios/").insert_header(autorizacion())).await;
+    assert_eq!(usuarios.as_array().map(Vec::len), Some(2));
+    assert!(usuarios.as_array().unwrap().iter().all(|u| u.get("password").is_none()));
+    assert!(leido.get("password").is_none());
+
+    let login = |usuario: &str, password: &str| {
+        TestRequest::post().uri("/api/usuarios/login").set_json(json!({ "usuario": usuario, "password": password }))
+    };
+    assert_eq!(llamar!(&app, login("cajero", "caja")).await, (StatusCode::OK, json!("success")));
+    assert_eq!(llamar!(&app, login("cajero", "otra")).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(llamar!(&app, login("nadie", "caja")).await.0, StatusCode::NOT_FOUND);
+
+    let cambios = json!({ "email": "cajero@tienda.co", "nombre": "Cajero", "password": "nueva", "usuario": "cajero" });
+    assert_eq!(llamar!(&app, patch("/api/usuarios/1000", cambios)).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, login("cajero", "caja")).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(llamar!(&app, login("cajero", "nueva")).await.0, StatusCode::OK);
+
+    // un usuario borrado no puede ingresar
+    assert_eq!(llamar!(&app, delete("/api/usuarios/1000")).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, login("cajero", "nueva")).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(llamar!(&app, restaurar("/api/usuarios/1000/restaurar")).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, login("cajero", "nueva")).await.0, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn solo_un_administrador_cambia_el_nivel_de_los_logs() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+    let cajero = json!({ "cedula": 1000, "email": "cajero@tienda.co", "nombre": "Cajero", "password": "caja", "usuario": "cajero" });
+    assert_eq!(llamar!(&app, post("/api/usuarios/", cajero)).await.0, StatusCode::CREATED);
+
+    // un usuario que no es administrador no puede leer la contraseña del administrador para cambiarlo
+    let (estado, admin) = llamar!(&app, get("/api/usuarios/1").insert_header(credenciales("cajero", "caja"))).await;
+    assert_eq!((estado, admin.get("password")), (StatusCode::OK, None));
+    let nivel = json!({ "filtro": "debug" });
+    let por_cajero = patch("/api/logs/nivel", nivel.clone()).insert_header(credenciales("cajero", "caja"));
+    assert_eq!(llamar!(&app, por_cajero).await.0, StatusCode::FORBIDDEN);
+    let sin_credenciales = TestRequest::patch().uri("/api/logs/nivel").set_json(nivel.clone());
+    assert_eq!(llamar!(&app, sin_credenciales).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(llamar!(&app, get("/api/logs/nivel")).await.0, StatusCode::UNAUTHORIZED);
+    // en las pruebas los logs no estan iniciados: el administrador pasa la autorizacion
+    assert_eq!(llamar!(&app, patch("/api/logs/nivel", nivel)).await.0, StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[actix_web::test]
+async fn proveedores_crud() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+
+    assert_eq!(llamar!(&app, post("/api/proveedores/", proveedor())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, get("/api/proveedores/1234")).await.1["ciudad"], "Bogota");
+    let cambios = json!({ "ciudad": "Medellin", "direccion": "Calle 1 # 2-3", "nombre": "Distribuidora", "telefono": "604 555 1234" });
+    assert_eq!(llamar!(&app, patch("/api/proveedores/1234", cambios)).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/proveedores/")).await.1[0]["ciudad"], "Medellin");
+
+    assert_eq!(llamar!(&app, post("/api/productos/json", producto())).await.0, StatusCode::CREATED);
+    let (_, informe) = llamar!(&app, delete("/api/proveedores/1234?dry_run=true")).await;
+    assert_eq!(informe["afectados"]["productos"], 1);
+    assert_eq!(llamar!(&app, delete("/api/proveedores/1234")).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/proveedores/")).await.1, json!([]));
+    assert_eq!(llamar!(&app, restaurar("/api/proveedores/1234/restaurar")).await.0, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn productos_crud_e_importacion_csv() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+    assert_eq!(llamar!(&app, post("/api/proveedores/", proveedor())).await.0, StatusCode::CREATED);
+
+    let csv = include_str!("../prod.csv");
+    let filas = csv.lines().skip(1).filter(|l| !l.trim().is_empty()).count();
+    let (estado, cuerpo) = llamar!(&app, formulario_csv("/api/productos/", csv)).await;
+    assert_eq!(estado, StatusCode::OK, "{cuerpo}");
+    assert_eq!(llamar!(&app, get("/api/productos/")).await.1.as_array().map(Vec::len), Some(filas));
+    let (_, pagina) = llamar!(&app, get("/api/proveedores/1234/productos?por_pagina=5")).await;
+    assert_eq!(pagina.as_array().map(Vec::len), Some(5));
+    let (estado, pagina) = llamar!(&app, get(&format!("/api/proveedores/1234/productos?pagina={}", i64::MAX))).await;
+    assert_eq!((estado, pagina), (StatusCode::OK, json!([])));
+
+    // importar el mismo archivo otra vez falla sin insertar nada
+    assert_ne!(llamar!(&app, formulario_csv("/api/productos/", csv)).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/productos/")).await.1.as_array().map(Vec::len), Some(filas));
+
+    assert_eq!(llamar!(&app, post("/api/productos/json", producto())).await.0, StatusCode::CREATED);
+    let mut cambios = producto();
+    cambios["precio_venta"] = json!(175.0);
+    assert_eq!(llamar!(&app, patch("/api/productos/77", cambios)).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/productos/77")).await.1["precio_venta"], 175.0);
+    assert_eq!(llamar!(&app, delete("/api/productos/77")).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, delete("/api/productos/77")).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(llamar!(&app, restaurar("/api/productos/77/restaurar")).await.0, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn ventas_y_detalle_ventas_crud() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+    assert_eq!(llamar!(&app, post("/api/proveedores/", proveedor())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/productos/json", producto())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/clientes/", cliente())).await.0, StatusCode::CREATED);
+
+    let venta = json!({ "codigo": 1, "cedula_cliente": 2001, "cedula_usuario": 1, "iva_venta": 28.5, "total_venta": 178.5, "valor_venta": 150.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/", venta)).await.0, StatusCode::CREATED);
+    let detalle = json!({
+        "codigo": 11, "codigo_producto": 77, "codigo_venta": 1, "cantidad_producto": 1,
+        "valor_total": 178.5, "valor_venta": 150.0, "valor_iva": 28.5,
+    });
+    assert_eq!(llamar!(&app, post("/api/detalle_ventas/", detalle)).await.0, StatusCode::CREATED);
+
+    let (estado, leida) = llamar!(&app, get("/api/ventas/1?expand=detalles,cliente")).await;
+    assert_eq!(estado, StatusCode::OK);
+    assert_eq!(leida["detalles"][0]["codigo"], 11);
+    assert_eq!(leida["cliente"]["cedula"], 2001);
+    assert_eq!(llamar!(&app, get("/api/clientes/2001/ventas")).await.1[0]["codigo"], 1);
+    assert_eq!(llamar!(&app, get("/api/usuarios/1/ventas")).await.1[0]["codigo"], 1);
+
+    let cambios = json!({ "iva_venta": 38.0, "total_venta": 238.0, "valor_venta": 200.0 });
+    assert_eq!(llamar!(&app, patch("/api/ventas/1", cambios)).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/ventas/")).await.1[0]["total_venta"], 238.0);
+    let cambios = json!({ "cantidad_producto": 2, "valor_total": 357.0, "valor_venta": 300.0, "valor_iva": 57.0 });
+    assert_eq!(llamar!(&app, patch("/api/detalle_ventas/11", cambios)).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/detalle_ventas/11")).await.1["cantidad_producto"], 2.0);
+    assert_eq!(llamar!(&app, get("/api/ventas/1/detalles")).await.1.as_array().map(Vec::len), Some(1));
+
+    // una venta con detalles no se puede borrar
+    assert_eq!(llamar!(&app, delete("/api/ventas/1")).await.0, StatusCode::CONFLICT);
+    assert_eq!(llamar!(&app, delete("/api/detalle_ventas/11")).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/detalle_ventas/")).await.1, json!([]));
+    assert_eq!(llamar!(&app, delete("/api/ventas/1")).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/ventas/1")).await.0, StatusCode::NOT_FOUND);
+
+    // cada operacion queda en la auditoria con el usuario que la realizo
+    let (_, registros) = llamar!(&app, get("/api/auditoria/?entidad=ventas").insert_header(autorizacion())).await;
+    let acciones: Vec<_> = registros.as_array().unwrap().iter().map(|r| r["accion"].clone()).collect();
+    assert_eq!(acciones, [json!("delete"), json!("update"), json!("create")]);
+    assert_eq!(registros[0]["cedula_usuario"], 1);
+}
+
+#[actix_web::test]
+async fn turnos_movimientos_y_cierre_de_caja() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+    assert_eq!(llamar!(&app, post("/api/proveedores/", proveedor())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/productos/json", producto())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/clientes/", cliente())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/cajas/", json!({ "codigo": 1, "nombre": "Caja 1" }))).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, patch("/api/cajas/1", json!({ "nombre": "Caja principal" }))).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/cajas/1")).await.1["nombre"], "Caja principal");
+
+    // una venta sin turno abierto no queda en ningun turno
+    let venta = json!({ "codigo": 1, "cedula_cliente": 2001, "cedula_usuario": 1, "iva_venta": 19.0, "total_venta": 119.0, "valor_venta": 100.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/", venta)).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, get("/api/ventas/1")).await.1["codigo_turno"], Value::Null);
+
+    let apertura = json!({ "codigo_caja": 1, "efectivo_inicial": 50000.0 });
+    let (estado, turno) = llamar!(&app, post("/api/turnos/", apertura.clone())).await;
+    assert_eq!(estado, StatusCode::CREATED);
+    assert_eq!(turno["cedula_usuario"], 1);
+    let codigo = turno["codigo"].as_i64().unwrap();
+    assert_eq!(llamar!(&app, post("/api/turnos/", apertura)).await.0, StatusCode::CONFLICT);
+    let (estado, actual) = llamar!(&app, get("/api/turnos/actual").insert_header(autorizacion())).await;
+    assert_eq!((estado, actual["codigo"].as_i64()), (StatusCode::OK, Some(codigo)));
+
+    let venta = json!({ "codigo": 2, "cedula_cliente": 2001, "cedula_usuario": 1, "iva_venta": 3800.0, "total_venta": 23800.0, "valor_venta": 20000.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/", venta)).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, get("/api/ventas/2")).await.1["codigo_turno"], codigo);
+    assert_eq!(llamar!(&app, post("/api/detalle_ventas/", detalle(21, 2, 23800.0))).await.0, StatusCode::CREATED);
+    let pago = json!({ "metodo": "efectivo", "valor": 23800.0, "recibido": 25000.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/2/pagos", pago)).await.1["cambio"], 1200.0);
+    assert_eq!(llamar!(&app, post("/api/ventas/2/finalizar", json!(null))).await.0, StatusCode::OK);
+    // una venta abierta y sus pagos no entran en el arqueo
+    let venta = json!({ "codigo": 3, "cedula_cliente": 2001, "cedula_usuario": 1, "iva_venta": 0.0, "total_venta": 1000.0, "valor_venta": 1000.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/", venta)).await.0, StatusCode::CREATED);
+    let pago = json!({ "metodo": "efectivo", "valor": 500.0, "recibido": 500.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/3/pagos", pago)).await.0, StatusCode::CREATED);
+    let uri = format!("/api/turnos/{codigo}/movimientos");
+    let retiro = json!({ "tipo": "salida", "valor": 30000.0, "motivo": "retiro parcial" });
+    assert_eq!(llamar!(&app, post(&uri, retiro)).await.0, StatusCode::CREATED);
+    let invalido = json!({ "tipo": "prestamo", "valor": 10.0, "motivo": "x" });
+    assert_eq!(llamar!(&app, post(&uri, invalido)).await.0, StatusCode::BAD_REQUEST);
+    assert_eq!(llamar!(&app, get(&uri)).await.1.as_array().map(Vec::len), Some(1));
+
+    let (estado, arqueo) = llamar!(&app, get(&format!("/api/turnos/{codigo}/arqueo"))).await;
+    assert_eq!(estado, StatusCode::OK);
+    assert_eq!((arqueo["esperado"].as_f64(), arqueo["contado"].clone()), (Some(43800.0), Value::Null));
+
+    // solo el usuario del turno lo puede cerrar
+    let cierre = format!("/api/turnos/{codigo}/cierre");
+    let cajero = json!({ "cedula": 3000, "email": "cajero@tienda.co", "nombre": "Cajero", "password": "caja", "usuario": "cajero" });
+    assert_eq!(llamar!(&app, post("/api/usuarios/", cajero)).await.0, StatusCode::CREATED);
+    let ajeno = post(&cierre, json!({ "efectivo_contado": 1.0 })).insert_header(credenciales("cajero", "caja"));
+    assert_eq!(llamar!(&app, ajeno).await.0, StatusCode::FORBIDDEN);
+    let (estado, arqueo) = llamar!(&app, post(&cierre, json!({ "efectivo_contado": 43000.0 }))).await;
+    assert_eq!(estado, StatusCode::OK);
+    assert_eq!(arqueo["ventas"], 1);
+    assert_eq!(arqueo["salidas"], 30000.0);
+    assert_eq!(arqueo["diferencia"], -800.0);
+    assert!(arqueo["turno"]["cerrado_at"].is_string());
+    assert_eq!(llamar!(&app, post(&cierre, json!({ "efectivo_contado": 1.0 }))).await.0, StatusCode::CONFLICT);
+    let retiro = json!({ "tipo": "salida", "valor": 1.0, "motivo": "tarde" });
+    assert_eq!(llamar!(&app, post(&uri, retiro)).await.0, StatusCode::CONFLICT);
+    assert_eq!(llamar!(&app, get("/api/turnos/actual").insert_header(autorizacion())).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(llamar!(&app, post("/api/turnos/99/cierre", json!({ "efectivo_contado": 1.0 }))).await.0, StatusCode::NOT_FOUND);
+
+    // ni en una sucursal que ya no es suya
+    let apertura = post("/api/turnos/", json!({ "codigo_caja": 1, "efectivo_inicial": 0.0 }));
+    let (estado, turno) = llamar!(&app, apertura.insert_header(credenciales("cajero", "caja"))).await;
+    assert_eq!(estado, StatusCode::CREATED);
+    let norte = json!({ "codigo": 2, "nombre": "Norte", "direccion": "Calle 170" });
+    assert_eq!(llamar!(&app, post("/api/sucursales/", norte)).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/usuarios/3000/sucursales", json!({ "codigo_sucursal": 2 }))).await.0, StatusCode::CREATED);
+    let cierre = post(&format!("/api/turnos/{}/cierre", turno["codigo"]), json!({ "efectivo_contado": 0.0 }));
+    assert_eq!(llamar!(&app, cierre.insert_header(credenciales("cajero", "caja"))).await.0, StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn pagos_divididos_y_finalizacion_de_ventas() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+    assert_eq!(llamar!(&app, post("/api/proveedores/", proveedor())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/productos/json", producto())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/clientes/", cliente())).await.0, StatusCode::CREATED);
+    let venta = json!({ "codigo": 1, "cedula_cliente": 2001, "cedula_usuario": 1, "iva_venta": 19000.0, "total_venta": 119000.0, "valor_venta": 100000.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/", venta)).await.0, StatusCode::CREATED);
+
+    let tarjeta = json!({ "metodo": "tarjeta", "valor": 100000.0, "referencia": "APROB-123" });
+    assert_eq!(llamar!(&app, post("/api/ventas/1/pagos", tarjeta)).await.0, StatusCode::CREATED);
+    // no se puede finalizar con un saldo pendiente
+    assert_eq!(llamar!(&app, post("/api/ventas/1/finalizar", json!(null))).await.0, StatusCode::CONFLICT);
+    let excedido = json!({ "metodo": "transferencia", "valor": 20000.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/1/pagos", excedido)).await.0, StatusCode::CONFLICT);
+    let sin_cambio = json!({ "metodo": "tarjeta", "valor": 19000.0, "recibido": 20000.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/1/pagos", sin_cambio)).await.0, StatusCode::BAD_REQUEST);
+    let invalido = json!({ "metodo": "cheque", "valor": 19000.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/1/pagos", invalido)).await.0, StatusCode::BAD_REQUEST);
+
+    let (estado, pago) = llamar!(&app, post("/api/ventas/1/pagos", json!({ "metodo": "efectivo", "valor": 19000.0, "recibido": 20000.0 }))).await;
+    assert_eq!((estado, pago["cambio"].as_f64()), (StatusCode::CREATED, Some(1000.0)));
+    let (_, pagos) = llamar!(&app, get("/api/ventas/1/pagos")).await;
+    assert_eq!((pagos["pagado"].as_f64(), pagos["pendiente"].as_f64()), (Some(119000.0), Some(0.0)));
+    assert_eq!(pagos["pagos"].as_array().map(Vec::len), Some(2));
+    // ni con un total que no suman sus detalles
+    let (estado, mensaje) = llamar!(&app, post("/api/ventas/1/finalizar", json!(null))).await;
+    assert_eq!((estado, mensaje), (StatusCode::CONFLICT, json!("detalle_ventas add up to 0 but total_venta is 119000")));
+    assert_eq!(llamar!(&app, post("/api/detalle_ventas/", detalle(11, 1, 100000.0))).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/ventas/1/finalizar", json!(null))).await.0, StatusCode::CONFLICT);
+    assert_eq!(llamar!(&app, patch("/api/detalle_ventas/11", detalle(11, 1, 119000.0))).await.0, StatusCode::OK);
+
+    let (estado, finalizada) = llamar!(&app, post("/api/ventas/1/finalizar", json!(null))).await;
+    assert_eq!(estado, StatusCode::OK);
+    assert!(finalizada["finalizada_at"].is_string());
+    assert_eq!(llamar!(&app, post("/api/ventas/1/finalizar", json!(null))).await.0, StatusCode::CONFLICT);
+    // una venta finalizada no se modifica ni cambian sus pagos
+    let cambios = json!({ "iva_venta": 0.0, "total_venta": 1.0, "valor_venta": 1.0 });
+    assert_eq!(llamar!(&app, patch("/api/ventas/1", cambios)).await.0, StatusCode::CONFLICT);
+    let codigo = pago["codigo"].as_i64().unwrap();
+    assert_eq!(llamar!(&app, delete(&format!("/api/ventas/1/pagos/{codigo}"))).await.0, StatusCode::CONFLICT);
+    assert_eq!(llamar!(&app, delete("/api/ventas/1/pagos/999")).await.0, StatusCode::NOT_FOUND);
+    let (_, informe) = llamar!(&app, delete("/api/ventas/1?dry_run")).await;
+    assert_eq!(informe["bloqueantes"]["pagos"], 2);
+}
+
+#[actix_web::test]
+async fn los_pagos_no_cambian_durante_la_finalizacion() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+    assert_eq!(llamar!(&app, post("/api/proveedores/", proveedor())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/productos/json", producto())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/clientes/", cliente())).await.0, StatusCode::CREATED);
+    for codigo in 1..=5 {
+        let venta = json!({ "codigo": codigo, "cedula_cliente": 2001, "cedula_usuario": 1, "iva_venta": 19.0, "total_venta": 119.0, "valor_venta": 100.0 });
+        assert_eq!(llamar!(&app, post("/api/ventas/", venta)).await.0, StatusCode::CREATED);
+        assert_eq!(llamar!(&app, post("/api/detalle_ventas/", detalle(codigo * 10, codigo, 119.0))).await.0, StatusCode::CREATED);
+        let pagos = format!("/api/ventas/{codigo}/pagos");
+        assert_eq!(llamar!(&app, post(&pagos, json!({ "metodo": "tarjeta", "valor": 100.0 }))).await.0, StatusCode::CREATED);
+        let (_, pago) = llamar!(&app, post(&pagos, json!({ "metodo": "tarjeta", "valor": 19.0 }))).await;
+
+        // la venta se finaliza con todos sus pagos, o el pago se borra y la venta queda con saldo pendiente
+        let finalizar = async { llamar!(&app, post(&format!("/api/ventas/{codigo}/finalizar"), json!(null))).await };
+        let borrar = async { llamar!(&app, delete(&format!("{pagos}/{}", pago["codigo"]))).await };
+        let ((finalizada, _), (borrado, _)) = futures_util::future::join(finalizar, borrar).await;
+        assert_ne!(finalizada == StatusCode::OK, borrado == StatusCode::OK, "finalizar: {finalizada}, borrar: {borrado}");
+        let (_, pagos) = llamar!(&app, get(&pagos)).await;
+        assert_eq!(pagos["pendiente"].as_f64(), Some(if finalizada == StatusCode::OK { 0.0 } else { 19.0 }));
+    }
+}
+
+#[actix_web::test]
+async fn promociones_y_calculo_de_ventas() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+    assert_eq!(llamar!(&app, post("/api/proveedores/", proveedor())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/productos/json", producto())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/clientes/", cliente())).await.0, StatusCode::CREATED);
+
+    let tres_por_dos = json!({ "codigo": 1, "nombre": "3x2 arroz", "tipo": "lleve_pague", "lleve": 3, "pague": 2, "codigo_producto": 77 });
+    assert_eq!(llamar!(&app, post("/api/promociones/", tres_por_dos)).await.0, StatusCode::CREATED);
+    let cliente = json!({ "codigo": 2, "nombre": "Ana", "tipo": "porcentaje", "valor": 5.0, "cedula_cliente": 2001 });
+    assert_eq!(llamar!(&app, post("/api/promociones/", cliente)).await.0, StatusCode::CREATED);
+    let sin_producto = json!({ "codigo": 3, "nombre": "x", "tipo": "valor", "valor": 10.0, "codigo_producto": 999 });
+    assert_eq!(llamar!(&app, post("/api/promociones/", sin_producto)).await.0, StatusCode::BAD_REQUEST);
+    let vencida = json!({ "codigo": 4, "nombre": "Vencida", "tipo": "porcentaje", "valor": 90.0, "hasta": "2020-01-31" });
+    assert_eq!(llamar!(&app, post("/api/promociones/", vencida)).await.0, StatusCode::CREATED);
+
+    // con 3 unidades el 3x2 descuenta mas que el 5% del cliente
+    let calculo = json!({ "cedula_cliente": 2001, "detalles": [{ "codigo_producto": 77, "cantidad_producto": 3 }] });
+    let (estado, venta) = llamar!(&app, post("/api/ventas/calcular", calculo)).await;
+    assert_eq!(estado, StatusCode::OK);
+    assert_eq!(venta["detalles"][0]["codigo_promocion"], 1);
+    assert_eq!((venta["descuento"].as_f64(), venta["valor_venta"].as_f64()), (Some(150.0), Some(300.0)));
+    assert_eq!(venta["total_venta"].as_f64(), Some(357.0));
+    let calculo = json!({ "cedula_cliente": 2001, "detalles": [{ "codigo_producto": 77, "cantidad_producto": 2 }] });
+    assert_eq!(llamar!(&app, post("/api/ventas/calcular", calculo)).await.1["detalles"][0]["codigo_promocion"], 2);
+    let calculo = json!({ "detalles": [{ "codigo_producto": 999, "cantidad_producto": 1 }] });
+    assert_eq!(llamar!(&app, post("/api/ventas/calcular", calculo)).await.0, StatusCode::BAD_REQUEST);
+
+    let venta = json!({ "codigo": 1, "cedula_cliente": 2001, "cedula_usuario": 1, "iva_venta": 57.0, "total_venta": 357.0, "valor_venta": 300.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/", venta)).await.0, StatusCode::CREATED);
+    let detalle = json!({
+        "codigo": 11, "codigo_producto": 77, "codigo_venta": 1, "cantidad_producto": 3,
+        "valor_total": 357.0, "valor_venta": 300.0, "valor_iva": 57.0, "codigo_promocion": 1, "descuento": 150.0,
+    });
+    // un descuento distinto al de la promocion, o sin promocion, se rechaza
+    let mut falso = detalle.clone();
+    falso["descuento"] = json!(300.0);
+    assert_eq!(llamar!(&app, post("/api/detalle_ventas/", falso.clone())).await.0, StatusCode::BAD_REQUEST);
+    falso["codigo_promocion"] = json!(null);
+    assert_eq!(llamar!(&app, post("/api/detalle_ventas/", falso)).await.0, StatusCode::BAD_REQUEST);
+    assert_eq!(llamar!(&app, post("/api/detalle_ventas/", detalle)).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, get("/api/detalle_ventas/11")).await.1["descuento"], 150.0);
+    let cambios = json!({ "cantidad_producto": 3, "valor_total": 0.0, "valor_venta": 0.0, "valor_iva": 0.0, "codigo_promocion": 1, "descuento": 450.0 });
+    assert_eq!(llamar!(&app, patch("/api/detalle_ventas/11", cambios)).await.0, StatusCode::BAD_REQUEST);
+
+    let (_, informe) = llamar!(&app, delete("/api/promociones/1?dry_run")).await;
+    assert_eq!(informe["afectados"]["detalle_ventas"], 1);
+    assert_eq!(llamar!(&app, delete("/api/promociones/1")).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/promociones/")).await.1.as_array().map(Vec::len), Some(2));
+    assert_eq!(llamar!(&app, restaurar("/api/promociones/1/restaurar")).await.0, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn puntos_de_fidelidad_acumulados_y_redimidos() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+    assert_eq!(llamar!(&app, post("/api/proveedores/", proveedor())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/productos/json", producto())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/clientes/", cliente())).await.0, StatusCode::CREATED);
+    let venta = json!({ "codigo": 1, "cedula_cliente": 2001, "cedula_usuario": 1, "iva_venta": 19000.0, "total_venta": 119000.0, "valor_venta": 100000.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/", venta)).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/detalle_ventas/", detalle(11, 1, 119000.0))).await.0, StatusCode::CREATED);
+    let tarjeta = json!({ "metodo": "tarjeta", "valor": 119000.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/1/pagos", tarjeta)).await.0, StatusCode::CREATED);
+    // sin finalizar la venta no hay puntos
+    assert_eq!(llamar!(&app, get("/api/clientes/2001/puntos")).await.1["saldo"], 0);
+    assert_eq!(llamar!(&app, post("/api/ventas/1/finalizar", json!(null))).await.0, StatusCode::OK);
+
+    // con la configuracion predeterminada, un punto por cada 1000 pesos
+    let (estado, puntos) = llamar!(&app, get("/api/clientes/2001/puntos")).await;
+    assert_eq!(estado, StatusCode::OK);
+    assert_eq!((puntos["saldo"].as_i64(), puntos["valor_saldo"].as_f64()), (Some(119), Some(1190.0)));
+    assert_eq!(puntos["historial"][0]["tipo"], "acumulacion");
+    assert_eq!(puntos["proximo_vencimiento"]["puntos"], 119);
+    // una venta finalizada no se borra, y sus puntos tambien lo impedirian
+    let (_, informe) = llamar!(&app, delete("/api/ventas/1?dry_run")).await;
+    assert_eq!(informe["bloqueantes"]["movimientos_puntos"], 1);
+    assert_eq!(llamar!(&app, delete("/api/ventas/1")).await.0, StatusCode::CONFLICT);
+
+    let venta = json!({ "codigo": 2, "cedula_cliente": 2001, "cedula_usuario": 1, "iva_venta": 0.0, "total_venta": 3000.0, "valor_venta": 3000.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/", venta)).await.0, StatusCode::CREATED);
+    let excedido = json!({ "metodo": "puntos", "valor": 2000.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/2/pagos", excedido)).await.0, StatusCode::CONFLICT);
+    // la redencion rechazada no deja el pago
+    assert_eq!(llamar!(&app, get("/api/ventas/2/pagos")).await.1["pagos"], json!([]));
+    let (estado, pago) = llamar!(&app, post("/api/ventas/2/pagos", json!({ "metodo": "puntos", "valor": 500.0 }))).await;
+    assert_eq!(estado, StatusCode::CREATED);
+    let (_, puntos) = llamar!(&app, get("/api/clientes/2001/puntos")).await;
+    assert_eq!(puntos["saldo"], 69);
+    assert_eq!(puntos["historial"][1]["puntos"], -50);
+
+    // borrar el pago devuelve los puntos
+    let codigo = pago["codigo"].as_i64().unwrap();
+    assert_eq!(llamar!(&app, delete(&format!("/api/ventas/2/pagos/{codigo}"))).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/clientes/2001/puntos")).await.1["saldo"], 119);
+    assert_eq!(llamar!(&app, get("/api/clientes/999/puntos")).await.0, StatusCode::NOT_FOUND);
+
+    // dos redenciones a la vez en ventas distintas no gastan los mismos puntos
+    let venta = json!({ "codigo": 3, "cedula_cliente": 2001, "cedula_usuario": 1, "iva_venta": 0.0, "total_venta": 3000.0, "valor_venta": 3000.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/", venta)).await.0, StatusCode::CREATED);
+    let redencion = json!({ "metodo": "puntos", "valor": 1000.0 });
+    let segunda = async { llamar!(&app, post("/api/ventas/2/pagos", redencion.clone())).await };
+    let tercera = async { llamar!(&app, post("/api/ventas/3/pagos", redencion.clone())).await };
+    let ((segunda, _), (tercera, _)) = futures_util::future::join(segunda, tercera).await;
+    let mut estados = [segunda, tercera];
+    estados.sort();
+    assert_eq!(estados, [StatusCode::CREATED, StatusCode::CONFLICT]);
+    assert_eq!(llamar!(&app, get("/api/clientes/2001/puntos")).await.1["saldo"], 19);
+}
+
+#[actix_web::test]
+async fn historial_y_cambios_de_precio_programados() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+    assert_eq!(llamar!(&app, post("/api/proveedores/", proveedor())).await.0, StatusCode::CREATED);
+    let csv = "codigo,nit_proveedor,iva_compra,nombre_producto,precio_compra,precio_venta\n5,1234,19,Sal,50,80\n";
+    assert_eq!(llamar!(&app, formulario_csv("/api/productos/", csv)).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/productos/5/precios")).await.1["historial"][0]["origen"], "importacion");
+
+    assert_eq!(llamar!(&app, post("/api/productos/json", producto())).await.0, StatusCode::CREATED);
+    let mut cambios = producto();
+    cambios["precio_venta"] = json!(175.0);
+    assert_eq!(llamar!(&app, patch("/api/productos/77", cambios.clone())).await.0, StatusCode::OK);
+    // un cambio que no toca los precios no queda en el historial
+    cambios["nombre_producto"] = json!("Arroz blanco");
+    assert_eq!(llamar!(&app, patch("/api/productos/77", cambios)).await.0, StatusCode::OK);
+    let (_, precios) = llamar!(&app, get("/api/productos/77/precios")).await;
+    let origenes: Vec<_> = precios["historial"].as_array().unwrap().iter().map(|h| h["origen"].clone()).collect();
+    assert_eq!(origenes, [json!("creacion"), json!("actualizacion")]);
+    assert_eq!(precios["historial"][1]["precio_venta"], 175.0);
+    assert_eq!(precios["historial"][1]["cedula_usuario"], 1);
+
+    let pasado = json!({ "precio_venta": 200.0, "fecha": "2020-01-01T00:00:00Z" });
+    assert_eq!(llamar!(&app, post("/api/productos/77/precios/programados", pasado)).await.0, StatusCode::BAD_REQUEST);
+    let sin_precios = json!({ "fecha": "2999-01-01T00:00:00Z" });
+    assert_eq!(llamar!(&app, post("/api/productos/77/precios/programados", sin_precios)).await.0, StatusCode::BAD_REQUEST);
+    let lejano = json!({ "precio_compra": 90.0, "fecha": "2999-01-01T00:00:00Z" });
+    let (estado, lejano) = llamar!(&app, post("/api/productos/77/precios/programados", lejano)).await;
+    assert_eq!(estado, StatusCode::CREATED);
+    let pronto = (chrono::Utc::now() + chrono::Duration::milliseconds(500)).to_rfc3339();
+    let programado = json!({ "precio_venta": 200.0, "fecha": pronto });
+    assert_eq!(llamar!(&app, post("/api/productos/77/precios/programados", programado.clone())).await.0, StatusCode::CREATED);
+    // el de un producto borrado no se aplica mientras siga borrado
+    assert_eq!(llamar!(&app, post("/api/productos/5/precios/programados", programado)).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, delete("/api/productos/5")).await.0, StatusCode::OK);
+    let sin_producto = json!({ "precio_venta": 1.0, "fecha": "2999-01-01T00:00:00Z" });
+    assert_eq!(llamar!(&app, post("/api/productos/999/precios/programados", sin_producto)).await.0, StatusCode::NOT_FOUND);
+    let cancelar = format!("/api/productos/77/precios/programados/{}", lejano["codigo"]);
+    assert_eq!(llamar!(&app, delete(&cancelar)).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/productos/77/precios")).await.1["programados"].as_array().map(Vec::len), Some(1));
+
+    // el cambio se aplica cuando llega su fecha
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(600)).await;
+    tareas::ejecutar(&db).await;
+    let (_, precios) = llamar!(&app, get("/api/productos/77/precios")).await;
+    assert_eq!((precios["precio_venta"].as_f64(), precios["precio_compra"].as_f64()), (Some(200.0), Some(100.0)));
+    assert_eq!(precios["historial"][2]["origen"], "programado");
+    assert_eq!(precios["programados"], json!([]));
+    assert_eq!(llamar!(&app, restaurar("/api/productos/5/restaurar")).await.0, StatusCode::OK);
+    let (_, precios) = llamar!(&app, get("/api/productos/5/precios")).await;
+    assert_eq!(precios["precio_venta"].as_f64(), Some(80.0));
+    assert_eq!(precios["programados"].as_array().map(Vec::len), Some(1));
+    tareas::ejecutar(&db).await;
+    assert_eq!(llamar!(&app, get("/api/productos/5/precios")).await.1["precio_venta"].as_f64(), Some(200.0));
+
+    // si el historial no se puede registrar el cambio se revierte y queda pendiente
+    let pronto = (chrono::Utc::now() + chrono::Duration::milliseconds(200)).to_rfc3339();
+    let programado = json!({ "precio_venta": 250.0, "fecha": pronto });
+    assert_eq!(llamar!(&app, post("/api/productos/77/precios/programados", programado)).await.0, StatusCode::CREATED);
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(300)).await;
+    ejecutar_sql(&db, "alter table precios_historial rename to precios_historial_movido;").await;
+    tareas::ejecutar(&db).await;
+    ejecutar_sql(&db, "alter table precios_historial_movido rename to precios_historial;").await;
+    let (_, precios) = llamar!(&app, get("/api/productos/77/precios")).await;
+    assert_eq!((precios["precio_venta"].as_f64(), precios["programados"].as_array().map(Vec::len)), (Some(200.0), Some(1)));
+    tareas::ejecutar(&db).await;
+    let (_, precios) = llamar!(&app, get("/api/productos/77/precios")).await;
+    assert_eq!((precios["precio_venta"].as_f64(), precios["programados"].as_array().map(Vec::len)), (Some(250.0), Some(0)));
+}
+
+#[actix_web::test]
+async fn codigos_de_barras_y_etiquetas() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+    assert_eq!(llamar!(&app, post("/api/proveedores/", proveedor())).await.0, StatusCode::CREATED);
+    // los codigos de barras se importan con el CSV (un UPC-A se guarda con un 0 adelante)
+    let csv = "codigo,nit_proveedor,iva_compra,nombre_producto,precio_compra,precio_venta,codigo_barras\n\
+               5,1234,19,Sal,50,80,4006381333931|036000291452\n6,1234,19,Azucar,60,90,\n";
+    assert_eq!(llamar!(&app, formulario_csv("/api/productos/", csv)).await.0, StatusCode::OK);
+    let (_, codigos) = llamar!(&app, get("/api/productos/5/codigos_barras")).await;
+    let codigos: Vec<_> = codigos.as_array().unwrap().iter().map(|c| c["codigo_barras"].clone()).collect();
+    assert_eq!(codigos, [json!("4006381333931"), json!("0036000291452")]);
+    let (estado, producto) = llamar!(&app, get("/api/productos/barcode/036000291452")).await;
+    assert_eq!((estado, &producto["codigo"]), (StatusCode::OK, &json!(5)));
+
+    // un digito de control invalido se rechaza, en el CSV y al asignarlo
+    let invalido = "codigo,nit_proveedor,iva_compra,nombre_producto,precio_compra,precio_venta,codigo_barras\n\
+                    7,1234,19,Te,50,80,4006381333932\n";
+    let (estado, cuerpo) = llamar!(&app, formulario_csv("/api/productos/", invalido)).await;
+    assert_eq!((estado, &cuerpo["errores"]["codigo_barras"][0]), (StatusCode::BAD_REQUEST, &json!("invalid check digit")));
+    let (estado, cuerpo) = llamar!(&app, post("/api/productos/6/codigos_barras", json!({ "codigo_barras": "5901234123458" }))).await;
+    assert_eq!((estado, &cuerpo["codigo_barras"][0]), (StatusCode::BAD_REQUEST, &json!("invalid check digit")));
+    assert_eq!(llamar!(&app, get("/api/productos/barcode/12345")).await.0, StatusCode::BAD_REQUEST);
+
+    let (estado, creado) = llamar!(&app, post("/api/productos/6/codigos_barras", json!({ "codigo_barras": "5901234123457" }))).await;
+    assert_eq!((estado, &creado["codigo_producto"]), (StatusCode::CREATED, &json!(6)));
+    let repetido = json!({ "codigo_barras": "4006381333931" });
+    assert_eq!(llamar!(&app, post("/api/productos/6/codigos_barras", repetido)).await.0, StatusCode::CONFLICT);
+    let otro = json!({ "codigo_barras": "9780201379624" });
+    assert_eq!(llamar!(&app, post("/api/productos/999/codigos_barras", otro)).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(llamar!(&app, delete("/api/productos/6/codigos_barras/5901234123457")).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/productos/barcode/5901234123457")).await.0, StatusCode::NOT_FOUND);
+
+    // un producto sin codigo de barras tiene uno interno, con el que se busca y se imprime su etiqueta
+    assert_eq!(llamar!(&app, get("/api/productos/barcode/2000000000060")).await.1["codigo"], 6);
+    let (estado, svg) = llamar!(&app, get("/api/productos/6/etiqueta")).await;
+    let svg = svg.as_str().unwrap();
+    assert_eq!(estado, StatusCode::OK);
+    assert!(svg.starts_with("<svg") && svg.contains("Azucar") && svg.contains(">6</text>"));
+    let respuesta = test::call_service(&app, get("/api/productos/5/etiqueta?formato=png").to_request()).await;
+    assert_eq!(respuesta.headers().get(header::CONTENT_TYPE).unwrap(), "image/png");
+    assert!(test::read_body(respuesta).await.starts_with(b"\x89PNG"));
+    assert_eq!(llamar!(&app, get("/api/productos/5/etiqueta?formato=gif")).await.0, StatusCode::BAD_REQUEST);
+
+    // los productos borrados no se encuentran por su codigo de barras
+    assert_eq!(llamar!(&app, delete("/api/productos/5")).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/productos/barcode/4006381333931")).await.0, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn categorias_y_unidades_de_medida() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+    assert_eq!(llamar!(&app, post("/api/proveedores/", proveedor())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/clientes/", cliente())).await.0, StatusCode::CREATED);
+
+    // alimentos > granos, y aseo por aparte
+    for categoria in [
+        json!({ "codigo": 1, "nombre": "Alimentos" }),
+        json!({ "codigo": 2, "nombre": "Granos", "codigo_padre": 1 }),
+        json!({ "codigo": 3, "nombre": "Aseo" }),
+    ] {
+        assert_eq!(llamar!(&app, post("/api/categorias/", categoria)).await.0, StatusCode::CREATED);
+    }
+    let sin_padre = json!({ "codigo": 4, "nombre": "Otros", "codigo_padre": 99 });
+    assert_eq!(llamar!(&app, post("/api/categorias/", sin_padre)).await.0, StatusCode::BAD_REQUEST);
+    let ciclo = json!({ "nombre": "Alimentos", "codigo_padre": 2 });
+    assert_eq!(llamar!(&app, patch("/api/categorias/1", ciclo)).await.0, StatusCode::BAD_REQUEST);
+
+    let arroz = json!({
+        "codigo": 77, "nit_proveedor": 1234, "iva_compra": 19.0, "nombre_producto": "Arroz a granel",
+        "precio_compra": 100.0, "precio_venta": 150.0, "codigo_categoria": 2, "unidad_medida": "kg",
+    });
+    assert_eq!(llamar!(&app, post("/api/productos/json", arroz)).await.0, StatusCode::CREATED);
+    let jabon = json!({
+        "codigo": 78, "nit_proveedor": 1234, "iva_compra": 19.0, "nombre_producto": "Jabon",
+        "precio_compra": 10.0, "precio_venta": 20.0, "codigo_categoria": 3,
+    });
+    assert_eq!(llamar!(&app, post("/api/productos/json", jabon)).await.0, StatusCode::CREATED);
+    let onzas = json!({
+        "codigo": 79, "nit_proveedor": 1234, "iva_compra": 19.0, "nombre_producto": "Queso",
+        "precio_compra": 10.0, "precio_venta": 20.0, "unidad_medida": "onza",
+    });
+    assert_eq!(llamar!(&app, post("/api/productos/json", onzas)).await.0, StatusCode::BAD_REQUEST);
+    assert_eq!(llamar!(&app, get("/api/productos/78")).await.1["unidad_medida"], "unidad");
+
+    // filtrar por una categoria incluye sus subcategorias
+    let (_, productos) = llamar!(&app, get("/api/productos/?categoria=1")).await;
+    let codigos: Vec<_> = productos.as_array().unwrap().iter().map(|p| p["codigo"].clone()).collect();
+    assert_eq!(codigos, [json!(77)]);
+
+    // los productos por peso aceptan cantidades fraccionarias, los por unidad no
+    let calculo = json!({ "detalles": [{ "codigo_producto": 77, "cantidad_producto": 1.5 }] });
+    let (estado, venta) = llamar!(&app, post("/api/ventas/calcular", calculo)).await;
+    assert_eq!((estado, venta["valor_venta"].as_f64()), (StatusCode::OK, Some(225.0)));
+    let calculo = json!({ "detalles": [{ "codigo_producto": 78, "cantidad_producto": 0.5 }] });
+    assert_eq!(llamar!(&app, post("/api/ventas/calcular", calculo)).await.0, StatusCode::BAD_REQUEST);
+
+    let venta = json!({ "codigo": 1, "cedula_cliente": 2001, "cedula_usuario": 1, "iva_venta": 50.35, "total_venta": 315.35, "valor_venta": 265.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/", venta)).await.0, StatusCode::CREATED);
+    let detalle = json!({
+        "codigo": 11, "codigo_producto": 77, "codigo_venta": 1, "cantidad_producto": 1.5,
+        "valor_total": 267.75, "valor_venta": 225.0, "valor_iva": 42.75,
+    });
+    assert_eq!(llamar!(&app, post("/api/detalle_ventas/", detalle)).await.0, StatusCode::CREATED);
+    let detalle = json!({
+        "codigo": 12, "codigo_producto": 78, "codigo_venta": 1, "cantidad_producto": 2.5,
+        "valor_total": 59.5, "valor_venta": 50.0, "valor_iva": 9.5,
+    });
+    assert_eq!(llamar!(&app, post("/api/detalle_ventas/", detalle)).await.0, StatusCode::BAD_REQUEST);
+    let detalle = json!({
+        "codigo": 12, "codigo_producto": 78, "codigo_venta": 1, "cantidad_producto": 2,
+        "valor_total": 47.6, "valor_venta": 40.0, "valor_iva": 7.6,
+    });
+    assert_eq!(llamar!(&app, post("/api/detalle_ventas/", detalle)).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, get("/api/detalle_ventas/11")).await.1["cantidad_producto"], 1.5);
+    let (_, detalles) = llamar!(&app, get("/api/detalle_ventas/?categoria=3")).await;
+    assert_eq!((detalles.as_array().map(Vec::len), &detalles[0]["codigo"]), (Some(1), &json!(12)));
+
+    // el informe agrupa las ventas por la categoria de cada producto
+    let (estado, informe) = llamar!(&app, get("/api/ventas/por_categoria")).await;
+    assert_eq!(estado, StatusCode::OK);
+    let filas: Vec<_> = informe.as_array().unwrap().iter().map(|f| (f["codigo_categoria"].clone(), f["cantidad"].clone())).collect();
+    assert_eq!(filas, [(json!(2), json!(1.5)), (json!(3), json!(2.0))]);
+    assert_eq!((informe[0]["nombre"].as_str(), informe[0]["ventas"].as_i64()), (Some("Granos"), Some(1)));
+    let (_, informe) = llamar!(&app, get("/api/ventas/por_categoria?categoria=1")).await;
+    assert_eq!(informe.as_array().map(Vec::len), Some(1));
+
+    let (_, borrado) = llamar!(&app, delete("/api/categorias/1?dry_run")).await;
+    assert_eq!(borrado["afectados"]["categorias"], 1);
+    assert_eq!(llamar!(&app, delete("/api/categorias/3")).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/categorias/")).await.1.as_array().map(Vec::len), Some(2));
+    assert_eq!(llamar!(&app, restaurar("/api/categorias/3/restaurar")).await.0, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn alertas_de_inventario_y_ordenes_de_compra() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+    assert_eq!(llamar!(&app, post("/api/proveedores/", proveedor())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/clientes/", cliente())).await.0, StatusCode::CREATED);
+    let mut arroz = producto();
+    arroz["stock_minimo"] = json!(5.0);
+    arroz["stock_maximo"] = json!(20.0);
+    assert_eq!(llamar!(&app, post("/api/productos/json", arroz.clone())).await.0, StatusCode::CREATED);
+    let mut invertido = arroz.clone();
+    invertido["stock_maximo"] = json!(2.0);
+    assert_eq!(llamar!(&app, patch("/api/productos/77", invertido)).await.0, StatusCode::BAD_REQUEST);
+
+    // sin existencias el producto esta bajo su minimo y se sugiere comprar hasta el maximo;
+    // lo de una venta abierta no cuenta como vendido
+    let abierta = json!({ "codigo": 2, "cedula_cliente": 2001, "cedula_usuario": 1, "iva_venta": 0.0, "total_venta": 600.0, "valor_venta": 600.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/", abierta)).await.0, StatusCode::CREATED);
+    let detalle = json!({
+        "codigo": 21, "codigo_producto": 77, "codigo_venta": 2, "cantidad_producto": 4,
+        "valor_total": 600.0, "valor_venta": 600.0, "valor_iva": 0.0,
+    });
+    assert_eq!(llamar!(&app, post("/api/detalle_ventas/", detalle)).await.0, StatusCode::CREATED);
+    let (_, alertas) = llamar!(&app, get("/api/inventario/alertas")).await;
+    assert_eq!((alertas.as_array().map(Vec::len), &alertas[0]["cantidad"]), (Some(1), &json!(0.0)));
+    let (_, sugerencias) = llamar!(&app, get("/api/inventario/sugerencias")).await;
+    assert_eq!(sugerencias[0]["nit_proveedor"], 1234);
+    assert_eq!(sugerencias[0]["productos"][0]["cantidad"], 20.0);
+    assert_eq!(sugerencias[0]["productos"][0]["vendido"], 0.0);
+    let (estado, _) = llamar!(&app, post("/api/ordenes_compra/sugerida", json!({ "nit_proveedor": 999 }))).await;
+    assert_eq!(estado, StatusCode::CONFLICT);
+    let (estado, orden) = llamar!(&app, post("/api/ordenes_compra/sugerida", json!({ "nit_proveedor": 1234 }))).await;
+    assert_eq!((estado, &orden["estado"]), (StatusCode::CREATED, &json!("pendiente")));
+    assert_eq!((orden["detalles"][0]["cantidad"].as_f64(), orden["valor_total"].as_f64()), (Some(20.0), Some(2000.0)));
+    let sin_detalles = json!({ "nit_proveedor": 1234, "detalles": [] });
+    assert_eq!(llamar!(&app, post("/api/ordenes_compra/", sin_detalles)).await.0, StatusCode::BAD_REQUEST);
+
+    // al recibir la orden sus productos entran al inventario
+    let codigo = orden["codigo"].as_i64().unwrap();
+    let (estado, recibida) = llamar!(&app, post(&format!("/api/ordenes_compra/{codigo}/recibir"), json!(null))).await;
+    assert_eq!((estado, &recibida["estado"]), (StatusCode::OK, &json!("recibida")));
+    assert_eq!(llamar!(&app, post(&format!("/api/ordenes_compra/{codigo}/recibir"), json!(null))).await.0, StatusCode::CONFLICT);
+    assert_eq!(llamar!(&app, post(&format!("/api/ordenes_compra/{codigo}/cancelar"), json!(null))).await.0, StatusCode::CONFLICT);
+    assert_eq!(llamar!(&app, get("/api/inventario/")).await.1[0]["cantidad"], 20.0);
+    assert_eq!(llamar!(&app, get("/api/inventario/alertas")).await.1, json!([]));
+    assert_eq!(llamar!(&app, get("/api/ordenes_compra/?estado=pendiente")).await.1, json!([]));
+
+    // las ventas finalizadas descuentan del inventario
+    let venta = json!({ "codigo": 1, "cedula_cliente": 2001, "cedula_usuario": 1, "iva_venta": 85.5, "total_venta": 535.5, "valor_venta": 450.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/", venta)).await.0, StatusCode::CREATED);
+    let detalle = json!({
+        "codigo": 11, "codigo_producto": 77, "codigo_venta": 1, "cantidad_producto": 3,
+        "valor_total": 535.5, "valor_venta": 450.0, "valor_iva": 85.5,
+    });
+    assert_eq!(llamar!(&app, post("/api/detalle_ventas/", detalle)).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, get("/api/inventario/")).await.1[0]["cantidad"], 20.0);
+    let pago = json!({ "metodo": "tarjeta", "valor": 535.5 });
+    assert_eq!(llamar!(&app, post("/api/ventas/1/pagos", pago)).await.0, StatusCode::CREATED);
+    // si no se pueden descontar sus productos la venta no se finaliza
+    ejecutar_sql(&db, "alter table movimientos_inventario rename to movimientos_inventario_movido;").await;
+    let finalizar = llamar!(&app, post("/api/ventas/1/finalizar", json!(null))).await.0;
+    ejecutar_sql(&db, "alter table movimientos_inventario_movido rename to movimientos_inventario;").await;
+    assert_eq!(finalizar, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(llamar!(&app, get("/api/ventas/1")).await.1["finalizada_at"], Value::Null);
+    assert_eq!(llamar!(&app, post("/api/ventas/1/finalizar", json!(null))).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/inventario/")).await.1[0]["cantidad"], 17.0);
+    let (_, movimientos) = llamar!(&app, get("/api/inventario/77/movimientos")).await;
+    let tipos: Vec<_> = movimientos.as_array().unwrap().iter().map(|m| (m["tipo"].clone(), m["cantidad"].clone())).collect();
+    assert_eq!(tipos, [(json!("compra"), json!(20.0)), (json!("venta"), json!(-3.0))]);
+    assert_eq!(llamar!(&app, get("/api/inventario/999/movimientos")).await.0, StatusCode::NOT_FOUND);
+
+    // los detalles de una venta finalizada ya no cambian
+    let otro = json!({
+        "codigo": 12, "codigo_producto": 77, "codigo_venta": 1, "cantidad_producto": 1,
+        "valor_total": 178.5, "valor_venta": 150.0, "valor_iva": 28.5,
+    });
+    assert_eq!(llamar!(&app, post("/api/detalle_ventas/", otro)).await.0, StatusCode::CONFLICT);
+    let cambios = json!({ "cantidad_producto": 1, "valor_total": 178.5, "valor_venta": 150.0, "valor_iva": 28.5 });
+    assert_eq!(llamar!(&app, patch("/api/detalle_ventas/11", cambios)).await.0, StatusCode::CONFLICT);
+    assert_eq!(llamar!(&app, delete("/api/detalle_ventas/11")).await.0, StatusCode::CONFLICT);
+    assert_eq!(llamar!(&app, get("/api/detalle_ventas/11")).await.1["cantidad_producto"], 3.0);
+    let (_, informe) = llamar!(&app, delete("/api/ventas/1?dry_run")).await;
+    assert_eq!(informe["bloqueantes"]["movimientos_inventario"], 1);
+}
+
+#[actix_web::test]
+async fn conteos_fisicos_y_ajustes() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+    assert_eq!(llamar!(&app, post("/api/proveedores/", proveedor())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/productos/json", producto())).await.0, StatusCode::CREATED);
+    let mut azucar = producto();
+    azucar["codigo"] = json!(78);
+    azucar["nombre_producto"] = json!("Azucar");
+    azucar["unidad_medida"] = json!("kg");
+    assert_eq!(llamar!(&app, post("/api/productos/json", azucar)).await.0, StatusCode::CREATED);
+    let orden = json!({ "nit_proveedor": 1234, "detalles": [{ "codigo_producto": 77, "cantidad": 10, "precio_compra": 100.0 }] });
+    let (_, orden) = llamar!(&app, post("/api/ordenes_compra/", orden)).await;
+    let recibir = format!("/api/ordenes_compra/{}/recibir", orden["codigo"]);
+    assert_eq!(llamar!(&app, post(&recibir, json!(null))).await.0, StatusCode::OK);
+
+    let (estado, conteo) = llamar!(&app, post("/api/conteos/", json!({ "descripcion": "Cierre de mes" }))).await;
+    assert_eq!((estado, &conteo["estado"]), (StatusCode::CREATED, &json!("abierto")));
+    let codigo = conteo["codigo"].as_i64().unwrap();
+    let cantidades = format!("/api/conteos/{codigo}/cantidades");
+    assert_eq!(llamar!(&app, post(&cantidades, json!([{ "codigo_producto": 77, "cantidad": 8 }]))).await.0, StatusCode::OK);
+    // los productos por unidad se cuentan en cantidades enteras, y el producto debe existir
+    let fraccion = json!([{ "codigo_producto": 77, "cantidad": 2.5 }]);
+    assert_eq!(llamar!(&app, post(&cantidades, fraccion)).await.0, StatusCode::BAD_REQUEST);
+    let inexistente = json!([{ "codigo_producto": 999, "cantidad": 1 }]);
+    assert_eq!(llamar!(&app, post(&cantidades, inexistente)).await.0, StatusCode::BAD_REQUEST);
+
+    // el CSV reemplaza la cantidad ya contada
+    let csv = "codigo_producto,cantidad,motivo\n78,1.25,merma devuelta\n77,7,\n";
+    let (estado, conteo) = llamar!(&app, formulario_csv(&format!("/api/conteos/{codigo}/csv"), csv)).await;
+    assert_eq!(estado, StatusCode::OK);
+    let contados: Vec<_> =
+        conteo["detalles"].as_array().unwrap().iter().map(|d| (d["codigo_producto"].clone(), d["cantidad"].clone())).collect();
+    assert_eq!(contados, [(json!(77), json!(7.0)), (json!(78), json!(1.25))]);
+    let (_, diferencias) = llamar!(&app, get(&format!("/api/conteos/{codigo}/diferencias"))).await;
+    let arroz = (&diferencias[0]["sistema"], &diferencias[0]["diferencia"], &diferencias[0]["valor"]);
+    assert_eq!(arroz, (&json!(10.0), &json!(-3.0), &json!(-300.0)));
+    assert_eq!(diferencias[1]["diferencia"], 1.25);
+
+    // al aplicar el conteo las diferencias quedan como ajustes con su motivo
+    let aplicar = format!("/api/conteos/{codigo}/aplicar");
+    assert_eq!(llamar!(&app, post(&aplicar, json!({ "motivo": "" }))).await.0, StatusCode::BAD_REQUEST);
+    let (estado, aplicado) = llamar!(&app, post(&aplicar, json!({ "motivo": "Conteo de cierre" }))).await;
+    assert_eq!((estado, &aplicado["ajustes"]), (StatusCode::OK, &json!(2)));
+    let (_, existencias) = llamar!(&app, get("/api/inventario/")).await;
+    assert_eq!((&existencias[0]["cantidad"], &existencias[1]["cantidad"]), (&json!(7.0), &json!(1.25)));
+    let (_, movimientos) = llamar!(&app, get("/api/inventario/77/movimientos")).await;
+    assert_eq!((&movimientos[1]["tipo"], &movimientos[1]["cantidad"]), (&json!("ajuste"), &json!(-3.0)));
+    assert_eq!((&movimientos[1]["motivo"], &movimientos[1]["codigo_conteo"]), (&json!("Conteo de cierre"), &json!(codigo)));
+    assert_eq!(llamar!(&app, get("/api/inventario/78/movimientos")).await.1[0]["motivo"], "merma devuelta");
+
+    // un conteo aplicado ya no se modifica
+    assert_eq!(llamar!(&app, post(&aplicar, json!({ "motivo": "otra vez" }))).await.0, StatusCode::CONFLICT);
+    assert_eq!(llamar!(&app, post(&cantidades, json!([{ "codigo_producto": 77, "cantidad": 1 }]))).await.0, StatusCode::CONFLICT);
+    assert_eq!(llamar!(&app, post(&format!("/api/conteos/{codigo}/cancelar"), json!(null))).await.0, StatusCode::CONFLICT);
+    assert_eq!(llamar!(&app, get("/api/conteos/?estado=abierto")).await.1, json!([]));
+    assert_eq!(llamar!(&app, get("/api/conteos/999")).await.0, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn sucursales_ventas_y_traslados() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+    assert_eq!(llamar!(&app, post("/api/proveedores/", proveedor())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/productos/json", producto())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/clientes/", cliente())).await.0, StatusCode::CREATED);
+    let norte = json!({ "codigo": 2, "nombre": "Norte", "direccion": "Calle 170" });
+    assert_eq!(llamar!(&app, post("/api/sucursales/", norte)).await.0, StatusCode::CREATED);
+    let (_, sucursales) = llamar!(&app, get("/api/sucursales/")).await;
+    assert_eq!((&sucursales[0]["nombre"], &sucursales[1]["nombre"]), (&json!("Principal"), &json!("Norte")));
+    // sin sucursal la orden es de la principal
+    let orden = json!({ "nit_proveedor": 1234, "detalles": [{ "codigo_producto": 77, "cantidad": 10, "precio_compra": 100.0 }] });
+    let (_, orden) = llamar!(&app, post("/api/ordenes_compra/", orden)).await;
+    assert_eq!(orden["codigo_sucursal"], 1);
+    let recibir = format!("/api/ordenes_compra/{}/recibir", orden["codigo"]);
+    assert_eq!(llamar!(&app, post(&recibir, json!(null))).await.0, StatusCode::OK);
+
+    // los traslados mueven las existencias entre sucursales, sin cambiar las de la cadena
+    let traslado = |origen: i64, destino: i64, cantidad: f64| {
+        json!({ "codigo_sucursal_origen": origen, "codigo_sucursal_destino": destino,
+                "productos": [{ "codigo_producto": 77, "cantidad": cantidad }] })
+    };
+    let (estado, creado) = llamar!(&app, post("/api/traslados/", traslado(1, 2, 4.0))).await;
+    assert_eq!(estado, StatusCode::CREATED);
+    let cantidades: Vec<_> = creado["movimientos"].as_array().unwrap().iter().map(|m| m["cantidad"].clone()).collect();
+    assert_eq!(cantidades, [json!(-4.0), json!(4.0)]);
+    assert_eq!(llamar!(&app, get("/api/inventario/?sucursal=1")).await.1[0]["cantidad"], 6.0);
+    assert_eq!(llamar!(&app, get("/api/inventario/?sucursal=2")).await.1[0]["cantidad"], 4.0);
+    assert_eq!(llamar!(&app, get("/api/inventario/")).await.1[0]["cantidad"], 10.0);
+    assert_eq!(llamar!(&app, post("/api/traslados/", traslado(2, 1, 5.0))).await.0, StatusCode::CONFLICT);
+    assert_eq!(llamar!(&app, post("/api/traslados/", traslado(2, 2, 1.0))).await.0, StatusCode::BAD_REQUEST);
+    assert_eq!(llamar!(&app, get("/api/traslados/?sucursal=2")).await.1.as_array().map(Vec::len), Some(1));
+
+    // un usuario con sucursales asignadas solo vende en ellas
+    let cajero = json!({ "cedula": 3000, "email": "norte@tienda.co", "nombre": "Cajero", "password": "caja", "usuario": "norte" });
+    assert_eq!(llamar!(&app, post("/api/usuarios/", cajero)).await.0, StatusCode::CREATED);
+    let asignacion = json!({ "codigo_sucursal": 2 });
+    assert_eq!(llamar!(&app, post("/api/usuarios/3000/sucursales", asignacion.clone())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/usuarios/3000/sucursales", asignacion)).await.0, StatusCode::CONFLICT);
+    assert_eq!(llamar!(&app, get("/api/usuarios/3000/sucursales")).await.1[0]["codigo"], 2);
+    assert_eq!(llamar!(&app, get("/api/usuarios/?sucursal=2").insert_header(autorizacion())).await.1[0]["cedula"], 3000);
+    let venta = json!({
+        "codigo": 1, "cedula_cliente": 2001, "cedula_usuario": 3000, "iva_venta": 28.5, "total_venta": 178.5, "valor_venta": 150.0,
+    });
+    assert_eq!(llamar!(&app, post("/api/ventas/", venta.clone())).await.0, StatusCode::FORBIDDEN);
+    let mut en_norte = venta;
+    en_norte["codigo_sucursal"] = json!(2);
+    assert_eq!(llamar!(&app, post("/api/ventas/", en_norte)).await.0, StatusCode::CREATED);
+    let principal = json!({
+        "codigo": 2, "cedula_cliente": 2001, "cedula_usuario": 1, "iva_venta": 0.0, "total_venta": 1.0, "valor_venta": 1.0,
+    });
+    assert_eq!(llamar!(&app, post("/api/ventas/", principal)).await.0, StatusCode::CREATED);
+    let (_, ventas) = llamar!(&app, get("/api/ventas/?sucursal=2")).await;
+    assert_eq!((ventas.as_array().map(Vec::len), &ventas[0]["codigo"]), (Some(1), &json!(1)));
+    assert_eq!(llamar!(&app, get("/api/clientes/2001/ventas?sucursal=1")).await.1[0]["codigo"], 2);
+
+    // la venta finalizada se descuenta de las existencias de su sucursal
+    let detalle = json!({
+        "codigo": 11, "codigo_producto": 77, "codigo_venta": 1, "cantidad_producto": 1,
+        "valor_total": 178.5, "valor_venta": 150.0, "valor_iva": 28.5,
+    });
+    assert_eq!(llamar!(&app, post("/api/detalle_ventas/", detalle)).await.0, StatusCode::CREATED);
+    let pago = json!({ "metodo": "tarjeta", "valor": 178.5 });
+    assert_eq!(llamar!(&app, post("/api/ventas/1/pagos", pago)).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/ventas/1/finalizar", json!(null))).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, get("/api/inventario/?sucursal=2")).await.1[0]["cantidad"], 3.0);
+    assert_eq!(llamar!(&app, get("/api/inventario/?sucursal=1")).await.1[0]["cantidad"], 6.0);
+    assert_eq!(llamar!(&app, get("/api/detalle_ventas/?sucursal=1")).await.1, json!([]));
+
+    // los traslados concurrentes desde una sucursal no mueven mas de lo que tiene
+    let traslados = (0..4).map(|_| async { llamar!(&app, post("/api/traslados/", traslado(1, 2, 2.0))).await.0 });
+    let mut estados = futures_util::future::join_all(traslados).await;
+    estados.sort();
+    assert_eq!(estados, [StatusCode::CREATED, StatusCode::CREATED, StatusCode::CREATED, StatusCode::CONFLICT]);
+    assert_eq!(llamar!(&app, get("/api/inventario/?sucursal=1")).await.1[0]["cantidad"], 0.0);
+
+    assert_eq!(llamar!(&app, delete("/api/usuarios/3000/sucursales/2")).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, delete("/api/usuarios/3000/sucursales/2")).await.0, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn salud_preparacion_y_version() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+
+    assert_eq!(llamar!(&app, get("/health")).await, (StatusCode::OK, json!("ok")));
+    let (estado, preparacion) = llamar!(&app, get("/ready")).await;
+    assert_eq!(estado, StatusCode::OK);
+    assert_eq!(preparacion, json!({ "db": true, "migraciones_pendientes": 0 }));
+    let (_, version) = llamar!(&app, get("/version")).await;
+    assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+    assert!(version["migracion"].is_i64());
+
+    // con una migracion revertida el servidor no esta listo
+    migraciones::bajar(&db).await.expect("revertir la ultima migracion");
+    let (estado, preparacion) = llamar!(&app, get("/ready")).await;
+    assert_eq!(estado, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(preparacion["migraciones_pendientes"], 1);
+}
+
+#[actix_web::test]
+async fn la_preparacion_no_modifica_una_base_de_datos_sin_migrar() {
+    let directorio = tempfile::tempdir().expect("directorio temporal");
+    let db = Db::conectar(&format!("sqlite:{}", directorio.path().join("vacia.db").display())).await.expect("coneccion a SQLite");
+    let app = aplicacion!(db);
+    let (estado, preparacion) = llamar!(&app, get("/ready")).await;
+    assert_eq!((estado, &preparacion["db"]), (StatusCode::SERVICE_UNAVAILABLE, &json!(true)));
+    assert!(preparacion["migraciones_pendientes"].as_u64() > Some(0));
+    assert_eq!(llamar!(&app, get("/version")).await.1["migracion"], Value::Null);
+    let Db::Sqlite(pool) = &db else { unreachable!() };
+    let tablas: i64 = sqlx::query_scalar("select count(*) from sqlite_master where type = 'table';").fetch_one(pool).await.unwrap();
+    assert_eq!(tablas, 0);
+}
+
+#[actix_web::test]
+async fn metricas_de_requests_importaciones_y_ventas() {
+    let (_directorio, db) = base_de_datos().await;
+    let app = aplicacion!(db);
+    assert_eq!(llamar!(&app, post("/api/proveedores/", proveedor())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, formulario_csv("/api/productos/", include_str!("../prod.csv"))).await.0, StatusCode::OK);
+    assert_eq!(llamar!(&app, post("/api/clientes/", cliente())).await.0, StatusCode::CREATED);
+    // las ventas cuentan en las metricas al finalizarse
+    let venta = json!({ "codigo": 1, "cedula_cliente": 2001, "cedula_usuario": 1, "iva_venta": 19.0, "total_venta": 119.0, "valor_venta": 100.0 });
+    assert_eq!(llamar!(&app, post("/api/ventas/", venta)).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/productos/json", producto())).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/detalle_ventas/", detalle(11, 1, 119.0))).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/ventas/1/pagos", json!({ "metodo": "tarjeta", "valor": 119.0 }))).await.0, StatusCode::CREATED);
+    assert_eq!(llamar!(&app, post("/api/ventas/1/finalizar", json!(null))).await.0, StatusCode::OK);
+
+    let (estado, metricas) = llamar!(&app, get("/metrics")).await;
+    assert_eq!(estado, StatusCode::OK);
+    // las metricas son del proceso, compartidas con las otras pruebas: solo se verifica que existan
+    let metricas = metricas.as_str().expect("formato de texto de Prometheus");
+    for linea in [
+        r#"tienda_http_requests_total{method="POST",scope="ventas",status="201"}"#,
+        r#"tienda_http_request_duration_seconds_count{scope="proveedores"}"#,
+        r#"tienda_db_pool_connections{state="idle"}"#,
+        "tienda_db_pool_max_connections 10",
+        "tienda_csv_import_rows_total",
+        "tienda_sales_total",
+        "tienda_sales_revenue_total",
+    ] {
+        assert!(metricas.contains(linea), "falta {linea} en:\n{metricas}");
+    }
+}